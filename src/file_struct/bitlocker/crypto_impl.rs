@@ -0,0 +1,390 @@
+use std::ops::Range;
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit},
+    Aes128, Aes256,
+};
+use ccm::{
+    aead::AeadInPlace,
+    consts::{U12, U16},
+    Ccm,
+};
+use sha2::{Digest, Sha256};
+
+use crate::utils::{file::VolumeDecryptor, MRError};
+
+use super::{fve_impl::FVE_METADATA_AREA_SIZE, BDECipher, BDEDecryptor, FVEMetadata};
+
+pub const BDE_AES_128_CBC_DIFFUSER: u16 = 0x8000;
+pub const BDE_AES_256_CBC_DIFFUSER: u16 = 0x8001;
+pub const BDE_AES_128_CBC: u16 = 0x8002;
+pub const BDE_AES_256_CBC: u16 = 0x8003;
+pub const BDE_AES_128_XTS: u16 = 0x8004;
+pub const BDE_AES_256_XTS: u16 = 0x8005;
+
+const RECOVERY_PASSWORD_GROUPS: usize = 8;
+const RECOVERY_KEY_STRETCH_COUNT: u64 = 0x100000;
+const DIFFUSER_A_CYCLES: usize = 5;
+const DIFFUSER_B_CYCLES: usize = 3;
+const DIFFUSER_A_ROTATIONS: [u32; 4] = [9, 0, 13, 0];
+const DIFFUSER_B_ROTATIONS: [u32; 4] = [0, 10, 0, 25];
+
+type Aes256Ccm = Ccm<Aes256, U16, U12>;
+
+//Eight groups of six digits, each a multiple of 11 holding 16 bits of the key
+pub fn parse_recovery_password(password: &str) -> Result<[u8; 16], MRError> {
+    let digits = password
+        .chars()
+        .filter(|x| *x != '-' && !x.is_whitespace())
+        .collect::<Vec<char>>();
+    if digits.len() != RECOVERY_PASSWORD_GROUPS * 6 {
+        return Err(MRError::new("Recovery password must be 8 groups of 6 digits"));
+    }
+    let mut result = [0u8; 16];
+    for (i, group) in digits.chunks(6).enumerate() {
+        let group = group.iter().collect::<String>();
+        let value = match group.parse::<u32>() {
+            Ok(o) if o % 11 == 0 && o / 11 <= 0xffff => (o / 11) as u16,
+            _ => {
+                return Err(MRError::new(&format!("Invalid recovery password group {}", group)));
+            }
+        };
+        result[i * 2..i * 2 + 2].copy_from_slice(&value.to_le_bytes());
+    }
+    Ok(result)
+}
+
+//SHA-256 chained 0x100000 times over (last hash, password hash, salt, count)
+pub fn stretch_recovery_key(password: &[u8; 16], salt: &[u8]) -> Vec<u8> {
+    let mut data = [0u8; 88];
+    data[32..64].copy_from_slice(&Sha256::digest(password));
+    data[64..80].copy_from_slice(&salt[..16]);
+    for count in 0..RECOVERY_KEY_STRETCH_COUNT {
+        data[80..88].copy_from_slice(&count.to_le_bytes());
+        let hash = Sha256::digest(data);
+        data[0..32].copy_from_slice(&hash);
+    }
+    data[0..32].to_vec()
+}
+
+pub fn ccm_decrypt(key: &[u8], nonce: &[u8], tag: &[u8], data: &[u8]) -> Result<Vec<u8>, MRError> {
+    let cipher = Aes256Ccm::new_from_slice(key).map_err(|_| MRError::new("AES-CCM key must be 256 bits"))?;
+    let mut result = data.to_vec();
+    cipher
+        .decrypt_in_place_detached(
+            GenericArray::from_slice(nonce),
+            &[],
+            &mut result,
+            GenericArray::from_slice(tag),
+        )
+        .map_err(|_| MRError::new("Wrong key, AES-CCM authentication failed"))?;
+    Ok(result)
+}
+
+impl BDECipher {
+    pub fn new(key: &[u8]) -> Result<Self, MRError> {
+        match key.len() {
+            16 => Ok(Self::Aes128(Box::new(Aes128::new(GenericArray::from_slice(key))))),
+            32 => Ok(Self::Aes256(Box::new(Aes256::new(GenericArray::from_slice(key))))),
+            _ => Err(MRError::new("AES key must be 128 or 256 bits")),
+        }
+    }
+
+    pub fn encrypt_block(&self, block: &mut [u8]) {
+        let block = GenericArray::from_mut_slice(block);
+        match self {
+            Self::Aes128(c) => c.encrypt_block(block),
+            Self::Aes256(c) => c.encrypt_block(block),
+        }
+    }
+
+    pub fn decrypt_block(&self, block: &mut [u8]) {
+        let block = GenericArray::from_mut_slice(block);
+        match self {
+            Self::Aes128(c) => c.decrypt_block(block),
+            Self::Aes256(c) => c.decrypt_block(block),
+        }
+    }
+}
+
+fn diffuser_a_decrypt(words: &mut [u32]) {
+    let n = words.len();
+    for _ in 0..DIFFUSER_A_CYCLES {
+        for i in 0..n {
+            let x = words[(i + n - 2) % n] ^ words[(i + n - 5) % n].rotate_left(DIFFUSER_A_ROTATIONS[i % 4]);
+            words[i] = words[i].wrapping_add(x);
+        }
+    }
+}
+
+fn diffuser_b_decrypt(words: &mut [u32]) {
+    let n = words.len();
+    for _ in 0..DIFFUSER_B_CYCLES {
+        for i in 0..n {
+            let x = words[(i + 2) % n] ^ words[(i + 5) % n].rotate_left(DIFFUSER_B_ROTATIONS[i % 4]);
+            words[i] = words[i].wrapping_add(x);
+        }
+    }
+}
+
+//The offset encrypted twice with the tweak key, the second copy ends with 0x80
+fn sector_key(tweak: &BDECipher, offset: u64) -> [u8; 32] {
+    let mut result = [0u8; 32];
+    result[0..8].copy_from_slice(&offset.to_le_bytes());
+    result[16..24].copy_from_slice(&offset.to_le_bytes());
+    result[31] = 0x80;
+    tweak.encrypt_block(&mut result[0..16]);
+    tweak.encrypt_block(&mut result[16..32]);
+    result
+}
+
+//Multiplies the XTS tweak by x in GF(2^128)
+fn xts_next_tweak(tweak: &mut [u8; 16]) {
+    let carry = tweak[15] >> 7;
+    for i in (1..16).rev() {
+        tweak[i] = (tweak[i] << 1) | (tweak[i - 1] >> 7);
+    }
+    tweak[0] = (tweak[0] << 1) ^ (carry * 0x87);
+}
+
+impl BDEDecryptor {
+    pub fn new(metadata: &FVEMetadata, fvek: &[u8]) -> Result<Self, MRError> {
+        let method = metadata.get_header().get_encryption_method();
+        //Diffuser keys are padded to 256 bits, the tweak key starts at 32
+        let (fvek_range, tweak_range) = match method {
+            BDE_AES_128_CBC_DIFFUSER => (0..16, Some(32..48)),
+            BDE_AES_256_CBC_DIFFUSER => (0..32, Some(32..64)),
+            BDE_AES_128_CBC => (0..16, None),
+            BDE_AES_256_CBC => (0..32, None),
+            BDE_AES_128_XTS => (0..16, Some(16..32)),
+            BDE_AES_256_XTS => (0..32, Some(32..64)),
+            _ => {
+                return Err(MRError::new(&format!("Unknown encryption method {:#x}", method)));
+            }
+        };
+        let key = |range: Range<usize>| fvek.get(range).ok_or(MRError::new("FVEK too short for the encryption method"));
+        let block_header = metadata.get_block_header();
+        let sector_size = metadata.get_volume_header().get_bytes_per_sector() as usize;
+        let metadata_ranges = block_header
+            .get_metadata_offsets()
+            .iter()
+            .map(|x| *x..*x + FVE_METADATA_AREA_SIZE)
+            .collect::<Vec<Range<u64>>>();
+        Ok(Self {
+            encryption_method: method,
+            fvek: BDECipher::new(key(fvek_range)?)?,
+            tweak: match tweak_range {
+                Some(s) => Some(BDECipher::new(key(s)?)?),
+                None => None,
+            },
+            sector_size,
+            encrypted_size: block_header.get_encrypted_size(),
+            volume_header_offset: block_header.get_volume_header_offset(),
+            volume_header_size: block_header.get_volume_header_sectors() as u64 * sector_size as u64,
+            metadata_ranges,
+        })
+    }
+
+    fn decrypt_cbc(&self, offset: u64, data: &mut [u8]) {
+        let mut iv = [0u8; 16];
+        iv[0..8].copy_from_slice(&offset.to_le_bytes());
+        self.fvek.encrypt_block(&mut iv);
+        for block in data.chunks_exact_mut(16) {
+            let mut next_iv = [0u8; 16];
+            next_iv.copy_from_slice(block);
+            self.fvek.decrypt_block(block);
+            block.iter_mut().zip(iv.iter()).for_each(|(a, b)| *a ^= b);
+            iv = next_iv;
+        }
+    }
+
+    //Elephant diffuser, undone after the CBC decryption
+    fn undiffuse(&self, offset: u64, data: &mut [u8]) {
+        let tweak = match &self.tweak {
+            Some(s) => s,
+            None => return,
+        };
+        let mut words = data
+            .chunks_exact(4)
+            .map(|a| u32::from_le_bytes([a[0], a[1], a[2], a[3]]))
+            .collect::<Vec<u32>>();
+        diffuser_b_decrypt(&mut words);
+        diffuser_a_decrypt(&mut words);
+        let sector_key = sector_key(tweak, offset);
+        for (i, word) in words.iter().enumerate() {
+            let bs = word.to_le_bytes();
+            for j in 0..4 {
+                data[i * 4 + j] = bs[j] ^ sector_key[(i * 4 + j) % 32];
+            }
+        }
+    }
+
+    fn decrypt_xts(&self, offset: u64, data: &mut [u8]) {
+        let tweak_cipher = match &self.tweak {
+            Some(s) => s,
+            None => return,
+        };
+        let mut tweak = [0u8; 16];
+        tweak[0..8].copy_from_slice(&(offset / self.sector_size as u64).to_le_bytes());
+        tweak_cipher.encrypt_block(&mut tweak);
+        for block in data.chunks_exact_mut(16) {
+            block.iter_mut().zip(tweak.iter()).for_each(|(a, b)| *a ^= b);
+            self.fvek.decrypt_block(block);
+            block.iter_mut().zip(tweak.iter()).for_each(|(a, b)| *a ^= b);
+            xts_next_tweak(&mut tweak);
+        }
+    }
+}
+
+impl VolumeDecryptor for BDEDecryptor {
+    fn get_sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    fn map_sector(&self, offset: u64) -> Option<u64> {
+        if self.metadata_ranges.iter().any(|x| x.contains(&offset)) {
+            return None;
+        }
+        //The first sectors of the volume are moved to make room for the BitLocker boot sector
+        if offset < self.volume_header_size {
+            return Some(self.volume_header_offset + offset);
+        }
+        Some(offset)
+    }
+
+    fn decrypt_sector(&self, offset: u64, data: &mut [u8]) {
+        //Past the encrypted size the conversion has not reached the data yet
+        if offset >= self.encrypted_size {
+            return;
+        }
+        match self.encryption_method {
+            BDE_AES_128_XTS | BDE_AES_256_XTS => self.decrypt_xts(offset, data),
+            _ => {
+                self.decrypt_cbc(offset, data);
+                self.undiffuse(offset, data);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Expected values computed with Python's hashlib and cryptography
+    const SALT: [u8; 16] = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f];
+    const OFFSET: u64 = 0x12345600;
+    const FVEK: [u8; 16] = [0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f];
+    const TWEAK: [u8; 16] = [0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x4b, 0x4c, 0x4d, 0x4e, 0x4f];
+
+    fn plain_sector() -> Vec<u8> {
+        (0..64).map(|x| (x * 7) as u8).collect()
+    }
+
+    fn decryptor(encryption_method: u16) -> BDEDecryptor {
+        BDEDecryptor {
+            encryption_method,
+            fvek: BDECipher::new(&FVEK).unwrap(),
+            tweak: Some(BDECipher::new(&TWEAK).unwrap()),
+            sector_size: 64,
+            encrypted_size: u64::MAX,
+            volume_header_offset: 0,
+            volume_header_size: 0,
+            metadata_ranges: vec![],
+        }
+    }
+
+    #[test]
+    fn recovery_password() {
+        let key = parse_recovery_password("111111-222222-333333-444444-555555-666666-000000-111122").unwrap();
+        assert_eq!(key, [0x75, 0x27, 0xea, 0x4e, 0x5f, 0x76, 0xd4, 0x9d, 0x49, 0xc5, 0xbe, 0xec, 0x00, 0x00, 0x76, 0x27]);
+        assert_eq!(
+            stretch_recovery_key(&key, &SALT),
+            [
+                0x65, 0x54, 0x37, 0x4e, 0x1d, 0xf3, 0x28, 0x44, 0xaa, 0x26, 0x74, 0x29, 0x26, 0xe5, 0x32, 0x2b, 0x65, 0xf4,
+                0x99, 0x72, 0x19, 0x62, 0x01, 0x60, 0x68, 0x89, 0xbd, 0x1a, 0xf7, 0x78, 0x9c, 0x10,
+            ]
+        );
+        //Not a multiple of 11, over 16 bits, too short
+        assert!(parse_recovery_password("111112-222222-333333-444444-555555-666666-000000-111122").is_err());
+        assert!(parse_recovery_password("111111-222222-333333-444444-555555-666666-000000-888888").is_err());
+        assert!(parse_recovery_password("111111-222222").is_err());
+    }
+
+    #[test]
+    fn ccm() {
+        let key: Vec<u8> = (0x20..0x40).collect();
+        let nonce: Vec<u8> = (0xa0..0xac).collect();
+        let tag = [0x12, 0x12, 0x08, 0xb4, 0xe4, 0xf1, 0x23, 0x15, 0x6d, 0x5c, 0xfb, 0x5b, 0xa8, 0x42, 0x6c, 0x9f];
+        let data = [
+            0xf8, 0xa5, 0x96, 0x26, 0x65, 0xb5, 0xed, 0x57, 0xee, 0x4b, 0x2c, 0x84, 0x9b, 0x60, 0x1f, 0x74, 0xbe, 0x25,
+            0x70, 0x7d, 0x7b, 0xa5, 0x4d, 0x7c, 0xc7, 0xa6, 0x96, 0x32, 0x45, 0x55, 0xa7, 0x81, 0x28, 0xc6, 0x7c, 0x1a,
+            0x3e, 0xc1, 0x84, 0xfd, 0x5b, 0xb9, 0x1d, 0xaa,
+        ];
+        let plain = ccm_decrypt(&key, &nonce, &tag, &data).unwrap();
+        //A key entry of 44 bytes holding the 256 bits key 0x60..0x80
+        assert_eq!(plain[0..12], [0x2c, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x20, 0x00, 0x00]);
+        assert_eq!(plain[12..], (0x60..0x80).collect::<Vec<u8>>()[..]);
+        let mut wrong = key.clone();
+        wrong[0] ^= 1;
+        assert!(ccm_decrypt(&wrong, &nonce, &tag, &data).is_err());
+    }
+
+    #[test]
+    fn diffuser() {
+        let words: Vec<u32> = (0..16).map(|x: u32| x.wrapping_mul(0x01020304)).collect();
+        let mut a = vec![
+            0xf2700a87, 0x8cf75500, 0x0244f394, 0xccb88ff3, 0x83e21ff8, 0xa803e09a, 0x18f43e74, 0x25e212a7, 0x442098c8,
+            0xc9a427cc, 0xc67dd7ef, 0xe930f8b5, 0x10c8bfae, 0xbadef177, 0xc79195c0, 0x48ee1a6a,
+        ];
+        diffuser_a_decrypt(&mut a);
+        assert_eq!(a, words);
+        let mut b = vec![
+            0x736cd0b9, 0x04981790, 0xe67afcbe, 0xb1426e22, 0x9315cb51, 0x3eaf1a24, 0xfc0032c6, 0x59bad969, 0xcfbfc9d7,
+            0x93a54f00, 0x3981411a, 0x40861f86, 0x0b91c049, 0xc4f7646a, 0xdb05b3c4, 0x0d3c19c1,
+        ];
+        diffuser_b_decrypt(&mut b);
+        assert_eq!(b, words);
+    }
+
+    #[test]
+    fn sector_keys() {
+        assert_eq!(
+            sector_key(&BDECipher::new(&TWEAK).unwrap(), OFFSET),
+            [
+                0x73, 0xa1, 0x58, 0x49, 0xd7, 0x72, 0xf2, 0xe9, 0x5d, 0x8b, 0x01, 0x27, 0x07, 0xc6, 0x43, 0xde, 0x78, 0x87,
+                0x33, 0x17, 0xc2, 0xbe, 0x8e, 0x81, 0xa8, 0xb1, 0xb8, 0x90, 0x1e, 0x59, 0xde, 0x71,
+            ]
+        );
+    }
+
+    #[test]
+    fn cbc_diffuser_sector() {
+        let mut data = [
+            0x3f, 0x2c, 0xb3, 0x7b, 0x52, 0x70, 0x27, 0x4c, 0x95, 0x76, 0x4c, 0x8c, 0x8e, 0x83, 0xca, 0xec, 0x52, 0xc0,
+            0x53, 0x5f, 0x8f, 0x93, 0x98, 0xd2, 0xce, 0xda, 0xc2, 0x97, 0x36, 0x50, 0x8e, 0x28, 0xe2, 0x3f, 0x7a, 0x65,
+            0xcc, 0x18, 0x70, 0xea, 0xe2, 0xfc, 0xdd, 0x31, 0xed, 0x5e, 0x0c, 0xd3, 0xb5, 0x57, 0x44, 0x4d, 0x23, 0x57,
+            0x4f, 0x2c, 0xc6, 0x8f, 0xaf, 0x61, 0x7d, 0x29, 0x56, 0x05,
+        ];
+        decryptor(BDE_AES_128_CBC_DIFFUSER).decrypt_sector(OFFSET, &mut data);
+        assert_eq!(data.to_vec(), plain_sector());
+    }
+
+    #[test]
+    fn xts_sector() {
+        let mut data = [
+            0xf2, 0x56, 0xd1, 0x23, 0x9b, 0xf2, 0x6a, 0x20, 0xf3, 0xe4, 0x86, 0x6f, 0xe1, 0xe5, 0xb4, 0x05, 0x18, 0x10,
+            0x73, 0x17, 0xf5, 0xbf, 0x4a, 0x81, 0x95, 0x7b, 0x53, 0x44, 0xe8, 0x86, 0xa6, 0x08, 0xb6, 0xa4, 0xe3, 0xb6,
+            0xd1, 0x97, 0x22, 0x56, 0x1f, 0xc6, 0xbb, 0x06, 0x6a, 0xa0, 0xff, 0xd3, 0xb1, 0x53, 0x22, 0x5c, 0xc6, 0xe8,
+            0x29, 0xe3, 0xa2, 0x0c, 0x8a, 0x66, 0xb8, 0x0d, 0x05, 0x9f,
+        ];
+        decryptor(BDE_AES_128_XTS).decrypt_sector(OFFSET, &mut data);
+        assert_eq!(data.to_vec(), plain_sector());
+        //Past the encrypted size the sector is left as is
+        let mut decryptor = decryptor(BDE_AES_128_XTS);
+        decryptor.encrypted_size = OFFSET;
+        let mut data = plain_sector();
+        decryptor.decrypt_sector(OFFSET, &mut data);
+        assert_eq!(data, plain_sector());
+    }
+}
@@ -0,0 +1,458 @@
+use bytes::{Buf, Bytes};
+
+use crate::{
+    file_struct::ntfs::FileTime,
+    utils::{file::MRFile, funcs::sub_bytes, MRError},
+};
+
+use super::{
+    crypto_impl::{ccm_decrypt, parse_recovery_password, stretch_recovery_key},
+    BDEUnlockKey, BDEVolumeHeader, FVEMetadata, FVEMetadataBlockHeader, FVEMetadataEntry,
+    FVEMetadataHeader, VolumeMasterKey,
+};
+
+pub const BDE_SIGNATURE: &[u8; 8] = b"-FVE-FS-";
+//{4967d63b-2e29-4ad8-8399-f6a339e3d001}
+const BDE_IDENTIFIER: [u8; 16] = [
+    0x3b, 0xd6, 0x67, 0x49, 0x29, 0x2e, 0xd8, 0x4a, 0x83, 0x99, 0xf6, 0xa3, 0x39, 0xe3, 0xd0, 0x01,
+];
+const FVE_BLOCK_HEADER_SIZE: usize = 64;
+const FVE_HEADER_SIZE: usize = 48;
+const FVE_ENTRY_HEADER_SIZE: usize = 8;
+//Each copy of the metadata owns 64KB of the volume
+pub const FVE_METADATA_AREA_SIZE: u64 = 0x10000;
+
+pub const FVE_ENTRY_TYPE_VMK: u16 = 0x0002;
+pub const FVE_ENTRY_TYPE_FVEK: u16 = 0x0003;
+
+pub const FVE_VALUE_TYPE_KEY: u16 = 0x0001;
+pub const FVE_VALUE_TYPE_UNICODE: u16 = 0x0002;
+pub const FVE_VALUE_TYPE_STRETCH_KEY: u16 = 0x0003;
+pub const FVE_VALUE_TYPE_AES_CCM_KEY: u16 = 0x0005;
+pub const FVE_VALUE_TYPE_VMK: u16 = 0x0008;
+pub const FVE_VALUE_TYPE_EXTERNAL_KEY: u16 = 0x0009;
+
+pub const VMK_PROTECTION_CLEAR_KEY: u16 = 0x0000;
+pub const VMK_PROTECTION_TPM: u16 = 0x0100;
+pub const VMK_PROTECTION_STARTUP_KEY: u16 = 0x0200;
+pub const VMK_PROTECTION_TPM_PIN: u16 = 0x0500;
+pub const VMK_PROTECTION_RECOVERY_PASSWORD: u16 = 0x0800;
+pub const VMK_PROTECTION_PASSWORD: u16 = 0x2000;
+
+fn read_guid(bs: &Bytes, offset: usize) -> Result<[u8; 16], MRError> {
+    let mut result = [0u8; 16];
+    result.copy_from_slice(sub_bytes(bs, offset..offset + 16)?);
+    Ok(result)
+}
+
+//Entries follow each other until one does not fit
+pub fn parse_entries(bs: &Bytes) -> Vec<FVEMetadataEntry> {
+    let mut result = vec![];
+    let mut offset = 0;
+    while offset + FVE_ENTRY_HEADER_SIZE <= bs.len() {
+        let size = (&bs[offset..offset + 2]).get_u16_le() as usize;
+        if size < FVE_ENTRY_HEADER_SIZE || offset + size > bs.len() {
+            break;
+        }
+        result.push(FVEMetadataEntry {
+            entry_type: (&bs[offset + 2..offset + 4]).get_u16_le(),
+            value_type: (&bs[offset + 4..offset + 6]).get_u16_le(),
+            version: (&bs[offset + 6..offset + 8]).get_u16_le(),
+            data: bs.slice(offset + FVE_ENTRY_HEADER_SIZE..offset + size),
+        });
+        offset += size;
+    }
+    result
+}
+
+fn find_value(entries: &[FVEMetadataEntry], value_type: u16) -> Option<&FVEMetadataEntry> {
+    entries.iter().find(|x| x.value_type == value_type)
+}
+
+//Key entries start with the encryption method the key is for
+fn key_from_entry(entry: &FVEMetadataEntry) -> Result<Vec<u8>, MRError> {
+    Ok(sub_bytes(&entry.data, 4..entry.data.len())?.to_vec())
+}
+
+//An AES-CCM entry decrypts to a key entry
+fn decrypt_key_entry(entry: &FVEMetadataEntry, key: &[u8]) -> Result<Vec<u8>, MRError> {
+    let nonce = sub_bytes(&entry.data, 0..12)?;
+    let tag = sub_bytes(&entry.data, 12..28)?;
+    let plain = Bytes::from(ccm_decrypt(key, nonce, tag, &entry.data[28..])?);
+    let inner = parse_entries(&plain);
+    let inner = find_value(&inner, FVE_VALUE_TYPE_KEY)
+        .ok_or(MRError::new("Decrypted data is not a key"))?;
+    key_from_entry(inner)
+}
+
+impl BDEVolumeHeader {
+    pub fn parse(bs: &Bytes) -> Result<Self, MRError> {
+        if sub_bytes(bs, 3..11)? != BDE_SIGNATURE {
+            return Err(MRError::new("Not a BitLocker volume"));
+        }
+        let bitlocker_id = read_guid(bs, 160)?;
+        if bitlocker_id != BDE_IDENTIFIER {
+            return Err(MRError::new("BitLocker volumes from Windows Vista are not supported"));
+        }
+        Ok(Self {
+            bytes_per_sector: (sub_bytes(bs, 11..13)?).get_u16_le(),
+            sectors_per_cluster_block: (sub_bytes(bs, 13..14)?).get_u8(),
+            total_sectors: (sub_bytes(bs, 40..48)?).get_u64_le(),
+            bitlocker_id,
+            metadata_offsets: [
+                (sub_bytes(bs, 176..184)?).get_u64_le(),
+                (sub_bytes(bs, 184..192)?).get_u64_le(),
+                (sub_bytes(bs, 192..200)?).get_u64_le(),
+            ],
+        })
+    }
+
+    pub fn get_bytes_per_sector(&self) -> u16 {
+        self.bytes_per_sector
+    }
+
+    pub fn get_metadata_offsets(&self) -> &[u64; 3] {
+        &self.metadata_offsets
+    }
+}
+
+impl FVEMetadataBlockHeader {
+    pub fn parse(bs: &Bytes) -> Result<Self, MRError> {
+        if sub_bytes(bs, 0..8)? != BDE_SIGNATURE {
+            return Err(MRError::new("Not a FVE metadata block"));
+        }
+        Ok(Self {
+            version: (sub_bytes(bs, 10..12)?).get_u16_le(),
+            encrypted_size: (sub_bytes(bs, 16..24)?).get_u64_le(),
+            volume_header_sectors: (sub_bytes(bs, 28..32)?).get_u32_le(),
+            metadata_offsets: [
+                (sub_bytes(bs, 32..40)?).get_u64_le(),
+                (sub_bytes(bs, 40..48)?).get_u64_le(),
+                (sub_bytes(bs, 48..56)?).get_u64_le(),
+            ],
+            volume_header_offset: (sub_bytes(bs, 56..64)?).get_u64_le(),
+        })
+    }
+
+    pub fn get_version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn get_encrypted_size(&self) -> u64 {
+        self.encrypted_size
+    }
+
+    pub fn get_volume_header_sectors(&self) -> u32 {
+        self.volume_header_sectors
+    }
+
+    pub fn get_volume_header_offset(&self) -> u64 {
+        self.volume_header_offset
+    }
+
+    pub fn get_metadata_offsets(&self) -> &[u64; 3] {
+        &self.metadata_offsets
+    }
+}
+
+impl FVEMetadataHeader {
+    pub fn parse(bs: &Bytes) -> Result<Self, MRError> {
+        let header_size = (sub_bytes(bs, 8..12)?).get_u32_le() as usize;
+        if header_size != FVE_HEADER_SIZE {
+            return Err(MRError::new("Unknown FVE metadata header size"));
+        }
+        Ok(Self {
+            metadata_size: (sub_bytes(bs, 0..4)?).get_u32_le(),
+            volume_id: read_guid(bs, 16)?,
+            next_nonce_counter: (sub_bytes(bs, 32..36)?).get_u32_le(),
+            encryption_method: (sub_bytes(bs, 36..38)?).get_u16_le(),
+            creation_time: FileTime::parse_from_u64((sub_bytes(bs, 40..48)?).get_u64()),
+        })
+    }
+
+    pub fn get_encryption_method(&self) -> u16 {
+        self.encryption_method
+    }
+
+    pub fn get_creation_time(&self) -> &FileTime {
+        &self.creation_time
+    }
+}
+
+impl FVEMetadataEntry {
+    pub fn get_entry_type(&self) -> u16 {
+        self.entry_type
+    }
+
+    pub fn get_value_type(&self) -> u16 {
+        self.value_type
+    }
+
+    pub fn get_data(&self) -> &Bytes {
+        &self.data
+    }
+}
+
+impl VolumeMasterKey {
+    pub fn parse(entry: &FVEMetadataEntry) -> Result<Self, MRError> {
+        let bs = &entry.data;
+        Ok(Self {
+            key_id: read_guid(bs, 0)?,
+            modification_time: FileTime::parse_from_u64((sub_bytes(bs, 16..24)?).get_u64()),
+            protection: (sub_bytes(bs, 26..28)?).get_u16_le(),
+            properties: parse_entries(&bs.slice(28..)),
+        })
+    }
+
+    pub fn get_key_id(&self) -> &[u8; 16] {
+        &self.key_id
+    }
+
+    pub fn get_modification_time(&self) -> &FileTime {
+        &self.modification_time
+    }
+
+    pub fn get_protection(&self) -> u16 {
+        self.protection
+    }
+
+    pub fn get_protection_name(&self) -> &str {
+        match self.protection {
+            VMK_PROTECTION_CLEAR_KEY => "clear key",
+            VMK_PROTECTION_TPM => "TPM",
+            VMK_PROTECTION_STARTUP_KEY => "startup key",
+            VMK_PROTECTION_TPM_PIN => "TPM and PIN",
+            VMK_PROTECTION_RECOVERY_PASSWORD => "recovery password",
+            VMK_PROTECTION_PASSWORD => "password",
+            _ => "unknown",
+        }
+    }
+
+    //Decrypts the VMK with the key the protector was unlocked with
+    fn decrypt(&self, key: &[u8]) -> Result<Vec<u8>, MRError> {
+        let encrypted = find_value(&self.properties, FVE_VALUE_TYPE_AES_CCM_KEY)
+            .ok_or(MRError::new("VMK has no encrypted key"))?;
+        decrypt_key_entry(encrypted, key)
+    }
+
+    //Whether this protector is the kind the key unlocks
+    pub fn accepts(&self, key: &BDEUnlockKey) -> bool {
+        self.protection
+            == match key {
+                BDEUnlockKey::ClearKey => VMK_PROTECTION_CLEAR_KEY,
+                BDEUnlockKey::RecoveryPassword(_) => VMK_PROTECTION_RECOVERY_PASSWORD,
+                BDEUnlockKey::StartupKey(_) => VMK_PROTECTION_STARTUP_KEY,
+            }
+    }
+
+    pub fn unlock(&self, key: &BDEUnlockKey) -> Result<Vec<u8>, MRError> {
+        match key {
+            BDEUnlockKey::ClearKey => {
+                if self.protection != VMK_PROTECTION_CLEAR_KEY {
+                    return Err(MRError::new("Not a clear key protector"));
+                }
+                let clear = find_value(&self.properties, FVE_VALUE_TYPE_KEY)
+                    .ok_or(MRError::new("Clear key protector has no key"))?;
+                self.decrypt(&key_from_entry(clear)?)
+            }
+            BDEUnlockKey::RecoveryPassword(password) => {
+                if self.protection != VMK_PROTECTION_RECOVERY_PASSWORD {
+                    return Err(MRError::new("Not a recovery password protector"));
+                }
+                let stretch = find_value(&self.properties, FVE_VALUE_TYPE_STRETCH_KEY)
+                    .ok_or(MRError::new("Recovery password protector has no salt"))?;
+                let salt = sub_bytes(&stretch.data, 4..20)?;
+                let key = stretch_recovery_key(&parse_recovery_password(password)?, salt);
+                self.decrypt(&key)
+            }
+            BDEUnlockKey::StartupKey(bek) => {
+                if self.protection != VMK_PROTECTION_STARTUP_KEY {
+                    return Err(MRError::new("Not a startup key protector"));
+                }
+                let (key_id, key) = parse_startup_key(bek)?;
+                if key_id != self.key_id {
+                    return Err(MRError::new("Startup key is for another protector"));
+                }
+                self.decrypt(&key)
+            }
+        }
+    }
+}
+
+//A .BEK file is a metadata header followed by an external key entry
+pub fn parse_startup_key(bek: &[u8]) -> Result<([u8; 16], Vec<u8>), MRError> {
+    let bs = Bytes::copy_from_slice(bek);
+    let size = (sub_bytes(&bs, 0..4)?).get_u32_le() as usize;
+    let entries = parse_entries(&bs.slice(FVE_HEADER_SIZE..size.min(bs.len())));
+    let external = find_value(&entries, FVE_VALUE_TYPE_EXTERNAL_KEY)
+        .ok_or(MRError::new("No external key in the startup key file"))?;
+    let key_id = read_guid(&external.data, 0)?;
+    let properties = parse_entries(&external.data.slice(24..));
+    let key = find_value(&properties, FVE_VALUE_TYPE_KEY)
+        .ok_or(MRError::new("No key in the startup key file"))?;
+    Ok((key_id, key_from_entry(key)?))
+}
+
+impl FVEMetadata {
+    //Reads the first of the three metadata copies that parses
+    pub fn read(reader: &MRFile) -> Result<Self, MRError> {
+        let volume_header = BDEVolumeHeader::parse(&Bytes::from(reader.read_n(0, 512)?))?;
+        let mut error = MRError::new("No FVE metadata block");
+        for offset in volume_header.metadata_offsets {
+            match Self::read_block(reader, offset) {
+                Ok((block_header, header, entries)) => {
+                    return Ok(Self {
+                        volume_header,
+                        block_header,
+                        header,
+                        entries,
+                    });
+                }
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    fn read_block(
+        reader: &MRFile,
+        offset: u64,
+    ) -> Result<(FVEMetadataBlockHeader, FVEMetadataHeader, Vec<FVEMetadataEntry>), MRError> {
+        let bs = Bytes::from(reader.read_n(offset as usize, FVE_METADATA_AREA_SIZE as usize)?);
+        let block_header = FVEMetadataBlockHeader::parse(&bs)?;
+        if block_header.version == 1 {
+            return Err(MRError::new("FVE metadata version 1, BitLocker volumes from Windows Vista are not supported"));
+        }
+        if block_header.version != 2 {
+            return Err(MRError::new(&format!(
+                "FVE metadata version {} is not supported",
+                block_header.version
+            )));
+        }
+        let header = FVEMetadataHeader::parse(&bs.slice(FVE_BLOCK_HEADER_SIZE..))?;
+        let end = FVE_BLOCK_HEADER_SIZE + header.metadata_size as usize;
+        let entries = parse_entries(&bs.slice(
+            FVE_BLOCK_HEADER_SIZE + FVE_HEADER_SIZE..end.min(bs.len()),
+        ));
+        Ok((block_header, header, entries))
+    }
+
+    pub fn get_volume_header(&self) -> &BDEVolumeHeader {
+        &self.volume_header
+    }
+
+    pub fn get_block_header(&self) -> &FVEMetadataBlockHeader {
+        &self.block_header
+    }
+
+    pub fn get_header(&self) -> &FVEMetadataHeader {
+        &self.header
+    }
+
+    pub fn get_entries(&self) -> &Vec<FVEMetadataEntry> {
+        &self.entries
+    }
+
+    pub fn get_description(&self) -> Option<String> {
+        let entry = find_value(&self.entries, FVE_VALUE_TYPE_UNICODE)?;
+        let name = entry
+            .data
+            .chunks_exact(2)
+            .map(|a| u16::from_le_bytes([a[0], a[1]]))
+            .take_while(|x| *x != 0)
+            .collect::<Vec<u16>>();
+        Some(String::from_utf16_lossy(&name))
+    }
+
+    pub fn get_vmks(&self) -> Vec<VolumeMasterKey> {
+        self.entries
+            .iter()
+            .filter(|x| x.entry_type == FVE_ENTRY_TYPE_VMK && x.value_type == FVE_VALUE_TYPE_VMK)
+            .filter_map(|x| VolumeMasterKey::parse(x).ok())
+            .collect()
+    }
+
+    //The VMK of the first protector the key unlocks
+    pub fn unlock(&self, key: &BDEUnlockKey) -> Result<Vec<u8>, MRError> {
+        let mut error = MRError::new("No key protector matches the key");
+        for vmk in self.get_vmks().iter().filter(|x| x.accepts(key)) {
+            match vmk.unlock(key) {
+                Ok(o) => return Ok(o),
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    pub fn get_fvek(&self, vmk: &[u8]) -> Result<Vec<u8>, MRError> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|x| x.entry_type == FVE_ENTRY_TYPE_FVEK && x.value_type == FVE_VALUE_TYPE_AES_CCM_KEY)
+            .ok_or(MRError::new("No FVEK in the metadata"))?;
+        decrypt_key_entry(entry, vmk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Key entry holding the 256 bits key 0x20..0x40
+    const CLEAR_KEY: [u8; 8] = [0x2c, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00];
+    //AES-CCM entry made with Python's cryptography, decrypts with 0x20..0x40 to a key entry holding 0x60..0x80
+    const CCM_KEY: [u8; 80] = [
+        0x50, 0x00, 0x00, 0x00, 0x05, 0x00, 0x01, 0x00, 0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa,
+        0xab, 0x12, 0x12, 0x08, 0xb4, 0xe4, 0xf1, 0x23, 0x15, 0x6d, 0x5c, 0xfb, 0x5b, 0xa8, 0x42, 0x6c, 0x9f, 0xf8, 0xa5,
+        0x96, 0x26, 0x65, 0xb5, 0xed, 0x57, 0xee, 0x4b, 0x2c, 0x84, 0x9b, 0x60, 0x1f, 0x74, 0xbe, 0x25, 0x70, 0x7d, 0x7b,
+        0xa5, 0x4d, 0x7c, 0xc7, 0xa6, 0x96, 0x32, 0x45, 0x55, 0xa7, 0x81, 0x28, 0xc6, 0x7c, 0x1a, 0x3e, 0xc1, 0x84, 0xfd,
+        0x5b, 0xb9, 0x1d, 0xaa,
+    ];
+
+    fn vmk_entry(protection: u16) -> FVEMetadataEntry {
+        let mut bs = vec![0x11; 16];
+        bs.extend([0; 10]);
+        bs.extend(protection.to_le_bytes());
+        bs.extend(CLEAR_KEY);
+        bs.extend(0x2000u32.to_le_bytes());
+        bs.extend(0x20..0x40);
+        bs.extend(CCM_KEY);
+        FVEMetadataEntry {
+            entry_type: FVE_ENTRY_TYPE_VMK,
+            value_type: FVE_VALUE_TYPE_VMK,
+            version: 1,
+            data: Bytes::from(bs),
+        }
+    }
+
+    #[test]
+    fn unwrap_key() {
+        let entries = parse_entries(&Bytes::from_static(&CCM_KEY));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].get_value_type(), FVE_VALUE_TYPE_AES_CCM_KEY);
+        let key = (0x20..0x40).collect::<Vec<u8>>();
+        assert_eq!(decrypt_key_entry(&entries[0], &key).unwrap(), (0x60..0x80).collect::<Vec<u8>>());
+        assert!(decrypt_key_entry(&entries[0], &[0; 32]).is_err());
+    }
+
+    #[test]
+    fn clear_key_vmk() {
+        let vmk = VolumeMasterKey::parse(&vmk_entry(VMK_PROTECTION_CLEAR_KEY)).unwrap();
+        assert_eq!(vmk.get_key_id(), &[0x11; 16]);
+        assert_eq!(vmk.get_protection_name(), "clear key");
+        assert_eq!(vmk.unlock(&BDEUnlockKey::ClearKey).unwrap(), (0x60..0x80).collect::<Vec<u8>>());
+        let vmk = VolumeMasterKey::parse(&vmk_entry(VMK_PROTECTION_RECOVERY_PASSWORD)).unwrap();
+        assert!(!vmk.accepts(&BDEUnlockKey::ClearKey));
+        assert!(vmk.unlock(&BDEUnlockKey::ClearKey).is_err());
+    }
+
+    #[test]
+    fn vista_volume() {
+        let mut bs = vec![0; 512];
+        bs[3..11].copy_from_slice(BDE_SIGNATURE);
+        let e = BDEVolumeHeader::parse(&Bytes::from(bs.clone())).unwrap_err();
+        assert!(e.to_string().contains("Vista"));
+        bs[160..176].copy_from_slice(&BDE_IDENTIFIER);
+        assert!(BDEVolumeHeader::parse(&Bytes::from(bs)).is_ok());
+    }
+}
@@ -1,3 +1,13 @@
+use std::ops::Range;
+
+use aes::{Aes128, Aes256};
+use bytes::Bytes;
+
+use super::ntfs::FileTime;
+
+pub mod fve_impl;
+pub mod crypto_impl;
+
 #[allow(unused)]
 pub struct BDEHeaderVista {
     entry_point         : [u8;3],
@@ -6,4 +16,87 @@ pub struct BDEHeaderVista {
     sectors_per_clushter_block  : u8,
     root_directory_entries      : u16,
     number_sectors      : u16,
-}
\ No newline at end of file
+}
+
+//Boot sector of a Windows 7 and later BitLocker volume
+#[derive(Debug)]
+pub struct BDEVolumeHeader {
+    bytes_per_sector    : u16,
+    sectors_per_cluster_block   : u8,
+    total_sectors       : u64,
+    bitlocker_id        : [u8;16],
+    metadata_offsets    : [u64;3]
+}
+
+#[derive(Debug)]
+pub struct FVEMetadataBlockHeader {
+    version             : u16,
+    encrypted_size      : u64,
+    volume_header_sectors   : u32,
+    metadata_offsets    : [u64;3],
+    volume_header_offset    : u64
+}
+
+#[derive(Debug)]
+pub struct FVEMetadataHeader {
+    metadata_size       : u32,
+    volume_id           : [u8;16],
+    next_nonce_counter  : u32,
+    encryption_method   : u16,
+    creation_time       : FileTime
+}
+
+//Entries nest, a VMK entry holds the entries needed to decrypt it
+#[derive(Debug, Clone)]
+pub struct FVEMetadataEntry {
+    entry_type          : u16,
+    value_type          : u16,
+    version             : u16,
+    data                : Bytes
+}
+
+#[derive(Debug)]
+pub struct FVEMetadata {
+    volume_header       : BDEVolumeHeader,
+    block_header        : FVEMetadataBlockHeader,
+    header              : FVEMetadataHeader,
+    entries             : Vec<FVEMetadataEntry>
+}
+
+#[derive(Debug)]
+pub struct VolumeMasterKey {
+    key_id              : [u8;16],
+    modification_time   : FileTime,
+    protection          : u16,
+    properties          : Vec<FVEMetadataEntry>
+}
+
+pub enum BDEUnlockKey {
+    //Protector left by suspending BitLocker
+    ClearKey,
+    //48 digits, with or without dashes
+    RecoveryPassword(String),
+    //Content of a .BEK startup key file
+    StartupKey(Vec<u8>)
+}
+
+#[derive(Debug, Clone)]
+pub enum BDECipher {
+    Aes128(Box<Aes128>),
+    Aes256(Box<Aes256>)
+}
+
+//Decrypts the sectors of a BitLocker volume with its FVEK
+#[derive(Debug)]
+pub struct BDEDecryptor {
+    encryption_method   : u16,
+    fvek                : BDECipher,
+    //Elephant diffuser sector key or second XTS key
+    tweak               : Option<BDECipher>,
+    sector_size         : usize,
+    encrypted_size      : u64,
+    volume_header_offset    : u64,
+    volume_header_size  : u64,
+    //FVE metadata areas, read as zeros
+    metadata_ranges     : Vec<Range<u64>>
+}
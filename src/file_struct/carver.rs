@@ -0,0 +1,304 @@
+use std::ops::Range;
+
+use memchr::memmem;
+
+use crate::utils::{file::MRFile, MRError};
+
+use super::{elf, email, jpg, pdf, pe, png, sqlite, zip};
+
+//Bytes of the ranges searched for headers at once
+pub const CARVE_CHUNK_SIZE: usize = 0x100000;
+//Enough to see a header starting at the very end of a chunk
+pub const MAGIC_OVERLAP: usize = 0x20;
+//First bytes given to a validator, doubled each time it needs more
+const FIRST_WINDOW: usize = 0x10000;
+
+//What a validator makes of the bytes starting at a header
+#[derive(Debug, Clone, PartialEq)]
+pub enum Carving {
+    //confidence goes from 0 to 100, ext is set by containers telling their content, docx in a zip
+    Found { size: usize, confidence: u8, ext: Option<&'static str> },
+    //The end of the file is past the bytes given
+    NeedMore,
+    Invalid,
+}
+
+impl Carving {
+    pub fn found(size: usize, confidence: u8) -> Self {
+        Self::Found { size, confidence, ext: None }
+    }
+}
+
+pub struct Signature {
+    kind        : &'static str,
+    ext         : &'static str,
+    magics      : &'static [&'static [u8]],
+    max_size    : usize,
+    //Gets the bytes from the header on, last is set when no more bytes can be given
+    validate    : fn(&[u8], bool) -> Carving
+}
+
+pub const SIGNATURES: &[Signature] = &[
+    Signature { kind: "jpg", ext: "jpg", magics: &[jpg::MAGIC], max_size: 0x4000000, validate: jpg::carve },
+    Signature { kind: "png", ext: "png", magics: &[png::MAGIC], max_size: 0x4000000, validate: png::carve },
+    Signature { kind: "pdf", ext: "pdf", magics: &[pdf::MAGIC], max_size: 0x10000000, validate: pdf::carve },
+    Signature { kind: "zip", ext: "zip", magics: &[zip::MAGIC], max_size: 0x10000000, validate: zip::carve },
+    Signature { kind: "sqlite", ext: "sqlite", magics: &[sqlite::MAGIC], max_size: 0x40000000, validate: sqlite::carve },
+    Signature { kind: "elf", ext: "elf", magics: &[elf::MAGIC], max_size: 0x10000000, validate: elf::carve },
+    Signature { kind: "pe", ext: "exe", magics: &[pe::MAGIC], max_size: 0x10000000, validate: pe::carve },
+    Signature { kind: "eml", ext: "eml", magics: email::MAGICS, max_size: 0x4000000, validate: email::carve },
+];
+
+impl Signature {
+    pub fn get_kind(&self) -> &'static str {
+        self.kind
+    }
+
+    pub fn get_ext(&self) -> &'static str {
+        self.ext
+    }
+}
+
+//Signatures named in kinds, all of them with None
+pub fn get_signatures(kinds: Option<&[&str]>) -> Result<Vec<&'static Signature>, MRError> {
+    let kinds = match kinds {
+        Some(s) => s,
+        None => {
+            return Ok(SIGNATURES.iter().collect());
+        }
+    };
+    kinds
+        .iter()
+        .map(|kind| {
+            SIGNATURES
+                .iter()
+                .find(|x| x.kind.eq(*kind))
+                .ok_or(MRError::new(&format!("Unknown type {}", kind)))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct CarvedFile {
+    kind        : &'static str,
+    ext         : &'static str,
+    //Byte offset in the reader
+    offset      : u64,
+    size        : u64,
+    confidence  : u8
+}
+
+impl CarvedFile {
+    pub fn get_kind(&self) -> &'static str {
+        self.kind
+    }
+
+    pub fn get_ext(&self) -> &'static str {
+        self.ext
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn get_confidence(&self) -> u8 {
+        self.confidence
+    }
+}
+
+//Bytes of the file at offset, None when the validator turns it down
+fn validate(reader: &MRFile, signature: &Signature, offset: u64, end: u64) -> Result<Option<(Carving, Vec<u8>)>, MRError> {
+    let limit = (signature.max_size as u64).min(end - offset) as usize;
+    let mut window = FIRST_WINDOW.min(limit);
+    loop {
+        let bs = reader.read_n(offset as usize, window)?;
+        let last = window == limit;
+        match (signature.validate)(&bs, last) {
+            Carving::Invalid => return Ok(None),
+            Carving::NeedMore if last => return Ok(None),
+            Carving::NeedMore => {
+                window = (window * 2).min(limit);
+            }
+            //A size past the bytes given is a bug of the validator, not a file
+            Carving::Found { size, .. } if size > bs.len() => return Ok(None),
+            found => return Ok(Some((found, bs))),
+        }
+    }
+}
+
+//Scan fed block by block, by carve or by the block iterators of a file system
+pub struct Carver<'a> {
+    reader      : &'a MRFile,
+    //Sorted byte ranges searched, a file never runs past the end of its range
+    ranges      : &'a [Range<u64>],
+    signatures  : &'a [&'static Signature],
+    align       : u64,
+    //Headers inside the last carved file are part of it, thumbnails of a jpg
+    skip_until  : u64
+}
+
+impl<'a> Carver<'a> {
+    pub fn new(reader: &'a MRFile, ranges: &'a [Range<u64>], signatures: &'a [&'static Signature], align: u64) -> Self {
+        Self { reader, ranges, signatures, align: align.max(1), skip_until: 0 }
+    }
+
+    //Looks for headers in the first len bytes of bs, read at base, only at multiples of align, and
+    //hands the bytes to the validator of each until the end of the file is known, bytes missing to
+    //see a header across the end of the block are read again, false once f asked to stop
+    pub fn carve_block<F>(&mut self, base: u64, bs: &[u8], len: usize, scanned: u64, f: &mut F) -> Result<bool, MRError>
+    where
+        F: FnMut(&CarvedFile, &[u8], u64) -> bool,
+    {
+        let i = self.ranges.partition_point(|x| x.end <= base);
+        let end = match self.ranges.get(i) {
+            Some(s) if s.start <= base => s.end,
+            _ => return Ok(true),
+        };
+        let len = (len as u64).min(end - base) as usize;
+        let n = ((len + MAGIC_OVERLAP) as u64).min(end - base) as usize;
+        let mut block = bs[..n.min(bs.len())].to_vec();
+        if block.len() < n {
+            block.extend(self.reader.read_n((base as usize) + block.len(), n - block.len())?);
+        }
+        let mut headers = vec![];
+        for (i, signature) in self.signatures.iter().enumerate() {
+            for magic in signature.magics {
+                headers.extend(
+                    memmem::find_iter(&block, magic)
+                        .filter(|x| *x < len && (base + *x as u64).is_multiple_of(self.align))
+                        .map(|x| (x, i)),
+                );
+            }
+        }
+        headers.sort();
+        for (pos, i) in headers {
+            let offset = base + pos as u64;
+            if offset < self.skip_until {
+                continue;
+            }
+            let signature = self.signatures[i];
+            let (size, confidence, ext, data) = match validate(self.reader, signature, offset, end)? {
+                Some((Carving::Found { size, confidence, ext }, data)) => (size, confidence, ext, data),
+                _ => continue,
+            };
+            self.skip_until = offset + size as u64;
+            let carved = CarvedFile {
+                kind: signature.kind,
+                ext: ext.unwrap_or(signature.ext),
+                offset,
+                size: size as u64,
+                confidence,
+            };
+            if !f(&carved, &data[..size], scanned + pos as u64) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+//Scans the sorted byte ranges of the reader chunk by chunk, see Carver::carve_block
+pub fn carve<F>(reader: &MRFile, ranges: &[Range<u64>], signatures: &[&'static Signature], align: u64, mut f: F) -> Result<(), MRError>
+where
+    F: FnMut(&CarvedFile, &[u8], u64) -> bool,
+{
+    let mut carver = Carver::new(reader, ranges, signatures, align);
+    let mut scanned = 0;
+    for range in ranges {
+        let mut base = range.start;
+        while base < range.end {
+            let len = (CARVE_CHUNK_SIZE as u64).min(range.end - base) as usize;
+            let n = ((len + MAGIC_OVERLAP) as u64).min(range.end - base) as usize;
+            let bs = reader.read_n(base as usize, n)?;
+            if !carver.carve_block(base, &bs, len, scanned, &mut f)? {
+                return Ok(());
+            }
+            scanned += len as u64;
+            base += len as u64;
+        }
+    }
+    Ok(())
+}
+
+pub fn be_u16(bs: &[u8], offset: usize) -> usize {
+    u16::from_be_bytes([bs[offset], bs[offset + 1]]) as usize
+}
+
+pub fn be_u32(bs: &[u8], offset: usize) -> usize {
+    u32::from_be_bytes([bs[offset], bs[offset + 1], bs[offset + 2], bs[offset + 3]]) as usize
+}
+
+pub fn le_u16(bs: &[u8], offset: usize) -> usize {
+    u16::from_le_bytes([bs[offset], bs[offset + 1]]) as usize
+}
+
+pub fn le_u32(bs: &[u8], offset: usize) -> usize {
+    u32::from_le_bytes([bs[offset], bs[offset + 1], bs[offset + 2], bs[offset + 3]]) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JPG: &[u8] = &[
+        0xff, 0xd8, 0xff, 0xc0, 0x00, 0x0b, 0x08, 0x00, 0x01, 0x00, 0x01, 0x01, 0x01, 0x11, 0x00, 0xff, 0xda, 0x00,
+        0x08, 0x01, 0x01, 0x00, 0x00, 0x3f, 0x00, 0x12, 0x34, 0xff, 0xd9,
+    ];
+
+    //Carves a file holding bs with every signature
+    fn carve_bytes(name: &str, bs: &[u8], align: u64) -> Vec<(&'static str, u64, u64, Vec<u8>)> {
+        let path = std::env::temp_dir().join(format!("meta_reader_{}_{}.bin", name, std::process::id()));
+        std::fs::write(&path, bs).unwrap();
+        let reader = MRFile::new(path.to_string_lossy().to_string()).unwrap();
+        let signatures = get_signatures(None).unwrap();
+        let mut result = vec![];
+        carve(&reader, &[Range { start: 0, end: bs.len() as u64 }], &signatures, align, |x, data, _| {
+            result.push((x.get_kind(), x.get_offset(), x.get_size(), data.to_vec()));
+            true
+        })
+        .unwrap();
+        std::fs::remove_file(path).unwrap();
+        result
+    }
+
+    #[test]
+    fn valid() {
+        let mut bs = vec![0x5a; 100];
+        bs.extend(JPG);
+        bs.extend([0x5a; 50]);
+        let found = carve_bytes("valid", &bs, 1);
+        assert_eq!(found, vec![("jpg", 100, JPG.len() as u64, JPG.to_vec())]);
+        //Off the alignment
+        assert!(carve_bytes("aligned", &bs, 512).is_empty());
+    }
+
+    #[test]
+    fn truncated() {
+        let mut bs = vec![0x5a; 16];
+        bs.extend(&JPG[..JPG.len() - 2]);
+        let found = carve_bytes("truncated", &bs, 1);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].1, found[0].2), (16, JPG.len() as u64 - 2));
+        //A segment length running past the end of the range
+        let mut bs = JPG[..27].to_vec();
+        bs.extend([0xff, 0xc4, 0x00, 0xff, 0x00, 0x00]);
+        for (_, _, size, data) in carve_bytes("past_end", &bs, 1) {
+            assert!(size as usize <= bs.len() && data.len() == size as usize);
+        }
+    }
+
+    #[test]
+    fn garbage() {
+        let mut bs = vec![];
+        for magic in [jpg::MAGIC, png::MAGIC, pdf::MAGIC, zip::MAGIC, sqlite::MAGIC] {
+            bs.extend(magic);
+            bs.extend([0x01; 40]);
+        }
+        assert!(carve_bytes("garbage", &bs, 1).is_empty());
+        assert!(get_signatures(Some(&["gif"])).is_err());
+    }
+}
@@ -1,3 +1,129 @@
 pub mod elf32;
 pub mod elf64;
-mod elf_pub;
\ No newline at end of file
+mod elf_pub;
+
+use super::carver::Carving;
+
+pub const MAGIC: &[u8] = b"\x7fELF";
+const SHT_NOBITS: u64 = 8;
+const PT_INTERP: u64 = 3;
+
+//Reads the fields of a header in the byte order of the file
+struct Fields<'a> {
+    bs      : &'a [u8],
+    big     : bool,
+    is_64   : bool
+}
+
+impl Fields<'_> {
+    fn get(&self, offset: usize, size: usize) -> u64 {
+        let bs = &self.bs[offset..offset + size];
+        let fold = |n: u64, b: &u8| (n << 8) | *b as u64;
+        match self.big {
+            true => bs.iter().fold(0, fold),
+            false => bs.iter().rev().fold(0, fold),
+        }
+    }
+
+    fn half(&self, offset: usize) -> u64 {
+        self.get(offset, 2)
+    }
+
+    fn word(&self, offset: usize) -> u64 {
+        self.get(offset, 4)
+    }
+
+    //Addresses and offsets are 4 or 8 bytes with the class
+    fn addr(&self, offset32: usize, offset64: usize) -> u64 {
+        match self.is_64 {
+            true => self.get(offset64, 8),
+            false => self.get(offset32, 4),
+        }
+    }
+}
+
+//The file ends with the furthest of the headers, the segments and the sections
+pub fn carve(bs: &[u8], last: bool) -> Carving {
+    let more = || match last {
+        true => Carving::Invalid,
+        false => Carving::NeedMore,
+    };
+    if bs.len() < 64 {
+        return more();
+    }
+    let is_64 = match bs[4] {
+        1 => false,
+        2 => true,
+        _ => return Carving::Invalid,
+    };
+    let big = match bs[5] {
+        1 => false,
+        2 => true,
+        _ => return Carving::Invalid,
+    };
+    let f = Fields { bs, big, is_64 };
+    let kind = f.half(16);
+    if bs[6] != 1 || f.word(20) != 1 || !(1..=4).contains(&kind) {
+        return Carving::Invalid;
+    }
+    let (ph_offset, sh_offset) = (f.addr(28, 32), f.addr(32, 40));
+    let base = if is_64 { 52 } else { 40 };
+    let (header_size, ph_size, ph_num) = (f.half(base), f.half(base + 2), f.half(base + 4));
+    let (sh_size, sh_num, sh_strndx) = (f.half(base + 6), f.half(base + 8), f.half(base + 10));
+    let sizes = if is_64 { (64, 56, 64) } else { (52, 32, 40) };
+    if header_size != sizes.0 || (ph_num > 0 && ph_size != sizes.1) || (sh_num > 0 && sh_size != sizes.2) {
+        return Carving::Invalid;
+    }
+    //Offsets and sizes come from the bytes, a sum past u64 is no ELF
+    let table_end = |offset: u64, num: u64, size: u64| num.checked_mul(size).and_then(|x| x.checked_add(offset));
+    let (ph_end, sh_end) = match (table_end(ph_offset, ph_num, ph_size), table_end(sh_offset, sh_num, sh_size)) {
+        (Some(ph), Some(sh)) => (ph, sh),
+        _ => return Carving::Invalid,
+    };
+    if ph_end.max(sh_end) > u32::MAX as u64 {
+        return Carving::Invalid;
+    }
+    if ph_end.max(sh_end) > bs.len() as u64 {
+        return more();
+    }
+    let mut size = header_size.max(ph_end).max(sh_end);
+    //Position independent executables are shared objects asking for an interpreter
+    let mut interp = false;
+    for i in 0..ph_num {
+        let ph = (ph_offset + i * ph_size) as usize;
+        interp |= f.word(ph) == PT_INTERP;
+        match f.addr(ph + 4, ph + 8).checked_add(f.addr(ph + 16, ph + 32)) {
+            Some(s) => size = size.max(s),
+            None => return Carving::Invalid,
+        }
+    }
+    for i in 0..sh_num {
+        let sh = (sh_offset + i * sh_size) as usize;
+        if f.word(sh + 4) != SHT_NOBITS {
+            match f.addr(sh + 16, sh + 24).checked_add(f.addr(sh + 20, sh + 32)) {
+                Some(s) => size = size.max(s),
+                None => return Carving::Invalid,
+            }
+        }
+    }
+    if size > u32::MAX as u64 {
+        return Carving::Invalid;
+    }
+    let size = size as usize;
+    if size > bs.len() {
+        return match last {
+            true => Carving::found(bs.len(), 30),
+            false => Carving::NeedMore,
+        };
+    }
+    let confidence = match sh_num > 0 && sh_strndx < sh_num {
+        true => 90,
+        false => 70,
+    };
+    let ext = match kind {
+        1 => "o",
+        3 if !interp => "so",
+        _ => "elf",
+    };
+    Carving::Found { size, confidence, ext: Some(ext) }
+}
@@ -0,0 +1,158 @@
+use memchr::{memchr, memmem};
+
+use super::carver::Carving;
+
+//Fields a message usually starts with
+pub const MAGICS: &[&[u8]] = &[
+    b"Return-Path: ",
+    b"Received: ",
+    b"Delivered-To: ",
+    b"From: ",
+    b"MIME-Version: ",
+    b"Message-ID: ",
+];
+//Headers longer than this are not a message
+const MAX_HEADER_SIZE: usize = 0x40000;
+const KEY_FIELDS: &[&str] = &["from", "to", "subject", "date", "message-id", "received"];
+
+fn is_text(b: u8) -> bool {
+    !(b < 0x09 || ((0x0e..0x20).contains(&b) && b != 0x1b) || b == 0x7f)
+}
+
+//boundary of a multipart Content-Type, with or without quotes
+fn get_boundary(value: &str) -> Option<String> {
+    let lower = value.to_ascii_lowercase();
+    let start = lower.find("boundary=")? + "boundary=".len();
+    let value = &value[start..];
+    let boundary = match value.strip_prefix('"') {
+        Some(s) => s.split('"').next()?,
+        None => value.split(|x: char| x == ';' || x.is_ascii_whitespace()).next()?,
+    };
+    match boundary.is_empty() {
+        true => None,
+        false => Some(boundary.to_string()),
+    }
+}
+
+//Header fields up to the blank line, then a body of text ending at the closing boundary of a
+//multipart message or at the first byte that is not text
+pub fn carve(bs: &[u8], last: bool) -> Carving {
+    let mut offset = 0;
+    let mut fields = 0;
+    let mut keys = 0;
+    let mut boundary = None;
+    let mut content_type = false;
+    let body = loop {
+        if offset >= MAX_HEADER_SIZE {
+            return Carving::Invalid;
+        }
+        let end = match memchr(b'\n', &bs[offset..]) {
+            Some(s) => offset + s + 1,
+            None => {
+                return match last {
+                    true => Carving::Invalid,
+                    false => Carving::NeedMore,
+                };
+            }
+        };
+        let line = &bs[offset..end];
+        if !line.iter().all(|x| is_text(*x)) {
+            return Carving::Invalid;
+        }
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break end;
+        }
+        offset = end;
+        //Folded lines go on with the field before
+        if line.starts_with([' ', '\t']) {
+            if fields == 0 {
+                return Carving::Invalid;
+            }
+            if content_type && boundary.is_none() {
+                boundary = get_boundary(line);
+            }
+            continue;
+        }
+        let (name, value) = match line.split_once(':') {
+            Some(s) => s,
+            None => return Carving::Invalid,
+        };
+        if name.is_empty() || !name.bytes().all(|x| x.is_ascii_graphic()) {
+            return Carving::Invalid;
+        }
+        fields += 1;
+        let name = name.to_ascii_lowercase();
+        if KEY_FIELDS.contains(&name.as_str()) {
+            keys += 1;
+        }
+        content_type = name.eq("content-type");
+        if content_type {
+            boundary = get_boundary(value);
+        }
+    };
+    if keys < 3 {
+        return Carving::Invalid;
+    }
+    let text_end = bs[body..].iter().position(|x| !is_text(*x)).map(|x| body + x);
+    if let Some(boundary) = boundary {
+        let close = format!("--{}--", boundary);
+        let text = &bs[body..text_end.unwrap_or(bs.len())];
+        if let Some(pos) = memmem::find(text, close.as_bytes()) {
+            let mut end = body + pos + close.len();
+            if bs.get(end) == Some(&b'\r') {
+                end += 1;
+            }
+            if bs.get(end) == Some(&b'\n') {
+                end += 1;
+            }
+            return Carving::found(end, 90);
+        }
+    }
+    match (text_end, last) {
+        (Some(end), _) => Carving::found(end, 70),
+        (None, false) => Carving::NeedMore,
+        (None, true) => Carving::found(bs.len(), 40),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &[u8] = b"From: a@example.com\r\nTo: b@example.com\r\nSubject: hi\r\nDate: Mon, 1 Jan 2024 00:00:00 +0000\r\n";
+
+    #[test]
+    fn valid() {
+        let mut bs = HEADER.to_vec();
+        bs.extend(b"\r\nbody text\r\n");
+        let size = bs.len();
+        bs.extend([0, 1, 2]);
+        assert_eq!(carve(&bs, false), Carving::found(size, 70));
+        //A multipart message ends at its closing boundary
+        let mut bs = HEADER.to_vec();
+        bs.extend(b"Content-Type: multipart/mixed;\r\n boundary=\"xx\"\r\n\r\n--xx\r\npart\r\n--xx--\r\n");
+        let size = bs.len();
+        bs.extend(b"more text");
+        assert_eq!(carve(&bs, false), Carving::found(size, 90));
+    }
+
+    #[test]
+    fn truncated() {
+        let bs = &HEADER[..HEADER.len() - 2];
+        assert_eq!(carve(bs, false), Carving::NeedMore);
+        assert_eq!(carve(bs, true), Carving::Invalid);
+        let mut bs = HEADER.to_vec();
+        bs.extend(b"\r\nbody");
+        assert_eq!(carve(&bs, false), Carving::NeedMore);
+        assert_eq!(carve(&bs, true), Carving::found(bs.len(), 40));
+    }
+
+    #[test]
+    fn garbage() {
+        assert_eq!(carve(b"From: \x01\x02\x03\r\n\r\n", true), Carving::Invalid);
+        //Too few of the usual fields
+        assert_eq!(carve(b"From: a@example.com\r\nX-Any: 1\r\n\r\nbody\0", true), Carving::Invalid);
+    }
+}
@@ -0,0 +1,90 @@
+use std::ops::Range;
+
+use crate::utils::MRError;
+
+use super::Ext4;
+
+pub fn is_bit_set(bits: &[u8], bit: u64) -> bool {
+    match bits.get((bit / 8) as usize) {
+        Some(b) => (b >> (bit % 8)) & 1 == 1,
+        None => false,
+    }
+}
+
+impl Ext4 {
+    //Bitmap of a BLOCK_UNINIT group as the kernel builds it on first use: the super block and
+    //descriptor copies, and the bitmaps and inode tables placed in the group, are in use
+    fn uninit_block_bitmap(&self, group: u64) -> Result<Vec<u8>, MRError> {
+        let sb = self.get_super_block()?;
+        let block_size = self.get_block_size() as u64;
+        let blocks_per_group = sb.s_blocks_per_group as u64;
+        let first = sb.s_first_data_block as u64 + group * blocks_per_group;
+        let descs = self.get_descs()?;
+        let table_blocks = (sb.s_inodes_per_group as u64 * self.get_s_inode_size() as u64).div_ceil(block_size);
+
+        let mut used = vec![];
+        if sb.has_super_block(group) {
+            used.push(first..first + 1);
+        }
+        let (gdt, reserved, _) = sb.get_gdt_blocks(group, descs.len() as u64, block_size);
+        used.push(gdt);
+        used.push(reserved);
+        for desc in descs {
+            let block = desc.get_block_bitmap().start as u64 / block_size;
+            used.push(block..block + 1);
+            let block = desc.get_inode_bitmap().start as u64 / block_size;
+            used.push(block..block + 1);
+            let block = desc.get_inode_table();
+            used.push(block..block + table_blocks);
+        }
+
+        let mut bits = vec![0u8; blocks_per_group.div_ceil(8) as usize];
+        for range in used {
+            for block in range.start.max(first)..range.end.min(first + blocks_per_group) {
+                let i = block - first;
+                bits[(i / 8) as usize] |= 1 << (i % 8);
+            }
+        }
+        Ok(bits)
+    }
+
+    pub fn is_block_allocated(&self, block: u64) -> Result<bool, MRError> {
+        let sb = self.get_super_block()?;
+        let bit = block.saturating_sub(sb.s_first_data_block as u64);
+        let desc = match self.get_descs()?.get((bit / sb.s_blocks_per_group as u64) as usize) {
+            Some(s) => s,
+            None => return Ok(true),
+        };
+        let bits = match desc.is_block_uninit() {
+            true => self.uninit_block_bitmap(desc.get_group() as u64)?,
+            false => self.get_reader().read_range(desc.get_block_bitmap())?,
+        };
+        Ok(is_bit_set(&bits, bit % sb.s_blocks_per_group as u64))
+    }
+
+    //Runs of free blocks from the block bitmaps, BLOCK_UNINIT groups only lose their metadata
+    pub fn get_unallocated_blocks(&self) -> Result<Vec<Range<u64>>, MRError> {
+        let sb = self.get_super_block()?;
+        let blocks_per_group = sb.s_blocks_per_group as u64;
+        let block_count = sb.s_block_count as u64;
+        let mut result: Vec<Range<u64>> = vec![];
+        for desc in self.get_descs()? {
+            let first = sb.s_first_data_block as u64 + desc.get_group() as u64 * blocks_per_group;
+            let bits = match desc.is_block_uninit() {
+                true => self.uninit_block_bitmap(desc.get_group() as u64)?,
+                false => self.get_reader().read_range(desc.get_block_bitmap())?,
+            };
+            for i in 0..blocks_per_group.min(block_count.saturating_sub(first)) {
+                if is_bit_set(&bits, i) {
+                    continue;
+                }
+                let block = first + i;
+                match result.last_mut() {
+                    Some(last) if last.end == block => last.end += 1,
+                    _ => result.push(block..block + 1),
+                }
+            }
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,490 @@
+use std::fmt;
+
+use bytes::{Buf, Bytes};
+
+use crate::utils::{funcs::sub_bytes, MRError};
+
+use super::{
+    ChecksumMismatch, Ext4, GroupDescriptor, Inode, MetadataStructure, SuperBlock,
+    EXT4_FEATURE_INCOMPAT_64BIT, EXT4_FEATURE_INCOMPAT_CSUM_SEED,
+    EXT4_FEATURE_RO_COMPAT_GDT_CSUM, EXT4_FEATURE_RO_COMPAT_METADATA_CSUM,
+};
+
+const EXT4_EXTENTS_FL: u32 = 0x80000;
+const EXT4_INDEX_FL: u32 = 0x1000;
+const EXT4_INODE_CSUM_LO_OFFSET: usize = 0x7c;
+const EXT4_INODE_CSUM_HI_OFFSET: usize = 0x82;
+const EXT4_GOOD_OLD_INODE_SIZE: usize = 0x80;
+const EXT4_BG_CHECKSUM_OFFSET: usize = 0x1e;
+const EXT4_BG_BLOCK_BITMAP_CSUM_HI_END: usize = 0x3a;
+const EXT4_BG_INODE_BITMAP_CSUM_HI_END: usize = 0x3c;
+const EXT4_DIR_TAIL_SIZE: usize = 12;
+const EXT4_DIR_TAIL_FT: u8 = 0xde;
+
+//crc32c as used by ext4/jbd2: no pre or post inversion, the caller passes the seed
+pub fn ext4_chksum(crc: u32, data: &[u8]) -> u32 {
+    !crc32c::crc32c_append(!crc, data)
+}
+
+//crc16 (ANSI, reflected) used by the legacy GDT_CSUM feature
+pub fn crc16(mut crc: u16, data: &[u8]) -> u16 {
+    for b in data {
+        crc ^= *b as u16;
+        for _ in 0..8 {
+            if crc & 1 == 1 {
+                crc = (crc >> 1) ^ 0xa001;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    crc
+}
+
+impl fmt::Display for MetadataStructure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            MetadataStructure::SuperBlock => "super block",
+            MetadataStructure::GroupDescriptor => "group descriptor",
+            MetadataStructure::BlockBitmap => "block bitmap",
+            MetadataStructure::InodeBitmap => "inode bitmap",
+            MetadataStructure::Inode => "inode",
+            MetadataStructure::ExtentBlock => "extent block",
+            MetadataStructure::DirectoryBlock => "directory block",
+            MetadataStructure::JournalSuperBlock => "jbd2 super block",
+            MetadataStructure::JournalDescriptor => "jbd2 descriptor block",
+            MetadataStructure::JournalCommit => "jbd2 commit block",
+            MetadataStructure::JournalRevoke => "jbd2 revoke block",
+            MetadataStructure::JournalData => "jbd2 data block",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl ChecksumMismatch {
+    pub fn new(structure: MetadataStructure, block: u64, stored: u32, computed: u32) -> Self {
+        Self {
+            structure,
+            group: None,
+            inode: None,
+            block,
+            stored,
+            computed,
+        }
+    }
+
+    pub fn with_group(mut self, group: u32) -> Self {
+        self.group = Some(group);
+        self
+    }
+
+    pub fn with_inode(mut self, inode: u32) -> Self {
+        self.inode = Some(inode);
+        self
+    }
+
+    pub fn get_structure(&self) -> MetadataStructure {
+        self.structure
+    }
+
+    pub fn get_group(&self) -> Option<u32> {
+        self.group
+    }
+
+    pub fn get_inode(&self) -> Option<u32> {
+        self.inode
+    }
+
+    pub fn get_block(&self) -> u64 {
+        self.block
+    }
+
+    pub fn get_stored(&self) -> u32 {
+        self.stored
+    }
+
+    pub fn get_computed(&self) -> u32 {
+        self.computed
+    }
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} checksum mismatch at block {}", self.structure, self.block)?;
+        if let Some(group) = self.group {
+            write!(f, ", group {}", group)?;
+        }
+        if let Some(inode) = self.inode {
+            write!(f, ", inode {}", inode)?;
+        }
+        write!(f, ": stored {:#010x}, computed {:#010x}", self.stored, self.computed)
+    }
+}
+
+impl SuperBlock {
+    pub fn has_metadata_csum(&self) -> bool {
+        self.s_feature_ro_compat & EXT4_FEATURE_RO_COMPAT_METADATA_CSUM != 0
+    }
+
+    pub fn has_gdt_csum(&self) -> bool {
+        self.s_feature_ro_compat & EXT4_FEATURE_RO_COMPAT_GDT_CSUM != 0
+    }
+
+    pub fn get_feature_compat(&self) -> u32 {
+        self.s_feature_compat
+    }
+
+    pub fn get_feature_incompat(&self) -> u32 {
+        self.s_feature_incompat
+    }
+
+    pub fn get_feature_ro_compat(&self) -> u32 {
+        self.s_feature_ro_compat
+    }
+
+    pub fn get_uuid(&self) -> &[u8; 16] {
+        &self.s_uuid
+    }
+
+    pub fn get_checksum(&self) -> u32 {
+        self.s_checksum
+    }
+
+    pub fn calc_checksum(&self, sbytes: &[u8]) -> u32 {
+        ext4_chksum(!0, &sbytes[..0x3fc])
+    }
+
+    pub fn get_csum_seed(&self) -> u32 {
+        if self.s_feature_incompat & EXT4_FEATURE_INCOMPAT_CSUM_SEED != 0 {
+            return self.s_checksum_seed;
+        }
+        ext4_chksum(!0, &self.s_uuid)
+    }
+}
+
+impl GroupDescriptor {
+    //Returns the mismatch if the descriptor does not match its bg_checksum
+    pub fn verify_checksum(&self, raw: &[u8]) -> Result<Option<ChecksumMismatch>, MRError> {
+        let ext4 = unsafe { &*self.ext4_to_self.unwrap() };
+        let sb = ext4.get_super_block()?;
+        let desc_size = sb.s_desc_size as usize;
+        if raw.len() < desc_size {
+            return Err(MRError::new("Group descriptor is too short"));
+        }
+        let group = self.group.to_le_bytes();
+        let computed = if sb.has_metadata_csum() {
+            let mut csum = ext4_chksum(sb.get_csum_seed(), &group);
+            csum = ext4_chksum(csum, &raw[..EXT4_BG_CHECKSUM_OFFSET]);
+            csum = ext4_chksum(csum, &[0, 0]);
+            if desc_size > EXT4_BG_CHECKSUM_OFFSET + 2 {
+                csum = ext4_chksum(csum, &raw[EXT4_BG_CHECKSUM_OFFSET + 2..desc_size]);
+            }
+            csum & 0xffff
+        } else if sb.has_gdt_csum() {
+            let mut crc = crc16(!0, &sb.s_uuid);
+            crc = crc16(crc, &group);
+            crc = crc16(crc, &raw[..EXT4_BG_CHECKSUM_OFFSET]);
+            if sb.s_feature_incompat & EXT4_FEATURE_INCOMPAT_64BIT != 0
+                && desc_size > EXT4_BG_CHECKSUM_OFFSET + 2
+            {
+                crc = crc16(crc, &raw[EXT4_BG_CHECKSUM_OFFSET + 2..desc_size]);
+            }
+            crc as u32
+        } else {
+            return Ok(None);
+        };
+
+        if computed == self.bg_checksum as u32 {
+            return Ok(None);
+        }
+        let block = (sb.s_first_data_block as u64 + 1)
+            + (self.group as u64 * desc_size as u64) / ext4.get_block_size() as u64;
+        Ok(Some(
+            ChecksumMismatch::new(
+                MetadataStructure::GroupDescriptor,
+                block,
+                self.bg_checksum as u32,
+                computed,
+            )
+            .with_group(self.group),
+        ))
+    }
+
+    pub fn verify_block_bitmap(&self, bitmap: &[u8]) -> Result<Option<ChecksumMismatch>, MRError> {
+        let ext4 = unsafe { &*self.ext4_to_self.unwrap() };
+        let sb = ext4.get_super_block()?;
+        if !sb.has_metadata_csum() || self.is_block_uninit() {
+            return Ok(None);
+        }
+        let size = (sb.s_clusters_per_group / 8) as usize;
+        if bitmap.len() < size {
+            return Err(MRError::new("Block bitmap is too short"));
+        }
+        let mut computed = ext4_chksum(sb.get_csum_seed(), &bitmap[..size]);
+        let mut stored = self.bg_block_bitmap_csum_lo as u32;
+        if sb.s_desc_size as usize >= EXT4_BG_BLOCK_BITMAP_CSUM_HI_END {
+            stored |= (self.bg_block_bitmap_csum_hi as u32) << 16;
+        } else {
+            computed &= 0xffff;
+        }
+        if computed == stored {
+            return Ok(None);
+        }
+        let block = (self.get_block_bitmap().start / ext4.get_block_size()) as u64;
+        Ok(Some(
+            ChecksumMismatch::new(MetadataStructure::BlockBitmap, block, stored, computed)
+                .with_group(self.group),
+        ))
+    }
+
+    pub fn verify_inode_bitmap(&self, bitmap: &[u8]) -> Result<Option<ChecksumMismatch>, MRError> {
+        let ext4 = unsafe { &*self.ext4_to_self.unwrap() };
+        let sb = ext4.get_super_block()?;
+        if !sb.has_metadata_csum() || self.is_inode_uninit() {
+            return Ok(None);
+        }
+        let size = (sb.s_inodes_per_group / 8) as usize;
+        if bitmap.len() < size {
+            return Err(MRError::new("Inode bitmap is too short"));
+        }
+        let mut computed = ext4_chksum(sb.get_csum_seed(), &bitmap[..size]);
+        let mut stored = self.bg_inode_bitmap_csum_lo as u32;
+        if sb.s_desc_size as usize >= EXT4_BG_INODE_BITMAP_CSUM_HI_END {
+            stored |= (self.bg_inode_bitmap_csum_hi as u32) << 16;
+        } else {
+            computed &= 0xffff;
+        }
+        if computed == stored {
+            return Ok(None);
+        }
+        let block = (self.get_inode_bitmap().start / ext4.get_block_size()) as u64;
+        Ok(Some(
+            ChecksumMismatch::new(MetadataStructure::InodeBitmap, block, stored, computed)
+                .with_group(self.group),
+        ))
+    }
+}
+
+impl Inode {
+    pub fn is_extents(&self) -> bool {
+        self.i_flags & EXT4_EXTENTS_FL == EXT4_EXTENTS_FL
+    }
+
+    pub fn is_htree(&self) -> bool {
+        self.i_flags & EXT4_INDEX_FL == EXT4_INDEX_FL
+    }
+
+    //Physical block numbers of every non-root node of the extent tree
+    pub fn get_extent_node_blocks(&self) -> Result<Vec<u64>, MRError> {
+        let mut result = vec![];
+        if !self.is_extents() {
+            return Ok(result);
+        }
+        let ext4 = unsafe { &*self.ext4.unwrap() };
+        let reader = ext4.get_reader();
+        let block_size = ext4.get_block_size();
+        let mut stack = vec![(Bytes::from(self.i_block.clone()), 0)];
+        while let Some((node, level)) = stack.pop() {
+            if level > 5 {
+                return Err(MRError::new("Extent tree is too deep"));
+            }
+            let magic = (sub_bytes(&node, 0..2)?).get_u16_le();
+            if magic != 0xf30a {
+                return Err(MRError::new("Not valid extent"));
+            }
+            let entries = (sub_bytes(&node, 2..4)?).get_u16_le() as usize;
+            let depth = (sub_bytes(&node, 6..8)?).get_u16_le();
+            if depth == 0 {
+                continue;
+            }
+            for i in 0..entries {
+                let base = 12 + i * 12;
+                let leaf_lo = (sub_bytes(&node, base + 4..base + 8)?).get_u32_le() as u64;
+                let leaf_hi = (sub_bytes(&node, base + 8..base + 10)?).get_u16_le() as u64;
+                let leaf = (leaf_hi << 32) + leaf_lo;
+                let bs = reader.read_n(leaf as usize * block_size, block_size)?;
+                result.push(leaf);
+                stack.push((Bytes::from(bs), level + 1));
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl Ext4 {
+    pub fn has_metadata_csum(&self) -> bool {
+        match self.get_super_block() {
+            Ok(sb) => sb.has_metadata_csum(),
+            Err(_) => false,
+        }
+    }
+
+    pub fn verify_super_block(&self) -> Result<Option<ChecksumMismatch>, MRError> {
+        let sb = self.get_super_block()?;
+        if !sb.has_metadata_csum() {
+            return Ok(None);
+        }
+        let sbytes = self.get_reader().read_n(1024, 1024)?;
+        let computed = sb.calc_checksum(&sbytes);
+        if computed == sb.s_checksum {
+            return Ok(None);
+        }
+        let block = (1024 / self.get_block_size().max(1024)) as u64;
+        Ok(Some(ChecksumMismatch::new(
+            MetadataStructure::SuperBlock,
+            block,
+            sb.s_checksum,
+            computed,
+        )))
+    }
+
+    fn inode_csum_seed(&self, id: u32, generation: u32) -> Result<u32, MRError> {
+        let sb = self.get_super_block()?;
+        let csum = ext4_chksum(sb.get_csum_seed(), &id.to_le_bytes());
+        Ok(ext4_chksum(csum, &generation.to_le_bytes()))
+    }
+
+    //Checks a raw on-disk inode (s_inode_size bytes). Returns (stored, computed)
+    pub fn calc_inode_checksum(&self, id: u32, raw: &[u8]) -> Result<(u32, u32), MRError> {
+        let inode_size = self.get_s_inode_size() as usize;
+        if raw.len() < inode_size || inode_size < EXT4_GOOD_OLD_INODE_SIZE {
+            return Err(MRError::new("Inode is too short"));
+        }
+        let generation = (sub_bytes(&Bytes::copy_from_slice(raw), 0x64..0x68)?).get_u32_le();
+        let mut stored = (&raw[EXT4_INODE_CSUM_LO_OFFSET..EXT4_INODE_CSUM_LO_OFFSET + 2])
+            .get_u16_le() as u32;
+        let has_hi = inode_size > EXT4_GOOD_OLD_INODE_SIZE
+            && (&raw[0x80..0x82]).get_u16_le() >= 4;
+
+        let mut csum = self.inode_csum_seed(id, generation)?;
+        csum = ext4_chksum(csum, &raw[..EXT4_INODE_CSUM_LO_OFFSET]);
+        csum = ext4_chksum(csum, &[0, 0]);
+        csum = ext4_chksum(csum, &raw[EXT4_INODE_CSUM_LO_OFFSET + 2..EXT4_GOOD_OLD_INODE_SIZE]);
+        if inode_size > EXT4_GOOD_OLD_INODE_SIZE {
+            csum = ext4_chksum(csum, &raw[EXT4_GOOD_OLD_INODE_SIZE..EXT4_INODE_CSUM_HI_OFFSET]);
+            if has_hi {
+                stored |= ((&raw[EXT4_INODE_CSUM_HI_OFFSET..EXT4_INODE_CSUM_HI_OFFSET + 2])
+                    .get_u16_le() as u32)
+                    << 16;
+                csum = ext4_chksum(csum, &[0, 0]);
+                csum = ext4_chksum(csum, &raw[EXT4_INODE_CSUM_HI_OFFSET + 2..inode_size]);
+            } else {
+                csum = ext4_chksum(csum, &raw[EXT4_INODE_CSUM_HI_OFFSET..inode_size]);
+            }
+        }
+        if !has_hi {
+            csum &= 0xffff;
+        }
+        Ok((stored, csum))
+    }
+
+    pub fn verify_inode(&self, inode: &Inode) -> Result<Option<ChecksumMismatch>, MRError> {
+        if !self.has_metadata_csum() {
+            return Ok(None);
+        }
+        if inode.i_num == 0 {
+            return Err(MRError::new("Inode number is unknown"));
+        }
+        let raw = self
+            .get_reader()
+            .read_n(inode.base_addr as usize, self.get_s_inode_size() as usize)?;
+        let (stored, computed) = self.calc_inode_checksum(inode.i_num, &raw)?;
+        if stored == computed {
+            return Ok(None);
+        }
+        let block = inode.base_addr / self.get_block_size() as u64;
+        Ok(Some(
+            ChecksumMismatch::new(MetadataStructure::Inode, block, stored, computed)
+                .with_inode(inode.i_num),
+        ))
+    }
+
+    pub fn verify_extent_block(
+        &self,
+        inode: &Inode,
+        block_bs: &[u8],
+        block: u64,
+    ) -> Result<Option<ChecksumMismatch>, MRError> {
+        if !self.has_metadata_csum() {
+            return Ok(None);
+        }
+        let eh_max = (sub_bytes(&Bytes::copy_from_slice(block_bs), 4..6)?).get_u16_le() as usize;
+        let tail = 12 + eh_max * 12;
+        if tail + 4 > block_bs.len() {
+            return Err(MRError::new("Extent block tail out of range"));
+        }
+        let stored = (&block_bs[tail..tail + 4]).get_u32_le();
+        let seed = self.inode_csum_seed(inode.i_num, inode.i_generation)?;
+        let computed = ext4_chksum(seed, &block_bs[..tail]);
+        if stored == computed {
+            return Ok(None);
+        }
+        Ok(Some(
+            ChecksumMismatch::new(MetadataStructure::ExtentBlock, block, stored, computed)
+                .with_inode(inode.i_num),
+        ))
+    }
+
+    pub fn verify_dir_block(
+        &self,
+        inode: &Inode,
+        block_bs: &[u8],
+        block: u64,
+    ) -> Result<Option<ChecksumMismatch>, MRError> {
+        if !self.has_metadata_csum() {
+            return Ok(None);
+        }
+        let len = block_bs.len();
+        if len < EXT4_DIR_TAIL_SIZE {
+            return Err(MRError::new("Directory block is too short"));
+        }
+        let seed = self.inode_csum_seed(inode.i_num, inode.i_generation)?;
+        let tail = &block_bs[len - EXT4_DIR_TAIL_SIZE..];
+        let is_tail = (&tail[0..4]).get_u32_le() == 0
+            && (&tail[4..6]).get_u16_le() as usize == EXT4_DIR_TAIL_SIZE
+            && tail[6] == 0
+            && tail[7] == EXT4_DIR_TAIL_FT;
+
+        let (stored, computed) = if is_tail {
+            let stored = (&tail[8..12]).get_u32_le();
+            (stored, ext4_chksum(seed, &block_bs[..len - EXT4_DIR_TAIL_SIZE]))
+        } else if let Some(r) = self.calc_dx_checksum(seed, block_bs) {
+            r
+        } else {
+            //A leaf block without the fake dirent tail has nowhere to keep its checksum
+            (0, ext4_chksum(seed, &block_bs[..len - EXT4_DIR_TAIL_SIZE]))
+        };
+        if stored == computed {
+            return Ok(None);
+        }
+        Ok(Some(
+            ChecksumMismatch::new(MetadataStructure::DirectoryBlock, block, stored, computed)
+                .with_inode(inode.i_num),
+        ))
+    }
+
+    //htree interior nodes keep a dx_tail after the dx_entry array
+    fn calc_dx_checksum(&self, seed: u32, block_bs: &[u8]) -> Option<(u32, u32)> {
+        let len = block_bs.len();
+        let first_rec_len = (&block_bs[4..6]).get_u16_le() as usize;
+        let count_offset = if (&block_bs[0..4]).get_u32_le() == 0 && first_rec_len == len {
+            8
+        } else if first_rec_len == 12 && block_bs[8] == b'.' {
+            let info_length = *block_bs.get(0x1d)? as usize;
+            0x18 + info_length
+        } else {
+            return None;
+        };
+        let limit = (block_bs.get(count_offset..count_offset + 2)?).get_u16_le() as usize;
+        let count = (block_bs.get(count_offset + 2..count_offset + 4)?).get_u16_le() as usize;
+        let tail = count_offset + limit * 8;
+        if count > limit || tail + 8 > len {
+            return None;
+        }
+        let stored = (&block_bs[tail + 4..tail + 8]).get_u32_le();
+        let mut csum = ext4_chksum(seed, &block_bs[..count_offset + count * 8]);
+        csum = ext4_chksum(csum, &block_bs[tail..tail + 4]);
+        csum = ext4_chksum(csum, &[0, 0, 0, 0]);
+        Some((stored, csum))
+    }
+}
@@ -2,9 +2,12 @@ use std::{ops::Range, path::Path};
 
 use bytes::{Buf, Bytes};
 
-use crate::utils::{file::MRFile, MRErrKind, MRError};
+use crate::utils::{file::MRFile, funcs::sub_bytes, MRErrKind, MRError};
 
-use super::{Block, DirectoryEntry, Ext4, GroupDescriptor, Inode, Journal, SuperBlock};
+use super::{
+    Block, DirectoryEntry, Ext4, GroupDescriptor, Inode, Journal, SuperBlock,
+    EXT4_FEATURE_INCOMPAT_JOURNAL_DEV,
+};
 
 impl Ext4 {
     pub fn open<P>(path: P) -> Result<Self, MRError>
@@ -34,12 +37,29 @@ impl Ext4 {
             .ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?)
         .get_u32_le();
         super_block.s_block_count = (sbytes.get(4..8).unwrap()).get_u32_le();
+        super_block.s_first_data_block = (sbytes.get(0x14..0x18).unwrap()).get_u32_le();
         super_block.s_log_block_size = (sbytes.get(0x18..0x1c).unwrap()).get_u32_le();
+        super_block.s_log_cluster_size = (sbytes.get(0x1c..0x20).unwrap()).get_u32_le();
+        super_block.s_blocks_per_group = (sbytes.get(0x20..0x24).unwrap()).get_u32_le();
+        super_block.s_clusters_per_group = (sbytes.get(0x24..0x28).unwrap()).get_u32_le();
         super_block.s_inodes_per_group = (sbytes.get(0x28..0x2c).unwrap()).get_u32_le();
         super_block.s_creator_os = (sbytes.get(0x48..0x4c).unwrap()).get_u32_le();
         super_block.s_inode_size = (sbytes.get(0x58..0x5a).unwrap()).get_u16_le();
+        super_block.s_feature_compat = (sbytes.get(0x5c..0x60).unwrap()).get_u32_le();
+        super_block.s_feature_incompat = (sbytes.get(0x60..0x64).unwrap()).get_u32_le();
+        super_block.s_feature_ro_compat = (sbytes.get(0x64..0x68).unwrap()).get_u32_le();
+        super_block.s_uuid.copy_from_slice(sbytes.get(0x68..0x78).unwrap());
+        super_block.s_checksum_type = (sbytes.get(0x175..0x176).unwrap()).get_u8();
+        super_block.s_checksum_seed = (sbytes.get(0x270..0x274).unwrap()).get_u32_le();
+        super_block.s_checksum = (sbytes.get(0x3fc..0x400).unwrap()).get_u32_le();
         super_block.s_reserved_gdt_blocks = (sbytes.get(0xce..0xd0).unwrap()).get_u16_le();
+        super_block.s_journal_uuid.copy_from_slice(sbytes.get(0xd0..0xe0).unwrap());
+        super_block.s_journal_inum = (sbytes.get(0xe0..0xe4).unwrap()).get_u32_le();
+        super_block.s_journal_dev = (sbytes.get(0xe4..0xe8).unwrap()).get_u32_le();
         super_block.s_desc_size = (sbytes.get(0xfe..0x100).unwrap()).get_u16_le();
+        super_block.s_first_meta_bg = (sbytes.get(0x104..0x108).unwrap()).get_u32_le();
+        super_block.s_backup_bgs[0] = (sbytes.get(0x24c..0x250).unwrap()).get_u32_le();
+        super_block.s_backup_bgs[1] = (sbytes.get(0x250..0x254).unwrap()).get_u32_le();
         if super_block.s_desc_size > 32 {
             super_block.is_64bit = true;
         } else {
@@ -50,6 +70,15 @@ impl Ext4 {
         if super_block.is_64bit {
             super_block.s_log_groups_per_flex = (sbytes.get(0x174..0x175).unwrap()).get_u8();
         }
+        if self.is_strict() && super_block.has_metadata_csum() {
+            let computed = super_block.calc_checksum(&sbytes);
+            if computed != super_block.s_checksum {
+                return Err(MRError::new(&format!(
+                    "Super block checksum mismatch: stored {:#x}, computed {:#x}",
+                    super_block.s_checksum, computed
+                )));
+            }
+        }
         self.super_block.replace(Some(super_block));
         let v = self.super_block.as_ptr();
         match unsafe { &*v } {
@@ -100,7 +129,7 @@ impl Ext4 {
         }
 
         let len = super_block.s_log_groups_per_flex as usize * descs_size;
-        if len % descs_size != 0 {
+        if !len.is_multiple_of(descs_size) {
             return Err(MRError::new(
                 "Parse error: Group Descriptor size is not right",
             ));
@@ -121,7 +150,14 @@ impl Ext4 {
             if (&gdt[0..4]).get_u32_le() == 0 {
                 break;
             }
-            result.push(GroupDescriptor::parse(gdt, self)?);
+            let mut desc = GroupDescriptor::parse(gdt.clone(), self)?;
+            desc.group = count;
+            if self.is_strict() {
+                if let Some(mismatch) = desc.verify_checksum(&gdt)? {
+                    return Err(MRError::new(&format!("{}", mismatch)));
+                }
+            }
+            result.push(desc);
             i += descs_size;
             count += 1;
         }
@@ -137,6 +173,14 @@ impl Ext4 {
         self.reader.as_ref().unwrap()
     }
 
+    pub fn set_strict(&self, strict: bool) {
+        self.strict.set(strict);
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict.get()
+    }
+
     pub fn get_descs(&self) -> Result<&Vec<GroupDescriptor>, MRError> {
         let v = self.group_descriptors.as_ptr();
         if let Some(s) = unsafe { &*v } {
@@ -159,6 +203,10 @@ impl Ext4 {
         Ok(self.get_super_block()?.s_inodes_per_group)
     }
 
+    pub fn get_first_data_block(&self) -> Result<u32, MRError> {
+        Ok(self.get_super_block()?.s_first_data_block)
+    }
+
     pub fn get_reserved_gdt_num(&self) -> Result<u16, MRError> {
         Ok(self.get_super_block()?.s_reserved_gdt_blocks)
     }
@@ -363,6 +411,12 @@ impl Ext4 {
     }
 
     pub fn get_block_size(&self) -> usize {
+        //block_size is only cached once the group descriptors were read
+        if self.block_size.get() == 0 {
+            if let Ok(sb) = self.get_super_block() {
+                self.block_size.set(1024 << sb.s_log_block_size);
+            }
+        }
         self.block_size.get()
     }
 
@@ -371,18 +425,93 @@ impl Ext4 {
     }
 
     pub fn get_jbd2(&self) -> Result<Journal, MRError> {
-        let inode = self.get_journal_inode().unwrap();
+        if self.journal_reader.is_some() {
+            return self.get_external_jbd2();
+        }
+        let sb = self.get_super_block()?;
+        if sb.s_journal_inum == 0 && sb.s_journal_uuid.iter().any(|x| *x != 0) {
+            return Err(MRError::new(
+                "Filesystem uses an external journal, add argument:journal=${journal_image}",
+            ));
+        }
+        let inode = self.get_journal_inode()?;
         let extents = match inode.get_flat_extents() {
             Ok(o) => o,
             Err(e) => {
                 return Err(e);
             }
         };
-        let extent = &extents[0];
-        Journal::parse(self, extent.get_start() * self.get_block_size())
+        let extents = extents
+            .iter()
+            .map(|x| (x.get_block() as u64, x.get_start() as u64, x.get_len() as u64))
+            .collect();
+        Journal::parse(self, extents, false)
+    }
+
+    //An external journal device starts with its own ext4 super block, the jbd2 log follows
+    //it contiguously from the next block
+    fn get_external_jbd2(&self) -> Result<Journal, MRError> {
+        let reader = self.get_journal_reader()?;
+        let sbytes = Bytes::from(reader.read_n(1024, 1024)?);
+        let magic = (sub_bytes(&sbytes, 0x38..0x3a)?).get_u16_le();
+        let incompat = (sub_bytes(&sbytes, 0x60..0x64)?).get_u32_le();
+        if magic != 0xEF53 || incompat & EXT4_FEATURE_INCOMPAT_JOURNAL_DEV == 0 {
+            return Err(MRError::new("Not an ext4 external journal device"));
+        }
+        let block_size = 1024 << (sub_bytes(&sbytes, 0x18..0x1c)?).get_u32_le();
+        if block_size != self.get_block_size() {
+            return Err(MRError::new(
+                "Journal device block size does not match the filesystem",
+            ));
+        }
+        let journal = Journal::parse(self, vec![(0, 0, u32::MAX as u64)], true)?;
+        if journal.get_super_block().get_uuid()[..] != self.get_super_block()?.s_journal_uuid {
+            return Err(MRError::new("Journal UUID does not match s_journal_uuid"));
+        }
+        Ok(journal)
+    }
+
+    pub fn set_journal_device<P>(&mut self, path: P) -> Result<(), MRError>
+    where
+        P: AsRef<Path> + ToString,
+    {
+        self.journal_reader = Some(MRFile::new(path)?);
+        Ok(())
+    }
+
+    pub fn get_journal_reader(&self) -> Result<&MRFile, MRError> {
+        match self.journal_reader.as_ref() {
+            Some(o) => Ok(o),
+            None => Err(MRError::new("No external journal device")),
+        }
     }
 
     pub fn read_raw(&mut self, range: Range<usize>) -> Result<Vec<u8>, MRError> {
         self.reader.as_ref().unwrap().read_range(range)
     }
+
+    //Byte ranges read by chunks of size, each chunk carries redundancy more bytes of its range
+    //so matches crossing two chunks are seen, f gets the offset and returns true to stop
+    pub fn iter_ranges<F>(&self, ranges: &[Range<u64>], size: usize, redundancy: usize, mut f: F) -> Result<(), MRError>
+    where
+        F: FnMut(u64, &[u8]) -> bool,
+    {
+        let reader = self.get_reader();
+        for range in ranges {
+            let mut offset = range.start;
+            while offset < range.end {
+                let n = (range.end - offset).min((size + redundancy) as u64);
+                let bs = reader.read_n(offset as usize, n as usize)?;
+                if f(offset, &bs) {
+                    return Ok(());
+                }
+                offset += size as u64;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_total_size(&self) -> Result<u64, MRError> {
+        Ok(self.get_super_block()?.s_block_count as u64 * self.get_block_size() as u64)
+    }
 }
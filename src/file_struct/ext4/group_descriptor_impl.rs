@@ -17,6 +17,7 @@ impl GroupDescriptor {
         if self.is_64bit {
             offset |= (self.bg_block_bitmap_hi as usize) << 32;
         }
+        let offset = offset * block_size;
         Range {
             start: offset,
             end: offset + block_size,
@@ -61,7 +62,30 @@ impl GroupDescriptor {
         let index = (id - 1) % sb.s_inodes_per_group;
         let mut base = offset * ext4.get_block_size() + index as usize * 0x100;
         let bs = reader.read_n(base, 0x100).unwrap();
-        Inode::parse(&Bytes::from(bs), ext4, base as u64)
+        let mut inode = Inode::parse(&Bytes::from(bs), ext4, base as u64)?;
+        inode.i_num = id;
+        if ext4.is_strict() {
+            if let Some(mismatch) = ext4.verify_inode(&inode)? {
+                return Err(MRError::new(&format!("{}", mismatch)));
+            }
+        }
+        Ok(inode)
+    }
+
+    pub fn get_group(&self) -> u32 {
+        self.group
+    }
+
+    pub fn get_flags(&self) -> u16 {
+        self.bg_flags
+    }
+
+    pub fn is_inode_uninit(&self) -> bool {
+        self.bg_flags & 0x1 == 0x1
+    }
+
+    pub fn is_block_uninit(&self) -> bool {
+        self.bg_flags & 0x2 == 0x2
     }
 
     pub fn get_inode_table(&self) -> u64 {
@@ -128,6 +152,31 @@ impl GroupDescriptor {
             MRErrKind::OutOfByteRange,
         ))?)
         .get_u16_le();
+        s.bg_used_dirs_count_lo = (bs.get(0x10..0x12).ok_or(MRError::new_with_kind(
+            "Out of range",
+            MRErrKind::OutOfByteRange,
+        ))?)
+        .get_u16_le();
+        s.bg_flags = (bs.get(0x12..0x14).ok_or(MRError::new_with_kind(
+            "Out of range",
+            MRErrKind::OutOfByteRange,
+        ))?)
+        .get_u16_le();
+        s.bg_block_bitmap_csum_lo = (bs.get(0x18..0x1a).ok_or(MRError::new_with_kind(
+            "Out of range",
+            MRErrKind::OutOfByteRange,
+        ))?)
+        .get_u16_le();
+        s.bg_inode_bitmap_csum_lo = (bs.get(0x1a..0x1c).ok_or(MRError::new_with_kind(
+            "Out of range",
+            MRErrKind::OutOfByteRange,
+        ))?)
+        .get_u16_le();
+        s.bg_checksum = (bs.get(0x1e..0x20).ok_or(MRError::new_with_kind(
+            "Out of range",
+            MRErrKind::OutOfByteRange,
+        ))?)
+        .get_u16_le();
         let s_block = ext4_self.get_super_block().unwrap();
         s.is_64bit = s_block.is_64bit;
         if s_block.is_64bit {
@@ -156,6 +205,21 @@ impl GroupDescriptor {
                 MRErrKind::OutOfByteRange,
             ))?)
             .get_u16_le();
+            s.bg_used_dirs_count_hi = (bs.get(0x30..0x32).ok_or(MRError::new_with_kind(
+                "Out of range",
+                MRErrKind::OutOfByteRange,
+            ))?)
+            .get_u16_le();
+            s.bg_block_bitmap_csum_hi = (bs.get(0x38..0x3a).ok_or(MRError::new_with_kind(
+                "Out of range",
+                MRErrKind::OutOfByteRange,
+            ))?)
+            .get_u16_le();
+            s.bg_inode_bitmap_csum_hi = (bs.get(0x3a..0x3c).ok_or(MRError::new_with_kind(
+                "Out of range",
+                MRErrKind::OutOfByteRange,
+            ))?)
+            .get_u16_le();
         }
         Ok(s)
     }
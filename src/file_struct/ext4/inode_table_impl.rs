@@ -8,6 +8,39 @@ use super::{
     FileMode, FileType, Inode,
 };
 
+//i_block of block mapped inodes: 12 direct pointers, then single, double and triple indirect
+const EXT4_NDIR_BLOCKS: usize = 12;
+const EXT4_N_BLOCKS: usize = 15;
+const EXT4_INLINE_DATA_FL: u32 = 0x10000000;
+//Longest initialized extent
+const MAX_EXTENT_LEN: u16 = 32768;
+
+fn block_pointers(bs: &[u8]) -> Vec<u32> {
+    bs.chunks_exact(4)
+        .map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+        .collect()
+}
+
+//Appends a block to the last extent when both are contiguous
+fn push_mapped_block(extents: &mut Vec<Extent>, lblk: u32, pblk: u32) {
+    if let Some(last) = extents.last_mut() {
+        if last.ee_len < MAX_EXTENT_LEN
+            && last.ee_block + last.ee_len as u32 == lblk
+            && last.ee_start_hi == 0
+            && last.ee_start_lo + last.ee_len as u32 == pblk
+        {
+            last.ee_len += 1;
+            return;
+        }
+    }
+    extents.push(Extent {
+        ee_block: lblk,
+        ee_len: 1,
+        ee_start_hi: 0,
+        ee_start_lo: pblk,
+    });
+}
+
 impl Extent {
     pub fn parse(bs: &Bytes) -> Result<Extent, MRError> {
         Ok(Extent {
@@ -25,6 +58,19 @@ impl Extent {
     pub fn get_len(&self) -> usize {
         self.ee_len as usize
     }
+
+    //Uninitialized extents store their length plus 32768
+    pub fn get_real_len(&self) -> usize {
+        match self.ee_len > 32768 {
+            true => self.ee_len as usize - 32768,
+            false => self.ee_len as usize,
+        }
+    }
+
+    //First logical block of the file covered by this extent
+    pub fn get_block(&self) -> u32 {
+        self.ee_block
+    }
 }
 
 impl ExtentIdx {
@@ -149,6 +195,26 @@ impl Inode {
         self.i_uid
     }
 
+    pub fn get_id(&self) -> u32 {
+        self.i_num
+    }
+
+    pub fn get_generation(&self) -> u32 {
+        self.i_generation
+    }
+
+    pub fn get_flags(&self) -> u32 {
+        self.i_flags
+    }
+
+    pub fn get_links_count(&self) -> u16 {
+        self.i_links_count
+    }
+
+    pub fn get_base_addr(&self) -> u64 {
+        self.base_addr
+    }
+
     pub fn is_empty(&self) -> Result<bool, MRError> {
         let tree = self.get_extent_tree()?;
         if tree.extents.len() != 0 || tree.idx_items.len() != 0 {
@@ -190,8 +256,24 @@ impl Inode {
         let ext4 = self.get_ext4();
         let mut cur = &value;
         let mut result = vec![];
+        //Physical blocks in the order the extents were read into value
+        let blocks: Vec<u64> = match ext4.is_strict() {
+            true => self
+                .get_flat_extents()?
+                .iter()
+                .flat_map(|x| (0..x.get_len()).map(move |i| (x.get_start() + i) as u64))
+                .collect(),
+            false => vec![],
+        };
         let mut base_block = 0;
         while base_block < value.len() {
+            if ext4.is_strict() && self.i_num != 0 {
+                let block_bs = &value[base_block..base_block + ext4.get_block_size()];
+                let block = blocks.get(base_block / ext4.get_block_size()).copied().unwrap_or(0);
+                if let Some(mismatch) = ext4.verify_dir_block(self, block_bs, block)? {
+                    return Err(MRError::new(&format!("{}", mismatch)));
+                }
+            }
             let mut base_addr = 0;
             while base_addr < ext4.get_block_size() {
                 let bs = value.slice(base_block..base_block + ext4.get_block_size());
@@ -213,7 +295,7 @@ impl Inode {
     }
 
     fn align_of_4(&self, n: usize) -> usize {
-        if n % 4 == 0 {
+        if n.is_multiple_of(4) {
             n
         } else {
             (4 - (n % 4)) + n
@@ -329,24 +411,103 @@ impl Inode {
     }
 
     pub fn get_flat_extents(&self) -> Result<Vec<Extent>, MRError> {
+        self.walk_extents(&mut vec![])
+    }
+
+    //Extents and the blocks holding the extent tree nodes out of i_block
+    pub fn get_extents_with_nodes(&self) -> Result<(Vec<Extent>, Vec<u64>), MRError> {
+        let mut nodes = vec![];
+        let extents = self.walk_extents(&mut nodes)?;
+        Ok((extents, nodes))
+    }
+
+    //Blocks of an inode without EXT4_EXTENTS_FL as extents, the indirect blocks go to `nodes`
+    fn walk_block_map(&self, nodes: &mut Vec<u64>) -> Result<Vec<Extent>, MRError> {
+        let mut extents = vec![];
+        //Device numbers, inline data and fast symlink targets are kept in i_block
+        let kind = self.i_mode & 0xf000;
+        if self.i_flags & EXT4_INLINE_DATA_FL != 0
+            || !(kind == 0x8000 || kind == 0x4000 || kind == 0xa000)
+            || (kind == 0xa000 && self.get_size() < self.i_block.len() as u64)
+        {
+            return Ok(extents);
+        }
+        let ext4 = self.get_ext4();
+        let reader = ext4.get_reader();
+        let block_size = ext4.get_block_size();
+        let total_blocks = ext4.get_total_size()? / block_size as u64;
+        let per_block = (block_size / 4) as u64;
+        let pointers = block_pointers(&self.i_block);
+        for (i, p) in pointers.iter().take(EXT4_NDIR_BLOCKS).enumerate() {
+            if *p != 0 && (*p as u64) < total_blocks {
+                push_mapped_block(&mut extents, i as u32, *p);
+            }
+        }
+
+        //(pointer block, levels of indirection left, first logical block it maps)
+        let mut stack = vec![];
+        let mut lblk = EXT4_NDIR_BLOCKS as u64;
+        for (level, p) in pointers[EXT4_NDIR_BLOCKS..EXT4_N_BLOCKS].iter().enumerate() {
+            stack.push((*p, level as u32, lblk));
+            lblk += per_block.pow(level as u32 + 1);
+        }
+        //Popped in logical order so the extents stay sorted
+        stack.reverse();
+        while let Some((block, level, lblk)) = stack.pop() {
+            if block == 0 || block as u64 >= total_blocks || lblk > u32::MAX as u64 {
+                continue;
+            }
+            nodes.push(block as u64);
+            let bs = reader.read_n(block as usize * block_size, block_size)?;
+            let span = per_block.pow(level);
+            let mut children = vec![];
+            for (i, p) in block_pointers(&bs).into_iter().enumerate() {
+                let child = lblk + i as u64 * span;
+                if level == 0 {
+                    if p != 0 && (p as u64) < total_blocks && child <= u32::MAX as u64 {
+                        push_mapped_block(&mut extents, child as u32, p);
+                    }
+                } else {
+                    children.push((p, level - 1, child));
+                }
+            }
+            stack.extend(children.into_iter().rev());
+        }
+        Ok(extents)
+    }
+
+    fn walk_extents(&self, nodes: &mut Vec<u64>) -> Result<Vec<Extent>, MRError> {
+        //ext2/ext3 style inodes, a stray extent header still wins
+        if !self.is_extents() && (&self.i_block[0..2]).get_u16_le() != 0xF30A {
+            return self.walk_block_map(nodes);
+        }
         let mut extents = vec![];
         let mut stack = vec![];
         let ext4 = self.get_ext4();
         let reader = ext4.get_reader();
-        let first = ExtentTree::parse(&Bytes::from(self.i_block.clone()), self.base_addr + 0x28)?;
+        let root = self.base_addr + 0x28;
+        //Entries of the root node come from i_block so copies of the inode (journal, fast
+        //commit, external journal) do not depend on where they were read from
+        let read_entry = |addr: u64, n: usize| -> Result<Vec<u8>, MRError> {
+            if addr >= root && addr + n as u64 <= root + self.i_block.len() as u64 {
+                let start = (addr - root) as usize;
+                return Ok(self.i_block[start..start + n].to_vec());
+            }
+            reader.read_n(addr as usize, n)
+        };
+        let first = ExtentTree::parse(&Bytes::from(self.i_block.clone()), root)?;
         stack.push(first);
         while let Some(f) = stack.pop() {
             if f.node_type.eq(&ExtentNodeType::ExtentType) {
                 let mut index = f.base_addr + EXTENT_HEADER_SIZE as u64;
                 for i in 0..f.header.eh_entries {
-                    let v = reader.read_n(index as usize, EXTENT_SIZE).unwrap();
-                    let t = Bytes::from(v);
+                    let t = Bytes::from(read_entry(index, EXTENT_SIZE)?);
                     let extent = Extent::parse(&t)?;
+                    index += EXTENT_SIZE as u64;
                     if extent.ee_start_lo == 0 && extent.ee_start_hi == 0 {
                         continue;
                     }
                     extents.push(extent);
-                    index += 12;
                 }
             } else {
                 let mut index = f.base_addr + EXTENT_HEADER_SIZE as u64;
@@ -354,15 +515,24 @@ impl Inode {
                     return Err(MRError::new("Not valid extent"));
                 }
                 for i in 0..f.header.eh_entries {
-                    let v = reader.read_n(index as usize, EXTENT_IDX_SIZE).unwrap();
-                    let t = Bytes::from(v);
+                    let t = Bytes::from(read_entry(index, EXTENT_IDX_SIZE)?);
                     let idx = ExtentIdx::parse(&t)?;
+                    index += EXTENT_IDX_SIZE as u64;
                     if idx.ei_leaf_lo == 0 && idx.ei_leaf_hi == 0 {
                         continue;
                     }
 
-                    let leaf_offset = ((idx.ei_leaf_hi as u64) << 32) + idx.ei_leaf_lo as u64;
-                    let leaf_offset = (leaf_offset as usize) * ext4.get_block_size();
+                    let leaf_block = ((idx.ei_leaf_hi as u64) << 32) + idx.ei_leaf_lo as u64;
+                    nodes.push(leaf_block);
+                    let leaf_offset = (leaf_block as usize) * ext4.get_block_size();
+                    if ext4.is_strict() && self.i_num != 0 {
+                        let block_bs = reader.read_n(leaf_offset, ext4.get_block_size())?;
+                        if let Some(mismatch) =
+                            ext4.verify_extent_block(self, &block_bs, leaf_block)?
+                        {
+                            return Err(MRError::new(&format!("{}", mismatch)));
+                        }
+                    }
                     let header_bs = reader.read_n(leaf_offset, 12).unwrap();
                     let mut child_node =
                         ExtentNode::parse_header(&Bytes::from(header_bs), leaf_offset as u64)?;
@@ -377,7 +547,6 @@ impl Inode {
                         child_node.idx_items.push(child_idx);
                         idx_index += EXTENT_IDX_SIZE;
                     }
-                    index += EXTENT_IDX_SIZE as u64;
                     stack.push(child_node);
                 }
             }
@@ -489,6 +658,7 @@ impl Inode {
             i_version_hi,
             i_projid,
             i_block: i_block.to_vec(),
+            i_num: 0,
             ext4: Some(ext4 as *const Ext4),
             base_addr: offset,
         })
@@ -522,6 +692,19 @@ impl TryFrom<u8> for FileType {
 }
 
 impl DirectoryEntry {
+    //Entry rebuilt from a record that is not a directory block, e.g. a fast commit tag
+    pub fn new(inode: u32, name: &str, file_type: FileType) -> Self {
+        Self {
+            inode,
+            rec_len: 0,
+            name_len: name.len() as u8,
+            file_type,
+            name: name.as_bytes().to_vec(),
+            utf8_name: name.to_string(),
+            with_zero_end_string: name.to_string(),
+        }
+    }
+
     pub fn get_zero_end_name(&self) -> &String {
         &self.with_zero_end_string
     }
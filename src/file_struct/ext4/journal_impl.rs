@@ -1,14 +1,75 @@
-use std::ops::Range;
+use std::{
+    cell::OnceCell,
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+};
 
 use bytes::{Buf, Bytes};
+use chrono::NaiveDateTime;
 
-use crate::utils::{funcs::sub_bytes, MRErrKind, MRError};
+use crate::utils::{file::MRFile, funcs::sub_bytes, MRErrKind, MRError};
 
 use super::{
-    CommitBlock, Ext4, Inode, Journal, JournalBlockTag, JournalDataBlock, JournalDescriptorBlock,
-    JournalHeader, JournalSuperBlock, JournalTransaction, JournalTransactionIteration,
+    checksum_impl::ext4_chksum, ChecksumMismatch, CommitBlock, Ext4, Extent, FastCommitRecord,
+    FastCommitTag, Inode, Journal, JournalBlockTag, JournalDataBlock, JournalDescriptorBlock,
+    JournalHeader, JournalSuperBlock, JournalTransaction, MetadataStructure, RevocationBlock,
+    EXT4_FEATURE_COMPAT_FAST_COMMIT,
 };
 
+pub const JBD2_MAGIC_NUMBER: u32 = 0xC03B3998;
+pub const JBD2_DESCRIPTOR_BLOCK: u32 = 1;
+pub const JBD2_COMMIT_BLOCK: u32 = 2;
+pub const JBD2_SUPERBLOCK_V1: u32 = 3;
+pub const JBD2_SUPERBLOCK_V2: u32 = 4;
+pub const JBD2_REVOKE_BLOCK: u32 = 5;
+
+pub const JBD2_FEATURE_INCOMPAT_REVOKE: u32 = 0x1;
+pub const JBD2_FEATURE_INCOMPAT_64BIT: u32 = 0x2;
+pub const JBD2_FEATURE_INCOMPAT_ASYNC_COMMIT: u32 = 0x4;
+pub const JBD2_FEATURE_INCOMPAT_CSUM_V2: u32 = 0x8;
+pub const JBD2_FEATURE_INCOMPAT_CSUM_V3: u32 = 0x10;
+pub const JBD2_FEATURE_INCOMPAT_FAST_COMMIT: u32 = 0x20;
+
+pub const EXT4_FC_TAG_ADD_RANGE: u16 = 0x1;
+pub const EXT4_FC_TAG_DEL_RANGE: u16 = 0x2;
+pub const EXT4_FC_TAG_CREAT: u16 = 0x3;
+pub const EXT4_FC_TAG_LINK: u16 = 0x4;
+pub const EXT4_FC_TAG_UNLINK: u16 = 0x5;
+pub const EXT4_FC_TAG_INODE: u16 = 0x6;
+pub const EXT4_FC_TAG_PAD: u16 = 0x7;
+pub const EXT4_FC_TAG_TAIL: u16 = 0x8;
+pub const EXT4_FC_TAG_HEAD: u16 = 0x9;
+
+pub const JBD2_MIN_JOURNAL_BLOCKS: u32 = 1024;
+pub const JBD2_DEFAULT_FAST_COMMIT_BLOCKS: u32 = 256;
+
+pub const JBD2_FLAG_ESCAPE: u32 = 0x1;
+pub const JBD2_FLAG_SAME_UUID: u32 = 0x2;
+pub const JBD2_FLAG_DELETED: u32 = 0x4;
+pub const JBD2_FLAG_LAST_TAG: u32 = 0x8;
+
+//Sequence comparison that survives the 32 bit tid wrapping around
+fn tid_gt(x: u32, y: u32) -> bool {
+    (x.wrapping_sub(y) as i32) > 0
+}
+
+fn map_journal_block(
+    extents: &[(u64, u64, u64)],
+    blocknr: u32,
+    block_size: usize,
+) -> Result<usize, MRError> {
+    let blocknr = blocknr as u64;
+    for (logical, physical, len) in extents {
+        if blocknr >= *logical && blocknr < logical + len {
+            return Ok(((physical + blocknr - logical) * block_size as u64) as usize);
+        }
+    }
+    Err(MRError::new_with_kind(
+        &format!("Journal block {} is not mapped", blocknr),
+        MRErrKind::OutOfByteRange,
+    ))
+}
+
 impl JournalHeader {
     pub fn parse(bs: Bytes) -> Result<JournalHeader, MRError> {
         Ok(Self {
@@ -24,103 +85,78 @@ impl JournalBlockTag {
         self.flag
     }
 
+    pub fn get_checksum(&self) -> u32 {
+        self.checksum
+    }
+
     pub fn get_block_id(&self) -> u64 {
         ((self.blocknr_high as u64) << 32) + self.blocknr as u64
     }
 
     pub fn parse(bs: Bytes, feature: u32, is_64bit: bool) -> Result<Self, MRError> {
-        if feature & 0x10 == 0x10 {
-            if bs.len() < 16 {
-                return Err(MRError::new("Parse size error"));
-            }
-            let blocknr = (bs.get(0..4).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
-            let flags = (bs.get(4..8).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
-            let blocknr_high = (bs.get(8..0xc).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
-            let checksum = (bs.get(0xc..0x10).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
-
-            if flags & 0x2 == 0x2 {
-                return Ok(Self {
-                    blocknr,
-                    blocknr_high: 0,
-                    size: 16,
-                    uuid: Vec::default(),
-                    flag: flags,
-                });
-            } else {
-                if bs.len() < 32 {
+        let (blocknr, flags, blocknr_high, checksum, base) =
+            if feature & JBD2_FEATURE_INCOMPAT_CSUM_V3 == JBD2_FEATURE_INCOMPAT_CSUM_V3 {
+                if bs.len() < 16 {
                     return Err(MRError::new("Parse size error"));
                 }
-                let uuid = sub_bytes(&bs,0xc..0xc + 16)?.to_vec();
-                return Ok(Self {
-                    blocknr,
-                    blocknr_high,
-                    size: 32,
-                    uuid,
-                    flag: flags,
-                });
-            }
-        } else {
-            if bs.len() < 8 {
-                return Err(MRError::new("Parse size error"));
-            }
-            let blocknr = (bs.get(0..4).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
-            let checksum = (bs.get(4..6).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u16();
-            let flags = (bs.get(6..8).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u16();
-
-            if is_64bit {
-                if bs.len() < 12 {
-                    return Err(MRError::new("Parse size error"));
-                }
-                let blocknr_high = (bs.get(8..12).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
-                if flags & 0x2 == 0x2 {
-                    return Ok(Self {
-                        blocknr,
-                        blocknr_high,
-                        size: 12,
-                        uuid: Vec::default(),
-                        flag: flags as u32,
-                    });
+                let blocknr = sub_bytes(&bs, 0..4)?.get_u32();
+                let flags = sub_bytes(&bs, 4..8)?.get_u32();
+                let blocknr_high = if is_64bit {
+                    sub_bytes(&bs, 8..0xc)?.get_u32()
                 } else {
-                    if bs.len() < 28 {
-                        return Err(MRError::new("Parse size error"));
-                    }
-                    let uuid = sub_bytes(&bs,12..12 + 16)?.to_vec();
-                    return Ok(Self {
-                        blocknr,
-                        blocknr_high,
-                        size: 28,
-                        uuid,
-                        flag: flags as u32,
-                    });
-                }
+                    0
+                };
+                let checksum = sub_bytes(&bs, 0xc..0x10)?.get_u32();
+                (blocknr, flags, blocknr_high, checksum, 16)
             } else {
-                if bs.len() < 8 {
+                //csum v2 puts 2 more bytes after the tag, the tag checksum itself is the 16 bit field
+                let base = if is_64bit { 12 } else { 8 };
+                let base = match feature & JBD2_FEATURE_INCOMPAT_CSUM_V2 {
+                    0 => base,
+                    _ => base + 2,
+                };
+                if bs.len() < base {
                     return Err(MRError::new("Parse size error"));
                 }
-                if flags & 0x2 == 0x2 {
-                    return Ok(Self {
-                        blocknr,
-                        blocknr_high: 0,
-                        size: 8,
-                        uuid: Vec::default(),
-                        flag: flags as u32,
-                    });
+                let blocknr = sub_bytes(&bs, 0..4)?.get_u32();
+                let checksum = sub_bytes(&bs, 4..6)?.get_u16() as u32;
+                let flags = sub_bytes(&bs, 6..8)?.get_u16() as u32;
+                let blocknr_high = if is_64bit {
+                    sub_bytes(&bs, 8..12)?.get_u32()
                 } else {
-                    if bs.len() < 24 {
-                        return Err(MRError::new("Parse size error"));
-                    }
-                    let uuid = sub_bytes(&bs,8..8 + 16)?.to_vec();
-                    return Ok(Self {
-                        blocknr,
-                        blocknr_high: 0,
-                        size: 24,
-                        uuid,
-                        flag: flags as u32,
-                    });
-                }
+                    0
+                };
+                (blocknr, flags, blocknr_high, checksum, base)
+            };
+
+        //Without SAME_UUID the tag is followed by a 16 byte uuid
+        let (uuid, size) = if flags & JBD2_FLAG_SAME_UUID == JBD2_FLAG_SAME_UUID {
+            (Vec::default(), base)
+        } else {
+            if bs.len() < base + 16 {
+                return Err(MRError::new("Parse size error"));
             }
+            (sub_bytes(&bs, base..base + 16)?.to_vec(), base + 16)
+        };
+        Ok(Self {
+            blocknr,
+            blocknr_high,
+            checksum,
+            size,
+            uuid,
+            flag: flags,
+        })
+    }
+
+    //Checksum of the data block the tag describes, `sequence` is the transaction id
+    pub fn calc_checksum(&self, sb: &JournalSuperBlock, sequence: u32, data: &[u8]) -> u32 {
+        let csum = ext4_chksum(sb.get_csum_seed(), &sequence.to_be_bytes());
+        let csum = ext4_chksum(csum, data);
+        if sb.get_feature_incompat() & JBD2_FEATURE_INCOMPAT_CSUM_V3 != 0 {
+            csum
+        } else {
+            csum & 0xffff
         }
-        Err(MRError::new("Error size"))
     }
 }
 
@@ -131,17 +167,23 @@ impl JournalDescriptorBlock {
 
     pub fn parse(bs: Bytes, sb: &JournalSuperBlock, ext4: &Ext4) -> Result<Self, MRError> {
         let header = JournalHeader::parse(bs.slice(0..0xc))?;
-        if header.h_magic != 0xC03B3998 {
+        if header.h_magic != JBD2_MAGIC_NUMBER || header.h_blocktype != JBD2_DESCRIPTOR_BLOCK {
             return Err(MRError::new("Not a valid descriptor"));
         }
+        if ext4.is_strict() {
+            let (stored, computed) = sb.calc_tail_checksum(&bs);
+            if sb.has_csum_v2or3() && stored != computed {
+                return Err(MRError::new("Journal descriptor block checksum mismatch"));
+            }
+        }
+        let end = if sb.has_csum_v2or3() { bs.len() - 4 } else { bs.len() };
         let mut base_addr = 0xc;
         let mut tags = vec![];
-        while base_addr < bs.len() {
-            let test = bs.slice(base_addr..).to_vec();
+        while base_addr < end {
             let tag = match JournalBlockTag::parse(
-                bs.slice(base_addr..),
+                bs.slice(base_addr..end),
                 sb.get_feature_incompat(),
-                ext4.is_64bit(),
+                sb.get_feature_incompat() & JBD2_FEATURE_INCOMPAT_64BIT != 0,
             ) {
                 Ok(o) => o,
                 Err(e) => {
@@ -163,14 +205,32 @@ impl JournalDescriptorBlock {
 }
 
 impl Journal {
-    pub fn parse(ext4: &Ext4, offset: usize) -> Result<Self, MRError> {
-        let reader = ext4.get_reader();
-        let sb_bs = reader.read_n(offset, 0x100 + 16 * 48).unwrap();
-        let sb = JournalSuperBlock::parse(Bytes::from(sb_bs), ext4)?;
+    //`external` journals keep their super block right after the ext4 super block of the
+    //journal device and address the log with device block numbers
+    pub fn parse(
+        ext4: &Ext4,
+        extents: Vec<(u64, u64, u64)>,
+        external: bool,
+    ) -> Result<Self, MRError> {
+        let block_size = ext4.get_block_size();
+        let (reader, offset) = if external {
+            (ext4.get_journal_reader()?, (1024 / block_size + 1) * block_size)
+        } else {
+            (ext4.get_reader(), map_journal_block(&extents, 0, block_size)?)
+        };
+        let sb_bs = reader.read_n(offset, 0x100 + 16 * 48)?;
+        let sb = JournalSuperBlock::parse(Bytes::from(sb_bs.clone()), ext4)?;
+        if ext4.is_strict() && sb.has_csum_v2or3() && sb.calc_checksum(&sb_bs) != sb.s_checksum {
+            return Err(MRError::new("Journal super block checksum mismatch"));
+        }
         Ok(Self {
             super_block: sb,
             ext4: Some(ext4 as *const Ext4),
+            external,
             offset,
+            extents,
+            transactions: OnceCell::new(),
+            fast_commits: OnceCell::new(),
         })
     }
 
@@ -178,59 +238,364 @@ impl Journal {
         unsafe { &*self.ext4.unwrap() }
     }
 
-    pub fn iter_transaction<F>(&self, f: &mut F)
+    //Reader of the device holding the journal blocks
+    pub fn get_reader(&self) -> &MRFile {
+        let ext4 = self.get_ext4();
+        match ext4.get_journal_reader() {
+            Ok(o) if self.external => o,
+            _ => ext4.get_reader(),
+        }
+    }
+
+    pub fn is_external(&self) -> bool {
+        self.external
+    }
+
+    //Maps a journal block number to its byte offset on the filesystem
+    pub fn block_offset(&self, blocknr: u32) -> Result<usize, MRError> {
+        map_journal_block(&self.extents, blocknr, self.get_ext4().get_block_size())
+    }
+
+    //The log is circular, after the last block it continues at s_first
+    fn next_block(&self, blocknr: u32) -> u32 {
+        if blocknr + 1 >= self.super_block.get_log_end() {
+            self.super_block.s_first
+        } else {
+            blocknr + 1
+        }
+    }
+
+    fn read_block(&self, blocknr: u32) -> Result<Vec<u8>, MRError> {
+        let block_size = self.super_block.s_blocksize as usize;
+        self.get_reader()
+            .read_n(self.block_offset(blocknr)?, block_size)
+    }
+
+    //Returns the header of descriptor, commit and revoke blocks
+    fn read_header(&self, blocknr: u32) -> Result<Option<JournalHeader>, MRError> {
+        let bs = self
+            .get_reader()
+            .read_n(self.block_offset(blocknr)?, 0xc)?;
+        let header = JournalHeader::parse(Bytes::from(bs))?;
+        if header.h_magic != JBD2_MAGIC_NUMBER {
+            return Ok(None);
+        }
+        match header.h_blocktype {
+            JBD2_DESCRIPTOR_BLOCK | JBD2_COMMIT_BLOCK | JBD2_REVOKE_BLOCK => Ok(Some(header)),
+            _ => Ok(None),
+        }
+    }
+
+    //Reads a logged copy of a filesystem block, restoring the magic of escaped blocks
+    pub fn read_data_block(&self, block: &JournalDataBlock) -> Result<Vec<u8>, MRError> {
+        let mut bs = self.get_reader().read_range(block.get_range())?;
+        if block.is_escaped() && bs.len() >= 4 {
+            bs[..4].copy_from_slice(&JBD2_MAGIC_NUMBER.to_be_bytes());
+        }
+        Ok(bs)
+    }
+
+    //Rebuilds every transaction left in the circular log. Transactions of earlier mounts stay
+    //behind the head, so all blocks are scanned, from s_start when the log still has to be
+    //replayed. A transaction still open at the end of the log continues at s_first. The tid wraps
+    //around, transactions are ordered by their distance from s_sequence.
+    fn walk_transactions(&self) -> Result<Vec<JournalTransaction>, MRError> {
+        let sb = &self.super_block;
+        let ext4 = self.get_ext4();
+        let block_size = sb.s_blocksize as usize;
+        let log_len = sb.get_log_end().saturating_sub(sb.s_first);
+        let mut found: HashMap<u32, JournalTransaction> = HashMap::new();
+
+        let mut sequence = None;
+        let mut data_blocks = vec![];
+        let mut revocation_blocks = vec![];
+        let mut blocknr = match sb.s_start {
+            0 => sb.s_first,
+            n => n,
+        };
+        let mut walked = 0;
+        while walked < log_len || (sequence.is_some() && walked < 2 * log_len) {
+            let header = match self.read_header(blocknr)? {
+                Some(o) => o,
+                None => {
+                    //Blocks of one transaction are contiguous, anything else breaks it
+                    sequence = None;
+                    blocknr = self.next_block(blocknr);
+                    walked += 1;
+                    continue;
+                }
+            };
+            if sequence != Some(header.h_sequence) {
+                data_blocks.clear();
+                revocation_blocks.clear();
+                sequence = None;
+                if walked >= log_len {
+                    break;
+                }
+                //A commit without descriptor belongs to a transaction overwritten by the head
+                if header.h_blocktype != JBD2_COMMIT_BLOCK {
+                    sequence = Some(header.h_sequence);
+                }
+            }
+            let seq = match sequence {
+                Some(o) => o,
+                None => {
+                    blocknr = self.next_block(blocknr);
+                    walked += 1;
+                    continue;
+                }
+            };
+
+            let block = Bytes::from(self.read_block(blocknr)?);
+            match header.h_blocktype {
+                JBD2_DESCRIPTOR_BLOCK => match JournalDescriptorBlock::parse(block, sb, ext4) {
+                    Ok(desc) => {
+                        for tag in &desc.open_coded_array {
+                            blocknr = self.next_block(blocknr);
+                            walked += 1;
+                            let offset = self.block_offset(blocknr)?;
+                            data_blocks.push(JournalDataBlock::new(
+                                tag.get_block_id(),
+                                offset..offset + block_size,
+                                tag.get_flag(),
+                                seq,
+                            ));
+                        }
+                    }
+                    Err(_) => sequence = None,
+                },
+                JBD2_REVOKE_BLOCK => match RevocationBlock::parse(block, sb) {
+                    Ok(revoke) => revocation_blocks.push(revoke),
+                    Err(_) => sequence = None,
+                },
+                _ => {
+                    let transaction = JournalTransaction {
+                        sequence: seq,
+                        data_blocks: std::mem::take(&mut data_blocks),
+                        revocation_blocks: std::mem::take(&mut revocation_blocks),
+                        commit_block: CommitBlock::parse(block)?,
+                    };
+                    //The part of a wrapped transaction seen at s_first is shorter than the whole one
+                    let replace = match found.get(&seq) {
+                        Some(o) => o.data_blocks.len() <= transaction.data_blocks.len(),
+                        None => true,
+                    };
+                    if replace {
+                        found.insert(seq, transaction);
+                    }
+                    sequence = None;
+                }
+            }
+            blocknr = self.next_block(blocknr);
+            walked += 1;
+        }
+        let mut transactions: Vec<JournalTransaction> = found.into_values().collect();
+        transactions.sort_by_key(|x| x.sequence.wrapping_sub(sb.s_sequence) as i32);
+
+        //A revoke record cancels the copies logged by its own and all earlier transactions
+        let mut revoked: HashMap<u64, u32> = HashMap::new();
+        for transaction in &transactions {
+            for revoke in &transaction.revocation_blocks {
+                for block_id in &revoke.blocks {
+                    let entry = revoked.entry(*block_id).or_insert(transaction.sequence);
+                    if tid_gt(transaction.sequence, *entry) {
+                        *entry = transaction.sequence;
+                    }
+                }
+            }
+        }
+        for transaction in &mut transactions {
+            for block in &mut transaction.data_blocks {
+                if let Some(seq) = revoked.get(&block.block_id) {
+                    block.revoked = !tid_gt(block.sequence, *seq);
+                }
+            }
+        }
+        Ok(transactions)
+    }
+
+    //The log is walked once per Journal, later calls reuse the result
+    pub fn get_transactions(&self) -> Result<&Vec<JournalTransaction>, MRError> {
+        if let Some(o) = self.transactions.get() {
+            return Ok(o);
+        }
+        let transactions = self.walk_transactions()?;
+        Ok(self.transactions.get_or_init(|| transactions))
+    }
+
+    pub fn get_fast_commits(&self) -> Result<&Vec<FastCommitRecord>, MRError> {
+        if let Some(o) = self.fast_commits.get() {
+            return Ok(o);
+        }
+        let records = self.parse_fast_commits()?;
+        Ok(self.fast_commits.get_or_init(|| records))
+    }
+
+    pub fn iter_transaction<F>(&self, f: &mut F) -> Result<(), MRError>
     where
         F: FnMut(&JournalTransaction),
     {
+        for transaction in self.get_transactions()? {
+            f(transaction);
+        }
+        Ok(())
+    }
+
+    pub fn get_super_block(&self) -> &JournalSuperBlock {
+        &self.super_block
+    }
+
+    //Checks the jbd2 v2/v3 checksums of every journal block carrying one
+    pub fn verify<F>(&self, mut f: F) -> Result<(), MRError>
+    where
+        F: FnMut(&ChecksumMismatch),
+    {
+        let sb = &self.super_block;
+        if !sb.has_csum_v2or3() {
+            return Ok(());
+        }
+        let raw = self.get_reader().read_n(self.offset, 0x400)?;
+        let computed = sb.calc_checksum(&raw);
+        if computed != sb.s_checksum {
+            f(&ChecksumMismatch::new(
+                MetadataStructure::JournalSuperBlock,
+                0,
+                sb.s_checksum,
+                computed,
+            ));
+        }
 
-            let i = 1;
-            let ext4 = self.get_ext4();
-            let reader = ext4.get_reader();
-
-            let mut base_offset = self.offset + self.super_block.s_blocksize as usize;
-            for _dummy in 0..self.super_block.s_maxlen {
-                let bs = reader
-                    .read_n(base_offset, self.super_block.s_blocksize as usize)
-                    .unwrap();
-                let bs = Bytes::from(bs);
-                let desc = match JournalDescriptorBlock::parse(bs, &self.super_block, ext4) {
-                    Ok(o) => o,
-                    Err(e) => {
-                        base_offset += self.super_block.s_blocksize as usize;
-                        continue;
+        let ext4 = self.get_ext4();
+        let mut blocknr = sb.s_first.max(1);
+        while blocknr < sb.get_log_end() {
+            let block = self.read_block(blocknr)?;
+            let header = JournalHeader::parse(Bytes::from(block[..12].to_vec()))?;
+            if header.h_magic != JBD2_MAGIC_NUMBER {
+                blocknr += 1;
+                continue;
+            }
+            match header.h_blocktype {
+                JBD2_DESCRIPTOR_BLOCK => {
+                    let (stored, computed) = sb.calc_tail_checksum(&block);
+                    if stored != computed {
+                        f(&ChecksumMismatch::new(
+                            MetadataStructure::JournalDescriptor,
+                            blocknr as u64,
+                            stored,
+                            computed,
+                        ));
                     }
-                };
-                let count = desc.get_block_count();
-                let mut vs = vec![];
-                let mut i = 1;
-                for tag in &desc.open_coded_array {
-                    let offset = base_offset + i * self.super_block.s_blocksize as usize;
-                    let range = Range {
-                        start: offset,
-                        end: offset + self.super_block.s_blocksize as usize,
+                    let desc = match JournalDescriptorBlock::parse(Bytes::from(block), sb, ext4) {
+                        Ok(o) => o,
+                        Err(_) => {
+                            blocknr += 1;
+                            continue;
+                        }
                     };
-                    i += 1;
-                    vs.push(JournalDataBlock::new(tag.get_block_id(), range));
+                    let mut data_nr = blocknr;
+                    for tag in &desc.open_coded_array {
+                        data_nr += 1;
+                        if data_nr >= sb.get_log_end() {
+                            data_nr = sb.s_first;
+                        }
+                        let data = self.read_block(data_nr)?;
+                        let computed = tag.calc_checksum(sb, header.h_sequence, &data);
+                        if computed != tag.checksum {
+                            f(&ChecksumMismatch::new(
+                                MetadataStructure::JournalData,
+                                data_nr as u64,
+                                tag.checksum,
+                                computed,
+                            ));
+                        }
+                    }
+                    blocknr += 1 + desc.get_block_count() as u32;
+                }
+                JBD2_COMMIT_BLOCK => {
+                    let (stored, computed) = sb.calc_commit_checksum(&block);
+                    if stored != computed {
+                        f(&ChecksumMismatch::new(
+                            MetadataStructure::JournalCommit,
+                            blocknr as u64,
+                            stored,
+                            computed,
+                        ));
+                    }
+                    blocknr += 1;
+                }
+                JBD2_REVOKE_BLOCK => {
+                    let (stored, computed) = sb.calc_tail_checksum(&block);
+                    if stored != computed {
+                        f(&ChecksumMismatch::new(
+                            MetadataStructure::JournalRevoke,
+                            blocknr as u64,
+                            stored,
+                            computed,
+                        ));
+                    }
+                    blocknr += 1;
+                }
+                _ => {
+                    blocknr += 1;
                 }
-                let bs = reader.read_n(base_offset, 0x3c).unwrap();
-                let commit_block = CommitBlock::parse(Bytes::from(bs)).unwrap();
-                let transaction = JournalTransaction {
-                    desc_block: desc,
-                    data_blocks: vs,
-                    revocation_blocks: Vec::default(),
-                    commit_block,
-                };
-                f(&transaction);
-                base_offset += (count + 2) * self.super_block.s_blocksize as usize;
             }
-        
+        }
+        Ok(())
     }
 
-    pub fn reset_iter(&mut self) {
-        unimplemented!()
+    //Parses the ext4 fast commit tags kept after the regular log. Tags never cross a block,
+    //records are assigned the tid of the tail tag that closes them.
+    fn parse_fast_commits(&self) -> Result<Vec<FastCommitRecord>, MRError> {
+        let mut result = vec![];
+        if !self.super_block.has_fast_commit() {
+            return Ok(result);
+        }
+        let mut pending: Vec<FastCommitRecord> = vec![];
+        let mut tid = 0;
+        for blocknr in self.super_block.get_fast_commit_blocks() {
+            let bs = Bytes::from(self.read_block(blocknr)?);
+            let block_offset = self.block_offset(blocknr)?;
+            let mut cur = 0;
+            while cur + 4 <= bs.len() {
+                let fc_tag = sub_bytes(&bs, cur..cur + 2)?.get_u16_le();
+                let fc_len = sub_bytes(&bs, cur + 2..cur + 4)?.get_u16_le() as usize;
+                let value = match bs.get(cur + 4..cur + 4 + fc_len) {
+                    Some(o) => Bytes::copy_from_slice(o),
+                    None => break,
+                };
+                match fc_tag {
+                    EXT4_FC_TAG_HEAD => {
+                        if let Ok(mut o) = sub_bytes(&value, 4..8) {
+                            tid = o.get_u32_le();
+                        }
+                    }
+                    EXT4_FC_TAG_TAIL => {
+                        let tail_tid = match sub_bytes(&value, 0..4) {
+                            Ok(mut o) => o.get_u32_le(),
+                            Err(_) => break,
+                        };
+                        for mut record in pending.drain(..) {
+                            record.tid = tail_tid;
+                            result.push(record);
+                        }
+                        tid = tail_tid;
+                    }
+                    EXT4_FC_TAG_PAD => {}
+                    _ => match FastCommitTag::parse(fc_tag, &value, block_offset + cur + 4) {
+                        Ok(tag) => pending.push(FastCommitRecord { tid, blocknr, tag }),
+                        //Zeroed or stale space, the rest of the block can not be trusted
+                        Err(_) => break,
+                    },
+                }
+                cur += 4 + fc_len;
+            }
+        }
+        //Records without a tail were never committed but are kept as evidence
+        result.append(&mut pending);
+        Ok(result)
     }
 
-    pub fn find_blocks(&self, block_id: u64) -> Vec<JournalDataBlock> {
+    pub fn find_blocks(&self, block_id: u64) -> Result<Vec<JournalDataBlock>, MRError> {
         let mut vs = vec![];
         self.iter_transaction(&mut |x| {
             let data_blocks = &x.data_blocks;
@@ -239,84 +604,149 @@ impl Journal {
                     vs.push(block.clone());
                 }
             }
-        });
-        vs
+        })?;
+        Ok(vs)
     }
 
     pub fn find_inodes(&self, id: u32) -> Result<Vec<Inode>, MRError> {
         let mut result = vec![];
         let ext4 = self.get_ext4();
-        let reader = ext4.get_reader();
         let block_size = ext4.get_block_size();
-        let s_inodes_per_group = ext4.get_s_inodes_per_group()?;
-        let index = (id - 1) / s_inodes_per_group;
-        let offset = index * ext4.get_s_inode_size() as u32;
+        let inode_size = ext4.get_s_inode_size() as usize;
         let gdt = match ext4.get_inode_belong_gdt(id) {
             Ok(o) => o,
-            Err(e) => {
+            Err(_) => {
                 return Ok(result);
             }
         };
         let inode_table = gdt.get_inode_table();
-        let sb = ext4.get_super_block().unwrap();
-        let index = (id - 1) % sb.s_inodes_per_group;
-        let inode_block = index / 0x10;
-        let inode_block_offset = index % 0x10;
-        let inode_table_block = self.find_blocks(inode_table + inode_block as u64);
+        let index = (id - 1) % ext4.get_s_inodes_per_group()?;
+        let inodes_per_block = (block_size / inode_size) as u32;
+        let inode_block = index / inodes_per_block;
+        let inode_block_offset = (index % inodes_per_block) as usize * inode_size;
+        let inode_table_block = self.find_blocks(inode_table + inode_block as u64)?;
         for i in inode_table_block {
-            let mut base = i.range.start + 0x100 * inode_block_offset as usize;
-            let bs = reader.read_n(base, 0x100).unwrap();
-            let inode = Inode::parse(&Bytes::from(bs), ext4, base as u64)?;
+            let bs = Bytes::from(self.read_data_block(&i)?);
+            let base = i.range.start + inode_block_offset;
+            let inode = Inode::parse(
+                &bs.slice(inode_block_offset..inode_block_offset + inode_size),
+                ext4,
+                base as u64,
+            )?;
             result.push(inode);
         }
+
+        //Fast commits log only the first 128 + i_extra_isize bytes of the inode
+        for record in self.get_fast_commits()? {
+            if let FastCommitTag::Inode { inode, offset, raw } = record.get_tag() {
+                if *inode != id {
+                    continue;
+                }
+                let mut raw = raw.clone();
+                raw.resize(inode_size.max(0x100), 0);
+                result.push(Inode::parse(&Bytes::from(raw), ext4, *offset as u64)?);
+            }
+        }
         Ok(result)
     }
 
-    pub fn iter_files<F>(&self, mut f: F)
+    //Replays the ADD_RANGE and DEL_RANGE fast commit tags of `id` over the extents of `base`,
+    //None when the fast commit area holds no range of the inode
+    pub fn get_fast_commit_value(&self, id: u32, base: &Inode) -> Result<Option<Bytes>, MRError> {
+        let ranges: Vec<&FastCommitTag> = self
+            .get_fast_commits()?
+            .iter()
+            .map(|x| x.get_tag())
+            .filter(|x| match x {
+                FastCommitTag::AddRange { inode, .. } | FastCommitTag::DelRange { inode, .. } => {
+                    *inode == id
+                }
+                _ => false,
+            })
+            .collect();
+        if ranges.is_empty() {
+            return Ok(None);
+        }
+
+        //Physical block of every logical block, holes are left out
+        let mut blocks: BTreeMap<u32, u64> = BTreeMap::new();
+        for extent in base.get_flat_extents().unwrap_or_default() {
+            for i in 0..extent.get_real_len() as u32 {
+                blocks.insert(extent.get_block() + i, extent.get_start() as u64 + i as u64);
+            }
+        }
+        for tag in ranges {
+            match tag {
+                FastCommitTag::AddRange { lblk, len, pblk, .. } => {
+                    for i in 0..*len as u32 {
+                        blocks.insert(lblk + i, pblk + i as u64);
+                    }
+                }
+                FastCommitTag::DelRange { lblk, len, .. } => {
+                    let end = lblk.saturating_add(*len);
+                    let removed: Vec<u32> = blocks.range(lblk..&end).map(|x| *x.0).collect();
+                    for i in removed {
+                        blocks.remove(&i);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let ext4 = self.get_ext4();
+        let block_size = ext4.get_block_size();
+        let reader = ext4.get_reader();
+        //Trailing holes are not written out
+        let end = match blocks.last_key_value() {
+            Some(s) => (*s.0 as usize + 1) * block_size,
+            None => 0,
+        };
+        let size = (base.get_size() as usize).min(end);
+        let mut result = vec![0; size];
+        for (lblk, pblk) in blocks {
+            let start = lblk as usize * block_size;
+            if start >= size {
+                break;
+            }
+            let n = block_size.min(size - start);
+            let bs = reader.read_n(pblk as usize * block_size, n)?;
+            result[start..start + n].copy_from_slice(&bs);
+        }
+        Ok(Some(Bytes::from(result)))
+    }
+
+    pub fn iter_files<F>(&self, mut f: F) -> Result<(), MRError>
     where
         F: FnMut(u32, &Inode),
     {
         let ext4 = self.get_ext4();
-        let reader = ext4.get_reader();
-        let gdts = ext4.get_descs().unwrap();
-        self.iter_transaction(&mut |transaction| {
-            let data_blocks = &transaction.data_blocks;
-            for block in data_blocks {
+        let block_size = ext4.get_block_size();
+        let inode_size = ext4.get_s_inode_size() as usize;
+        let gdts = ext4.get_descs()?;
+        let num_blocks = (ext4.get_s_inodes_per_group()? as usize * inode_size / block_size) as u64;
+        for transaction in self.get_transactions()? {
+            for block in &transaction.data_blocks {
                 let block_id = block.block_id;
-                let inodes_per_group = ext4.get_s_inodes_per_group().unwrap();
-                let num_blocks = inodes_per_group as usize * 0x100 / ext4.get_block_size();
                 if !gdts.iter().any(|x| {
-                    block_id >= x.get_inode_table() && block_id <= x.get_inode_table() + num_blocks as u64
+                    block_id >= x.get_inode_table() && block_id < x.get_inode_table() + num_blocks
                 }) {
                     continue;
                 }
+                let bs = Bytes::from(self.read_data_block(block)?);
                 let mut base_addr = 0;
-                let mut count = 0;
-                let bs = reader.read_range(block.range.clone()).unwrap();
-                let bs = Bytes::from(bs);
-                while base_addr < ext4.get_block_size() {
-                    if block.block_id.checked_mul(ext4.get_block_size() as u64).is_none() {
-                        base_addr += 0x100;
-                        count += 1;
-                        continue;
+                while base_addr + inode_size <= bs.len() {
+                    let real_addr = block_id as usize * block_size + base_addr;
+                    if let Ok(id) = ext4.get_inode_id_by_addr(real_addr) {
+                        let inode_bs = bs.slice(base_addr..base_addr + inode_size);
+                        let inode =
+                            Inode::parse(&inode_bs, ext4, (block.range.start + base_addr) as u64)?;
+                        f(id, &inode);
                     }
-                    let real_addr = block.block_id as usize*ext4.get_block_size() + count * 0x100;
-                    let id = match ext4.get_inode_id_by_addr(real_addr) {
-                        Ok(o) => o,
-                        Err(e) => {
-                            base_addr += 0x100;
-                            count += 1;
-                            continue;
-                        }
-                    };
-                    let inode_bs = bs.slice(base_addr..base_addr+0x100);
-                    let inode = Inode::parse(&inode_bs, ext4, base_addr as u64).unwrap();
-                    f(id, &inode);
-                    base_addr += 0x100;
-                    count += 1;
+                    base_addr += inode_size;
                 }
             }
-        });
+        }
+        Ok(())
     }
 }
 
@@ -325,30 +755,120 @@ impl JournalSuperBlock {
         let header = JournalHeader::parse(bs.slice(0..0xc))?;
         let s_blocksize = (bs.get(0xc..0x10).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
         let s_maxlen = (bs.get(0x10..0x14).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
+        if header.h_magic != JBD2_MAGIC_NUMBER
+            || (header.h_blocktype != JBD2_SUPERBLOCK_V1 && header.h_blocktype != JBD2_SUPERBLOCK_V2)
+        {
+            return Err(MRError::new("Not a valid jbd2 super block"));
+        }
         let s_first = (bs.get(0x14..0x18).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
+        let s_sequence = (bs.get(0x18..0x1c).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
+        let s_start = (bs.get(0x1c..0x20).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
         let s_max_transaction = (bs.get(0x48..0x4c).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
         let s_errno = (bs.get(0x20..0x24).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
         let s_max_trans_data = (bs.get(0x4c..0x50).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
         let s_feature_compat = (bs.get(0x24..0x28).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
         let s_feature_incompat = (bs.get(0x28..0x2c).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
         let s_feature_ro_compat = (bs.get(0x2c..0x30).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
+        let s_uuid = sub_bytes(&bs, 0x30..0x40)?.to_vec();
+        let s_nr_users = (bs.get(0x40..0x44).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
+        let s_checksum_type = (bs.get(0x50..0x51).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u8();
+        let s_num_fc_blks = (bs.get(0x54..0x58).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
+        let s_checksum = (bs.get(0xfc..0x100).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
+        let compat = match ext4.get_super_block() {
+            Ok(sb) => sb.get_feature_compat(),
+            Err(_) => 0,
+        };
+        let fast_commit = s_feature_incompat & JBD2_FEATURE_INCOMPAT_FAST_COMMIT != 0
+            || compat & EXT4_FEATURE_COMPAT_FAST_COMMIT != 0;
         Ok(Self {
             header,
             s_blocksize,
             s_maxlen,
             s_first,
+            s_sequence,
+            s_start,
             s_errno,
             s_max_transaction,
             s_max_trans_data,
             s_feature_compat,
             s_feature_incompat,
             s_feature_ro_compat,
+            s_uuid,
+            s_nr_users,
+            s_checksum_type,
+            s_num_fc_blks,
+            s_checksum,
+            fast_commit,
         })
     }
 
     pub fn get_feature_incompat(&self) -> u32 {
         self.s_feature_incompat
     }
+
+    pub fn has_fast_commit(&self) -> bool {
+        self.fast_commit
+    }
+
+    //One beyond the last block of the regular log, the fast commit area follows it
+    pub fn get_log_end(&self) -> u32 {
+        if !self.has_fast_commit() {
+            return self.s_maxlen;
+        }
+        let num_fc_blks = if self.s_num_fc_blks == 0 {
+            JBD2_DEFAULT_FAST_COMMIT_BLOCKS
+        } else {
+            self.s_num_fc_blks
+        };
+        if self.s_maxlen.saturating_sub(num_fc_blks) >= JBD2_MIN_JOURNAL_BLOCKS {
+            self.s_maxlen - num_fc_blks
+        } else {
+            self.s_maxlen
+        }
+    }
+
+    //Fast commit blocks as in jbd2, from j_last + 1 up to s_maxlen
+    pub fn get_fast_commit_blocks(&self) -> Range<u32> {
+        if self.get_log_end() == self.s_maxlen {
+            return self.s_maxlen..self.s_maxlen;
+        }
+        self.get_log_end() + 1..self.s_maxlen
+    }
+
+    pub fn get_uuid(&self) -> &Vec<u8> {
+        &self.s_uuid
+    }
+
+    pub fn has_csum_v2or3(&self) -> bool {
+        self.s_feature_incompat & (JBD2_FEATURE_INCOMPAT_CSUM_V2 | JBD2_FEATURE_INCOMPAT_CSUM_V3) != 0
+    }
+
+    pub fn get_csum_seed(&self) -> u32 {
+        ext4_chksum(!0, &self.s_uuid)
+    }
+
+    //Checksum over the 1024 bytes super block with s_checksum zeroed
+    pub fn calc_checksum(&self, raw: &[u8]) -> u32 {
+        let csum = ext4_chksum(!0, &raw[..0xfc]);
+        let csum = ext4_chksum(csum, &[0, 0, 0, 0]);
+        ext4_chksum(csum, &raw[0x100..0x400.min(raw.len())])
+    }
+
+    //Descriptor and revoke blocks keep a jbd2_journal_block_tail in the last 4 bytes
+    pub fn calc_tail_checksum(&self, block: &[u8]) -> (u32, u32) {
+        let len = block.len();
+        let stored = (&block[len - 4..]).get_u32();
+        let csum = ext4_chksum(self.get_csum_seed(), &block[..len - 4]);
+        (stored, ext4_chksum(csum, &[0, 0, 0, 0]))
+    }
+
+    //Commit blocks keep the checksum in h_chksum[0]
+    pub fn calc_commit_checksum(&self, block: &[u8]) -> (u32, u32) {
+        let stored = (&block[0x10..0x14]).get_u32();
+        let csum = ext4_chksum(self.get_csum_seed(), &block[..0x10]);
+        let csum = ext4_chksum(csum, &[0, 0, 0, 0]);
+        (stored, ext4_chksum(csum, &block[0x14..]))
+    }
 }
 
 impl CommitBlock {
@@ -359,17 +879,174 @@ impl CommitBlock {
             commit_nsec: (bs.get(0x38..0x3c).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32(),
         })
     }
+
+    pub fn get_commit_sec(&self) -> u64 {
+        self.commit_sec
+    }
+
+    pub fn get_commit_nsec(&self) -> u32 {
+        self.commit_nsec
+    }
+
+    #[allow(deprecated)]
+    pub fn get_commit_time(&self) -> NaiveDateTime {
+        NaiveDateTime::from_timestamp_opt(self.commit_sec as i64, self.commit_nsec)
+            .unwrap_or_default()
+    }
+}
+
+impl RevocationBlock {
+    pub fn parse(bs: Bytes, sb: &JournalSuperBlock) -> Result<Self, MRError> {
+        let header = JournalHeader::parse(bs.slice(0..0xc))?;
+        if header.h_magic != JBD2_MAGIC_NUMBER || header.h_blocktype != JBD2_REVOKE_BLOCK {
+            return Err(MRError::new("Not a valid revoke block"));
+        }
+        let r_count = (bs.get(0xc..0x10).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32();
+        //r_count is the number of used bytes including the 16 bytes header
+        let end = (r_count as usize).min(bs.len());
+        let record_size = if sb.get_feature_incompat() & JBD2_FEATURE_INCOMPAT_64BIT != 0 {
+            8
+        } else {
+            4
+        };
+        let mut blocks = vec![];
+        let mut base_addr = 0x10;
+        while base_addr + record_size <= end {
+            let mut record = sub_bytes(&bs, base_addr..base_addr + record_size)?;
+            if record_size == 8 {
+                blocks.push(record.get_u64());
+            } else {
+                blocks.push(record.get_u32() as u64);
+            }
+            base_addr += record_size;
+        }
+        Ok(Self {
+            header,
+            r_count,
+            blocks,
+        })
+    }
+
+    pub fn get_blocks(&self) -> &Vec<u64> {
+        &self.blocks
+    }
+}
+
+impl JournalTransaction {
+    pub fn get_sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    pub fn get_data_blocks(&self) -> &Vec<JournalDataBlock> {
+        &self.data_blocks
+    }
+
+    pub fn get_revocation_blocks(&self) -> &Vec<RevocationBlock> {
+        &self.revocation_blocks
+    }
+
+    pub fn get_commit_block(&self) -> &CommitBlock {
+        &self.commit_block
+    }
+
+    pub fn get_commit_time(&self) -> NaiveDateTime {
+        self.commit_block.get_commit_time()
+    }
+}
+
+impl FastCommitTag {
+    //`offset` is the device offset of the tag value
+    pub fn parse(fc_tag: u16, value: &Bytes, offset: usize) -> Result<Self, MRError> {
+        let inode = sub_bytes(value, 0..4)?.get_u32_le();
+        match fc_tag {
+            EXT4_FC_TAG_ADD_RANGE => {
+                let extent = Extent::parse(&value.slice(4.min(value.len())..))?;
+                Ok(Self::AddRange {
+                    inode,
+                    lblk: extent.ee_block,
+                    //Lengths above 32768 mark unwritten extents
+                    len: if extent.ee_len > 0x8000 {
+                        extent.ee_len - 0x8000
+                    } else {
+                        extent.ee_len
+                    },
+                    pblk: extent.get_start() as u64,
+                })
+            }
+            EXT4_FC_TAG_DEL_RANGE => Ok(Self::DelRange {
+                inode,
+                lblk: sub_bytes(value, 4..8)?.get_u32_le(),
+                len: sub_bytes(value, 8..12)?.get_u32_le(),
+            }),
+            EXT4_FC_TAG_CREAT | EXT4_FC_TAG_LINK | EXT4_FC_TAG_UNLINK => {
+                //fc_parent_ino comes first in ext4_fc_dentry_info
+                let parent = inode;
+                let inode = sub_bytes(value, 4..8)?.get_u32_le();
+                let name = String::from_utf8_lossy(sub_bytes(value, 8..value.len())?).to_string();
+                Ok(match fc_tag {
+                    EXT4_FC_TAG_CREAT => Self::Create { parent, inode, name },
+                    EXT4_FC_TAG_LINK => Self::Link { parent, inode, name },
+                    _ => Self::Unlink { parent, inode, name },
+                })
+            }
+            EXT4_FC_TAG_INODE => Ok(Self::Inode {
+                inode,
+                offset: offset + 4,
+                raw: value[4..].to_vec(),
+            }),
+            _ => Err(MRError::new("Unknown fast commit tag")),
+        }
+    }
+}
+
+impl FastCommitRecord {
+    pub fn get_tid(&self) -> u32 {
+        self.tid
+    }
+
+    pub fn get_blocknr(&self) -> u32 {
+        self.blocknr
+    }
+
+    pub fn get_tag(&self) -> &FastCommitTag {
+        &self.tag
+    }
 }
 
 impl JournalDataBlock {
-    pub fn new(block_id: u64, range: Range<usize>) -> Self {
+    pub fn new(block_id: u64, range: Range<usize>, flag: u32, sequence: u32) -> Self {
         Self {
             range,
             block_id,
+            flag,
+            sequence,
+            revoked: false,
         }
     }
 
     pub fn get_range(&self) -> Range<usize> {
         self.range.clone()
     }
+
+    pub fn get_block_id(&self) -> u64 {
+        self.block_id
+    }
+
+    pub fn get_sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    //The first 4 bytes of the logged copy were zeroed because they matched the jbd2 magic
+    pub fn is_escaped(&self) -> bool {
+        self.flag & JBD2_FLAG_ESCAPE == JBD2_FLAG_ESCAPE
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.flag & JBD2_FLAG_DELETED == JBD2_FLAG_DELETED
+    }
+
+    //A later revoke record forbids replaying this copy
+    pub fn is_revoked(&self) -> bool {
+        self.revoked
+    }
 }
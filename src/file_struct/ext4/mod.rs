@@ -1,4 +1,4 @@
-use std::{cell::{Cell, RefCell, UnsafeCell}, ops::Range};
+use std::{cell::{Cell, OnceCell, RefCell, UnsafeCell}, collections::HashMap, ops::Range};
 
 use bytes::Bytes;
 
@@ -8,12 +8,31 @@ pub mod group_descriptor_impl;
 pub mod inode_table_impl;
 pub mod journal_impl;
 pub mod fs_impl;
+pub mod checksum_impl;
+pub mod whose_impl;
+pub mod bitmap_impl;
+
+pub const EXT4_FEATURE_COMPAT_HAS_JOURNAL       : u32 = 0x4;
+pub const EXT4_FEATURE_COMPAT_FAST_COMMIT       : u32 = 0x400;
+pub const EXT4_FEATURE_COMPAT_SPARSE_SUPER2     : u32 = 0x200;
+pub const EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER   : u32 = 0x1;
+pub const EXT4_FEATURE_RO_COMPAT_GDT_CSUM       : u32 = 0x10;
+pub const EXT4_FEATURE_RO_COMPAT_METADATA_CSUM  : u32 = 0x400;
+pub const EXT4_FEATURE_INCOMPAT_JOURNAL_DEV     : u32 = 0x8;
+pub const EXT4_FEATURE_INCOMPAT_META_BG         : u32 = 0x10;
+pub const EXT4_FEATURE_INCOMPAT_64BIT           : u32 = 0x80;
+pub const EXT4_FEATURE_INCOMPAT_CSUM_SEED       : u32 = 0x2000;
+
 #[derive(Debug,Default)]
 pub struct Ext4 {
     reader                  : Option<MRFile>,
     super_block             : RefCell<Option<SuperBlock>>,
     group_descriptors       : RefCell<Option<Vec<GroupDescriptor>>>,
-    block_size              : Cell<usize>
+    block_size              : Cell<usize>,
+    //Reject structures whose metadata_csum does not match
+    strict                  : Cell<bool>,
+    //External journal device referenced by s_journal_uuid
+    journal_reader          : Option<MRFile>
 }
 
 #[derive(Debug,Default, Clone, Copy)]
@@ -22,17 +41,28 @@ pub struct SuperBlock {
     pub s_block_count       : u32,          //0x4  
     pub s_log_block_size    : u32,          //0x18
     pub s_log_cluster_size  : u32,          //0x1c
+    s_first_data_block      : u32,          //0x14
     s_blocks_per_group      : u32,          //0x20
     s_clusters_per_group    : u32,          //0x24
     s_inodes_per_group      : u32,          //0x28
     s_creator_os            : u32,          //0x48
-    s_inode_size            : u16,
-    s_uuid                  : [u8;16],
+    s_inode_size            : u16,          //0x58
+    s_feature_compat        : u32,          //0x5c
+    s_feature_incompat      : u32,          //0x60
+    s_feature_ro_compat     : u32,          //0x64
+    s_uuid                  : [u8;16],      //0x68
     s_volume_name           : [char;16],
     s_encrypt_algos         : u8,
-    s_checksum              : u32,
+    s_checksum_type         : u8,           //0x175
+    s_checksum_seed         : u32,          //0x270
+    s_checksum              : u32,          //0x3fc
     s_desc_size             : u16,
     s_reserved_gdt_blocks   : u16,
+    s_first_meta_bg         : u32,          //0x104
+    s_backup_bgs            : [u32;2],      //0x24c
+    s_journal_uuid          : [u8;16],      //0xd0
+    s_journal_inum          : u32,          //0xe0
+    s_journal_dev           : u32,          //0xe4
     s_log_groups_per_flex   : u8,
     is_64bit                : bool
 }
@@ -51,7 +81,14 @@ pub struct GroupDescriptor {
     bg_free_inodes_count_hi : u16,
     bg_used_dirs_count_lo   : u16,
     bg_used_dirs_count_hi   : u16,
-
+    bg_flags                : u16,
+    bg_block_bitmap_csum_lo : u16,
+    bg_block_bitmap_csum_hi : u16,
+    bg_inode_bitmap_csum_lo : u16,
+    bg_inode_bitmap_csum_hi : u16,
+    bg_checksum             : u16,
+
+    group                   : u32,
     ext4_to_self            : Option<*const Ext4>,
     is_64bit                : bool
 }
@@ -172,6 +209,8 @@ pub struct Inode {
     i_version_hi        : u32,
     i_projid            : u32,
 
+    //Inode number, 0 if the inode was not looked up by id (e.g. jbd2 copies)
+    i_num               : u32,
     ext4                : Option<*const Ext4>,
     base_addr           : u64
 }
@@ -202,13 +241,22 @@ pub struct JournalSuperBlock {
     s_blocksize     : u32,
     s_maxlen        : u32,
     s_first         : u32,
+    s_sequence      : u32,
+    s_start         : u32,
     s_errno         : u32,
     s_max_transaction   : u32,
     s_max_trans_data    : u32,
     s_feature_compat    : u32,
     s_feature_incompat  : u32,
     s_feature_ro_compat	: u32,
-
+    s_uuid              : Vec<u8>,
+    s_nr_users          : u32,
+    s_checksum_type     : u8,
+    s_num_fc_blks       : u32,
+    s_checksum          : u32,
+
+    //jbd2 only flags fast commits once mounted, the ext4 compat feature is set by mkfs
+    fast_commit         : bool,
 }
 
 #[derive(Debug)]
@@ -216,6 +264,7 @@ pub struct JournalBlockTag {
     blocknr         : u32,
     flag            : u32,
     blocknr_high    : u32,
+    checksum        : u32,
     size            : usize,
     uuid            : Vec<u8>,
 }
@@ -224,7 +273,7 @@ pub struct JournalBlockTag {
 pub struct RevocationBlock {
     header      : JournalHeader,
     r_count     : u32,
-    blocks      : u64
+    blocks      : Vec<u64>
 }
 
 #[derive(Debug)]
@@ -243,26 +292,95 @@ pub struct JournalDescriptorBlock {
 #[derive(Debug, Clone)]
 pub struct JournalDataBlock {
     range       : Range<usize>,
-    block_id    : u64
+    block_id    : u64,
+    flag        : u32,
+    sequence    : u32,
+    revoked     : bool
 }
 
 
 
 #[derive(Debug)]
 pub struct JournalTransaction {
-    desc_block          : JournalDescriptorBlock,
+    sequence            : u32,
     data_blocks         : Vec<JournalDataBlock>,
     revocation_blocks   : Vec<RevocationBlock>,
     commit_block        : CommitBlock
 }
 
-#[derive(Debug)]
-pub struct JournalTransactionIteration {
-    transaction         : JournalTransaction
+#[derive(Debug, Clone)]
+pub enum FastCommitTag {
+    AddRange { inode: u32, lblk: u32, len: u16, pblk: u64 },
+    DelRange { inode: u32, lblk: u32, len: u32 },
+    Create { parent: u32, inode: u32, name: String },
+    Link { parent: u32, inode: u32, name: String },
+    Unlink { parent: u32, inode: u32, name: String },
+    //Byte offset of the raw inode copy on the device
+    Inode { inode: u32, offset: usize, raw: Vec<u8> },
+}
+
+#[derive(Debug, Clone)]
+pub struct FastCommitRecord {
+    tid         : u32,
+    blocknr     : u32,
+    tag         : FastCommitTag
 }
 
 pub struct Journal {
     super_block     : JournalSuperBlock,
     ext4            : Option<*const Ext4>,
-    offset          : usize
-}
\ No newline at end of file
+    //Blocks are read from the external journal device instead of the filesystem
+    external        : bool,
+    //Byte offset of the jbd2 super block
+    offset          : usize,
+    //Journal blocks as (logical block, physical block, length) taken from the journal inode
+    extents         : Vec<(u64, u64, u64)>,
+    transactions    : OnceCell<Vec<JournalTransaction>>,
+    fast_commits    : OnceCell<Vec<FastCommitRecord>>
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetadataStructure {
+    SuperBlock,
+    GroupDescriptor,
+    BlockBitmap,
+    InodeBitmap,
+    Inode,
+    ExtentBlock,
+    DirectoryBlock,
+    JournalSuperBlock,
+    JournalDescriptor,
+    JournalCommit,
+    JournalRevoke,
+    JournalData
+}
+
+#[derive(Debug, Clone)]
+pub struct ChecksumMismatch {
+    structure       : MetadataStructure,
+    group           : Option<u32>,
+    inode           : Option<u32>,
+    block           : u64,
+    stored          : u32,
+    computed        : u32
+}
+
+//Physical blocks of an inode
+#[derive(Debug, Clone)]
+pub struct InodeExtent {
+    start           : u64,
+    len             : u64,
+    //Logical block of the first block
+    block           : u64,
+    inode           : u32
+}
+
+//Blocks of the inodes in use, see whose
+#[derive(Debug, Default)]
+pub struct BlockMap {
+    //Sorted by start
+    extents         : Vec<InodeExtent>,
+    //Extent tree nodes and xattr blocks as (block, inode, structure), sorted
+    nodes           : Vec<(u64, u32, &'static str)>,
+    sizes           : HashMap<u32, u64>,
+    paths           : HashMap<u32, String>
+}
@@ -0,0 +1,351 @@
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    file_struct::{BlockOwner, FileSlack},
+    utils::MRError,
+};
+
+use super::{
+    bitmap_impl::is_bit_set, BlockMap, Ext4, FileType, InodeExtent, SuperBlock,
+    EXT4_FEATURE_COMPAT_SPARSE_SUPER2, EXT4_FEATURE_INCOMPAT_META_BG,
+    EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER,
+};
+
+const ROOT_INODE: u32 = 2;
+//Inodes below are reserved for the file system
+const FIRST_INODE: u32 = 11;
+const BOOT_BLOCK_SIZE: u64 = 1024;
+
+fn reserved_inode_name(id: u32) -> String {
+    match id {
+        1 => "bad blocks".to_string(),
+        3 => "user quota".to_string(),
+        4 => "group quota".to_string(),
+        5 => "boot loader".to_string(),
+        6 => "undelete directory".to_string(),
+        7 => "resize inode".to_string(),
+        8 => "journal".to_string(),
+        9 => "exclude inode".to_string(),
+        10 => "replica inode".to_string(),
+        _ => format!("reserved inode {}", id),
+    }
+}
+
+//Only groups 0, 1 and powers of 3, 5 and 7 keep a super block with sparse_super
+fn is_sparse_group(group: u64) -> bool {
+    if group <= 1 {
+        return true;
+    }
+    [3, 5, 7].iter().any(|&base| {
+        let mut n = base;
+        while n < group {
+            n *= base;
+        }
+        n == group
+    })
+}
+
+impl SuperBlock {
+    //sparse_super2 keeps at most two backups, in the groups of s_backup_bgs
+    pub fn has_super_block(&self, group: u64) -> bool {
+        if group == 0 {
+            return true;
+        }
+        if self.s_feature_compat & EXT4_FEATURE_COMPAT_SPARSE_SUPER2 != 0 {
+            return self.s_backup_bgs.iter().any(|&x| x as u64 == group);
+        }
+        if self.s_feature_ro_compat & EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER == 0 {
+            return true;
+        }
+        is_sparse_group(group)
+    }
+
+    //Group descriptor and reserved descriptor blocks of a group, and whether they are the
+    //primary copy. With meta_bg the descriptors of a meta group are kept in its first,
+    //second and last group instead of after every super block.
+    pub fn get_gdt_blocks(&self, group: u64, group_count: u64, block_size: u64) -> (Range<u64>, Range<u64>, bool) {
+        let group_start = self.s_first_data_block as u64 + group * self.s_blocks_per_group as u64;
+        let has_super = self.has_super_block(group) as u64;
+        let desc_per_block = (block_size / self.s_desc_size as u64).max(1);
+        let meta_group = group / desc_per_block;
+        let meta_bg = self.s_feature_incompat & EXT4_FEATURE_INCOMPAT_META_BG != 0;
+        if meta_bg && meta_group >= self.s_first_meta_bg as u64 {
+            let index = group % desc_per_block;
+            if index == 0 || index == 1 || index == desc_per_block - 1 {
+                let start = group_start + has_super;
+                return (start..start + 1, start + 1..start + 1, index == 0);
+            }
+            return (0..0, 0..0, false);
+        }
+        if has_super == 0 {
+            return (0..0, 0..0, false);
+        }
+        let desc_blocks = match meta_bg {
+            true => self.s_first_meta_bg as u64,
+            false => (group_count * self.s_desc_size as u64).div_ceil(block_size),
+        };
+        let start = group_start + 1;
+        let reserved = match meta_bg {
+            true => 0,
+            false => self.s_reserved_gdt_blocks as u64,
+        };
+        (
+            start..start + desc_blocks,
+            start + desc_blocks..start + desc_blocks + reserved,
+            group == 0,
+        )
+    }
+}
+
+impl InodeExtent {
+    pub fn get_start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn get_len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn get_block(&self) -> u64 {
+        self.block
+    }
+
+    pub fn get_inode(&self) -> u32 {
+        self.inode
+    }
+}
+
+impl BlockMap {
+    pub fn get_extents(&self) -> &Vec<InodeExtent> {
+        &self.extents
+    }
+
+    pub fn get_path(&self, inode: u32) -> Option<&String> {
+        self.paths.get(&inode)
+    }
+
+    pub fn get_size(&self, inode: u32) -> Option<u64> {
+        self.sizes.get(&inode).copied()
+    }
+
+    //Extent holding a physical block
+    pub fn find_extent(&self, block: u64) -> Option<&InodeExtent> {
+        let i = self.extents.partition_point(|x| x.start <= block);
+        let extent = self.extents.get(i.checked_sub(1)?)?;
+        if block < extent.start + extent.len {
+            return Some(extent);
+        }
+        None
+    }
+
+    pub fn get_display_path(&self, inode: u32) -> String {
+        match self.paths.get(&inode) {
+            Some(s) => s.to_string(),
+            None => format!("<{}>", inode),
+        }
+    }
+
+    //Bytes past the size of every inode in its blocks, uninitialized extents included
+    pub fn get_slacks(&self, block_size: u64) -> Vec<FileSlack> {
+        let mut result = vec![];
+        for extent in &self.extents {
+            let size = match self.sizes.get(&extent.inode) {
+                Some(s) => *s,
+                None => continue,
+            };
+            let start = extent.block * block_size;
+            let length = extent.len * block_size;
+            if start + length <= size {
+                continue;
+            }
+            let skip = size.saturating_sub(start);
+            result.push(FileSlack::new(
+                extent.start * block_size + skip,
+                length - skip,
+                extent.inode as u64,
+                self.get_display_path(extent.inode),
+            ));
+        }
+        result
+    }
+
+    pub fn find_node(&self, block: u64) -> Option<(u32, &'static str)> {
+        let i = self.nodes.binary_search_by_key(&block, |x| x.0).ok()?;
+        Some((self.nodes[i].1, self.nodes[i].2))
+    }
+}
+
+impl Ext4 {
+    //Blocks of every inode in use from the inode bitmaps, paths from the directory tree
+    pub fn build_block_map(&self) -> Result<BlockMap, MRError> {
+        let mut result = BlockMap::default();
+        let inodes_per_group = self.get_s_inodes_per_group()?;
+        for desc in self.get_descs()? {
+            if desc.is_inode_uninit() {
+                continue;
+            }
+            let bits = self.get_reader().read_range(desc.get_inode_bitmap())?;
+            for i in 0..inodes_per_group {
+                if !is_bit_set(&bits, i as u64) {
+                    continue;
+                }
+                let id = desc.get_group() * inodes_per_group + i + 1;
+                let inode = match desc.get_inode(id) {
+                    Ok(o) => o,
+                    Err(_) => continue,
+                };
+                if inode.i_file_acl_lo != 0 {
+                    result.nodes.push((inode.i_file_acl_lo as u64, id, "extended attributes"));
+                }
+                let (extents, nodes) = match inode.get_extents_with_nodes() {
+                    Ok(o) => o,
+                    Err(_) => continue,
+                };
+                let node_name = match inode.is_extents() {
+                    true => "extent tree",
+                    false => "indirect block",
+                };
+                result.sizes.insert(id, inode.get_size());
+                result.nodes.extend(nodes.into_iter().map(|x| (x, id, node_name)));
+                result.extents.extend(extents.iter().map(|x| InodeExtent {
+                    start: x.get_start() as u64,
+                    len: x.get_real_len() as u64,
+                    block: x.get_block() as u64,
+                    inode: id,
+                }));
+            }
+        }
+        result.extents.sort_by_key(|x| x.start);
+        result.nodes.sort_by_key(|x| x.0);
+        self.collect_paths(&mut result.paths);
+        Ok(result)
+    }
+
+    //First path of every inode reachable from the root
+    fn collect_paths(&self, paths: &mut HashMap<u32, String>) {
+        paths.insert(ROOT_INODE, "/".to_string());
+        let mut stack = vec![(ROOT_INODE, String::new())];
+        while let Some((id, path)) = stack.pop() {
+            let inode = match self.get_inode_by_id(id) {
+                Ok(o) => o,
+                Err(_) => continue,
+            };
+            let entries = match inode.get_sub_dirs() {
+                Ok(o) => o,
+                Err(_) => continue,
+            };
+            for entry in entries {
+                let name = entry.get_name();
+                if name.eq(".") || name.eq("..") || paths.contains_key(&entry.get_id()) {
+                    continue;
+                }
+                let child = format!("{}/{}", path, name);
+                paths.insert(entry.get_id(), child.clone());
+                if entry.get_f_type().eq(&FileType::Directory) {
+                    stack.push((entry.get_id(), child));
+                }
+            }
+        }
+    }
+
+    //Super blocks, group descriptors, bitmaps and inode tables
+    fn whose_metadata(&self, offset: u64) -> Result<Option<BlockOwner>, MRError> {
+        let metadata = |name: String, record: Option<u64>, offset: u64| {
+            Ok(Some(BlockOwner::Metadata { name, record, offset }))
+        };
+        if offset < BOOT_BLOCK_SIZE {
+            return metadata("boot block".to_string(), None, offset);
+        }
+        let sb = self.get_super_block()?;
+        let block_size = self.get_block_size() as u64;
+        let block = offset / block_size;
+        let descs = self.get_descs()?;
+        let group = (block.saturating_sub(sb.s_first_data_block as u64)) / sb.s_blocks_per_group as u64;
+        let group_start = sb.s_first_data_block as u64 + group * sb.s_blocks_per_group as u64;
+        let backup = |primary: bool| match primary {
+            true => String::new(),
+            false => format!(" (backup, group {})", group),
+        };
+        if sb.has_super_block(group) && block == group_start {
+            //The primary super block sits after the boot block
+            let start = match group {
+                0 => BOOT_BLOCK_SIZE,
+                _ => group_start * block_size,
+            };
+            return metadata(format!("super block{}", backup(group == 0)), None, offset - start);
+        }
+        let (gdt, reserved, primary) = sb.get_gdt_blocks(group, descs.len() as u64, block_size);
+        if gdt.contains(&block) {
+            return metadata(format!("group descriptors{}", backup(primary)), None, offset - gdt.start * block_size);
+        }
+        if reserved.contains(&block) {
+            return metadata(
+                format!("reserved group descriptors{}", backup(primary)),
+                None,
+                offset - reserved.start * block_size,
+            );
+        }
+
+        let inode_size = self.get_s_inode_size() as u64;
+        let inodes_per_group = sb.s_inodes_per_group as u64;
+        let offset = offset as usize;
+        for desc in descs {
+            let group = desc.get_group();
+            let range = desc.get_block_bitmap();
+            if range.contains(&offset) {
+                return metadata(format!("block bitmap (group {})", group), None, (offset - range.start) as u64);
+            }
+            let range = desc.get_inode_bitmap();
+            if range.contains(&offset) {
+                return metadata(format!("inode bitmap (group {})", group), None, (offset - range.start) as u64);
+            }
+            let start = desc.get_inode_table() * block_size;
+            let distance = (offset as u64).wrapping_sub(start);
+            if distance < inodes_per_group * inode_size {
+                let record = group as u64 * inodes_per_group + distance / inode_size + 1;
+                return metadata(format!("inode table (group {})", group), Some(record), distance % inode_size);
+            }
+        }
+        Ok(None)
+    }
+
+    //Owner of a byte offset of the file system
+    pub fn whose(&self, map: &BlockMap, offset: u64) -> Result<BlockOwner, MRError> {
+        let block_size = self.get_block_size() as u64;
+        if offset >= self.get_total_size()? {
+            return Err(MRError::new("Offset past the end of the file system"));
+        }
+        if let Some(owner) = self.whose_metadata(offset)? {
+            return Ok(owner);
+        }
+
+        let block = offset / block_size;
+        if let Some(extent) = map.find_extent(block) {
+            let id = extent.inode;
+            let logical = (extent.block + block - extent.start) * block_size + offset % block_size;
+            if id < FIRST_INODE && id != ROOT_INODE {
+                return Ok(BlockOwner::Metadata { name: reserved_inode_name(id), record: None, offset: logical });
+            }
+            let path = map.get_display_path(id);
+            return Ok(BlockOwner::File {
+                id: id as u64,
+                path,
+                stream: String::new(),
+                offset: logical,
+                slack: logical >= map.get_size(id).unwrap_or(u64::MAX),
+            });
+        }
+        if let Some((id, name)) = map.find_node(block) {
+            return Ok(BlockOwner::Metadata {
+                name: format!("{} of inode {}", name, id),
+                record: Some(id as u64),
+                offset: offset % block_size,
+            });
+        }
+
+        match self.is_block_allocated(block)? {
+            true => Ok(BlockOwner::Unknown),
+            false => Ok(BlockOwner::Unallocated),
+        }
+    }
+}
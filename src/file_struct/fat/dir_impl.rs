@@ -0,0 +1,283 @@
+use std::ops::Range;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+use crate::utils::MRError;
+
+use super::{
+    fat_impl::{le_u16, le_u32},
+    DirectoryEntry, Fat, FatType, ATTR_DIRECTORY, ATTR_LONG_NAME, ATTR_VOLUME_ID, DIR_ENTRY_SIZE, FAT_DELETED,
+    FAT_KANJI_E5, LAST_LONG_ENTRY,
+};
+
+//Characters of a long name entry, 5, 6 and 2 of them
+const LONG_NAME_CHARS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+//Characters allowed in a short name besides letters and digits
+const SHORT_NAME_SPECIALS: &[u8] = b"!#$%&'()-@^_`{}~";
+
+//Long name entries waiting for their short entry, they are stored from the last part
+struct LongName {
+    checksum    : u8,
+    deleted     : bool,
+    parts       : Vec<Vec<u16>>
+}
+
+//Date and time in local time, 2 seconds steps plus hundredths
+pub fn dos_time(date: u16, time: u16, centis: u8) -> NaiveDateTime {
+    let day = NaiveDate::from_ymd_opt(1980 + (date >> 9) as i32, ((date >> 5) & 0xf) as u32, (date & 0x1f) as u32);
+    let day_time = day.and_then(|x| {
+        x.and_hms_opt((time >> 11) as u32, ((time >> 5) & 0x3f) as u32, (time & 0x1f) as u32 * 2)
+    });
+    match day_time {
+        Some(s) => s + Duration::milliseconds(centis as i64 * 10),
+        None => NaiveDateTime::default(),
+    }
+}
+
+pub fn short_name_checksum(name: &[u8]) -> u8 {
+    name.iter().take(11).fold(0u8, |sum, x| sum.rotate_right(1).wrapping_add(*x))
+}
+
+//Base and extension padded with spaces, NT flags in byte 12 ask for lower case
+fn short_name_string(name: &[u8], case: u8) -> String {
+    let mut base = String::from_utf8_lossy(&name[0..8]).trim_end().to_string();
+    let mut ext = String::from_utf8_lossy(&name[8..11]).trim_end().to_string();
+    if case & 0x08 != 0 {
+        base = base.to_lowercase();
+    }
+    if case & 0x10 != 0 {
+        ext = ext.to_lowercase();
+    }
+    match ext.is_empty() {
+        true => base,
+        false => format!("{}.{}", base, ext),
+    }
+}
+
+impl LongName {
+    fn get_name(&self) -> String {
+        let chars: Vec<u16> = self.parts.iter().rev().flatten().copied().take_while(|x| *x != 0).collect();
+        String::from_utf16_lossy(&chars)
+    }
+
+    //The first byte of a deleted short name is lost, the checksum kept by the long name tells it
+    //back, the first letter of the long name is tried before the others
+    fn guess_first_char(&self, name: &[u8]) -> Option<u8> {
+        let first = self.parts.last()?.first().copied().unwrap_or(0);
+        let mut candidates = vec![];
+        if first < 0x80 {
+            candidates.push((first as u8).to_ascii_uppercase());
+        }
+        candidates.extend(b'A'..=b'Z');
+        candidates.extend(b'0'..=b'9');
+        candidates.extend(SHORT_NAME_SPECIALS);
+        let mut name = name.to_vec();
+        candidates.into_iter().find(|x| {
+            name[0] = *x;
+            short_name_checksum(&name) == self.checksum
+        })
+    }
+}
+
+impl DirectoryEntry {
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn get_short_name(&self) -> &String {
+        &self.short_name
+    }
+
+    pub fn get_attributes(&self) -> u16 {
+        self.attributes
+    }
+
+    pub fn get_first_cluster(&self) -> u32 {
+        self.first_cluster
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn get_ctime(&self) -> NaiveDateTime {
+        self.ctime
+    }
+
+    pub fn get_mtime(&self) -> NaiveDateTime {
+        self.mtime
+    }
+
+    pub fn get_atime(&self) -> NaiveDateTime {
+        self.atime
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn is_contiguous(&self) -> bool {
+        self.contiguous
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attributes & ATTR_DIRECTORY != 0
+    }
+
+    pub fn is_dot(&self) -> bool {
+        self.name == "." || self.name == ".."
+    }
+}
+
+//Byte of the volume at pos of the data read from the runs
+pub(super) fn offset_in_runs(runs: &[Range<u64>], pos: usize) -> u64 {
+    let mut rest = pos as u64;
+    for run in runs {
+        if rest < run.end - run.start {
+            return run.start + rest;
+        }
+        rest -= run.end - run.start;
+    }
+    0
+}
+
+impl Fat {
+    //Live entries of a directory, without . and ..
+    pub fn get_dir_entries(&self, dir: &DirectoryEntry) -> Result<Vec<DirectoryEntry>, MRError> {
+        if !dir.is_dir() {
+            return Err(MRError::new("Not a dir"));
+        }
+        let runs = self.get_runs(dir)?;
+        let entries = self.parse_dir_runs(&runs, false)?;
+        Ok(entries.into_iter().filter(|x| !x.is_dot()).collect())
+    }
+
+    pub fn get_deleted_dir_entries(&self, dir: &DirectoryEntry) -> Result<Vec<DirectoryEntry>, MRError> {
+        if !dir.is_dir() {
+            return Err(MRError::new("Not a dir"));
+        }
+        let runs = self.get_runs(dir)?;
+        self.parse_dir_runs(&runs, true)
+    }
+
+    //Entries of the directory data in runs, the deleted ones or the others
+    pub fn parse_dir_runs(&self, runs: &[Range<u64>], deleted: bool) -> Result<Vec<DirectoryEntry>, MRError> {
+        let len = runs.iter().map(|x| x.end - x.start).sum();
+        let bs = self.read_runs(runs, 0, len)?;
+        Ok(match self.fat_type {
+            FatType::ExFat => self.parse_exfat_dir(&bs, runs, deleted),
+            _ => self.parse_fat_dir(&bs, runs, deleted),
+        })
+    }
+
+    //Long name entries come right before their short entry, their last part first
+    fn parse_fat_dir(&self, bs: &[u8], runs: &[Range<u64>], deleted: bool) -> Vec<DirectoryEntry> {
+        let mut result = vec![];
+        let mut long: Option<LongName> = None;
+        for pos in (0..bs.len() / DIR_ENTRY_SIZE).map(|x| x * DIR_ENTRY_SIZE) {
+            let e = &bs[pos..pos + DIR_ENTRY_SIZE];
+            //Nothing was ever written from here
+            if e[0] == 0 {
+                break;
+            }
+            let is_deleted = e[0] == FAT_DELETED;
+            if e[11] & 0x3f == ATTR_LONG_NAME {
+                let chars = LONG_NAME_CHARS.iter().map(|x| le_u16(e, *x)).collect();
+                //A deleted part lost its order, a new checksum starts another name
+                let restart = match &long {
+                    Some(l) => l.checksum != e[13] || l.deleted != is_deleted || (!is_deleted && e[0] & LAST_LONG_ENTRY != 0),
+                    None => true,
+                };
+                if restart {
+                    long = Some(LongName { checksum: e[13], deleted: is_deleted, parts: vec![] });
+                }
+                if let Some(l) = long.as_mut() {
+                    l.parts.push(chars);
+                }
+                continue;
+            }
+            let long_name = long.take().filter(|x| x.deleted == is_deleted);
+            let attributes = e[11] as u16;
+            if is_deleted != deleted || attributes & ATTR_VOLUME_ID != 0 {
+                continue;
+            }
+            let mut short = e[0..11].to_vec();
+            if is_deleted {
+                short[0] = long_name.as_ref().and_then(|x| x.guess_first_char(&short)).unwrap_or(b'_');
+            } else if short[0] == FAT_KANJI_E5 {
+                short[0] = FAT_DELETED;
+            }
+            let short_name = short_name_string(&short, e[12]);
+            let name = match long_name {
+                Some(l) if short_name_checksum(&short) == l.checksum => l.get_name(),
+                _ => short_name.clone(),
+            };
+            //Only FAT32 keeps the high half of the cluster
+            let first_cluster = match self.fat_type {
+                FatType::Fat32 => (le_u16(e, 20) as u32) << 16 | le_u16(e, 26) as u32,
+                _ => le_u16(e, 26) as u32,
+            };
+            result.push(DirectoryEntry {
+                name,
+                short_name,
+                attributes,
+                first_cluster,
+                size: le_u32(e, 28) as u64,
+                ctime: dos_time(le_u16(e, 16), le_u16(e, 14), e[13]),
+                mtime: dos_time(le_u16(e, 24), le_u16(e, 22), 0),
+                atime: dos_time(le_u16(e, 18), 0, 0),
+                contiguous: false,
+                deleted: is_deleted,
+                offset: offset_in_runs(runs, pos),
+            });
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Characters of a long name part, padded with 0 and 0xffff
+    fn part(s: &str) -> Vec<u16> {
+        let mut chars: Vec<u16> = s.encode_utf16().collect();
+        if chars.len() < 13 {
+            chars.push(0);
+        }
+        chars.resize(13, 0xffff);
+        chars
+    }
+
+    #[test]
+    fn checksum() {
+        assert_eq!(short_name_checksum(b"FOO     BAR"), 0x53);
+        assert_eq!(short_name_checksum(b"REPORT  PDF"), 0x8a);
+        assert_eq!(short_name_string(b"FOO     BAR", 0x18), "foo.bar");
+        assert_eq!(short_name_string(b"README     ", 0), "README");
+    }
+
+    #[test]
+    fn long_name() {
+        //The last part comes first on disk
+        let long = LongName { checksum: 0x8e, deleted: false, parts: vec![part("Report.pdf"), part("Deleted Annua")] };
+        assert_eq!(long.get_name(), "Deleted AnnuaReport.pdf");
+        let long = LongName { checksum: 0, deleted: false, parts: vec![part("exactly13char")] };
+        assert_eq!(long.get_name(), "exactly13char");
+    }
+
+    #[test]
+    fn first_char() {
+        let long = LongName { checksum: 0x8a, deleted: true, parts: vec![part("Report.pdf")] };
+        assert_eq!(long.guess_first_char(b"\xe5EPORT  PDF"), Some(b'R'));
+        //Taken from the long name even when it starts with another letter
+        let long = LongName { checksum: 0x8e, deleted: true, parts: vec![part("Deleted Report.pdf")] };
+        assert_eq!(long.guess_first_char(b"\xe5ELETE~1PDF"), Some(b'D'));
+        let long = LongName { checksum: 0x8e ^ 0x55, deleted: true, parts: vec![part("x")] };
+        assert_eq!(long.guess_first_char(b"\xe5ELETE~1PDF"), None);
+    }
+}
@@ -0,0 +1,208 @@
+use std::ops::Range;
+
+use chrono::{Duration, NaiveDateTime};
+
+use crate::utils::MRError;
+
+use super::{
+    dir_impl::{dos_time, offset_in_runs},
+    fat_impl::{le_u16, le_u32, le_u64},
+    DirectoryEntry, Fat, DIR_ENTRY_SIZE, EXFAT_BITMAP, EXFAT_FILE, EXFAT_IN_USE, EXFAT_LABEL, EXFAT_NAME,
+    EXFAT_NO_FAT_CHAIN, EXFAT_STREAM, EXFAT_UPCASE,
+};
+
+//Characters in a name entry
+const NAME_CHARS: usize = 15;
+//The bitmap of the second FAT of a TexFAT volume has this flag
+const BITMAP_SECOND_FAT: u8 = 0x1;
+
+//Entries of a set sum up over every byte but the checksum itself, rotating right each time
+fn set_checksum(set: &[u8], deleted: bool) -> u16 {
+    let mut sum: u16 = 0;
+    for (i, b) in set.iter().enumerate() {
+        if i == 2 || i == 3 {
+            continue;
+        }
+        //Deleting a set only clears the in use bit of its types
+        let b = match deleted && i % DIR_ENTRY_SIZE == 0 {
+            true => b | EXFAT_IN_USE,
+            false => *b,
+        };
+        sum = sum.rotate_right(1).wrapping_add(b as u16);
+    }
+    sum
+}
+
+//Local time with its offset from UTC in 15 minutes steps when bit 7 is set, turned into UTC
+fn exfat_time(stamp: u32, centis: u8, utc_offset: u8) -> NaiveDateTime {
+    let time = dos_time((stamp >> 16) as u16, stamp as u16, centis);
+    match utc_offset & 0x80 {
+        0 => time,
+        _ => {
+            let quarters = ((utc_offset << 1) as i8 >> 1) as i64;
+            time - Duration::minutes(quarters * 15)
+        }
+    }
+}
+
+impl Fat {
+    //Bitmap, upcase table and label are entries of the root directory
+    pub(super) fn read_exfat_metadata(&mut self) -> Result<(), MRError> {
+        let root = self.get_root();
+        let runs = self.get_runs(&root)?;
+        let len = runs.iter().map(|x| x.end - x.start).sum();
+        let bs = self.read_runs(&runs, 0, len)?;
+        for e in bs.chunks_exact(DIR_ENTRY_SIZE) {
+            match e[0] {
+                0 => break,
+                EXFAT_BITMAP if e[1] & BITMAP_SECOND_FAT == 0 => {
+                    self.bitmap = self.read_chain(le_u32(e, 20), le_u64(e, 24))?;
+                }
+                EXFAT_UPCASE => {
+                    self.upcase = decompress_upcase(&self.read_chain(le_u32(e, 20), le_u64(e, 24))?);
+                }
+                EXFAT_LABEL => {
+                    let count = (e[1] as usize).min(11);
+                    let chars: Vec<u16> = (0..count).map(|x| le_u16(e, 2 + x * 2)).collect();
+                    self.volume_label = String::from_utf16_lossy(&chars);
+                }
+                _ => {}
+            }
+        }
+        if self.bitmap.is_empty() {
+            return Err(MRError::new("No allocation bitmap in the exFAT root directory"));
+        }
+        Ok(())
+    }
+
+    fn read_chain(&self, first: u32, size: u64) -> Result<Vec<u8>, MRError> {
+        let runs = self.clusters_to_runs(&self.get_chain(first)?, Some(size));
+        self.read_runs(&runs, 0, size)
+    }
+
+    //Characters missing from the table are their own upper case
+    pub fn upcase_name(&self, name: &str) -> Vec<u16> {
+        name.encode_utf16().map(|x| self.upcase.get(x as usize).copied().unwrap_or(x)).collect()
+    }
+
+    //A set is the file entry, the stream extension and the name entries, secondary count after the type
+    pub(super) fn parse_exfat_dir(&self, bs: &[u8], runs: &[Range<u64>], deleted: bool) -> Vec<DirectoryEntry> {
+        let mut result = vec![];
+        let mut pos = 0;
+        while pos + DIR_ENTRY_SIZE <= bs.len() {
+            let kind = bs[pos];
+            if kind == 0 {
+                break;
+            }
+            let in_use = kind & EXFAT_IN_USE != 0;
+            let count = bs[pos + 1] as usize;
+            let end = pos + DIR_ENTRY_SIZE * (count + 1);
+            if kind | EXFAT_IN_USE != EXFAT_FILE || in_use == deleted || count < 2 || end > bs.len() {
+                pos += DIR_ENTRY_SIZE;
+                continue;
+            }
+            let set = &bs[pos..end];
+            //A deleted set partly written over by a newer one does not sum up any more
+            if set_checksum(set, deleted) != le_u16(set, 2) || set[32] | EXFAT_IN_USE != EXFAT_STREAM {
+                pos += DIR_ENTRY_SIZE;
+                continue;
+            }
+            let stream = &set[32..64];
+            let name_len = stream[3] as usize;
+            let mut chars = vec![];
+            for e in set[64..].chunks_exact(DIR_ENTRY_SIZE) {
+                if e[0] | EXFAT_IN_USE != EXFAT_NAME {
+                    break;
+                }
+                chars.extend((0..NAME_CHARS).map(|x| le_u16(e, 2 + x * 2)));
+            }
+            chars.truncate(name_len);
+            result.push(DirectoryEntry {
+                name: String::from_utf16_lossy(&chars),
+                short_name: String::new(),
+                attributes: le_u16(set, 4),
+                first_cluster: le_u32(stream, 20),
+                size: le_u64(stream, 24),
+                ctime: exfat_time(le_u32(set, 8), set[20], set[22]),
+                mtime: exfat_time(le_u32(set, 12), set[21], set[23]),
+                atime: exfat_time(le_u32(set, 16), 0, set[24]),
+                contiguous: stream[1] & EXFAT_NO_FAT_CHAIN != 0,
+                deleted: !in_use,
+                offset: offset_in_runs(runs, pos),
+            });
+            pos = end;
+        }
+        result
+    }
+}
+
+//0xffff is followed by the count of characters mapped to themselves
+fn decompress_upcase(bs: &[u8]) -> Vec<u16> {
+    let mut result = vec![];
+    let mut words = bs.chunks_exact(2).map(|x| u16::from_le_bytes([x[0], x[1]]));
+    while let Some(word) = words.next() {
+        match word {
+            0xffff => {
+                let count = words.next().unwrap_or(0);
+                for _ in 0..count {
+                    result.push(result.len() as u16);
+                }
+            }
+            _ => result.push(word),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //File, stream and name entries of "a.txt", 5 bytes in cluster 5
+    fn set() -> Vec<u8> {
+        let mut bs = vec![0; 96];
+        bs[0] = EXFAT_FILE;
+        bs[1] = 2;
+        bs[4] = 0x20;
+        bs[32] = EXFAT_STREAM;
+        bs[33] = 1;
+        bs[35] = 5;
+        bs[52..56].copy_from_slice(&5u32.to_le_bytes());
+        bs[56..64].copy_from_slice(&100u64.to_le_bytes());
+        bs[64] = EXFAT_NAME;
+        for (i, c) in "a.txt".encode_utf16().enumerate() {
+            bs[66 + i * 2..68 + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+        bs
+    }
+
+    #[test]
+    fn checksum() {
+        let mut bs = set();
+        assert_eq!(set_checksum(&bs, false), 0xa70b);
+        //The checksum field itself is left out
+        bs[2] = 0x12;
+        bs[3] = 0x34;
+        assert_eq!(set_checksum(&bs, false), 0xa70b);
+        //Deleting clears the in use bit of every type
+        for i in [0, 32, 64] {
+            bs[i] &= !EXFAT_IN_USE;
+        }
+        assert_eq!(set_checksum(&bs, true), 0xa70b);
+        assert_ne!(set_checksum(&bs, false), 0xa70b);
+    }
+
+    #[test]
+    fn upcase() {
+        //'a' to 'c' map to upper case, 0x61 characters before and 2 after map to themselves
+        let mut bs = vec![];
+        for word in [0xffff, 0x61, 0x41, 0x42, 0x43, 0xffff, 2, 0x100] {
+            bs.extend((word as u16).to_le_bytes());
+        }
+        let table = decompress_upcase(&bs);
+        assert_eq!(table.len(), 0x67);
+        assert_eq!(table[0x20], 0x20);
+        assert_eq!(&table[0x61..0x64], &[0x41, 0x42, 0x43]);
+        assert_eq!(&table[0x64..], &[0x64, 0x65, 0x100]);
+    }
+}
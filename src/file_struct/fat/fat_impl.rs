@@ -0,0 +1,458 @@
+use std::{ops::Range, path::Path};
+
+use crate::utils::{file::MRFile, MRError};
+
+use super::{
+    BootSector, DirectoryEntry, Fat, FatType, ATTR_DIRECTORY, BOOT_SIGNATURE, DIR_ENTRY_SIZE, EXFAT_OEM_NAME, FAT_BAD,
+    FAT_EOC, FAT_FREE,
+};
+
+//Everything on disk is little endian, fields out of the bytes read as 0
+pub fn le_u16(bs: &[u8], offset: usize) -> u16 {
+    bs.get(offset..offset + 2).map(|x| u16::from_le_bytes([x[0], x[1]])).unwrap_or(0)
+}
+
+pub fn le_u32(bs: &[u8], offset: usize) -> u32 {
+    bs.get(offset..offset + 4).map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]])).unwrap_or(0)
+}
+
+pub fn le_u64(bs: &[u8], offset: usize) -> u64 {
+    match bs.get(offset..offset + 8) {
+        Some(s) => u64::from_le_bytes([s[0], s[1], s[2], s[3], s[4], s[5], s[6], s[7]]),
+        None => 0,
+    }
+}
+
+//Cluster counts the type of a FAT volume is told by, whatever the boot sector says
+const FAT12_MAX_CLUSTERS: u32 = 4085;
+const FAT16_MAX_CLUSTERS: u32 = 65525;
+
+fn label(bs: &[u8]) -> String {
+    String::from_utf8_lossy(bs).trim_end_matches([' ', '\0']).to_string()
+}
+
+//Two 12 bits entries share three bytes, the odd one takes the high bits
+fn fat12_entry(bs: &[u8], i: usize) -> u32 {
+    let pair = le_u16(bs, i * 3 / 2) as u32;
+    if i % 2 == 1 { pair >> 4 } else { pair & 0xfff }
+}
+
+impl BootSector {
+    fn parse(bs: &[u8]) -> Result<(BootSector, FatType), MRError> {
+        if bs.len() < 512 || le_u16(bs, 510) != BOOT_SIGNATURE {
+            return Err(MRError::new("No boot sector signature"));
+        }
+        if &bs[3..11] == EXFAT_OEM_NAME {
+            return Self::parse_exfat(bs);
+        }
+        let bytes_per_sector = le_u16(bs, 0xb) as u32;
+        let sectors_per_cluster = bs[0xd] as u32;
+        let fat_count = bs[0x10] as u32;
+        let root_entries = le_u16(bs, 0x11) as u32;
+        let total_sectors = match le_u16(bs, 0x13) {
+            0 => le_u32(bs, 0x20) as u64,
+            n => n as u64,
+        };
+        let sectors_per_fat = match le_u16(bs, 0x16) {
+            0 => le_u32(bs, 0x24),
+            n => n as u32,
+        };
+        if !bytes_per_sector.is_power_of_two()
+            || !(512..=4096).contains(&bytes_per_sector)
+            || !sectors_per_cluster.is_power_of_two()
+            || fat_count == 0
+            || sectors_per_fat == 0
+        {
+            return Err(MRError::new("Not a FAT boot sector"));
+        }
+        let fat_offset = le_u16(bs, 0xe) as u32;
+        let root_sectors = (root_entries * DIR_ENTRY_SIZE as u32).div_ceil(bytes_per_sector);
+        let cluster_heap_offset = match fat_count
+            .checked_mul(sectors_per_fat)
+            .and_then(|x| x.checked_add(fat_offset + root_sectors))
+        {
+            Some(s) => s,
+            None => return Err(MRError::new("Broken FAT boot sector")),
+        };
+        let cluster_count = match total_sectors.checked_sub(cluster_heap_offset as u64) {
+            Some(n) => (n / sectors_per_cluster as u64) as u32,
+            None => return Err(MRError::new("Broken FAT boot sector")),
+        };
+        let fat_type = match cluster_count {
+            n if n < FAT12_MAX_CLUSTERS => FatType::Fat12,
+            n if n < FAT16_MAX_CLUSTERS => FatType::Fat16,
+            _ => FatType::Fat32,
+        };
+        //FAT32 moved the serial and the label behind its own fields
+        let extended = if fat_type == FatType::Fat32 { 0x40 } else { 0x24 };
+        let sb = BootSector {
+            oem_name: label(&bs[3..11]),
+            bytes_per_sector,
+            sectors_per_cluster,
+            fat_offset,
+            fat_count,
+            root_entries,
+            total_sectors,
+            sectors_per_fat,
+            root_cluster: if fat_type == FatType::Fat32 { le_u32(bs, 0x2c) } else { 0 },
+            cluster_heap_offset,
+            cluster_count,
+            volume_serial: le_u32(bs, extended + 3),
+            volume_label: label(&bs[extended + 7..extended + 18]),
+        };
+        Ok((sb, fat_type))
+    }
+
+    fn parse_exfat(bs: &[u8]) -> Result<(BootSector, FatType), MRError> {
+        let (sector_shift, cluster_shift) = (bs[0x6c] as u32, bs[0x6d] as u32);
+        if !(9..=12).contains(&sector_shift) || sector_shift + cluster_shift > 25 {
+            return Err(MRError::new("Broken exFAT boot sector"));
+        }
+        let sb = BootSector {
+            oem_name: label(&bs[3..11]),
+            bytes_per_sector: 1 << sector_shift,
+            sectors_per_cluster: 1 << cluster_shift,
+            fat_offset: le_u32(bs, 0x50),
+            fat_count: bs[0x6e] as u32,
+            root_entries: 0,
+            total_sectors: le_u64(bs, 0x48),
+            sectors_per_fat: le_u32(bs, 0x54),
+            root_cluster: le_u32(bs, 0x60),
+            cluster_heap_offset: le_u32(bs, 0x58),
+            cluster_count: le_u32(bs, 0x5c),
+            volume_serial: le_u32(bs, 0x64),
+            volume_label: String::new(),
+        };
+        Ok((sb, FatType::ExFat))
+    }
+
+    pub fn get_oem_name(&self) -> &String {
+        &self.oem_name
+    }
+
+    pub fn get_bytes_per_sector(&self) -> u32 {
+        self.bytes_per_sector
+    }
+
+    pub fn get_sectors_per_cluster(&self) -> u32 {
+        self.sectors_per_cluster
+    }
+
+    pub fn get_fat_offset(&self) -> u32 {
+        self.fat_offset
+    }
+
+    pub fn get_fat_count(&self) -> u32 {
+        self.fat_count
+    }
+
+    pub fn get_root_entries(&self) -> u32 {
+        self.root_entries
+    }
+
+    pub fn get_total_sectors(&self) -> u64 {
+        self.total_sectors
+    }
+
+    pub fn get_sectors_per_fat(&self) -> u32 {
+        self.sectors_per_fat
+    }
+
+    pub fn get_root_cluster(&self) -> u32 {
+        self.root_cluster
+    }
+
+    pub fn get_cluster_heap_offset(&self) -> u32 {
+        self.cluster_heap_offset
+    }
+
+    pub fn get_cluster_count(&self) -> u32 {
+        self.cluster_count
+    }
+
+    pub fn get_volume_serial(&self) -> u32 {
+        self.volume_serial
+    }
+}
+
+impl Fat {
+    pub fn open<P>(path: P) -> Result<Fat, MRError>
+    where
+        P: AsRef<Path> + ToString,
+    {
+        let reader = MRFile::new(path)?;
+        let (boot_sector, fat_type) = BootSector::parse(&reader.read_n(0, 512)?)?;
+        let volume_label = boot_sector.volume_label.clone();
+        let mut fat = Fat {
+            reader,
+            boot_sector,
+            fat_type,
+            table: vec![],
+            bitmap: vec![],
+            upcase: vec![],
+            volume_label,
+        };
+        fat.table = fat.read_table()?;
+        if fat_type == FatType::ExFat {
+            fat.read_exfat_metadata()?;
+        }
+        Ok(fat)
+    }
+
+    //Entries of the first FAT, 12 bit ones are packed by pairs in 3 bytes
+    fn read_table(&self) -> Result<Vec<u32>, MRError> {
+        let sb = &self.boot_sector;
+        let offset = sb.fat_offset as usize * sb.bytes_per_sector as usize;
+        let bs = self.reader.read_n(offset, sb.sectors_per_fat as usize * sb.bytes_per_sector as usize)?;
+        let (count, end, bad) = match self.fat_type {
+            FatType::Fat12 => (bs.len() * 2 / 3, 0xff8, 0xff7),
+            FatType::Fat16 => (bs.len() / 2, 0xfff8, 0xfff7),
+            FatType::Fat32 => (bs.len() / 4, 0x0ffffff8, 0x0ffffff7),
+            FatType::ExFat => (bs.len() / 4, 0xfffffff8, 0xfffffff7),
+        };
+        let count = count.min(sb.cluster_count as usize + 2);
+        let mut result = Vec::with_capacity(count);
+        for i in 0..count {
+            let value = match self.fat_type {
+                FatType::Fat12 => fat12_entry(&bs, i),
+                FatType::Fat16 => le_u16(&bs, i * 2) as u32,
+                //The high 4 bits are reserved
+                FatType::Fat32 => le_u32(&bs, i * 4) & 0x0fffffff,
+                FatType::ExFat => le_u32(&bs, i * 4),
+            };
+            result.push(match value {
+                v if v >= end => FAT_EOC,
+                v if v == bad => FAT_BAD,
+                v => v,
+            });
+        }
+        Ok(result)
+    }
+
+    pub fn get_reader(&self) -> &MRFile {
+        &self.reader
+    }
+
+    pub fn get_boot_sector(&self) -> &BootSector {
+        &self.boot_sector
+    }
+
+    pub fn get_fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    pub fn get_volume_label(&self) -> &String {
+        &self.volume_label
+    }
+
+    pub fn get_cluster_size(&self) -> u64 {
+        self.boot_sector.bytes_per_sector as u64 * self.boot_sector.sectors_per_cluster as u64
+    }
+
+    pub fn get_total_size(&self) -> u64 {
+        self.boot_sector.total_sectors * self.boot_sector.bytes_per_sector as u64
+    }
+
+    //Data clusters are numbered from 2
+    pub fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster - 2 < self.boot_sector.cluster_count
+    }
+
+    pub fn cluster_to_offset(&self, cluster: u32) -> u64 {
+        let sb = &self.boot_sector;
+        sb.cluster_heap_offset as u64 * sb.bytes_per_sector as u64
+            + (cluster as u64 - 2) * self.get_cluster_size()
+    }
+
+    //Cluster holding a byte of the volume, None before the data area
+    pub fn offset_to_cluster(&self, offset: u64) -> Option<u32> {
+        let sb = &self.boot_sector;
+        let heap = sb.cluster_heap_offset as u64 * sb.bytes_per_sector as u64;
+        let cluster = u32::try_from(offset.checked_sub(heap)? / self.get_cluster_size()).ok()?.checked_add(2)?;
+        self.is_valid_cluster(cluster).then_some(cluster)
+    }
+
+    //Next cluster in the first FAT, FAT_EOC ends the chain
+    pub fn get_next_cluster(&self, cluster: u32) -> u32 {
+        self.table.get(cluster as usize).copied().unwrap_or(FAT_FREE)
+    }
+
+    //exFAT tells free clusters by its bitmap, its FAT keeps the links of deleted files
+    pub fn is_cluster_allocated(&self, cluster: u32) -> bool {
+        if !self.is_valid_cluster(cluster) {
+            return false;
+        }
+        match self.fat_type {
+            FatType::ExFat => {
+                let i = (cluster - 2) as usize;
+                self.bitmap.get(i / 8).map(|x| x & (1 << (i % 8)) != 0).unwrap_or(false)
+            }
+            _ => self.get_next_cluster(cluster) != FAT_FREE,
+        }
+    }
+
+    pub fn get_free_clusters(&self) -> u32 {
+        (2..self.boot_sector.cluster_count + 2).filter(|x| !self.is_cluster_allocated(*x)).count() as u32
+    }
+
+    //Clusters linked from first, stops at the end mark or at a link out of the volume
+    pub fn get_chain(&self, first: u32) -> Result<Vec<u32>, MRError> {
+        let mut result = vec![];
+        let mut cluster = first;
+        while self.is_valid_cluster(cluster) {
+            if result.len() > self.boot_sector.cluster_count as usize {
+                return Err(MRError::new(&format!("Cluster chain from {} loops", first)));
+            }
+            result.push(cluster);
+            cluster = self.get_next_cluster(cluster);
+        }
+        Ok(result)
+    }
+
+    //Byte ranges of the clusters, cut at size, clusters in a row make one range
+    pub fn clusters_to_runs(&self, clusters: &[u32], size: Option<u64>) -> Vec<Range<u64>> {
+        let cluster_size = self.get_cluster_size();
+        let mut rest = size.unwrap_or(u64::MAX);
+        let mut result: Vec<Range<u64>> = vec![];
+        for cluster in clusters {
+            if rest == 0 {
+                break;
+            }
+            let start = self.cluster_to_offset(*cluster);
+            let len = cluster_size.min(rest);
+            rest -= len;
+            match result.last_mut() {
+                Some(last) if last.end == start => last.end += len,
+                _ => result.push(start..start + len),
+            }
+        }
+        result
+    }
+
+    //FAT12 and FAT16 keep the root directory in a fixed area before the clusters
+    pub fn get_root(&self) -> DirectoryEntry {
+        let first_cluster = self.boot_sector.root_cluster;
+        let size = match first_cluster {
+            0 => self.boot_sector.root_entries as u64 * DIR_ENTRY_SIZE as u64,
+            _ => 0,
+        };
+        DirectoryEntry::new_dir("/", first_cluster, size)
+    }
+
+    //Where the data of a live entry is, directories without a size take their whole chain
+    pub fn get_runs(&self, entry: &DirectoryEntry) -> Result<Vec<Range<u64>>, MRError> {
+        if entry.is_dir() && entry.get_first_cluster() == 0 && self.fat_type != FatType::ExFat {
+            let sb = &self.boot_sector;
+            let start = (sb.fat_offset + sb.fat_count * sb.sectors_per_fat) as u64 * sb.bytes_per_sector as u64;
+            return Ok(vec![Range { start, end: start + entry.get_size() }]);
+        }
+        let size = match entry.get_size() {
+            0 if entry.is_dir() => None,
+            n => Some(n),
+        };
+        let clusters = match entry.is_contiguous() {
+            true => {
+                let count = entry.get_size().div_ceil(self.get_cluster_size()) as u32;
+                let first = entry.get_first_cluster();
+                (first..first.saturating_add(count)).take_while(|x| self.is_valid_cluster(*x)).collect()
+            }
+            false => self.get_chain(entry.get_first_cluster())?,
+        };
+        Ok(self.clusters_to_runs(&clusters, size))
+    }
+
+    //len bytes of the runs from offset, bytes past the runs are left out
+    pub fn read_runs(&self, runs: &[Range<u64>], offset: u64, len: u64) -> Result<Vec<u8>, MRError> {
+        read_runs(&self.reader, runs, offset, len)
+    }
+
+    pub fn get_entry_by_path(&self, path: &str) -> Result<DirectoryEntry, MRError> {
+        let mut entry = self.get_root();
+        for name in path.split(['/', '\\']).filter(|x| !x.is_empty()) {
+            let entries = self.get_dir_entries(&entry)?;
+            entry = entries
+                .into_iter()
+                .find(|x| self.name_matches(x, name))
+                .ok_or(MRError::new(&format!("{} not found", path)))?;
+        }
+        Ok(entry)
+    }
+
+    //FAT names are not case sensitive, exFAT compares them through its upcase table
+    fn name_matches(&self, entry: &DirectoryEntry, name: &str) -> bool {
+        match self.fat_type {
+            FatType::ExFat => self.upcase_name(entry.get_name()) == self.upcase_name(name),
+            _ => {
+                let name = name.to_uppercase();
+                entry.get_name().to_uppercase() == name || entry.get_short_name().to_uppercase() == name
+            }
+        }
+    }
+}
+
+pub(super) fn read_runs(reader: &MRFile, runs: &[Range<u64>], offset: u64, len: u64) -> Result<Vec<u8>, MRError> {
+    let mut result = vec![];
+    let end = offset.saturating_add(len);
+    let mut pos = 0;
+    for run in runs {
+        let run_len = run.end - run.start;
+        let start = offset.max(pos);
+        let stop = end.min(pos + run_len);
+        if start < stop {
+            result.extend(reader.read_n((run.start + start - pos) as usize, (stop - start) as usize)?);
+        }
+        pos += run_len;
+        if pos >= end {
+            break;
+        }
+    }
+    Ok(result)
+}
+
+impl DirectoryEntry {
+    pub(super) fn new_dir(name: &str, first_cluster: u32, size: u64) -> Self {
+        DirectoryEntry {
+            name: name.to_string(),
+            short_name: String::new(),
+            attributes: ATTR_DIRECTORY,
+            first_cluster,
+            size,
+            ctime: Default::default(),
+            mtime: Default::default(),
+            atime: Default::default(),
+            contiguous: false,
+            deleted: false,
+            offset: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fat12_packing() {
+        //Entries 0x123, 0x456, 0xfff and 0x008
+        let bs = [0x23, 0x61, 0x45, 0xff, 0x8f, 0x00];
+        let entries: Vec<u32> = (0..4).map(|x| fat12_entry(&bs, x)).collect();
+        assert_eq!(entries, vec![0x123, 0x456, 0xfff, 0x008]);
+    }
+
+    #[test]
+    fn broken_boot_sector() {
+        let mut bs = vec![0; 512];
+        bs[510..512].copy_from_slice(&BOOT_SIGNATURE.to_le_bytes());
+        bs[0xb..0xd].copy_from_slice(&512u16.to_le_bytes());
+        bs[0xd] = 1;
+        bs[0xe] = 1;
+        bs[0x10] = 2;
+        bs[0x20..0x24].copy_from_slice(&u32::MAX.to_le_bytes());
+        bs[0x24..0x28].copy_from_slice(&0x90000000u32.to_le_bytes());
+        assert!(BootSector::parse(&bs).unwrap_err().to_string().ends_with("Broken FAT boot sector"));
+        bs[0x24..0x28].copy_from_slice(&0x100u32.to_le_bytes());
+        let (sb, fat_type) = BootSector::parse(&bs).unwrap();
+        assert_eq!(fat_type, FatType::Fat32);
+        assert_eq!(sb.get_cluster_heap_offset(), 0x201);
+    }
+}
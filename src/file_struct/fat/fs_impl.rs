@@ -0,0 +1,94 @@
+use std::{io::Write, ops::Range};
+
+use bytes::Bytes;
+use chrono::NaiveDateTime;
+
+use crate::{
+    file_struct::{File, FileSystem},
+    utils::MRError,
+};
+
+use super::{fat_impl::read_runs, DirectoryEntry, Fat, FatFile};
+
+const COPY_CHUNK_SIZE: usize = 1024 * 1024;
+
+impl FatFile {
+    pub fn get_entry(&self) -> &DirectoryEntry {
+        &self.entry
+    }
+
+    pub fn get_runs(&self) -> &Vec<Range<u64>> {
+        &self.runs
+    }
+
+    //Directories of FAT have no size, their runs tell it
+    fn data_size(&self) -> u64 {
+        match self.entry.get_size() {
+            0 if self.entry.is_dir() => self.runs.iter().map(|x| x.end - x.start).sum(),
+            n => n,
+        }
+    }
+}
+
+impl File for FatFile {
+    fn read(&self, start: usize, size: usize) -> Result<Bytes, MRError> {
+        let end = (start as u64 + size as u64).min(self.data_size());
+        if start as u64 >= end {
+            return Ok(Bytes::new());
+        }
+        let bs = read_runs(&self.reader, &self.runs, start as u64, end - start as u64)?;
+        Ok(Bytes::from(bs))
+    }
+
+    fn get_size(&self) -> Result<usize, MRError> {
+        Ok(self.data_size() as usize)
+    }
+
+    fn get_owner(&self) -> Result<String, MRError> {
+        Err(MRError::new("FAT keeps no owner"))
+    }
+
+    fn get_mtime(&self) -> Result<NaiveDateTime, MRError> {
+        Ok(self.entry.get_mtime())
+    }
+
+    fn get_ctime(&self) -> Result<NaiveDateTime, MRError> {
+        Ok(self.entry.get_ctime())
+    }
+
+    fn get_atime(&self) -> Result<NaiveDateTime, MRError> {
+        Ok(self.entry.get_atime())
+    }
+}
+
+impl FileSystem for Fat {
+    fn list_files(&self, path: &str) -> Result<Vec<Box<dyn File>>, MRError> {
+        let dir = self.get_entry_by_path(path)?;
+        let mut result: Vec<Box<dyn File>> = vec![];
+        for entry in self.get_dir_entries(&dir)? {
+            result.push(Box::new(self.open_entry(&entry)?));
+        }
+        Ok(result)
+    }
+
+    fn open_file(&self, path: &str) -> Result<Box<dyn File>, MRError> {
+        let entry = self.get_entry_by_path(path)?;
+        Ok(Box::new(self.open_entry(&entry)?))
+    }
+
+    fn copy(&self, fs_path: &str, local_path: &str) -> Result<(), MRError> {
+        let file = self.open_file(fs_path)?;
+        let mut out = std::fs::File::create(local_path).map_err(|e| MRError::from(Box::new(e)))?;
+        let size = file.get_size()?;
+        let mut pos = 0;
+        while pos < size {
+            let bs = file.read(pos, COPY_CHUNK_SIZE.min(size - pos))?;
+            if bs.is_empty() {
+                break;
+            }
+            out.write_all(&bs).map_err(|e| MRError::from(Box::new(e)))?;
+            pos += bs.len();
+        }
+        Ok(())
+    }
+}
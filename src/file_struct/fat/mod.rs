@@ -0,0 +1,131 @@
+use std::ops::Range;
+
+use chrono::NaiveDateTime;
+
+use crate::utils::file::MRFile;
+
+pub mod fat_impl;
+pub mod dir_impl;
+pub mod exfat_impl;
+pub mod recover_impl;
+pub mod fs_impl;
+
+pub const EXFAT_OEM_NAME            : &[u8] = b"EXFAT   ";
+pub const BOOT_SIGNATURE            : u16 = 0xaa55;
+pub const DIR_ENTRY_SIZE            : usize = 32;
+//First byte of a deleted FAT entry, 0x05 stands for a name really starting with 0xe5
+pub const FAT_DELETED               : u8 = 0xe5;
+pub const FAT_KANJI_E5              : u8 = 0x05;
+pub const ATTR_READ_ONLY            : u16 = 0x01;
+pub const ATTR_HIDDEN               : u16 = 0x02;
+pub const ATTR_SYSTEM               : u16 = 0x04;
+pub const ATTR_VOLUME_ID            : u16 = 0x08;
+pub const ATTR_DIRECTORY            : u16 = 0x10;
+pub const ATTR_ARCHIVE              : u16 = 0x20;
+pub const ATTR_LONG_NAME            : u8 = 0x0f;
+pub const LAST_LONG_ENTRY           : u8 = 0x40;
+//Types of exFAT entries, the high bit is cleared when the entry is deleted
+pub const EXFAT_IN_USE              : u8 = 0x80;
+pub const EXFAT_BITMAP              : u8 = 0x81;
+pub const EXFAT_UPCASE              : u8 = 0x82;
+pub const EXFAT_LABEL               : u8 = 0x83;
+pub const EXFAT_FILE                : u8 = 0x85;
+pub const EXFAT_STREAM              : u8 = 0xc0;
+pub const EXFAT_NAME                : u8 = 0xc1;
+pub const EXFAT_NO_FAT_CHAIN        : u8 = 0x2;
+//Values of the table once read, the end and bad marks differ with the width of the entries
+pub const FAT_FREE                  : u32 = 0;
+pub const FAT_BAD                   : u32 = 0xfffffff7;
+pub const FAT_EOC                   : u32 = 0xffffffff;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+    ExFat,
+}
+
+//Both layouts are read into one, exFAT keeps the shifts and the offsets in sectors
+#[derive(Debug, Default, Clone)]
+pub struct BootSector {
+    oem_name            : String,   //0x3
+    bytes_per_sector    : u32,      //0xb, exFAT 1 << 0x6c
+    sectors_per_cluster : u32,      //0xd, exFAT 1 << 0x6d
+    //Sector of the first FAT
+    fat_offset          : u32,      //0xe, exFAT 0x50
+    fat_count           : u32,      //0x10, exFAT 0x6e
+    //Entries of the fixed root directory of FAT12 and FAT16
+    root_entries        : u32,      //0x11
+    total_sectors       : u64,      //0x13 or 0x20, exFAT 0x48
+    sectors_per_fat     : u32,      //0x16 or 0x24, exFAT 0x54
+    //FAT32 and exFAT
+    root_cluster        : u32,      //0x2c, exFAT 0x60
+    //Computed on FAT, where cluster 2 starts
+    cluster_heap_offset : u32,      //exFAT 0x58
+    cluster_count       : u32,      //exFAT 0x5c
+    volume_serial       : u32,      //0x27, FAT32 0x43, exFAT 0x64
+    volume_label        : String,   //0x2b, FAT32 0x47
+}
+
+#[derive(Debug, Clone)]
+pub struct DirectoryEntry {
+    //Long name when there is one
+    name            : String,
+    //8.3 name, empty on exFAT
+    short_name      : String,
+    attributes      : u16,
+    first_cluster   : u32,
+    //0 for FAT directories
+    size            : u64,
+    //Creation time, FAT keeps no change time
+    ctime           : NaiveDateTime,
+    mtime           : NaiveDateTime,
+    //Only the day on FAT
+    atime           : NaiveDateTime,
+    //exFAT clusters in a row without links in the FAT
+    contiguous      : bool,
+    deleted         : bool,
+    //Byte of the volume of the short entry, or of the first entry of an exFAT set
+    offset          : u64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChainGuess {
+    //The FAT still links the clusters, exFAT leaves the links of deleted files
+    FatChain,
+    //Clusters in a row from the first one
+    Contiguous,
+    //Clusters in a row from the first one, stepping over the ones in use
+    SkipAllocated,
+}
+
+//Clusters picked for the data of a deleted entry
+#[derive(Debug, Clone)]
+pub struct RecoveredChain {
+    clusters        : Vec<u32>,
+    guess           : ChainGuess,
+    //Clusters of the guess that are allocated again
+    reused          : usize
+}
+
+//Data of an entry, live or deleted, with its own reader
+pub struct FatFile {
+    reader          : MRFile,
+    entry           : DirectoryEntry,
+    //Bytes of the volume holding the data, in file order
+    runs            : Vec<Range<u64>>
+}
+
+pub struct Fat {
+    reader          : MRFile,
+    boot_sector     : BootSector,
+    fat_type        : FatType,
+    //Next cluster of every cluster from the first FAT, with the end and bad marks widened
+    table           : Vec<u32>,
+    //exFAT allocation bitmap, FAT takes the free clusters from the table
+    bitmap          : Vec<u8>,
+    //exFAT upcase table, names compare through it
+    upcase          : Vec<u16>,
+    volume_label    : String
+}
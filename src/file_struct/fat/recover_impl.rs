@@ -0,0 +1,85 @@
+use crate::utils::MRError;
+
+use super::{ChainGuess, DirectoryEntry, Fat, FatFile, FatType, RecoveredChain};
+
+impl RecoveredChain {
+    pub fn get_clusters(&self) -> &Vec<u32> {
+        &self.clusters
+    }
+
+    pub fn get_guess(&self) -> ChainGuess {
+        self.guess
+    }
+
+    pub fn get_reused(&self) -> usize {
+        self.reused
+    }
+}
+
+impl Fat {
+    //Deleting a file frees its clusters, FAT zeroes their links and exFAT clears their bits only
+    pub fn recover_chain(&self, entry: &DirectoryEntry) -> RecoveredChain {
+        let first = entry.get_first_cluster();
+        //FAT keeps no size for directories, only their first cluster is sure
+        let needed = match entry.get_size() {
+            0 if entry.is_dir() => 1,
+            n => n.div_ceil(self.get_cluster_size()) as usize,
+        };
+        let reused = |clusters: &[u32]| clusters.iter().filter(|x| self.is_cluster_allocated(**x)).count();
+        if needed == 0 || !self.is_valid_cluster(first) {
+            return RecoveredChain { clusters: vec![], guess: ChainGuess::Contiguous, reused: 0 };
+        }
+        if entry.is_contiguous() {
+            let clusters: Vec<u32> = (first..).take(needed).take_while(|x| self.is_valid_cluster(*x)).collect();
+            let reused = reused(&clusters);
+            return RecoveredChain { clusters, guess: ChainGuess::Contiguous, reused };
+        }
+        //The links are left as long as no other file took the clusters
+        if self.fat_type == FatType::ExFat {
+            if let Ok(mut chain) = self.get_chain(first) {
+                chain.truncate(needed);
+                if chain.len() == needed && reused(&chain) == 0 {
+                    return RecoveredChain { clusters: chain, guess: ChainGuess::FatChain, reused: 0 };
+                }
+            }
+        }
+        //Files are mostly written in a row, around the clusters other files held at the time
+        let mut clusters = vec![first];
+        let mut skipped = false;
+        let mut cluster = first + 1;
+        while clusters.len() < needed && self.is_valid_cluster(cluster) {
+            match self.is_cluster_allocated(cluster) {
+                true => skipped = true,
+                false => clusters.push(cluster),
+            }
+            cluster += 1;
+        }
+        let reused = reused(&clusters);
+        let guess = if skipped { ChainGuess::SkipAllocated } else { ChainGuess::Contiguous };
+        RecoveredChain { clusters, guess, reused }
+    }
+
+    //Live entries follow their chain, deleted ones the guessed one
+    pub fn open_entry(&self, entry: &DirectoryEntry) -> Result<FatFile, MRError> {
+        match entry.is_deleted() {
+            true => self.open_chain(entry, &self.recover_chain(entry)),
+            false => Ok(FatFile {
+                reader: self.reader.try_clone()?,
+                entry: entry.clone(),
+                runs: self.get_runs(entry)?,
+            }),
+        }
+    }
+
+    pub fn open_chain(&self, entry: &DirectoryEntry, chain: &RecoveredChain) -> Result<FatFile, MRError> {
+        let size = match entry.get_size() {
+            0 if entry.is_dir() => None,
+            n => Some(n),
+        };
+        Ok(FatFile {
+            reader: self.reader.try_clone()?,
+            entry: entry.clone(),
+            runs: self.clusters_to_runs(chain.get_clusters(), size),
+        })
+    }
+}
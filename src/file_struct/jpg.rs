@@ -0,0 +1,162 @@
+use memchr::memchr;
+
+use super::carver::{be_u16, Carving};
+
+pub const MAGIC: &[u8] = &[0xff, 0xd8, 0xff];
+
+struct Walk {
+    //A SOF segment came before the scans
+    frame   : bool,
+    tables  : bool,
+    scans   : usize
+}
+
+impl Walk {
+    //Bytes up to a broken marker still make a picture once a scan was read
+    fn broken(&self, offset: usize) -> Carving {
+        match self.frame && self.scans > 0 {
+            true => Carving::found(offset, 20),
+            false => Carving::Invalid,
+        }
+    }
+
+    fn truncated(&self, offset: usize, last: bool) -> Carving {
+        match last {
+            true => self.broken(offset),
+            false => Carving::NeedMore,
+        }
+    }
+}
+
+//Walks the marker segments up to EOI, the entropy coded data after SOS is skipped to the next marker
+pub fn carve(bs: &[u8], last: bool) -> Carving {
+    let mut walk = Walk { frame: false, tables: false, scans: 0 };
+    let mut offset = 2;
+    loop {
+        //The length of the segment before may run past the bytes given
+        if offset + 2 > bs.len() {
+            return walk.truncated(offset.min(bs.len()), last);
+        }
+        if bs[offset] != 0xff {
+            return walk.broken(offset);
+        }
+        let marker = bs[offset + 1];
+        match marker {
+            //Fill bytes
+            0xff => {
+                offset += 1;
+                continue;
+            }
+            0xd9 => {
+                if !walk.frame || walk.scans == 0 {
+                    return Carving::Invalid;
+                }
+                let confidence = if walk.tables { 100 } else { 80 };
+                return Carving::found(offset + 2, confidence);
+            }
+            0x01 | 0xd0..=0xd7 => {
+                offset += 2;
+                continue;
+            }
+            0x02..=0xbf | 0x00 | 0xd8 => {
+                return walk.broken(offset);
+            }
+            _ => {}
+        }
+        if offset + 4 > bs.len() {
+            return walk.truncated(offset, last);
+        }
+        let length = be_u16(bs, offset + 2);
+        if length < 2 {
+            return walk.broken(offset);
+        }
+        match marker {
+            0xc4 | 0xdb => walk.tables = true,
+            0xc8 | 0xcc => {}
+            0xc0..=0xcf => walk.frame = true,
+            0xda if !walk.frame => {
+                return Carving::Invalid;
+            }
+            _ => {}
+        }
+        offset += 2 + length;
+        if marker != 0xda {
+            continue;
+        }
+        walk.scans += 1;
+        //FF 00 is a stuffed byte and FF D0-D7 a restart, anything else ends the scan
+        loop {
+            let pos = match memchr(0xff, bs.get(offset..).unwrap_or_default()) {
+                Some(s) => offset + s,
+                None => {
+                    return walk.truncated(bs.len(), last);
+                }
+            };
+            if pos + 1 >= bs.len() {
+                return walk.truncated(pos, last);
+            }
+            match bs[pos + 1] {
+                0x00 | 0xd0..=0xd7 => offset = pos + 2,
+                0xff => offset = pos + 1,
+                _ => {
+                    offset = pos;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //SOI, DQT, SOF0, SOS and 4 bytes of scan data with a stuffed byte
+    fn head() -> Vec<u8> {
+        let mut bs = vec![0xff, 0xd8];
+        bs.extend([0xff, 0xdb, 0x00, 0x04, 0x00, 0x00]);
+        bs.extend([0xff, 0xc0, 0x00, 0x0b, 0x08, 0x00, 0x01, 0x00, 0x01, 0x01, 0x01, 0x11, 0x00]);
+        bs.extend([0xff, 0xda, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3f, 0x00]);
+        bs.extend([0x12, 0xff, 0x00, 0x34]);
+        bs
+    }
+
+    #[test]
+    fn valid() {
+        let mut bs = head();
+        bs.extend([0xff, 0xd9]);
+        let size = bs.len();
+        bs.extend([0xaa; 16]);
+        assert_eq!(carve(&bs, false), Carving::found(size, 100));
+    }
+
+    #[test]
+    fn truncated() {
+        let bs = head();
+        assert_eq!(carve(&bs, false), Carving::NeedMore);
+        assert_eq!(carve(&bs, true), Carving::found(bs.len(), 20));
+    }
+
+    #[test]
+    fn garbage() {
+        let mut bs = MAGIC.to_vec();
+        bs.extend([0x13, 0x37, 0x00, 0x42, 0xff, 0xd9]);
+        assert_eq!(carve(&bs, true), Carving::Invalid);
+    }
+
+    //The length of a segment after the scan points past the bytes given
+    #[test]
+    fn segment_past_end() {
+        let mut bs = vec![0xff, 0xd8];
+        bs.extend([0xff, 0xc0, 0x00, 0x0b, 0x08, 0x00, 0x01, 0x00, 0x01, 0x01, 0x01, 0x11, 0x00]);
+        bs.extend([0xff, 0xda, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3f, 0x00]);
+        bs.extend([0x12, 0x34]);
+        bs.extend([0xff, 0xc4, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(bs.len(), 37);
+        assert_eq!(carve(&bs, false), Carving::NeedMore);
+        match carve(&bs, true) {
+            Carving::Found { size, .. } => assert!(size <= bs.len()),
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -1,6 +1,8 @@
 #![allow(unused)]
 #![allow(non_camel_case_types)]
 
+use std::fmt;
+
 use bytes::Bytes;
 use chrono::NaiveDateTime;
 
@@ -13,6 +15,14 @@ pub mod xfs;
 pub mod windows;
 pub mod bitlocker;
 pub mod vmdk;
+pub mod carver;
+pub mod jpg;
+pub mod png;
+pub mod pdf;
+pub mod zip;
+pub mod sqlite;
+pub mod email;
+pub mod fat;
 
 pub trait File {
     fn read(&self, start: usize, size: usize) -> Result<Bytes, MRError>;
@@ -34,4 +44,74 @@ pub trait FileSystem {
     fn open_file(&self, path: &str) -> Result<Box<dyn File>, MRError>;
 
     fn copy(&self, fs_path: &str, local_path: &str) -> Result<(), MRError>;
-}
\ No newline at end of file
+}
+
+//Owner of a byte of a volume, see whose of each file system
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockOwner {
+    //offset is the byte inside the stream, slack once it is past the end of the data
+    File { id: u64, path: String, stream: String, offset: u64, slack: bool },
+    //Structures of the file system, record is the MFT entry or inode at that byte and offset is inside it
+    Metadata { name: String, record: Option<u64>, offset: u64 },
+    Unallocated,
+    //Allocated but not referenced by anything known
+    Unknown,
+}
+
+impl fmt::Display for BlockOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File { id, path, stream, offset, slack } => {
+                write!(f, "file {}", path)?;
+                if !stream.is_empty() {
+                    write!(f, ":{}", stream)?;
+                }
+                write!(f, " (id {}), offset {}", id, offset)?;
+                if *slack {
+                    write!(f, ", slack")?;
+                }
+                Ok(())
+            }
+            Self::Metadata { name, record, offset } => {
+                write!(f, "metadata {}", name)?;
+                if let Some(record) = record {
+                    write!(f, ", record {}", record)?;
+                }
+                write!(f, ", offset {}", offset)
+            }
+            Self::Unallocated => write!(f, "unallocated"),
+            Self::Unknown => write!(f, "allocated, owner unknown"),
+        }
+    }
+}
+
+//Bytes between the end of the data of a file and the end of its last cluster
+#[derive(Debug, Clone)]
+pub struct FileSlack {
+    offset  : u64,
+    length  : u64,
+    id      : u64,
+    path    : String
+}
+
+impl FileSlack {
+    pub fn new(offset: u64, length: u64, id: u64, path: String) -> Self {
+        Self { offset, length, id, path }
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_length(&self) -> u64 {
+        self.length
+    }
+
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn get_path(&self) -> &String {
+        &self.path
+    }
+}
@@ -0,0 +1,166 @@
+use std::{path::Path, rc::Rc};
+
+use crate::{
+    file_struct::bitlocker::{BDEDecryptor, BDEUnlockKey, FVEMetadata},
+    utils::{file::MRFile, MRError},
+};
+
+use super::Ntfs;
+
+impl Ntfs {
+    //Unlocks a BitLocker volume and reads the NTFS volume inside it
+    pub fn open_bitlocker<P>(img: P, key: &BDEUnlockKey) -> Result<Ntfs, MRError>
+    where P: AsRef<Path> + ToString {
+        let path = img.to_string();
+        let metadata = FVEMetadata::read(&MRFile::new(&path)?)?;
+        let vmk = metadata.unlock(key)?;
+        let fvek = metadata.get_fvek(&vmk)?;
+        let decryptor = BDEDecryptor::new(&metadata, &fvek)?;
+        let mut ntfs = Ntfs::from_reader(MRFile::with_decryptor(&path, Rc::new(decryptor))?)?;
+        ntfs.is_bitlocker = true;
+        Ok(ntfs)
+    }
+
+    pub fn is_bitlocker(&self) -> bool {
+        self.is_bitlocker
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::cipher::generic_array::GenericArray;
+    use ccm::{
+        aead::{AeadInPlace, KeyInit},
+        consts::{U12, U16},
+        Ccm,
+    };
+
+    use crate::file_struct::bitlocker::{
+        crypto_impl::BDE_AES_128_CBC,
+        fve_impl::{
+            BDE_SIGNATURE, FVE_ENTRY_TYPE_FVEK, FVE_ENTRY_TYPE_VMK, FVE_VALUE_TYPE_AES_CCM_KEY, FVE_VALUE_TYPE_KEY,
+            FVE_VALUE_TYPE_VMK,
+        },
+        BDECipher,
+    };
+
+    use super::*;
+
+    const SECTOR_SIZE: usize = 512;
+    const VOLUME_SIZE: usize = 0x40000;
+    //Three copies of the metadata, only the first is written
+    const METADATA_OFFSETS: [usize; 3] = [0x10000, 0x20000, 0x30000];
+    const VOLUME_HEADER_OFFSET: usize = 0x8000;
+    const VOLUME_HEADER_SECTORS: usize = 16;
+
+    fn entry(entry_type: u16, value_type: u16, data: &[u8]) -> Vec<u8> {
+        let mut result = vec![];
+        result.extend(((data.len() + 8) as u16).to_le_bytes());
+        result.extend(entry_type.to_le_bytes());
+        result.extend(value_type.to_le_bytes());
+        result.extend(1u16.to_le_bytes());
+        result.extend(data);
+        result
+    }
+
+    //A key entry sealed in an AES-CCM entry
+    fn ccm_entry(entry_type: u16, key: &[u8], inner: &[u8]) -> Vec<u8> {
+        let nonce = [0x5a; 12];
+        let mut data = entry(0, FVE_VALUE_TYPE_KEY, &[&0x2000u32.to_le_bytes(), inner].concat());
+        let tag = Ccm::<aes::Aes256, U16, U12>::new_from_slice(key)
+            .unwrap()
+            .encrypt_in_place_detached(GenericArray::from_slice(&nonce), &[], &mut data)
+            .unwrap();
+        entry(entry_type, FVE_VALUE_TYPE_AES_CCM_KEY, &[&nonce[..], &tag, &data].concat())
+    }
+
+    fn encrypt_sector(fvek: &BDECipher, offset: u64, data: &mut [u8]) {
+        let mut iv = [0u8; 16];
+        iv[0..8].copy_from_slice(&offset.to_le_bytes());
+        fvek.encrypt_block(&mut iv);
+        for block in data.chunks_exact_mut(16) {
+            block.iter_mut().zip(iv.iter()).for_each(|(a, b)| *a ^= b);
+            fvek.encrypt_block(block);
+            iv.copy_from_slice(block);
+        }
+    }
+
+    //Suspended AES-128-CBC volume: the clear key unlocks the VMK which unlocks the FVEK
+    fn build_image() -> (Vec<u8>, Vec<u8>) {
+        let clear_key = [0x21; 32];
+        let vmk = [0x42; 32];
+        let fvek = [0x63; 16];
+        let mut plain: Vec<u8> = (0..VOLUME_SIZE).map(|x| (x / SECTOR_SIZE) as u8 ^ x as u8).collect();
+        plain[0..SECTOR_SIZE].fill(0);
+        plain[3..11].copy_from_slice(b"NTFS    ");
+        plain[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+        plain[13] = 8;
+        plain[40..48].copy_from_slice(&((VOLUME_SIZE / SECTOR_SIZE) as u64).to_le_bytes());
+
+        let cipher = BDECipher::new(&fvek).unwrap();
+        let mut image = vec![0u8; VOLUME_SIZE];
+        for offset in (0..VOLUME_SIZE).step_by(SECTOR_SIZE) {
+            //The first sectors are stored after the BitLocker boot sector
+            let stored = match offset < VOLUME_HEADER_SECTORS * SECTOR_SIZE {
+                true => VOLUME_HEADER_OFFSET + offset,
+                false => offset,
+            };
+            if (VOLUME_HEADER_OFFSET..VOLUME_HEADER_OFFSET + VOLUME_HEADER_SECTORS * SECTOR_SIZE).contains(&offset)
+                || METADATA_OFFSETS.iter().any(|x| (*x..*x + 0x10000).contains(&offset))
+            {
+                continue;
+            }
+            let mut sector = plain[offset..offset + SECTOR_SIZE].to_vec();
+            encrypt_sector(&cipher, stored as u64, &mut sector);
+            image[stored..stored + SECTOR_SIZE].copy_from_slice(&sector);
+        }
+
+        let mut vmk_data = vec![0x11; 16];
+        vmk_data.extend([0; 12]);
+        vmk_data.extend(entry(0, FVE_VALUE_TYPE_KEY, &[&0x2000u32.to_le_bytes()[..], &clear_key].concat()));
+        vmk_data.extend(ccm_entry(0, &clear_key, &vmk));
+        let mut entries = entry(FVE_ENTRY_TYPE_VMK, FVE_VALUE_TYPE_VMK, &vmk_data);
+        entries.extend(ccm_entry(FVE_ENTRY_TYPE_FVEK, &vmk, &fvek));
+
+        let block = &mut image[METADATA_OFFSETS[0]..];
+        block[0..8].copy_from_slice(BDE_SIGNATURE);
+        block[10..12].copy_from_slice(&2u16.to_le_bytes());
+        block[16..24].copy_from_slice(&(VOLUME_SIZE as u64).to_le_bytes());
+        block[28..32].copy_from_slice(&(VOLUME_HEADER_SECTORS as u32).to_le_bytes());
+        for (i, offset) in METADATA_OFFSETS.iter().enumerate() {
+            block[32 + i * 8..40 + i * 8].copy_from_slice(&(*offset as u64).to_le_bytes());
+        }
+        block[56..64].copy_from_slice(&(VOLUME_HEADER_OFFSET as u64).to_le_bytes());
+        block[64..68].copy_from_slice(&((48 + entries.len()) as u32).to_le_bytes());
+        block[72..76].copy_from_slice(&48u32.to_le_bytes());
+        block[100..102].copy_from_slice(&BDE_AES_128_CBC.to_le_bytes());
+        block[112..112 + entries.len()].copy_from_slice(&entries);
+
+        image[3..11].copy_from_slice(BDE_SIGNATURE);
+        image[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+        image[160..176].copy_from_slice(&[
+            0x3b, 0xd6, 0x67, 0x49, 0x29, 0x2e, 0xd8, 0x4a, 0x83, 0x99, 0xf6, 0xa3, 0x39, 0xe3, 0xd0, 0x01,
+        ]);
+        for (i, offset) in METADATA_OFFSETS.iter().enumerate() {
+            image[176 + i * 8..184 + i * 8].copy_from_slice(&(*offset as u64).to_le_bytes());
+        }
+        (image, plain)
+    }
+
+    #[test]
+    fn clear_key_volume() {
+        let (image, plain) = build_image();
+        let path = std::env::temp_dir().join(format!("meta_reader_bitlocker_{}.bin", std::process::id()));
+        std::fs::write(&path, image).unwrap();
+        let ntfs = Ntfs::open(path.to_string_lossy().to_string());
+        std::fs::remove_file(path).unwrap();
+        let ntfs = ntfs.unwrap();
+        assert!(ntfs.is_bitlocker());
+        assert_eq!(ntfs.get_sector_num(), (VOLUME_SIZE / SECTOR_SIZE) as u64);
+        let reader = ntfs.get_reader();
+        assert_eq!(reader.read_n(0, 0x3000).unwrap(), plain[0..0x3000]);
+        //The FVE metadata reads as zeros
+        assert!(reader.read_n(METADATA_OFFSETS[0], 16).unwrap().iter().all(|x| *x == 0));
+        assert_eq!(reader.read_n(0x4000, 0x1000).unwrap(), plain[0x4000..0x5000]);
+    }
+}
@@ -4,6 +4,31 @@ use crate::utils::MRError;
 
 use super::{Bitmap, DataDescriptor, MFTEntry, MFTValue, Ntfs};
 
+//Clusters of the range set in the $Bitmap content, clusters past its end count as used
+pub fn count_allocated(bits: &[u8], clusters: &Range<u64>) -> u64 {
+    let mut count = 0;
+    for cluster in clusters.clone() {
+        match bits.get((cluster / 8) as usize) {
+            Some(b) if (b >> (cluster % 8)) & 1 == 0 => {}
+            _ => count += 1,
+        }
+    }
+    count
+}
+
+impl Ntfs {
+    //Free cluster runs of $Bitmap, bits past the last cluster of the volume are dropped
+    pub fn get_unallocated_clusters(&mut self) -> Result<Vec<Range<u64>>, MRError> {
+        let total = self.get_total_clusters();
+        let (ranges, _) = self.get_bitmap()?.generate_unalloc()?;
+        Ok(ranges
+            .into_iter()
+            .map(|x| x.start as u64..(x.end as u64).min(total))
+            .filter(|x| x.start < x.end)
+            .collect())
+    }
+}
+
 impl Bitmap {
     pub fn from_mft(mft: MFTEntry, ntfs: &Ntfs) -> Result<Self, MRError> {
         Ok(Self {
@@ -129,8 +154,10 @@ impl Bitmap {
         let mut result = vec![];
         let ntfs = self.get_ntfs();
 
+        //A run longer than the bitmap of the whole volume is not one
+        let max_size = ntfs.get_total_clusters().div_ceil(8) + ntfs.get_cluster_size();
         for data in data_runs {
-            if data.datasize > 20 * 1024 * 1024 {
+            if data.datasize > max_size {
                 continue;
             }
             let tmp_data = ntfs
@@ -0,0 +1,266 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    hash::{Hash, Hasher},
+    ops::Range,
+};
+
+use bytes::{Buf, Bytes};
+
+use crate::utils::MRError;
+
+use super::{
+    journal_impl::match_usn_struct,
+    mft_impl::{apply_fixup, FIXUP_SECTOR_SIZE},
+    CarvedKind, CarvedRecord, IndexValue, MFTEntry, Ntfs, USNChangeJournalEntry,
+};
+
+//Bytes of the volume scanned at once
+const CARVE_CHUNK_SIZE: usize = 0x100000;
+//Attributes start right after the NTFS 3.1 entry header, MFTEntry::parse relies on it
+const MFT_ATTRIBUTES_OFFSET: usize = 0x38;
+const INDEX_ENTRY_HEADER_SIZE: usize = 24;
+const INDEX_NODE_HEADER_SIZE: usize = 16;
+
+fn le_u16(bs: &[u8], offset: usize) -> usize {
+    u16::from_le_bytes([bs[offset], bs[offset + 1]]) as usize
+}
+
+fn le_u32(bs: &[u8], offset: usize) -> usize {
+    u32::from_le_bytes([bs[offset], bs[offset + 1], bs[offset + 2], bs[offset + 3]]) as usize
+}
+
+//Walks the attribute headers so that parsing the entry never reads outside of it
+fn check_attributes(bs: &[u8]) -> bool {
+    let used_size = le_u32(bs, 24);
+    if le_u16(bs, 20) != MFT_ATTRIBUTES_OFFSET || used_size > bs.len() {
+        return false;
+    }
+    let mut offset = MFT_ATTRIBUTES_OFFSET;
+    let mut last_type = 0;
+    loop {
+        if offset + 4 > used_size {
+            return false;
+        }
+        let attr_type = le_u32(bs, offset);
+        if attr_type == 0xffffffff {
+            return true;
+        }
+        if attr_type < last_type || attr_type == 0 || attr_type > 0x100 || !attr_type.is_multiple_of(0x10) {
+            return false;
+        }
+        if offset + 16 > used_size {
+            return false;
+        }
+        let length = le_u32(bs, offset + 4);
+        if length < 24 || !length.is_multiple_of(8) || offset + length > used_size {
+            return false;
+        }
+        let name_length = bs[offset + 9] as usize;
+        let name_offset = le_u16(bs, offset + 10);
+        if name_length > 0 && name_offset + name_length * 2 > length {
+            return false;
+        }
+        match bs[offset + 8] {
+            0 => {
+                let data_size = le_u32(bs, offset + 16);
+                let data_offset = le_u16(bs, offset + 20);
+                if data_offset + data_size > length {
+                    return false;
+                }
+                let min_size = match attr_type {
+                    0x70 => 12,
+                    0x90 => 32,
+                    0xa0 => {
+                        return false;
+                    }
+                    _ => 0,
+                };
+                if data_size > 0 && data_size < min_size {
+                    return false;
+                }
+            }
+            1 => {
+                if length < 64 || offset + 72 > bs.len() {
+                    return false;
+                }
+                let run_offset = le_u16(bs, offset + 32);
+                if run_offset >= length {
+                    return false;
+                }
+                //ValueA0_IndexAlloction reads the first run without bound checks
+                if attr_type == 0xa0 {
+                    let header = bs[offset + run_offset] as usize;
+                    if run_offset + 1 + header % 16 + header / 16 > length {
+                        return false;
+                    }
+                }
+            }
+            _ => {
+                return false;
+            }
+        }
+        last_type = attr_type;
+        offset += length;
+    }
+}
+
+//A FILE record of the size used by the volume, returned with its fixups applied
+fn match_file_record(bs: &[u8], mft_size: usize) -> Option<Vec<u8>> {
+    if bs.len() < mft_size || &bs[0..4] != b"FILE" {
+        return None;
+    }
+    if le_u16(bs, 6) != mft_size / FIXUP_SECTOR_SIZE + 1 || le_u32(bs, 28) != mft_size {
+        return None;
+    }
+    let mut record = bs[0..mft_size].to_vec();
+    apply_fixup(&mut record).ok()?;
+    if !check_attributes(&record) {
+        return None;
+    }
+    Some(record)
+}
+
+//An INDX record, its size is taken from the node header
+pub(super) fn match_index_record(bs: &[u8]) -> Option<Vec<u8>> {
+    if bs.len() < INDEX_ENTRY_HEADER_SIZE + INDEX_NODE_HEADER_SIZE || &bs[0..4] != b"INDX" {
+        return None;
+    }
+    let values_offset = le_u32(bs, INDEX_ENTRY_HEADER_SIZE);
+    let node_size = le_u32(bs, INDEX_ENTRY_HEADER_SIZE + 4);
+    let allocated_size = le_u32(bs, INDEX_ENTRY_HEADER_SIZE + 8);
+    let size = allocated_size + INDEX_ENTRY_HEADER_SIZE;
+    if size > bs.len()
+        || !size.is_multiple_of(FIXUP_SECTOR_SIZE)
+        || le_u16(bs, 6) != size / FIXUP_SECTOR_SIZE + 1
+        || values_offset < INDEX_NODE_HEADER_SIZE
+        || values_offset > node_size
+        || node_size > allocated_size
+    {
+        return None;
+    }
+    let mut record = bs[0..size].to_vec();
+    apply_fixup(&mut record).ok()?;
+    Some(record)
+}
+
+impl CarvedRecord {
+    pub fn get_kind(&self) -> CarvedKind {
+        self.kind
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_data(&self) -> &Bytes {
+        &self.data
+    }
+
+    pub fn parse_mft(&self, ntfs: &Ntfs) -> Result<MFTEntry, MRError> {
+        if self.kind != CarvedKind::FileRecord {
+            return Err(MRError::new("Not a carved FILE record"));
+        }
+        let index = (&self.data[44..48]).get_u32_le() as u64;
+        MFTEntry::parse(self.data.clone(), ntfs, self.offset, index)
+    }
+
+    pub fn parse_usn(&self) -> Result<USNChangeJournalEntry, MRError> {
+        if self.kind != CarvedKind::UsnRecord {
+            return Err(MRError::new("Not a carved USN record"));
+        }
+        USNChangeJournalEntry::parse(self.data.clone())
+    }
+
+    //Live entries of an INDX record, each with its offset inside the record
+    pub fn parse_index_values(&self) -> Result<Vec<(usize, IndexValue)>, MRError> {
+        if self.kind != CarvedKind::IndexRecord {
+            return Err(MRError::new("Not a carved INDX record"));
+        }
+        let end = INDEX_ENTRY_HEADER_SIZE + le_u32(&self.data, INDEX_ENTRY_HEADER_SIZE + 4);
+        let mut offset = INDEX_ENTRY_HEADER_SIZE + le_u32(&self.data, INDEX_ENTRY_HEADER_SIZE);
+        let mut result = vec![];
+        while offset + INDEX_NODE_HEADER_SIZE <= end {
+            let size = (&self.data[offset + 8..offset + 10]).get_u16_le() as usize;
+            if size < INDEX_NODE_HEADER_SIZE || offset + size > end {
+                break;
+            }
+            let value = IndexValue::parse(self.data.slice(offset..offset + size))?;
+            //Last entry of the node, it has no key
+            if value.index_value_flags & 0x2 == 0x2 {
+                break;
+            }
+            result.push((offset, value));
+            offset += size;
+        }
+        Ok(result)
+    }
+}
+
+impl Ntfs {
+    //Scans byte ranges of the volume for FILE, INDX and USN records, the same record is only reported once
+    pub fn carve<F>(&self, ranges: &[Range<u64>], mut f: F) -> Result<(), MRError>
+    where
+        F: FnMut(&CarvedRecord, u64) -> bool,
+    {
+        let mft_size = self.get_mft_size();
+        //Enough to hold a record starting at the very end of a chunk
+        let overlap = mft_size.max(0x1000);
+        let mut seen: HashSet<(CarvedKind, u64)> = HashSet::new();
+        let mut scanned = 0;
+        for range in ranges {
+            let mut base = range.start;
+            while base < range.end {
+                let len = (CARVE_CHUNK_SIZE as u64).min(range.end - base) as usize;
+                let bs = match self.reader.read_n(base as usize, len + overlap) {
+                    Ok(o) => o,
+                    Err(_) => match self.reader.read_n(base as usize, len) {
+                        Ok(o) => o,
+                        Err(_) => break,
+                    },
+                };
+                let bs = Bytes::from(bs);
+                let mut offset = 0;
+                while offset < len.min(bs.len()) {
+                    let absolute = base + offset as u64;
+                    let mut found = None;
+                    if absolute.is_multiple_of(FIXUP_SECTOR_SIZE as u64) {
+                        if let Some(record) = match_file_record(&bs[offset..], mft_size) {
+                            found = Some((CarvedKind::FileRecord, record));
+                        } else if let Some(record) = match_index_record(&bs[offset..]) {
+                            found = Some((CarvedKind::IndexRecord, record));
+                        }
+                    }
+                    if found.is_none() {
+                        if let Some(size) = match_usn_struct(&bs.slice(offset..)) {
+                            found = Some((CarvedKind::UsnRecord, bs[offset..offset + size].to_vec()));
+                        }
+                    }
+                    let (kind, record) = match found {
+                        Some(s) => s,
+                        None => {
+                            offset += 8;
+                            continue;
+                        }
+                    };
+                    offset += record.len();
+                    let mut hasher = DefaultHasher::new();
+                    record.hash(&mut hasher);
+                    if !seen.insert((kind, hasher.finish())) {
+                        continue;
+                    }
+                    let carved = CarvedRecord {
+                        kind,
+                        offset: absolute,
+                        data: Bytes::from(record),
+                    };
+                    if !f(&carved, scanned + offset as u64) {
+                        return Ok(());
+                    }
+                }
+                scanned += len as u64;
+                base += len as u64;
+            }
+        }
+        Ok(())
+    }
+}
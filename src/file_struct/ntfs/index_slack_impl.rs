@@ -0,0 +1,177 @@
+use bytes::{Buf, Bytes};
+
+use crate::utils::MRError;
+
+use super::{
+    carve_impl::match_index_record, FileReference, FileTime, IndexSlackEntry, MFTEntry, MFTValue,
+    Value30_FileName, ValueA0_IndexAlloction,
+};
+
+const INDEX_ENTRY_HEADER_SIZE: usize = 24;
+const FILE_NAME_HEADER_SIZE: usize = 66;
+//FILETIMEs out of 1980..2100 are taken as random bytes
+const MIN_UNIX_TIME: u64 = 315532800;
+const MAX_UNIX_TIME: u64 = 4102444800;
+//Index blocks are 4KB unless the boot sector says otherwise
+const DEFAULT_INDEX_BLOCK_SIZE: usize = 0x1000;
+
+fn is_plausible_time(bs: &[u8]) -> bool {
+    let t = FileTime::parse_from_u64((&bs[0..8]).get_u64());
+    match t.get_unix_timestamp() {
+        Some(s) => (MIN_UNIX_TIME..MAX_UNIX_TIME).contains(&s),
+        None => false,
+    }
+}
+
+//Size of the $FILE_NAME key starting at bs[0], if it looks like one
+fn match_file_name(bs: &[u8]) -> Option<usize> {
+    if bs.len() < FILE_NAME_HEADER_SIZE {
+        return None;
+    }
+    let name_length = bs[64] as usize;
+    let size = FILE_NAME_HEADER_SIZE + name_length * 2;
+    if name_length == 0 || bs[65] > 3 || size > bs.len() {
+        return None;
+    }
+    //Parent sequence is never 0 for an entry in use
+    if u16::from_le_bytes([bs[6], bs[7]]) == 0 || bs[4] != 0 || bs[5] != 0 {
+        return None;
+    }
+    if !(8..40).step_by(8).all(|x| is_plausible_time(&bs[x..x + 8])) {
+        return None;
+    }
+    let name = bs[FILE_NAME_HEADER_SIZE..size]
+        .chunks_exact(2)
+        .map(|a| u16::from_le_bytes([a[0], a[1]]));
+    for c in char::decode_utf16(name) {
+        match c {
+            Ok(c) if !c.is_control() && c != '/' && c != '\\' => {}
+            _ => return None,
+        }
+    }
+    Some(size)
+}
+
+//Deleted or stale $FILE_NAME keys past index_node_size of an INDX record whose fixups are applied
+pub fn scan_index_slack(record: &[u8], base: u64) -> Vec<IndexSlackEntry> {
+    let mut result = vec![];
+    if record.len() < INDEX_ENTRY_HEADER_SIZE + 16 {
+        return result;
+    }
+    let node = &record[INDEX_ENTRY_HEADER_SIZE..];
+    let node_size = (&node[4..8]).get_u32_le() as usize;
+    let allocated_size = (&node[8..12]).get_u32_le() as usize;
+    let end = (INDEX_ENTRY_HEADER_SIZE + allocated_size).min(record.len());
+    //Index entries, and so their keys, are 8 bytes aligned
+    let mut offset = (INDEX_ENTRY_HEADER_SIZE + node_size).next_multiple_of(8);
+    while offset < end {
+        let size = match match_file_name(&record[offset..end]) {
+            Some(s) => s,
+            None => {
+                offset += 8;
+                continue;
+            }
+        };
+        let name = match Value30_FileName::parse(Bytes::copy_from_slice(&record[offset..offset + size])) {
+            Ok(o) => o,
+            Err(_) => {
+                offset += 8;
+                continue;
+            }
+        };
+        //The entry header survived if its key size still matches
+        let file_reference = if offset >= 16
+            && (&record[offset - 6..offset - 4]).get_u16_le() as usize == size
+        {
+            Some(FileReference::parse(Bytes::copy_from_slice(&record[offset - 16..offset - 8])))
+        } else {
+            None
+        };
+        result.push(IndexSlackEntry {
+            offset: base + offset as u64,
+            file_reference,
+            name,
+        });
+        offset += size.next_multiple_of(8);
+    }
+    result
+}
+
+impl IndexSlackEntry {
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_file_reference(&self) -> Option<&FileReference> {
+        self.file_reference.as_ref()
+    }
+
+    pub fn get_file_name(&self) -> &Value30_FileName {
+        &self.name
+    }
+}
+
+impl ValueA0_IndexAlloction {
+    //Scans the slack of every INDX record of the allocation
+    pub fn get_slack_entries(&self) -> Result<Vec<IndexSlackEntry>, MRError> {
+        let ntfs = self.get_ntfs();
+        let cluster_size = ntfs.get_cluster_size();
+        //(stream offset, device offset, size) of every run, records may cross two runs
+        let runs: Vec<(u64, u64, u64)> = if self.runs.is_empty() {
+            vec![(0, self.offset * cluster_size, self.size * cluster_size)]
+        } else {
+            self.runs
+                .iter()
+                .map(|x| (x.get_vcn(), x.get_start_addr(), x.get_datasize()))
+                .collect()
+        };
+        let stream_size = runs.iter().map(|x| x.0 + x.2).max().unwrap_or(0);
+        let mut bs = vec![0; stream_size as usize];
+        for (vcn, start, size) in &runs {
+            let data = ntfs.reader.read_n(*start as usize, *size as usize)?;
+            bs[*vcn as usize..(vcn + size) as usize].copy_from_slice(&data);
+        }
+        let to_device = |offset: u64| -> u64 {
+            match runs.iter().find(|x| (x.0..x.0 + x.2).contains(&offset)) {
+                Some(s) => s.1 + offset - s.0,
+                None => offset,
+            }
+        };
+
+        let mut result = vec![];
+        let mut offset = 0;
+        while offset < bs.len() {
+            let record = match match_index_record(&bs[offset..]) {
+                Some(s) => s,
+                None => {
+                    offset += DEFAULT_INDEX_BLOCK_SIZE;
+                    continue;
+                }
+            };
+            for mut entry in scan_index_slack(&record, offset as u64) {
+                entry.offset = to_device(entry.offset);
+                result.push(entry);
+            }
+            offset += record.len();
+        }
+        Ok(result)
+    }
+}
+
+impl MFTEntry {
+    //Deleted and renamed entries left in the slack of the $I30 index allocation
+    pub fn get_index_slack(&self) -> Result<Vec<IndexSlackEntry>, MRError> {
+        let mut result = vec![];
+        if let Some(indexs_a0) = self.map_attr_chains.get(&0xa0) {
+            for index in indexs_a0 {
+                if let MFTValue::IndexAlloc(is) = &index.value {
+                    if is.size == 0 {
+                        continue;
+                    }
+                    result.append(&mut is.get_slack_entries()?);
+                }
+            }
+        }
+        Ok(result)
+    }
+}
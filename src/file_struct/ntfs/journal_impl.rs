@@ -1,5 +1,4 @@
-
-use std::{collections::HashMap, fs, ptr::addr_of};
+use std::{collections::HashMap, fs, ops::Range};
 
 use bytes::{Buf, Bytes};
 
@@ -7,76 +6,221 @@ use crate::{
     file_struct::ntfs::{
         mft_impl::vec_u8_to_utf16string, FileReference, FileReference128, MFTValue, USNIdentifier,
     },
-    utils::{MRErrKind, MRError},
+    utils::{funcs::sub_bytes, MRErrKind, MRError},
+};
+
+use super::{
+    DataDescriptor, FileTime, MFTEntry, Ntfs, USNChangeJournal, USNChangeJournalEntry,
+    USNChangeJournalMetadata, USNFilter, USNNameRecord, USNPathResolver, USNRecordExtent,
 };
 
-use super::{DataDescriptor, FileTime, MFTEntry, Ntfs, USNChangeJournal, USNChangeJournalEntry};
+pub const USN_REASONS: [(u32, &str); 22] = [
+    (0x00000001, "DATA_OVERWRITE"),
+    (0x00000002, "DATA_EXTEND"),
+    (0x00000004, "DATA_TRUNCATION"),
+    (0x00000010, "NAMED_DATA_OVERWRITE"),
+    (0x00000020, "NAMED_DATA_EXTEND"),
+    (0x00000040, "NAMED_DATA_TRUNCATION"),
+    (0x00000100, "FILE_CREATE"),
+    (0x00000200, "FILE_DELETE"),
+    (0x00000400, "EA_CHANGE"),
+    (0x00000800, "SECURITY_CHANGE"),
+    (0x00001000, "RENAME_OLD_NAME"),
+    (0x00002000, "RENAME_NEW_NAME"),
+    (0x00004000, "INDEXABLE_CHANGE"),
+    (0x00008000, "BASIC_INFO_CHANGE"),
+    (0x00010000, "HARD_LINK_CHANGE"),
+    (0x00020000, "COMPRESSION_CHANGE"),
+    (0x00040000, "ENCRYPTION_CHANGE"),
+    (0x00080000, "OBJECT_ID_CHANGE"),
+    (0x00100000, "REPARSE_POINT_CHANGE"),
+    (0x00200000, "STREAM_CHANGE"),
+    (0x00400000, "TRANSACTED_CHANGE"),
+    (0x80000000, "CLOSE"),
+];
+
+pub const USN_REASON_DATA_OVERWRITE: u32 = 0x00000001;
+pub const USN_REASON_DATA_EXTEND: u32 = 0x00000002;
+pub const USN_REASON_DATA_TRUNCATION: u32 = 0x00000004;
+pub const USN_REASON_FILE_CREATE: u32 = 0x00000100;
+pub const USN_REASON_RENAME_OLD_NAME: u32 = 0x00001000;
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+const ROOT_MFT_INDEX: u64 = 5;
+//Deepest path followed before giving up on a parent loop
+const MAX_PATH_DEPTH: usize = 255;
+
+//Records are 8 bytes aligned and never cross a page of the journal
+const USN_PAGE_SIZE: u64 = 0x1000;
+//Bytes of $J held in memory at once
+const USN_CHUNK_SIZE: u64 = 0x100000;
+
+//Smallest record of each version, v4 without any extent
+pub const USN_RECORD_V2_SIZE: usize = 60;
+pub const USN_RECORD_V3_SIZE: usize = 76;
+pub const USN_RECORD_V4_SIZE: usize = 64;
+//A 255 characters name in a v3 record, rounded up
+pub const USN_RECORD_MAX_SIZE: u32 = 0x250;
+
+fn match_filetime(filetime: Bytes) -> bool {
+    let update_time = FileTime::parse_from_u64((&filetime[0..8]).get_u64());
+    update_time.get_unix_timestamp().is_some()
+}
+
+//Checks that bytes look like a USN record of any version, returns its size
+pub fn match_usn_struct(bs: &Bytes) -> Option<usize> {
+    if bs.len() < USN_RECORD_V4_SIZE {
+        return None;
+    }
+    let entry_size = (&bs[0..4]).get_u32_le();
+    if entry_size > USN_RECORD_MAX_SIZE || !entry_size.is_multiple_of(8) || entry_size as usize > bs.len() {
+        return None;
+    }
+    let majar_ver = (&bs[4..6]).get_u16_le();
+    let minjar_ver = (&bs[6..8]).get_u16_le();
+    if minjar_ver != 0 {
+        return None;
+    }
+
+    //Offset of the timestamp and of the name fields, v4 has neither
+    let (time_offset, name_field) = match majar_ver {
+        2 => (32, USN_RECORD_V2_SIZE - 4),
+        3 => (48, USN_RECORD_V3_SIZE - 4),
+        4 => {
+            let number_of_extents = (&bs[60..62]).get_u16_le() as usize;
+            let extent_size = (&bs[62..64]).get_u16_le() as usize;
+            if extent_size != 16 || USN_RECORD_V4_SIZE + number_of_extents * extent_size != entry_size as usize {
+                return None;
+            }
+            return Some(entry_size as usize);
+        }
+        _ => {
+            return None;
+        }
+    };
+    if (entry_size as usize) <= name_field + 4 {
+        return None;
+    }
+
+    if !match_filetime(bs.slice(time_offset..time_offset + 8)) {
+        return None;
+    }
+
+    let name_size = (&bs[name_field..name_field + 2]).get_u16_le() as usize;
+    let name_offset = (&bs[name_field + 2..name_field + 4]).get_u16_le() as usize;
+    if name_size == 0 || !name_size.is_multiple_of(2) || name_offset != name_field + 4 || name_offset + name_size > entry_size as usize {
+        return None;
+    }
+
+    Some(entry_size as usize)
+}
+
+impl FileReference128 {
+    //NTFS stores a 48 bits index and a 16 bits sequence in the low half, ReFS uses all 128 bits
+    fn parse(bs: &[u8]) -> Self {
+        let mut bs = bs;
+        let low = bs.get_u64_le();
+        let high = bs.get_u64_le();
+        if high == 0 {
+            return Self::from_u64(low);
+        }
+        Self {
+            mft_index: low,
+            seq_number: high,
+        }
+    }
+
+    fn from_u64(v: u64) -> Self {
+        Self {
+            mft_index: v & 0xffff_ffff_ffff,
+            seq_number: v >> 48,
+        }
+    }
 
-static mut USN_REASON: Option<HashMap<u32,&str>> = None;
-macro_rules! hashmap {
-    ($( $key: expr => $val: expr ),*) => {{
-         let mut map = ::std::collections::HashMap::new();
-         $( map.insert($key, $val); )*
-         map
-    }}
+    pub fn get_mft_index(&self) -> u64 {
+        self.mft_index
+    }
+
+    pub fn get_seq_number(&self) -> u64 {
+        self.seq_number
+    }
+}
+
+impl USNRecordExtent {
+    pub fn get_offset(&self) -> i64 {
+        self.offset
+    }
+
+    pub fn get_length(&self) -> i64 {
+        self.length
+    }
 }
+
 impl USNChangeJournalEntry {
     pub fn filetime(&self) -> Option<&FileTime> {
-        Some(&self.update_date)
+        self.update_date.as_ref()
     }
 
     pub fn get_time_string(&self) -> Option<String> {
-        self.update_date.to_native_date().map(|s| s.to_string())
+        self.update_date.as_ref()?.to_native_date().map(|s| s.to_string())
     }
 
     pub fn get_index(&self) -> u64 {
         self.reference.mft_index
     }
 
+    pub fn get_reference(&self) -> &FileReference128 {
+        &self.reference
+    }
+
+    pub fn get_parent_reference(&self) -> &FileReference128 {
+        &self.parent_reference
+    }
+
+    pub fn get_entry_size(&self) -> u32 {
+        self.entry_size
+    }
+
+    pub fn get_version(&self) -> (u16, u16) {
+        (self.major_version, self.minor_version)
+    }
+
+    pub fn get_usn(&self) -> u64 {
+        self.usn
+    }
+
+    pub fn get_reason_flags(&self) -> u32 {
+        self.update_reason_flags
+    }
+
+    pub fn get_source_flags(&self) -> u32 {
+        self.update_source_flags
+    }
+
+    pub fn get_file_attributes(&self) -> u32 {
+        self.file_attributes_flags
+    }
+
+    pub fn get_remaining_extents(&self) -> u32 {
+        self.remaining_extents
+    }
+
+    pub fn get_extents(&self) -> &Vec<USNRecordExtent> {
+        &self.extents
+    }
+
     pub fn filename(&self) -> &String {
         &self.name
     }
 
     pub fn get_timestamp(&self) -> u64 {
-        self.update_date.get_timestamp()
+        self.update_date.as_ref().map_or(0, |x| x.get_timestamp())
     }
 
     pub fn get_update_reason(&self) -> String {
-        if unsafe { USN_REASON.is_none() } {
-            let mut map = HashMap::new();
-            map.insert(0x00000001, "DATA_OVERWRITE");
-            map.insert(0x00000002, "DATA_EXTEND");
-            map.insert(0x00000004, "DATA_TRUNCATION");
-            map.insert(0x00000010, "NAMED_DATA_OVERWRITE");
-            map.insert(0x00000020, "NAMED_DATA_EXTEND");
-            map.insert(0x00000040, "NAMED_DATA_TRUNCATION");
-            map.insert(0x00000100, "FILE_CREATE");
-            map.insert(0x00000200, "FILE_DELETE");
-            map.insert(0x00000400, "EA_CHANGE");
-            map.insert(0x00000800, "SECURITY_CHANGE");
-            map.insert(0x00001000, "RENAME_OLD_NAME");
-            map.insert(0x00002000, "RENAME_NEW_NAME");
-            map.insert(0x00004000, "INDEXABLE_CHANGE");
-            map.insert(0x00008000, "BASIC_INFO_CHANGE");
-            map.insert(0x00010000, "HARD_LINK_CHANGE");
-            map.insert(0x00020000, "COMPRESSION_CHANGE");
-            map.insert(0x00040000, "ENCRYPTION_CHANGE");
-            map.insert(0x00080000, "OBJECT_ID_CHANGE");
-            map.insert(0x00100000, "REPARSE_POINT_CHANGE");
-            map.insert(0x00200000, "STREAM_CHANGE");
-            map.insert(0x00400000, "TRANSACTED_CHANGE");
-            map.insert(0x80000000, "CLOSE");
-            unsafe {
-                USN_REASON = Some(map);
-            }
-        }
-
         let mut reason = vec![];
-        if let Some(map) = unsafe { &*addr_of!(USN_REASON) } {
-            for i in map {
-                if self.update_reason_flags & i.0 != 0 {
-                    reason.push(*i.1);
-                }
+        for (flag, name) in USN_REASONS {
+            if self.update_reason_flags & flag != 0 {
+                reason.push(name);
             }
         }
 
@@ -84,41 +228,60 @@ impl USNChangeJournalEntry {
     }
 
     pub fn parse(bs: Bytes) -> Result<Self, MRError> {
-        if bs.len() < 60 || bs.len() % 8 != 0{
+        if bs.len() < 8 {
             return Err(MRError::new("size not right"));
         }
-        let size = (bs.get(0..4).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le();
-        let major_version = (bs.get(4..6).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u16_le();
-        let minor_version = (bs.get(6..8).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u16_le();
-        if major_version == 2 {
-            let file_ref = FileReference128 {
-                mft_index: (bs.get(8..12).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le() as u64,
-                seq_number: (bs.get(12..16).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le() as u64,
-            };
-            let parent_ref = FileReference128 {
-                mft_index: (bs.get(16..20).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le() as u64,
-                seq_number: (bs.get(20..24).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le() as u64,
-            };
-            let update_usn = (bs.get(24..32).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u64_le();
-            let update_time = FileTime::parse_from_u64((bs.get(32..40).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u64());
-            let update_reason = (bs.get(40..44).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le();
-            let update_flag = (bs.get(44..48).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le();
-            let security_d_id = (bs.get(48..52).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le();
-            let f_flag = (bs.get(52..56).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le();
-            let name_size = (bs.get(56..58).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u16_le();
-            let name_offset = (bs.get(58..60).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u16_le();
-            let name = {
-                if name_size == 0 || name_offset == 0 {
-                    Bytes::from(vec![])
-                } else {
-                    if name_offset as usize > bs.len() || name_offset as usize + name_size as usize > bs.len() {
-                        return Err(MRError::new("Error entry parse in name_offset or name_size"));
-                    }
-                    bs.slice(name_offset as usize..name_offset as usize + name_size as usize)
-                }
-            };
-
-            let name = vec_u8_to_utf16string(&name);
+        let size = (sub_bytes(&bs, 0..4)?).get_u32_le();
+        let major_version = (sub_bytes(&bs, 4..6)?).get_u16_le();
+        let minor_version = (sub_bytes(&bs, 6..8)?).get_u16_le();
+        let min_size = match major_version {
+            2 => USN_RECORD_V2_SIZE,
+            3 => USN_RECORD_V3_SIZE,
+            4 => USN_RECORD_V4_SIZE,
+            _ => {
+                return Err(MRError::new(
+                    "Not recognize the version of USNChangeJournal",
+                ));
+            }
+        };
+        if (size as usize) < min_size || size as usize > bs.len() || !size.is_multiple_of(8) {
+            return Err(MRError::new("size not right"));
+        }
+        let bs = bs.slice(0..size as usize);
+
+        //v2 uses 64 bits references, the common fields of v3 and v4 are shifted by 16 bytes
+        let (file_ref, parent_ref, base) = if major_version == 2 {
+            (
+                FileReference128::from_u64((sub_bytes(&bs, 8..16)?).get_u64_le()),
+                FileReference128::from_u64((sub_bytes(&bs, 16..24)?).get_u64_le()),
+                24,
+            )
+        } else {
+            (
+                FileReference128::parse(sub_bytes(&bs, 8..24)?),
+                FileReference128::parse(sub_bytes(&bs, 24..40)?),
+                40,
+            )
+        };
+        let update_usn = (sub_bytes(&bs, base..base + 8)?).get_u64_le();
+
+        if major_version == 4 {
+            let update_reason = (sub_bytes(&bs, 48..52)?).get_u32_le();
+            let update_flag = (sub_bytes(&bs, 52..56)?).get_u32_le();
+            let remaining_extents = (sub_bytes(&bs, 56..60)?).get_u32_le();
+            let number_of_extents = (sub_bytes(&bs, 60..62)?).get_u16_le() as usize;
+            let extent_size = (sub_bytes(&bs, 62..64)?).get_u16_le() as usize;
+            if extent_size < 16 {
+                return Err(MRError::new("Error entry parse in extent_size"));
+            }
+            let mut extents = vec![];
+            for i in 0..number_of_extents {
+                let offset = USN_RECORD_V4_SIZE + i * extent_size;
+                extents.push(USNRecordExtent {
+                    offset: (sub_bytes(&bs, offset..offset + 8)?).get_i64_le(),
+                    length: (sub_bytes(&bs, offset + 8..offset + 16)?).get_i64_le(),
+                });
+            }
             return Ok(USNChangeJournalEntry {
                 entry_size: size,
                 major_version,
@@ -126,66 +289,156 @@ impl USNChangeJournalEntry {
                 reference: file_ref,
                 parent_reference: parent_ref,
                 usn: update_usn,
-                update_date: update_time,
+                update_date: None,
                 update_reason_flags: update_reason,
                 update_source_flags: update_flag,
-                security_descriptor_id: security_d_id,
-                file_attributes_flags: f_flag,
-                name_size,
-                name_offset,
-                name,
+                security_descriptor_id: 0,
+                file_attributes_flags: 0,
+                name_size: 0,
+                name_offset: 0,
+                name: String::new(),
+                remaining_extents,
+                extents,
             });
-        } else if major_version == 3 {
-            if bs.len() < 76 {
-                return Err(MRError::new("size not right"));
+        }
+
+        let update_time = FileTime::parse_from_u64((sub_bytes(&bs, base + 8..base + 16)?).get_u64());
+        let update_reason = (sub_bytes(&bs, base + 16..base + 20)?).get_u32_le();
+        let update_flag = (sub_bytes(&bs, base + 20..base + 24)?).get_u32_le();
+        let security_d_id = (sub_bytes(&bs, base + 24..base + 28)?).get_u32_le();
+        let f_flag = (sub_bytes(&bs, base + 28..base + 32)?).get_u32_le();
+        let name_size = (sub_bytes(&bs, base + 32..base + 34)?).get_u16_le();
+        let name_offset = (sub_bytes(&bs, base + 34..base + 36)?).get_u16_le();
+        let name = {
+            if name_size == 0 || name_offset == 0 {
+                Bytes::from(vec![])
+            } else {
+                if name_offset as usize > bs.len() || name_offset as usize + name_size as usize > bs.len() {
+                    return Err(MRError::new("Error entry parse in name_offset or name_size"));
+                }
+                bs.slice(name_offset as usize..name_offset as usize + name_size as usize)
             }
-            let file_ref = FileReference128 {
-                mft_index: (bs.get(8..16).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u64_le(),
-                seq_number: (bs.get(16..24).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u64_le(),
-            };
-            let parent_ref = FileReference128 {
-                mft_index: (bs.get(24..32).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le() as u64,
-                seq_number: (bs.get(32..40).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le() as u64,
+        };
+
+        let name = vec_u8_to_utf16string(&name);
+        Ok(USNChangeJournalEntry {
+            entry_size: size,
+            major_version,
+            minor_version,
+            reference: file_ref,
+            parent_reference: parent_ref,
+            usn: update_usn,
+            update_date: Some(update_time),
+            update_reason_flags: update_reason,
+            update_source_flags: update_flag,
+            security_descriptor_id: security_d_id,
+            file_attributes_flags: f_flag,
+            name_size,
+            name_offset,
+            name,
+            remaining_extents: 0,
+            extents: vec![],
+        })
+    }
+}
+
+impl USNFilter {
+    //Accepts reason names joined by '|' (with or without the USN_REASON_ prefix) or a hex mask
+    pub fn parse_reason_mask(s: &str) -> Result<u32, MRError> {
+        if let Some(hex) = s.strip_prefix("0x") {
+            return u32::from_str_radix(hex, 16).map_err(|_| MRError::new("reason mask is not a hex number"));
+        }
+        let mut mask = 0;
+        for name in s.split('|') {
+            let name = name.trim().to_uppercase();
+            let name = name.strip_prefix("USN_REASON_").unwrap_or(&name);
+            match USN_REASONS.iter().find(|x| x.1 == name) {
+                Some(s) => mask |= s.0,
+                None => {
+                    return Err(MRError::new(&format!("Unknown usn reason {}", name)));
+                }
+            }
+        }
+        Ok(mask)
+    }
+
+    pub fn matches(&self, entry: &USNChangeJournalEntry) -> bool {
+        if self.index.is_some_and(|x| x != entry.reference.mft_index) {
+            return false;
+        }
+        if self.parent_index.is_some_and(|x| x != entry.parent_reference.mft_index) {
+            return false;
+        }
+        if self.reason_mask.is_some_and(|x| x & entry.update_reason_flags == 0) {
+            return false;
+        }
+        if self.after.is_some() || self.before.is_some() {
+            //v4 records have no timestamp and can not be placed in a range
+            let time = match entry.update_date.as_ref().and_then(|x| x.get_unix_timestamp()) {
+                Some(s) => s,
+                None => {
+                    return false;
+                }
             };
-            let update_usn = (bs.get(40..48).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u64_le();
-            let update_time = FileTime::parse_from_u64((bs.get(48..56).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u64());
-            let update_reason = (bs.get(56..60).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le();
-            let update_flag = (bs.get(60..64).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le();
-            let security_d_id = (bs.get(64..68).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le();
-            let f_flag = (bs.get(68..72).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le();
-            let name_size = (bs.get(72..74).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u16_le();
-            let name_offset = (bs.get(74..76).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u16_le();
-            let name = {
-                if name_size == 0 || name_offset == 0 {
-                    Bytes::from(vec![])
-                } else {
-                    if name_offset as usize > bs.len() || name_offset as usize + name_size as usize > bs.len() {
-                        return Err(MRError::new("Error entry parse in name_offset or name_size"));
-                    }
-                    bs.slice(name_offset as usize..name_offset as usize + name_size as usize)
+            if self.after.is_some_and(|x| time < x) || self.before.is_some_and(|x| time > x) {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.name {
+            if !regex.is_match(&entry.name) {
+                return false;
+            }
+        }
+        if !self.extensions.is_empty() {
+            let ext = match entry.name.rsplit_once('.') {
+                Some((_, ext)) => ext.to_lowercase(),
+                None => {
+                    return false;
                 }
             };
-            let name = vec_u8_to_utf16string(&name);
-            return Ok(USNChangeJournalEntry {
-                entry_size: size,
-                major_version,
-                minor_version,
-                reference: file_ref,
-                parent_reference: parent_ref,
-                usn: update_usn,
-                update_date: update_time,
-                update_reason_flags: update_reason,
-                update_source_flags: update_flag,
-                security_descriptor_id: security_d_id,
-                file_attributes_flags: f_flag,
-                name_size,
-                name_offset,
-                name,
-            });
+            if !self.extensions.contains(&ext) {
+                return false;
+            }
         }
-        Err(MRError::new(
-            "Not recognize the version of USNChangeJournal",
-        ))
+        true
+    }
+}
+
+impl USNChangeJournalMetadata {
+    pub fn parse(bs: &Bytes) -> Result<Self, MRError> {
+        let maximum_data = (sub_bytes(bs, 0..8)?).get_u64_le();
+        let allocation_data = (sub_bytes(bs, 8..16)?).get_u64_le();
+        let usn_identifier = (sub_bytes(bs, 16..24)?).get_u64_le();
+        let lowest_valid_usn = bs.get(24..32).map(|mut s| s.get_u64_le());
+        Ok(Self {
+            maximum_data,
+            allocation_data,
+            usn_identifier,
+            lowest_valid_usn,
+        })
+    }
+
+    pub fn get_maximum_size(&self) -> u64 {
+        self.maximum_data
+    }
+
+    pub fn get_allocation_delta(&self) -> u64 {
+        self.allocation_data
+    }
+
+    pub fn get_journal_id(&self) -> u64 {
+        self.usn_identifier
+    }
+
+    //The journal id is the creation time of the journal
+    pub fn get_journal_create_time(&self) -> Option<String> {
+        FileTime::parse_from_u64(self.usn_identifier.swap_bytes())
+            .to_native_date()
+            .map(|s| s.to_string())
+    }
+
+    pub fn get_lowest_valid_usn(&self) -> Option<u64> {
+        self.lowest_valid_usn
     }
 }
 
@@ -197,307 +450,312 @@ impl USNChangeJournal {
         })
     }
 
-    pub fn process_entry<F>(&self, mut handle: F) -> Result<(), MRError>
+    //Parses the records of a chunk, returns where unparsed bytes start and whether to go on
+    fn parse_chunk<F>(bs: &Bytes, mut handle: F) -> (usize, bool)
     where
-        F: FnMut(&USNChangeJournalEntry) -> bool,
+        F: FnMut(USNChangeJournalEntry) -> bool,
     {
-        let data = match self.read_all() {
-            Ok(o) => o,
-            Err(e) => {
-                return Err(e);
-            }
-        };
-
-        let bs = Bytes::from(data);
-        let mut i = 0;
         let mut offset = 0;
-        while offset < bs.len() {
-            let entry_size = (bs.get(offset..offset + 4).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le();
-            if entry_size == 0 || entry_size > 512 {
+        while offset + 8 <= bs.len() {
+            let entry_size = (&bs[offset..offset + 4]).get_u32_le();
+            if entry_size == 0 || entry_size > USN_RECORD_MAX_SIZE || !entry_size.is_multiple_of(8) {
                 offset += 0x8;
                 continue;
             }
-
+            //Cut by the end of the chunk
             if offset + entry_size as usize > bs.len() {
-                break;
+                return (offset, true);
             }
-            let v = USNChangeJournalEntry::parse(bs.slice(offset..offset + entry_size as usize));
-            let v = match v {
-                Ok(o) => o,
-                Err(e) => {
-                    //fs::write("./error_entry", bs.slice(offset..).to_vec());
+            match USNChangeJournalEntry::parse(bs.slice(offset..offset + entry_size as usize)) {
+                Ok(o) => {
+                    if !handle(o) {
+                        return (offset, false);
+                    }
+                    offset += entry_size as usize;
+                }
+                Err(_) => {
                     offset += 0x8;
-                    continue;
                 }
-            };
-            if !handle(&v) {
-                break;
             }
-            offset += entry_size as usize;
-            i += 1;
         }
-
-        Ok(())
+        (offset, true)
     }
 
-    fn get_data_runs(&self) -> Result<Vec<DataDescriptor>, MRError> {
-        let data_runs: Vec<DataDescriptor>;
-        if self.mft.map_attr_chains.contains_key(&0x20) {
-            let ntfs = self.get_ntfs();
-            let mut _data_runs: Option<Vec<DataDescriptor>> = None;
-            if let Some(attrs) = self.mft.map_attr_chains.get(&0x20) {
-                let attr = attrs.first().unwrap();
-                if let MFTValue::AttrList(attrlist) = &attr.value {
-                    let list = match &attrlist.list {
-                        Some(s) => s,
-                        None => {
-                            return Err(MRError::new("List is empty"));
-                        }
-                    };
-                    for l in list {
-                        if l.name.eq("$J") {
-                            let mft = match ntfs.get_mft_entry_by_index(l.file_reference.mft_index)
-                            {
-                                Some(s) => s,
-                                None => {
-                                    return Err(MRError::new("Not found mft"));
-                                }
-                            };
-
-                            let data = match mft.get_stream("$J") {
-                                Some(o) => o,
-                                None => {
-                                    return Err(MRError::new("Not found $J Stream, AttributeList"));
-                                }
-                            };
-                            let data = data.datas[1..].to_vec();
-                            _data_runs = Some(data);
-                        }
-                    }
+    //Allocated parts of $J as stream offsets, contiguous runs are merged and sparse runs left out
+    fn get_ranges(runs: &[DataDescriptor]) -> Vec<Range<u64>> {
+        let mut result: Vec<Range<u64>> = vec![];
+        for run in runs {
+            match result.last_mut() {
+                Some(last) if last.end == run.vcn => {
+                    last.end += run.datasize;
                 }
-            }
-            if _data_runs.is_none() {
-                return Err(MRError::new("Not found $J Attribute List"));
-            }
-
-            data_runs = _data_runs.unwrap();
-        } else {
-            let stream = match self.mft.get_stream("$J") {
-                Some(s) => s,
-                None => {
-                    return Err(MRError::new("Not found $J Stream, File"));
+                _ => {
+                    result.push(run.vcn..run.vcn + run.datasize);
                 }
-            };
-            if stream.datas[0].start_addr == 0 {
-                data_runs = stream.datas[1..].to_vec();
-            } else {
-                data_runs = stream.datas.to_vec();
             }
-            
         }
-
-        Ok(data_runs)
+        result
     }
 
-    fn get_ntfs(&self) -> &Ntfs {
-        unsafe { &* self.ntfs.unwrap() }
+    //Reads n bytes at a stream offset, sparse parts are zero
+    fn read_stream(&self, runs: &[DataDescriptor], offset: u64, n: u64) -> Result<Vec<u8>, MRError> {
+        let mut result = vec![0; n as usize];
+        let ntfs = self.get_ntfs();
+        for run in runs {
+            let start = offset.max(run.vcn);
+            let end = (offset + n).min(run.vcn + run.datasize);
+            if start >= end {
+                continue;
+            }
+            let bs = ntfs
+                .reader
+                .read_n((run.start_addr + start - run.vcn) as usize, (end - start) as usize)?;
+            result[(start - offset) as usize..(end - offset) as usize].copy_from_slice(&bs);
+        }
+        Ok(result)
     }
 
-    pub fn read_all(&self) -> Result<Vec<u8>, MRError> {
-        let data_runs: Vec<DataDescriptor> = match self.get_data_runs() {
-            Ok(o) => o,
-            Err(e) => {
-                return Err(e);
+    //Walks $J oldest record first, one chunk in memory at a time
+    pub fn process_entry<F>(&self, mut handle: F) -> Result<(), MRError>
+    where
+        F: FnMut(&USNChangeJournalEntry) -> bool,
+    {
+        let runs = self.get_data_runs()?;
+        for range in Self::get_ranges(&runs) {
+            let mut carry = vec![];
+            let mut offset = range.start;
+            while offset < range.end {
+                let end = ((offset + USN_CHUNK_SIZE) / USN_PAGE_SIZE * USN_PAGE_SIZE).min(range.end);
+                let mut bs = std::mem::take(&mut carry);
+                bs.extend(self.read_stream(&runs, offset, end - offset)?);
+                let bs = Bytes::from(bs);
+                let (rest, go_on) = Self::parse_chunk(&bs, |entry| handle(&entry));
+                if !go_on {
+                    return Ok(());
+                }
+                carry = bs[rest..].to_vec();
+                offset = end;
             }
-        };
+        }
 
-        let mut result = vec![];
-        let ntfs = self.get_ntfs();
+        Ok(())
+    }
 
-        for data in data_runs {
-            if data.datasize > 20*1024*1024 {
-                continue;
+    //Walks $J newest record first, chunks are page aligned so no record is cut
+    pub fn process_last<F>(&self, mut f: F) -> Result<(), MRError>
+    where
+        F: FnMut(&USNChangeJournalEntry) -> bool,
+    {
+        let runs = self.get_data_runs()?;
+        for range in Self::get_ranges(&runs).iter().rev() {
+            let mut end = range.end;
+            while end > range.start {
+                let start = (end.saturating_sub(USN_CHUNK_SIZE) / USN_PAGE_SIZE * USN_PAGE_SIZE).max(range.start);
+                let bs = Bytes::from(self.read_stream(&runs, start, end - start)?);
+                let mut entries = vec![];
+                Self::parse_chunk(&bs, |entry| {
+                    entries.push(entry);
+                    true
+                });
+                for entry in entries.iter().rev() {
+                    if !f(entry) {
+                        return Ok(());
+                    }
+                }
+                end = start;
             }
-            let tmp_data = ntfs
-                .reader
-                .read_n(data.start_addr as usize, data.datasize as usize)
-                .unwrap();
-            result.extend(tmp_data);
         }
-        Ok(result)
+        Ok(())
     }
 
-    fn read_data(&mut self, addr: usize, n: usize) -> Result<Vec<u8>, MRError> {
-        let data_runs: Vec<DataDescriptor> = match self.get_data_runs() {
-            Ok(o) => o,
-            Err(e) => {
-                return Err(e);
+    //Streams the records matching the filter
+    pub fn query<F>(&self, filter: &USNFilter, mut handle: F) -> Result<(), MRError>
+    where
+        F: FnMut(&USNChangeJournalEntry) -> bool,
+    {
+        self.process_entry(|entry| {
+            if !filter.matches(entry) {
+                return true;
             }
-        };
+            handle(entry)
+        })
+    }
 
-        let mut result = Vec::new();
-        let real_n = n;
-        let mut last_n = real_n as u64;
-        let mut last_addr = addr as u64;
-        let ntfs = self.get_ntfs();
-        for data in &data_runs {
-            if last_addr > data.datasize {
-                last_addr -= data.datasize;
-                continue;
+    pub fn query_last<F>(&self, filter: &USNFilter, mut handle: F) -> Result<(), MRError>
+    where
+        F: FnMut(&USNChangeJournalEntry) -> bool,
+    {
+        self.process_last(|entry| {
+            if !filter.matches(entry) {
+                return true;
             }
-            
-            let read_size = {
-                if n < data.datasize as usize {
-                    n
-                } else {
-                    data.datasize as usize
-                }
-            };
+            handle(entry)
+        })
+    }
 
-            let __offset = data.start_addr % 512;
-            let start_addr = data.start_addr - __offset;
-            let tmp_data = ntfs
-                .reader
-                .read_n(start_addr as usize, __offset as usize + read_size)
-                .unwrap();
-            let buffer_data: Vec<u8> = tmp_data[__offset as usize..].to_vec();
+    //Data runs of a stream of $UsnJrnl, following the attribute list when the stream is split over entries
+    fn get_stream_datas(&self, name: &str) -> Result<Vec<DataDescriptor>, MRError> {
+        Ok(self.mft.get_stream_runs(name)?.runs)
+    }
 
-            if last_addr < buffer_data.len() as u64 && last_n > buffer_data.len() as u64 - last_addr
-            {
-                let bs = buffer_data[(last_addr) as usize..(data.datasize) as usize].to_vec();
-                result.extend(bs);
-                last_n -= data.datasize - last_addr;
-                last_addr = 0;
+    fn get_data_runs(&self) -> Result<Vec<DataDescriptor>, MRError> {
+        self.get_stream_datas("$J")
+    }
 
-                continue;
-            }
+    //$UsnJrnl:$Max, a resident stream describing the journal
+    pub fn get_metadata(&self) -> Result<USNChangeJournalMetadata, MRError> {
+        let datas = self.get_stream_datas("$Max")?;
+        let data = datas.first().ok_or(MRError::new("Empty $Max Stream"))?;
+        let bs = self
+            .get_ntfs()
+            .reader
+            .read_n(data.start_addr as usize, data.datasize as usize)?;
+        USNChangeJournalMetadata::parse(&Bytes::from(bs))
+    }
 
-            if last_addr < buffer_data.len() as u64
-                && last_n <= buffer_data.len() as u64 - last_addr
-            {
-                let bs = buffer_data[(last_addr) as usize..(last_addr + last_n) as usize].to_vec();
-                result.extend(bs);
-                break;
-            }
-        }
+    fn get_ntfs(&self) -> &Ntfs {
+        unsafe { &* self.ntfs.unwrap() }
+    }
 
+    //Loads every allocated part of $J, process_entry should be used on large journals
+    pub fn read_all(&self) -> Result<Vec<u8>, MRError> {
+        let runs = self.get_data_runs()?;
+        let mut result = vec![];
+        for range in Self::get_ranges(&runs) {
+            result.extend(self.read_stream(&runs, range.start, range.end - range.start)?);
+        }
         Ok(result)
     }
 
-    pub fn read_first(&mut self) -> Result<USNChangeJournalEntry, MRError> {
-        let data = match self.read_data(0, 4096) {
-            Ok(o) => o,
-            Err(e) => {
-                return Err(MRError::new("Not found data"));
-            }
-        };
+    pub fn read_first(&self) -> Result<USNChangeJournalEntry, MRError> {
+        let mut result = None;
+        self.process_entry(|entry| {
+            result = Some(entry.clone());
+            false
+        })?;
+        result.ok_or(MRError::new("Not found data"))
+    }
 
-        USNChangeJournalEntry::parse(Bytes::from(data))
+    pub fn read_last(&self) -> Result<USNChangeJournalEntry, MRError> {
+        let mut result = None;
+        self.process_last(|entry| {
+            result = Some(entry.clone());
+            false
+        })?;
+        result.ok_or(MRError::new("Not found data"))
     }
 
-    pub fn read_last(&mut self) -> Result<Vec<USNChangeJournalEntry>, MRError> {
-        let data_runs: Vec<DataDescriptor> = match self.get_data_runs() {
-            Ok(o) => o,
-            Err(e) => {
-                return Err(e);
-            }
-        };
-        let ntfs = self.get_ntfs();
-        let data = &data_runs[data_runs.len()-1];
-        let tmp_data = ntfs
-            .reader
-            .read_n(data.start_addr as usize, data.datasize as usize)
-            .unwrap();
-        let mut offset = 0;
-        let bs = Bytes::from(tmp_data);
+    pub fn read_n_entry(&self, n: usize) -> Result<Vec<USNChangeJournalEntry>, MRError> {
         let mut result = vec![];
-        while offset < bs.len() {
-            let entry_size = (bs.get(offset..offset + 4).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le();
-            if entry_size == 0 || entry_size > 512 {
-                offset += 0x8;
-                continue;
-            }
-            let v = USNChangeJournalEntry::parse(bs.slice(offset..offset + entry_size as usize));
-            let v = match v {
-                Ok(o) => o,
-                Err(e) => {
-                    offset += 0x8;
-                    continue;
-                }
-            };
-            result.push(v);
-            offset += entry_size as usize;
-
+        if n == 0 {
+            return Ok(result);
         }
+        self.process_entry(|entry| {
+            result.push(entry.clone());
+            result.len() < n
+        })?;
         Ok(result)
     }
+}
 
-    pub fn process_last<F>(&mut self,mut f: F) -> Result<(), MRError> 
-    where F: FnMut(&USNChangeJournalEntry) -> bool {
-        let data_runs: Vec<DataDescriptor> = match self.get_data_runs() {
-            Ok(o) => o,
-            Err(e) => {
-                return Err(e);
-            }
-        };
-        let ntfs = self.get_ntfs();
-        let data = data_runs.last().unwrap();
-        let tmp_data = ntfs
-            .reader
-            .read_n(data.start_addr as usize, data.datasize as usize)
-            .unwrap();
-        let mut offset = 0;
-        let bs = Bytes::from(tmp_data);
-        while offset < bs.len() {
-            let entry_size = (bs.get(offset..offset + 4).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le();
-            if entry_size == 0 || entry_size > 512 {
-                offset += 0x8;
-                continue;
+impl USNPathResolver {
+    pub fn new(ntfs: &Ntfs) -> Self {
+        Self {
+            history: HashMap::new(),
+            live: HashMap::new(),
+            ntfs: Some(ntfs),
+        }
+    }
+
+    fn get_ntfs(&self) -> &Ntfs {
+        unsafe { &*self.ntfs.unwrap() }
+    }
+
+    //Records the name of a directory, entries must be added in journal order
+    pub fn add_entry(&mut self, entry: &USNChangeJournalEntry) {
+        if entry.file_attributes_flags & FILE_ATTRIBUTE_DIRECTORY == 0 || entry.name.is_empty() {
+            return;
+        }
+        let key = (entry.reference.mft_index, entry.reference.seq_number);
+        let history = self.history.entry(key).or_default();
+        if let Some(last) = history.last() {
+            if last.name == entry.name
+                && last.parent.mft_index == entry.parent_reference.mft_index
+                && last.reason & USN_REASON_RENAME_OLD_NAME == entry.update_reason_flags & USN_REASON_RENAME_OLD_NAME
+            {
+                return;
             }
-            let v = USNChangeJournalEntry::parse(bs.slice(offset..offset + entry_size as usize));
-            let v = match v {
-                Ok(o) => o,
-                Err(e) => {
-                    offset += 0x8;
-                    continue;
+        }
+        history.push(USNNameRecord {
+            usn: entry.usn,
+            reason: entry.update_reason_flags,
+            parent: entry.parent_reference.clone(),
+            name: entry.name.clone(),
+        });
+    }
+
+    //Name and parent of a directory at a given usn
+    fn lookup_history(&self, reference: &FileReference128, usn: u64) -> Option<(String, FileReference128)> {
+        let history = self.history.get(&(reference.mft_index, reference.seq_number))?;
+        //A RENAME_OLD_NAME record holds the name used before its usn
+        let record = history
+            .iter()
+            .rev()
+            .find(|x| x.usn <= usn && x.reason & USN_REASON_RENAME_OLD_NAME == 0)
+            .or_else(|| history.iter().find(|x| x.usn > usn))
+            .or(history.last())?;
+        Some((record.name.clone(), record.parent.clone()))
+    }
+
+    fn lookup_mft(&mut self, reference: &FileReference128) -> Option<(String, FileReference128)> {
+        if !self.live.contains_key(&reference.mft_index) {
+            let value = self.get_ntfs().get_mft_entry_by_index(reference.mft_index).and_then(|mft| {
+                if !mft.is_in_use() {
+                    return None;
                 }
-            };
-            if !f(&v) {
-                break;
+                let name = mft.filename()?;
+                let parent = FileReference128 {
+                    mft_index: mft.get_parent_index() as u64,
+                    seq_number: mft.get_parent_sequence()? as u64,
+                };
+                Some((mft.get_sequence() as u64, name, parent))
+            });
+            self.live.insert(reference.mft_index, value);
+        }
+        match self.live.get(&reference.mft_index)? {
+            //A different sequence means the entry was reused by another file
+            Some((seq, name, parent)) if *seq == reference.seq_number => {
+                Some((name.clone(), parent.clone()))
             }
-            offset += entry_size as usize;
-
+            _ => None,
         }
-        Ok(())
     }
 
-    pub fn read_n_entry(&mut self, n: usize) -> Result<Vec<USNChangeJournalEntry>, MRError> {
-        let mut result = vec![];
-        let size = n * 0x200;
-        let bs = match self.read_data(0, size) {
-            Ok(o) => o,
-            Err(e) => {
-                return Err(e);
+    //Full path of the record at the time it was written, false when a parent could not be found
+    pub fn resolve(&mut self, entry: &USNChangeJournalEntry) -> (String, bool) {
+        let mut names = vec![entry.name.clone()];
+        let mut parent = entry.parent_reference.clone();
+        while parent.mft_index != ROOT_MFT_INDEX {
+            if names.len() > MAX_PATH_DEPTH {
+                names.reverse();
+                return (format!("<{}>:{}", parent.mft_index, names.join("\\")), false);
             }
-        };
-
-        let bs = Bytes::from(bs);
-        let mut i = 0;
-        let mut offset = 0;
-        while i < n {
-            let entry_size = (bs.get(offset..offset + 4).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))?).get_u32_le();
-            let v = USNChangeJournalEntry::parse(bs.slice(offset..offset + entry_size as usize));
-            let v = match v {
-                Ok(o) => o,
-                Err(e) => {
-                    break;
-                }
+            let found = match self.lookup_history(&parent, entry.usn) {
+                Some(s) => Some(s),
+                None => self.lookup_mft(&parent),
             };
-            result.push(v);
-            offset += entry_size as usize;
-            i += 1;
+            match found {
+                Some((name, next)) => {
+                    names.push(name);
+                    parent = next;
+                }
+                None => {
+                    names.reverse();
+                    return (format!("<{}>:{}", parent.mft_index, names.join("\\")), false);
+                }
+            }
         }
-
-        Ok(result)
+        names.reverse();
+        (names.join("\\"), true)
     }
 }
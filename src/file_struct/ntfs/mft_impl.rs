@@ -1,12 +1,12 @@
-use std::{cell::RefCell, collections::HashMap, fs, io::Write, mem::align_of, panic};
+use std::{cell::RefCell, collections::HashMap, fs, io::Write, mem::align_of, ops::Range, panic};
 
 use bytes::{Buf, Bytes};
 use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
 
-use crate::utils::{funcs::sub_bytes, MRError};
+use crate::utils::{funcs::sub_bytes, MRErrKind, MRError};
 
 use super::{
-    CCommon, CNonResident, CResident, DataDescriptor, FileItem, FileReference, FileTime,
+    CCommon, CNonResident, CResident, DataDescriptor, EntryPath, FileItem, FileReference, FileTime,
     IndexEntryHeader, IndexNodeHeader, IndexRootHeader, IndexValue, MFTAttribute, MFTEntry,
     MFTStream, MFTValue, Ntfs, V20Attr, Value10_StandardInfomation, Value20_AttributeList,
     Value30_FileName, Value40_ObjectId, Value50_SecurityDescriptor, Value60_VolumeName,
@@ -17,30 +17,56 @@ pub fn long_to_short(name: &str, names: Vec<&str>) -> String {
     unimplemented!()
 }
 
+pub const FILE_NAME_POSIX: u8 = 0;
+pub const FILE_NAME_WIN32: u8 = 1;
+pub const FILE_NAME_DOS: u8 = 2;
+pub const FILE_NAME_WIN32_AND_DOS: u8 = 3;
+
+//Fixups always protect 512 bytes strides, whatever the sector size is
+pub const FIXUP_SECTOR_SIZE: usize = 512;
+
 fn align(n: usize, alignment: usize) -> usize {
     (alignment - n % alignment) + n
 }
 
 impl MFTEntry {
+    //The long name when the entry also has a DOS 8.3 name
     pub fn filename(&self) -> Option<String> {
-        let attr = match self.map_attr_chains.get(&0x30) {
-            Some(a) => a,
-            None => {
-                return None;
-            }
-        };
-        let attr = match attr.first() {
-            Some(s) => s,
-            None => {
-                return None;
-            }
-        };
-        if let MFTValue::FileName(s) = &attr.value {
-            return Some(s.name.to_string());
+        let names = self.get_file_names();
+        names
+            .iter()
+            .find(|x| x.name_space != FILE_NAME_DOS)
+            .or(names.first())
+            .map(|x| x.name.to_string())
+    }
+
+    pub fn get_short_name(&self) -> Option<String> {
+        self.get_file_names()
+            .iter()
+            .find(|x| x.name_space == FILE_NAME_DOS || x.name_space == FILE_NAME_WIN32_AND_DOS)
+            .map(|x| x.name.to_string())
+    }
+
+    pub fn get_standard_info(&self) -> Option<&Value10_StandardInfomation> {
+        if let MFTValue::StdInfo(s) = &self.map_attr_chains.get(&0x10)?.first()?.value {
+            return Some(s);
         }
         None
     }
 
+    //Every $FILE_NAME of the entry, the DOS name included
+    pub fn get_file_names(&self) -> Vec<&Value30_FileName> {
+        let mut result = vec![];
+        if let Some(attrs) = self.map_attr_chains.get(&0x30) {
+            for attr in attrs {
+                if let MFTValue::FileName(s) = &attr.value {
+                    result.push(s);
+                }
+            }
+        }
+        result
+    }
+
     pub fn get_stream(&self, name: &str) -> Option<&Value80_Data> {
         let attrs = match self.map_attr_chains.get(&0x80) {
             Some(o) => o,
@@ -95,32 +121,107 @@ impl MFTEntry {
         *self.parent_index.borrow()
     }
 
+    pub fn get_parent_sequence(&self) -> Option<u16> {
+        let attr = self.map_attr_chains.get(&0x30)?.first()?;
+        if let MFTValue::FileName(s) = &attr.value {
+            return Some(s.parent_seq_num);
+        }
+        None
+    }
+
+    //Volume range of the unnamed $DATA when it is stored inside the entry
+    pub fn get_resident_data_range(&self) -> Option<Range<u64>> {
+        let attr = self
+            .map_attr_chains
+            .get(&0x80)?
+            .iter()
+            .find(|x| x.attr_name.is_empty())?;
+        if attr.non_resident_flag != 0 {
+            return None;
+        }
+        if let MFTValue::Data(s) = &attr.value {
+            let data = s.datas.first()?;
+            return Some(data.start_addr..data.start_addr + data.datasize);
+        }
+        None
+    }
+
+    pub fn get_sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    pub fn get_lsn(&self) -> u64 {
+        self.journal_sequence_number
+    }
+
+    pub fn get_base_record(&self) -> u64 {
+        self.base_record
+    }
+
+    pub fn is_in_use(&self) -> bool {
+        self.entry_flags & 0x1 != 0
+    }
+
     pub fn contains_attr(&self, key: u32) -> bool {
         self.map_attr_chains.contains_key(&key)
     }
 
     pub fn fullpath(&self) -> Option<String> {
-        let mut index = 0;
         if self.index <= 13 {
             return Some(self.filename().unwrap());
         }
-        let mut mft: Option<MFTEntry> = None;
-        let mut names = vec![];
-        let filename = match self.filename() {
-            Some(s) => s,
-            None => {
-                names.reverse();
-                return Some(format!("<{}>:{}", self.get_index(), names.join("\\")));
+        match self.get_paths().into_iter().next() {
+            Some(s) => Some(s.path),
+            None => Some(format!("<{}>:", self.get_index())),
+        }
+    }
+
+    //Every hard link of the entry, each with its own parent chain
+    pub fn get_paths(&self) -> Vec<EntryPath> {
+        let names = self.get_file_names();
+        let mut result = vec![];
+        for name in names.iter().filter(|x| x.name_space != FILE_NAME_DOS) {
+            let path = if self.index <= 13 {
+                name.name.to_string()
+            } else {
+                self.build_path(name.name.to_string(), name.parent_file_num as i64)
+            };
+            //The DOS name lives in its own $FILE_NAME under the same parent
+            let short_name = match name.name_space {
+                FILE_NAME_WIN32 => names
+                    .iter()
+                    .find(|x| x.name_space == FILE_NAME_DOS && x.parent_file_num == name.parent_file_num)
+                    .map(|x| x.name.to_string()),
+                _ => None,
+            };
+            result.push(EntryPath {
+                path,
+                name_space: name.name_space,
+                parent_index: name.parent_file_num,
+                short_name,
+            });
+        }
+        //Only a DOS name survived, e.g. the other attribute is in a lost extension entry
+        if result.is_empty() {
+            if let Some(name) = names.first() {
+                result.push(EntryPath {
+                    path: self.build_path(name.name.to_string(), name.parent_file_num as i64),
+                    name_space: name.name_space,
+                    parent_index: name.parent_file_num,
+                    short_name: Some(name.name.to_string()),
+                });
             }
-        };
-        names.push(filename);
-        let ntfs = self.get_ntfs();
-        let parent_index = self.get_parent_index();
+        }
+        result
+    }
+
+    fn build_path(&self, filename: String, parent_index: i64) -> String {
+        let mut names = vec![filename];
         if parent_index < 5 {
-            names.reverse();
-            return Some(names.join("\\"));
+            return names.join("\\");
         }
-        index = parent_index;
+        let ntfs = self.get_ntfs();
+        let mut index = parent_index;
 
         while index > 13 {
             let mft = ntfs.get_mft_entry_by_index(index as u64);
@@ -128,14 +229,14 @@ impl MFTEntry {
                 Some(s) => s,
                 None => {
                     names.reverse();
-                    return Some(format!("<{}>:{}", index, names.join("\\")));
+                    return format!("<{}>:{}", index, names.join("\\"));
                 }
             };
             let filename = match mft.filename() {
                 Some(s) => s,
                 None => {
                     names.reverse();
-                    return Some(format!("<{}>:{}", index, names.join("\\")));
+                    return format!("<{}>:{}", index, names.join("\\"));
                 }
             };
 
@@ -143,7 +244,7 @@ impl MFTEntry {
             index = mft.get_parent_index();
         }
         names.reverse();
-        Some(names.join("\\"))
+        names.join("\\")
     }
 
     pub fn filename_creation_time(&self) -> Option<DateTime<Local>> {
@@ -301,7 +402,7 @@ impl MFTEntry {
         unimplemented!()
     }
 
-    fn get_ntfs(&self) -> &Ntfs{
+    pub(super) fn get_ntfs(&self) -> &Ntfs{
         unsafe { &*self.ntfs.unwrap() }
     }
 
@@ -466,6 +567,7 @@ impl MFTEntry {
         let entry_flags = (sub_bytes(&bs,22..24)?).get_u16_le();
         let used_size = (sub_bytes(&bs,24..28)?).get_u32_le();
         let total_size = (sub_bytes(&bs,28..32)?).get_u32_le();
+        let base_record = (sub_bytes(&bs,32..40)?).get_u64_le() & 0xffff_ffff_ffff;
 
         let mut map_attr_chains: HashMap<u32, Vec<MFTAttribute>> = HashMap::new();
         let mut base_addr = 56;
@@ -518,6 +620,7 @@ impl MFTEntry {
             entry_flags,
             used_size,
             total_size,
+            base_record,
             map_attr_chains,
             ntfs: Some(ntfs),
             index,
@@ -616,6 +719,7 @@ impl TryInto<Value10_StandardInfomation> for MFTValue {
 }
 
 impl MFTValue {
+    #[allow(clippy::needless_return)]
     pub fn parse(
         attr_type: u32,
         bs: Bytes,
@@ -676,9 +780,11 @@ impl MFTValue {
             };
             return Ok(MFTValue::IndexRoot(value));
         } else if attr_type == 0xa0 {
-            return Ok(MFTValue::IndexAlloc(
-                ValueA0_IndexAlloction::new(bs, ntfs, is_nonresident).unwrap(),
-            ));
+            let mut value = ValueA0_IndexAlloction::new(bs.clone(), ntfs, is_nonresident).unwrap();
+            if is_nonresident {
+                value.runs = Value80_Data::parse(index, bs, ntfs, is_nonresident, base, common)?.datas;
+            }
+            return Ok(MFTValue::IndexAlloc(value));
         } else {
             return Ok(MFTValue::None);
         }
@@ -708,6 +814,23 @@ impl FileTime {
         
         self.to_seconds(t).unwrap()
     }
+    //100ns intervals since 1601-01-01, as stored on disk
+    pub fn from_ticks(t: u64) -> Self {
+        Self {
+            low: t as u32,
+            high: (t >> 32) as u32,
+        }
+    }
+
+    pub fn get_ticks(&self) -> u64 {
+        ((self.high as u64) << 32) + self.low as u64
+    }
+
+    pub fn get_unix_timestamp(&self) -> Option<u64> {
+        let t = (self.high as u64) * num::pow(2_u64, 32) as u64 + self.low as u64;
+        self.to_seconds(t)
+    }
+
     pub fn to_native_date(&self) -> Option<DateTime<Local>> {
         let t = (self.high as u64) * num::pow(2_u64, 32) as u64 + self.low as u64;
         //NaiveDateTime::from_timestamp_opt(self.to_seconds(t) as i64, 0).unwrap();
@@ -781,6 +904,22 @@ impl Value10_StandardInfomation {
             update_sequence_num: None,
         })
     }
+
+    pub fn get_create_time(&self) -> &FileTime {
+        &self.file_create_time
+    }
+
+    pub fn get_change_time(&self) -> &FileTime {
+        &self.file_change_time
+    }
+
+    pub fn get_mft_change_time(&self) -> &FileTime {
+        &self.mft_change_time
+    }
+
+    pub fn get_access_time(&self) -> &FileTime {
+        &self.file_last_visited
+    }
 }
 
 impl Value20_AttributeList {
@@ -800,6 +939,9 @@ impl Value20_AttributeList {
                 let data_vcn = (sub_bytes(&bs,i + 8..i + 16)?).get_u64_le();
                 let file_reference = FileReference::parse(bs.slice(i + 16..i + 24));
                 let attribute_identifier = (sub_bytes(&bs,i + 24..i + 26)?).get_u16_le();
+                if size == 0 {
+                    break;
+                }
                 if i + name_offset as usize + 2 * name_size as usize > bs.len() {
                     i += size as usize;
                     let v20 = V20Attr {
@@ -848,6 +990,7 @@ impl Value30_FileName {
         }
         //let parent_file_num = (sub_bytes(&bs,0..8)?).get_u64_le();
         let parent_file_num = get_le_u64(bs.slice(0..6)).unwrap();
+        let parent_seq_num = (sub_bytes(&bs,6..8)?).get_u16_le();
         let creation_date = FileTime::parse_from_u64((sub_bytes(&bs,8..16)?).get_u64());
         let last_modify_time = FileTime::parse_from_u64((sub_bytes(&bs,16..24)?).get_u64());
         let mft_change_time = FileTime::parse_from_u64((sub_bytes(&bs,24..32)?).get_u64());
@@ -865,6 +1008,7 @@ impl Value30_FileName {
         let name = vec_u8_to_utf16string(&name_vec);
         Ok(Self {
             parent_file_num,
+            parent_seq_num,
             create_time: creation_date,
             change_time: last_modify_time,
             mft_change_time,
@@ -878,6 +1022,68 @@ impl Value30_FileName {
             name,
         })
     }
+
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn get_name_space(&self) -> u8 {
+        self.name_space
+    }
+
+    pub fn get_parent_index(&self) -> u64 {
+        self.parent_file_num
+    }
+
+    pub fn get_parent_sequence(&self) -> u16 {
+        self.parent_seq_num
+    }
+
+    pub fn get_create_time(&self) -> &FileTime {
+        &self.create_time
+    }
+
+    pub fn get_change_time(&self) -> &FileTime {
+        &self.change_time
+    }
+
+    pub fn get_mft_change_time(&self) -> &FileTime {
+        &self.mft_change_time
+    }
+
+    pub fn get_access_time(&self) -> &FileTime {
+        &self.last_visit_time
+    }
+
+    pub fn get_real_size(&self) -> u64 {
+        self.real_size
+    }
+
+    pub fn get_file_flags(&self) -> u32 {
+        self.file_flag
+    }
+}
+
+//Puts back the last two bytes of every sector from the update sequence array
+pub fn apply_fixup(bs: &mut [u8]) -> Result<(), MRError> {
+    if bs.len() < 8 {
+        return Err(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange));
+    }
+    let offset = u16::from_le_bytes([bs[4], bs[5]]) as usize;
+    let count = u16::from_le_bytes([bs[6], bs[7]]) as usize;
+    if count < 2 || offset + count * 2 > bs.len() || (count - 1) * FIXUP_SECTOR_SIZE > bs.len() {
+        return Err(MRError::new("Fixup array out of record"));
+    }
+    let check = [bs[offset], bs[offset + 1]];
+    for i in 1..count {
+        let end = i * FIXUP_SECTOR_SIZE;
+        if bs[end - 2..end] != check {
+            return Err(MRError::new("Fixup value not match"));
+        }
+        bs[end - 2] = bs[offset + i * 2];
+        bs[end - 1] = bs[offset + i * 2 + 1];
+    }
+    Ok(())
 }
 
 pub fn get_le_u64(bs: Bytes) -> Option<u64> {
@@ -950,6 +1156,7 @@ impl Value80_Data {
                 datas: vec![DataDescriptor {
                     datasize: filesize,
                     start_addr: base + offset,
+                    vcn: 0,
                 }],
             });
         }
@@ -957,6 +1164,7 @@ impl Value80_Data {
         let mut index = 0;
         let mut result = vec![];
         let mut cluster_number = 0;
+        let mut vcn = common.get_first_vcn();
         while index < bs.len() {
             let len = (sub_bytes(&bs,index..1 + index)?).get_u8();
             let filesize_len = len % 16;
@@ -983,8 +1191,10 @@ impl Value80_Data {
                 }
             };
 
+            //Sparse run, nothing is stored on disk
             if start_addr_len == 0 {
-                index = index + 1 + filesize as usize;
+                index = index + 1 + filesize_len as usize;
+                vcn += filesize;
                 continue;
             }
 
@@ -1027,7 +1237,9 @@ impl Value80_Data {
             let data = DataDescriptor {
                 datasize: filesize * ntfs.get_cluster_size(),
                 start_addr: offset,
+                vcn: vcn * ntfs.get_cluster_size(),
             };
+            vcn += filesize;
             
 
             if cluster_number
@@ -1039,6 +1251,7 @@ impl Value80_Data {
             let data = DataDescriptor {
                 datasize: data.datasize,
                 start_addr: cluster_number * ntfs.get_cluster_size(),
+                vcn: data.vcn,
             };
             // let _bs = ntfs.reader.read_n(data.start_addr as usize, 0x400).unwrap();
             //println!("{:?}", _bs);
@@ -1149,6 +1362,14 @@ impl FileReference {
             sequence_num: sequence as u16,
         }
     }
+
+    pub fn get_mft_index(&self) -> u64 {
+        self.mft_index
+    }
+
+    pub fn get_sequence(&self) -> u16 {
+        self.sequence_num
+    }
 }
 
 impl IndexValue {
@@ -1161,6 +1382,9 @@ impl IndexValue {
     }
 
     pub fn parse(bs: Bytes) -> Result<IndexValue, MRError> {
+        if bs.len() < 16 {
+            return Err(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange));
+        }
         let file_reference = FileReference::parse(bs.slice(0..8));
         let index_value_size = (sub_bytes(&bs,8..10)?).get_u16_le();
         let index_key_data_size = (sub_bytes(&bs,10..12)?).get_u16_le();
@@ -1183,6 +1407,9 @@ impl IndexValue {
             index_key_data = Some(filename);
             let value_offset = 16 + index_key_data_size as usize;
             if index_value_flags & 0x1 == 0x1 {
+                if value_offset + 8 > bs.len() {
+                    return Err(MRError::new("Structure error"));
+                }
                 index_value_data = Some(bs.slice(value_offset..bs.len() - 8).to_vec());
             } else {
                 index_value_data = Some(bs.slice(value_offset..).to_vec());
@@ -1228,6 +1455,7 @@ impl ValueA0_IndexAlloction {
                 size: 0,
                 node_header: RefCell::new(Some(vec![node_header])),
                 values: RefCell::new(Some(values)),
+                runs: vec![],
                 ntfs: Some(ntfs),
                 entry_header: RefCell::new(Some(vec![entry_header])),
             })
@@ -1256,6 +1484,7 @@ impl ValueA0_IndexAlloction {
                 size,
                 node_header: RefCell::new(None),
                 values: RefCell::new(None),
+                runs: vec![],
                 ntfs: Some(ntfs),
                 entry_header: RefCell::new(None),
             })
@@ -1266,7 +1495,11 @@ impl ValueA0_IndexAlloction {
         self.values.borrow().is_some()
     }
 
-    fn get_ntfs(&self) -> &Ntfs {
+    pub fn get_runs(&self) -> &Vec<DataDescriptor> {
+        &self.runs
+    }
+
+    pub(super) fn get_ntfs(&self) -> &Ntfs {
         unsafe { &*self.ntfs.unwrap() }
     }
 
@@ -1414,6 +1647,14 @@ impl CCommon {
         }
     }
 
+    pub fn get_first_vcn(&self) -> u64 {
+        match self {
+            Self::NonResident(c) => c.first_vcn,
+
+            Self::Resident(_) => 0,
+        }
+    }
+
     pub fn is_compress(&self) -> bool {
         if let Self::NonResident(c) = self {
             return c.compression_unit_size > 0;
@@ -1512,3 +1753,21 @@ impl MFTAttribute {
         self.attribute_flags & 0x4000 == 0x4000
     }
 }
+
+impl EntryPath {
+    pub fn get_path(&self) -> &String {
+        &self.path
+    }
+
+    pub fn get_name_space(&self) -> u8 {
+        self.name_space
+    }
+
+    pub fn get_parent_index(&self) -> u64 {
+        self.parent_index
+    }
+
+    pub fn get_short_name(&self) -> Option<&String> {
+        self.short_name.as_ref()
+    }
+}
@@ -0,0 +1,371 @@
+use std::{collections::HashMap, fs, path::Path, rc::Rc};
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::{file_struct::FileSlack, utils::MRError};
+
+use super::{
+    tree_impl::ROOT_INDEX, DataDescriptor, IndexExtent, MFTAttribute, MFTIndex, MFTTree, MFTValue,
+    Ntfs, TreeLink, TreeNode,
+};
+
+const MFT_INDEX_MAGIC: &[u8; 8] = b"MRMFTIX1";
+const LOGFILE_INDEX: u64 = 2;
+const RESTART_SIGNATURE: &[u8; 4] = b"RSTR";
+
+fn out_of_range() -> MRError {
+    MRError::new("Truncated MFT index file")
+}
+
+fn take_u64(bs: &mut Bytes) -> Result<u64, MRError> {
+    if bs.remaining() < 8 {
+        return Err(out_of_range());
+    }
+    Ok(bs.get_u64_le())
+}
+
+fn take_u16(bs: &mut Bytes) -> Result<u16, MRError> {
+    if bs.remaining() < 2 {
+        return Err(out_of_range());
+    }
+    Ok(bs.get_u16_le())
+}
+
+fn take_string(bs: &mut Bytes) -> Result<String, MRError> {
+    let size = take_u16(bs)? as usize;
+    if bs.remaining() < size {
+        return Err(out_of_range());
+    }
+    String::from_utf8(bs.split_to(size).to_vec()).map_err(|e| MRError::new(&e.to_string()))
+}
+
+fn put_string(bs: &mut Vec<u8>, s: &str) {
+    let s = &s.as_bytes()[..s.len().min(u16::MAX as usize)];
+    bs.put_u16_le(s.len() as u16);
+    bs.put_slice(s);
+}
+
+impl IndexExtent {
+    pub fn get_start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn get_length(&self) -> u64 {
+        self.length
+    }
+
+    pub fn get_index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn get_vcn(&self) -> u64 {
+        self.vcn
+    }
+
+    pub fn get_stream(&self) -> &String {
+        &self.stream
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+}
+
+//Runs of a non resident $DATA or $INDEX_ALLOCATION attribute and the stream name shown for it
+fn attr_runs(attr: &MFTAttribute) -> Option<(&Vec<DataDescriptor>, String)> {
+    if attr.non_resident_flag == 0 {
+        return None;
+    }
+    match &attr.value {
+        MFTValue::Data(data) => Some((&data.datas, attr.attr_name.to_string())),
+        MFTValue::IndexAlloc(alloc) => Some((alloc.get_runs(), format!("{}:$INDEX_ALLOCATION", attr.attr_name))),
+        _ => None,
+    }
+}
+
+impl MFTIndex {
+    pub fn get_tree(&self) -> &MFTTree {
+        &self.tree
+    }
+
+    pub fn get_extents(&self) -> &Vec<IndexExtent> {
+        &self.extents
+    }
+
+    //Extent holding a volume byte offset
+    pub fn find_extent(&self, offset: u64) -> Option<&IndexExtent> {
+        let i = self.extents.partition_point(|x| x.start <= offset);
+        let extent = self.extents.get(i.checked_sub(1)?)?;
+        if offset < extent.start + extent.length {
+            return Some(extent);
+        }
+        None
+    }
+
+    //Path from the root with a leading \\, "<index>:rest" when a parent is lost
+    pub fn get_full_path(&self, index: u64) -> String {
+        let path = self.tree.get_path(index);
+        match path.starts_with('<') {
+            true => path,
+            false => format!("\\{}", path),
+        }
+    }
+
+    //Bytes past the data size in the clusters of every stream
+    pub fn get_slacks(&self) -> Vec<FileSlack> {
+        let mut result = vec![];
+        for extent in &self.extents {
+            if extent.size == u64::MAX || extent.vcn + extent.length <= extent.size {
+                continue;
+            }
+            let skip = extent.size.saturating_sub(extent.vcn);
+            let mut path = self.get_full_path(extent.index);
+            if !extent.stream.is_empty() {
+                path = format!("{}:{}", path, extent.stream);
+            }
+            result.push(FileSlack::new(extent.start + skip, extent.length - skip, extent.index, path));
+        }
+        result
+    }
+
+    //Entry in use at a path, DOS names are not in the tree
+    pub fn lookup_path(&self, path: &str) -> Option<u64> {
+        let mut current = ROOT_INDEX;
+        for name in path.split('\\').filter(|x| !x.is_empty()) {
+            current = self
+                .tree
+                .get_children(current)
+                .iter()
+                .find(|(node, link)| node.in_use && link.name.eq_ignore_ascii_case(name))?
+                .0
+                .index;
+        }
+        Some(current)
+    }
+
+    pub fn is_current(&self, ntfs: &Ntfs) -> bool {
+        self.volume_serial == ntfs.get_volume_serial()
+            && self.mft_lsn == ntfs.get_mft_lsn()
+            && self.logfile_lsn == ntfs.get_logfile_lsn().unwrap_or(0)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), MRError> {
+        let mut bs = vec![];
+        bs.put_slice(MFT_INDEX_MAGIC);
+        bs.put_u64_le(self.volume_serial);
+        bs.put_u64_le(self.mft_lsn);
+        bs.put_u64_le(self.logfile_lsn);
+        let mut nodes = self.tree.nodes.values().collect::<Vec<&TreeNode>>();
+        nodes.sort_by_key(|x| x.index);
+        bs.put_u64_le(nodes.len() as u64);
+        for node in nodes {
+            bs.put_u64_le(node.index);
+            bs.put_u16_le(node.sequence);
+            bs.put_u16_le(node.in_use as u16 | (node.is_dir as u16) << 1);
+            bs.put_u64_le(node.size);
+            for t in node.times {
+                bs.put_u64_le(t);
+            }
+            bs.put_u16_le(node.links.len() as u16);
+            for link in &node.links {
+                bs.put_u64_le(link.parent_index);
+                bs.put_u16_le(link.parent_sequence);
+                put_string(&mut bs, &link.name);
+            }
+        }
+        bs.put_u64_le(self.extents.len() as u64);
+        for extent in &self.extents {
+            bs.put_u64_le(extent.start);
+            bs.put_u64_le(extent.length);
+            bs.put_u64_le(extent.index);
+            bs.put_u64_le(extent.vcn);
+            put_string(&mut bs, &extent.stream);
+            bs.put_u64_le(extent.size);
+        }
+        fs::write(path, bs).map_err(|e| MRError::new(&e.to_string()))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MRError> {
+        let mut bs = Bytes::from(fs::read(path).map_err(|e| MRError::new(&e.to_string()))?);
+        if bs.remaining() < 8 || bs.split_to(8) != MFT_INDEX_MAGIC.as_slice() {
+            return Err(MRError::new("Not an MFT index file"));
+        }
+        let mut result = Self {
+            volume_serial: take_u64(&mut bs)?,
+            mft_lsn: take_u64(&mut bs)?,
+            logfile_lsn: take_u64(&mut bs)?,
+            ..Default::default()
+        };
+        for _ in 0..take_u64(&mut bs)? {
+            let index = take_u64(&mut bs)?;
+            let sequence = take_u16(&mut bs)?;
+            let flags = take_u16(&mut bs)?;
+            let size = take_u64(&mut bs)?;
+            let mut times = [0; 4];
+            for t in times.iter_mut() {
+                *t = take_u64(&mut bs)?;
+            }
+            let mut links = vec![];
+            for _ in 0..take_u16(&mut bs)? {
+                links.push(TreeLink {
+                    parent_index: take_u64(&mut bs)?,
+                    parent_sequence: take_u16(&mut bs)?,
+                    name: take_string(&mut bs)?,
+                });
+            }
+            result.tree.insert(TreeNode {
+                index,
+                sequence,
+                in_use: flags & 0x1 != 0,
+                is_dir: flags & 0x2 != 0,
+                size,
+                times,
+                links,
+            });
+        }
+        for _ in 0..take_u64(&mut bs)? {
+            result.extents.push(IndexExtent {
+                start: take_u64(&mut bs)?,
+                length: take_u64(&mut bs)?,
+                index: take_u64(&mut bs)?,
+                vcn: take_u64(&mut bs)?,
+                stream: take_string(&mut bs)?,
+                size: take_u64(&mut bs)?,
+            });
+        }
+        Ok(result)
+    }
+}
+
+impl Ntfs {
+    pub fn get_volume_serial(&self) -> u64 {
+        self.reader
+            .read_n(0x48, 8)
+            .map(|x| u64::from_le_bytes([x[0], x[1], x[2], x[3], x[4], x[5], x[6], x[7]]))
+            .unwrap_or(0)
+    }
+
+    pub fn get_mft_lsn(&self) -> u64 {
+        self.get_mft_entry_by_index(0).map(|x| x.get_lsn()).unwrap_or(0)
+    }
+
+    //Highest current LSN of the two restart pages
+    pub fn get_logfile_lsn(&self) -> Option<u64> {
+        let logfile = self.get_mft_entry_by_index(LOGFILE_INDEX)?;
+        let runs = logfile.get_stream_runs("").ok()?;
+        let start = runs.get_runs().first()?.get_start_addr();
+        let mut result = None;
+        let mut offset = 0;
+        for _ in 0..2 {
+            let page = self.reader.read_n((start + offset) as usize, 0x200).ok()?;
+            if !page.starts_with(RESTART_SIGNATURE) {
+                break;
+            }
+            let page_size = u32::from_le_bytes([page[0x10], page[0x11], page[0x12], page[0x13]]) as u64;
+            let restart_offset = u16::from_le_bytes([page[0x18], page[0x19]]) as usize;
+            if let Some(lsn) = page.get(restart_offset..restart_offset + 8) {
+                let lsn = u64::from_le_bytes([lsn[0], lsn[1], lsn[2], lsn[3], lsn[4], lsn[5], lsn[6], lsn[7]]);
+                result = Some(result.map_or(lsn, |x: u64| x.max(lsn)));
+            }
+            if page_size == 0 {
+                break;
+            }
+            offset += page_size;
+        }
+        result
+    }
+
+    //One pass over the MFT for the tree and the clusters of every stream in use
+    pub fn build_index(&self) -> MFTIndex {
+        let mut result = MFTIndex {
+            volume_serial: self.get_volume_serial(),
+            mft_lsn: self.get_mft_lsn(),
+            logfile_lsn: self.get_logfile_lsn().unwrap_or(0),
+            ..Default::default()
+        };
+        //Only the attribute starting at vcn 0 holds the stream size
+        let mut sizes = HashMap::new();
+        self.iter_mft(|index, entry, _, _| {
+            let entry = match entry {
+                Ok(o) => o,
+                Err(_) => return,
+            };
+            if let Some(node) = TreeNode::from_entry(&entry) {
+                result.tree.insert(node);
+            }
+            if !entry.is_in_use() {
+                return;
+            }
+            //Extension entries hold streams of their base entry
+            let owner = match entry.get_base_record() {
+                0 => index,
+                s => s,
+            };
+            let attrs = [0x80, 0xa0].iter().filter_map(|x| entry.map_attr_chains.get(x)).flatten();
+            for attr in attrs {
+                let (runs, stream) = match attr_runs(attr) {
+                    Some(s) => s,
+                    None => continue,
+                };
+                if attr.common.get_first_vcn() == 0 {
+                    sizes.insert((owner, stream.clone()), attr.common.get_data_size() as u64);
+                }
+                for run in runs {
+                    result.extents.push(IndexExtent {
+                        start: run.start_addr,
+                        length: run.datasize,
+                        index: owner,
+                        vcn: run.vcn,
+                        stream: stream.clone(),
+                        size: 0,
+                    });
+                }
+            }
+        });
+        for extent in result.extents.iter_mut() {
+            extent.size = sizes.get(&(extent.index, extent.stream.clone())).copied().unwrap_or(u64::MAX);
+        }
+        result.extents.sort_by_key(|x| x.start);
+        result
+    }
+
+    pub fn get_loaded_index(&self) -> Option<Rc<MFTIndex>> {
+        self.mft_index.clone()
+    }
+
+    //Keeps a saved index when it still matches the volume, true if it was loaded
+    pub fn load_index<P: AsRef<Path>>(&mut self, path: P) -> bool {
+        if !path.as_ref().exists() {
+            return false;
+        }
+        match MFTIndex::load(path) {
+            Ok(o) if o.is_current(self) => {
+                self.mft_index = Some(Rc::new(o));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    //The loaded index, otherwise the saved one if current, otherwise a new one saved to path
+    pub fn get_index(&mut self, path: Option<&Path>) -> Rc<MFTIndex> {
+        if let Some(path) = path {
+            if self.mft_index.is_none() {
+                self.load_index(path);
+            }
+        }
+        if let Some(index) = &self.mft_index {
+            return index.clone();
+        }
+        println!("Loading mft....");
+        let index = self.build_index();
+        if let Some(path) = path {
+            if let Err(e) = index.save(path) {
+                println!("[Warning]: MFT index not saved, {}", e);
+            }
+        }
+        let index = Rc::new(index);
+        self.mft_index = Some(index.clone());
+        index
+    }
+}
@@ -9,6 +9,14 @@ pub mod mft_impl;
 pub mod journal_impl;
 pub mod fs_impl;
 pub mod bitmap_impl;
+pub mod carve_impl;
+pub mod index_slack_impl;
+pub mod vss_impl;
+pub mod bitlocker_impl;
+pub mod recover_impl;
+pub mod tree_impl;
+pub mod mft_index_impl;
+pub mod whose_impl;
 
 pub struct Ntfs {
     start_with                  : Vec<u8>,
@@ -24,7 +32,8 @@ pub struct Ntfs {
     version                     : Option<(u8,u8)>,
     reader                      : MRFile,
     datas_of_mft                : RefCell<Vec<DataDescriptor>>,
-    cache_mfts                  : Option<Vec<(Range<usize>, Rc<MFTEntry>)>>
+    //Loaded by load_index or get_index, path lookups use its tree
+    mft_index                   : Option<Rc<MFTIndex>>
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -94,6 +103,7 @@ pub struct Value20_AttributeList {
 #[derive(Debug, Clone)]
 pub struct Value30_FileName {
     parent_file_num     : u64,
+    parent_seq_num      : u16,
     create_time         : FileTime,
     change_time         : FileTime,
     mft_change_time     : FileTime,
@@ -136,9 +146,15 @@ pub struct Value70_VolumeInfomation {
 pub struct DataDescriptor {
     datasize    : u64,
     start_addr  : u64,
+    //Byte offset of the run inside the stream, sparse runs are not listed but still move it
+    vcn         : u64,
 }
 
 impl DataDescriptor {
+    pub fn get_vcn(&self) -> u64 {
+        self.vcn
+    }
+
     pub fn get_datasize(&self) -> u64 {
         self.datasize
     }
@@ -200,6 +216,26 @@ pub struct FileReference {
     sequence_num    : u16
 }
 
+//A $FILE_NAME key found past index_node_size of an INDX record
+#[derive(Debug)]
+pub struct IndexSlackEntry {
+    //Volume offset of the $FILE_NAME key
+    offset          : u64,
+    //Taken from the index entry header when it was not overwritten
+    file_reference  : Option<FileReference>,
+    name            : Value30_FileName
+}
+
+//One hard link of an entry, built from a non-DOS $FILE_NAME
+#[derive(Debug, Clone)]
+pub struct EntryPath {
+    path            : String,
+    name_space      : u8,
+    parent_index    : u64,
+    //8.3 name paired with this link, if the volume generated one
+    short_name      : Option<String>
+}
+
 #[derive(Debug)]
 pub struct Value90_IndexRoot {
     root_header     : IndexRootHeader,
@@ -219,6 +255,8 @@ pub struct ValueA0_IndexAlloction {
     entry_header: RefCell<Option<Vec<IndexEntryHeader>>>,
     node_header : RefCell<Option<Vec<IndexNodeHeader>>>,
     values      : RefCell<Option<Vec<IndexValue>>>,
+    //Every run of a non resident index allocation
+    runs        : Vec<DataDescriptor>,
     ntfs        : Option<*const Ntfs>
 }
 
@@ -291,6 +329,8 @@ pub struct MFTEntry {
     entry_flags                 : u16,
     used_size                   : u32,
     total_size                  : u32,
+    //MFT index of the base entry, 0 for a base entry
+    base_record                 : u64,
     map_attr_chains             : HashMap<u32,Vec<MFTAttribute>>,
     ntfs                        : Option<*const Ntfs>
 }
@@ -327,10 +367,14 @@ pub struct LFSRecordHeader {
     flags                               : u16,
 }
 
+#[derive(Debug, Clone)]
 pub struct USNChangeJournalMetadata {
     maximum_data        : u64,
     allocation_data     : u64,
-    usn_identifier      : FileTime
+    //FILETIME of the journal creation, changes every time the journal is recreated
+    usn_identifier      : u64,
+    //Only present since Windows 8
+    lowest_valid_usn    : Option<u64>
 }
 
 pub enum USNIdentifier {
@@ -358,13 +402,13 @@ pub enum USNIdentifier {
     USN_REASON_CLOSE
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FileReference128 {
     mft_index       : u64,
     seq_number      : u64
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct USNChangeJournalEntry {
     entry_size          : u32,
     major_version       : u16,
@@ -372,14 +416,24 @@ pub struct USNChangeJournalEntry {
     reference           : FileReference128,
     parent_reference    : FileReference128,
     usn                 : u64,
-    update_date         : FileTime,
+    //USN_RECORD_V4 carries no timestamp
+    update_date         : Option<FileTime>,
     update_reason_flags : u32,
     update_source_flags : u32,
     security_descriptor_id  : u32,
     file_attributes_flags   : u32,
     name_size               : u16,
     name_offset             : u16,
-    name                : String
+    name                : String,
+    //Range tracking of USN_RECORD_V4
+    remaining_extents       : u32,
+    extents                 : Vec<USNRecordExtent>
+}
+
+#[derive(Debug, Clone)]
+pub struct USNRecordExtent {
+    offset      : i64,
+    length      : i64
 }
 
 pub struct USNChangeJournal  {
@@ -387,7 +441,167 @@ pub struct USNChangeJournal  {
     ntfs    : Option<*const Ntfs>
 }
 
+#[derive(Debug, Clone)]
+pub struct USNNameRecord {
+    usn         : u64,
+    reason      : u32,
+    parent      : FileReference128,
+    name        : String
+}
+
+//Conditions a USN record must meet, unset fields match everything
+#[derive(Debug, Default)]
+pub struct USNFilter {
+    //Unix timestamps, inclusive
+    pub after           : Option<u64>,
+    pub before          : Option<u64>,
+    //Any of the reason flags
+    pub reason_mask     : Option<u32>,
+    pub name            : Option<regex::Regex>,
+    pub index           : Option<u64>,
+    pub parent_index    : Option<u64>,
+    //Lowercase, without the dot
+    pub extensions      : Vec<String>
+}
+
+pub struct USNPathResolver {
+    //Names a directory had over time, taken from the journal and keyed by (index, sequence)
+    history     : HashMap<(u64, u64), Vec<USNNameRecord>>,
+    //Live MFT lookups as (sequence, name, parent)
+    live        : HashMap<u64, Option<(u64, String, FileReference128)>>,
+    ntfs        : Option<*const Ntfs>
+}
+
 pub struct Bitmap {
     mft     : MFTEntry,
     ntfs    : Option<*const Ntfs>
-}
\ No newline at end of file
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CarvedKind {
+    FileRecord,
+    IndexRecord,
+    UsnRecord
+}
+
+#[derive(Debug, Clone)]
+pub struct CarvedRecord {
+    kind        : CarvedKind,
+    //Byte offset on the volume
+    offset      : u64,
+    //Record bytes with the fixups applied
+    data        : Bytes
+}
+
+//Runs of one $DATA stream, gathered over the extension entries of an attribute list
+#[derive(Debug, Clone)]
+pub struct StreamRuns {
+    runs        : Vec<DataDescriptor>,
+    //Real size taken from the attribute holding the first VCN
+    size        : u64,
+    resident    : bool,
+    compressed  : bool,
+    encrypted   : bool
+}
+
+//How many clusters of a deleted file $Bitmap gave to other files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recoverability {
+    Full,
+    Partial,
+    Overwritten
+}
+
+//A non-DOS $FILE_NAME of a tree node
+#[derive(Debug, Clone)]
+pub struct TreeLink {
+    parent_index    : u64,
+    parent_sequence : u16,
+    name            : String
+}
+
+//What one pass over the MFT keeps of an entry
+#[derive(Debug, Clone)]
+pub struct TreeNode {
+    index           : u64,
+    sequence        : u16,
+    in_use          : bool,
+    is_dir          : bool,
+    size            : u64,
+    //$STANDARD_INFORMATION creation, modification, mft change and access ticks
+    times           : [u64;4],
+    links           : Vec<TreeLink>
+}
+
+//Parent to children map of every entry with a $FILE_NAME, deleted ones included
+#[derive(Debug, Default)]
+pub struct MFTTree {
+    nodes           : HashMap<u64, TreeNode>,
+    children        : HashMap<u64, Vec<u64>>
+}
+
+//Clusters of one stream, in volume bytes
+#[derive(Debug, Clone)]
+pub struct IndexExtent {
+    start           : u64,
+    length          : u64,
+    index           : u64,
+    //Stream offset of the first byte
+    vcn             : u64,
+    //"" for the file content, "$I30:$INDEX_ALLOCATION" for directory index blocks
+    stream          : String,
+    //Data size of the stream, bytes past it are slack
+    size            : u64
+}
+
+//What the MFT gives, saved to disk so later runs skip the walk while the volume is unchanged
+#[derive(Debug, Default)]
+pub struct MFTIndex {
+    volume_serial   : u64,
+    //LSN of the $MFT entry
+    mft_lsn         : u64,
+    //Current LSN of the $LogFile restart area, moves on every metadata change
+    logfile_lsn     : u64,
+    tree            : MFTTree,
+    //Sorted by start, entries in use only
+    extents         : Vec<IndexExtent>
+}
+
+//Volume shadow copy header at 0x1e00 of the volume
+#[derive(Debug)]
+pub struct VSSVolumeHeader {
+    version             : u32,
+    catalog_offset      : u64,
+    maximum_size        : u64,
+    volume_id           : [u8;16],
+    storage_volume_id   : [u8;16]
+}
+
+#[derive(Debug, Clone)]
+pub struct VSSBlockDescriptor {
+    original_offset     : u64,
+    relative_offset     : u64,
+    store_offset        : u64,
+    flags               : u32,
+    bitmap              : u32
+}
+
+//One shadow copy, its blocks are the data overwritten on the volume after the previous snapshot
+#[derive(Debug)]
+pub struct VSSStore {
+    store_id            : [u8;16],
+    volume_size         : u64,
+    creation_time       : FileTime,
+    block_list_offset   : u64,
+    header_offset       : u64,
+    bitmap_offset       : u64,
+    shadow_copy_id      : [u8;16],
+    shadow_copy_set_id  : [u8;16],
+    attribute_flags     : u32,
+    operating_machine   : String,
+    service_machine     : String,
+    //Keyed by original offset, forwarders included
+    blocks              : HashMap<u64, VSSBlockDescriptor>,
+    overlays            : HashMap<u64, Vec<VSSBlockDescriptor>>,
+    //One bit per block, set for the blocks not in use when the snapshot was taken
+    bitmap              : Vec<u8>
+}
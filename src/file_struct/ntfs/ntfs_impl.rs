@@ -2,8 +2,9 @@ use std::{cell::RefCell, collections::HashMap, fs, io::Write, ops::Range, path::
 
 use bytes::{Buf, Bytes};
 
-use crate::utils::{
-    file::MRFile, MRErrKind, MRError
+use crate::{
+    file_struct::bitlocker::{fve_impl::BDE_SIGNATURE, BDEUnlockKey, BDEVolumeHeader},
+    utils::{file::MRFile, MRErrKind, MRError},
 };
 
 use super::{Bitmap, DataDescriptor, FileItem, MFTEntry, MFTValue, Ntfs, USNChangeJournal, Value20_AttributeList};
@@ -11,6 +12,7 @@ use super::{Bitmap, DataDescriptor, FileItem, MFTEntry, MFTValue, Ntfs, USNChang
 impl Ntfs {
     pub fn open<P>(img: P) -> Result<Ntfs, MRError>
     where P: AsRef<Path> + ToString {
+        let path = img.to_string();
         let mr_file = MRFile::new(img);
         let mr_file = match mr_file {
             Ok(file) => file,
@@ -18,14 +20,26 @@ impl Ntfs {
                 return Err(MRError::from(Box::new(e)));
             }
         };
+        //A suspended BitLocker volume opens without any key
+        if mr_file.read_n(3, 8)?.eq(BDE_SIGNATURE) {
+            //No key opens a Windows Vista volume
+            BDEVolumeHeader::parse(&Bytes::from(mr_file.read_n(0, 512)?))?;
+            return Self::open_bitlocker(&path, &BDEUnlockKey::ClearKey).map_err(|e| {
+                MRError::new(&format!("BitLocker volume, set recovery=${{password}} or bek=${{file}} ({})", e))
+            });
+        }
+        Self::from_reader(mr_file)
+    }
+
+    pub fn from_reader(mr_file: MRFile) -> Result<Ntfs, MRError> {
         let header = mr_file.read_n(0, 512).unwrap();
         let bep = header[0..3].to_vec();
         let signature = header[3..11].to_vec();
         let signature = String::from_utf8_lossy(&signature).to_string();
         
         let is_bitlocker: bool = signature.eq("-FVE-FS-");
-        if !signature.starts_with("NTFS") && is_bitlocker {
-            return Err(MRError::new("Not a valid NTFS image"));
+        if is_bitlocker {
+            return Err(MRError::new("BitLocker volume, it must be unlocked first"));
         }
 
         let header = Bytes::from(header);
@@ -64,7 +78,7 @@ impl Ntfs {
             is_bitlocker,
             version: None,
             datas_of_mft: RefCell::new(vec![]),
-            cache_mfts: None,
+            mft_index: None,
         })
     }
 
@@ -125,7 +139,7 @@ impl Ntfs {
         };
         let mut next = root_mft;
         for p in ps {
-            if p.eq("") {
+            if p.is_empty() {
                 continue;
             }
 
@@ -186,6 +200,11 @@ impl Ntfs {
     }
 
     pub fn get_mft_by_path(&mut self, path: &str) -> Result<MFTEntry, MRError> {
+        if let Some(index) = self.mft_index.as_ref().and_then(|x| x.lookup_path(path)) {
+            if let Some(entry) = self.get_mft_entry_by_index(index) {
+                return Ok(entry);
+            }
+        }
         let ps = path.split('\\').collect::<Vec<&str>>()[1..].to_vec();
         let root_mft = match self.get_root_mft() {
             Ok(o) => o,
@@ -195,7 +214,7 @@ impl Ntfs {
         };
         let mut next = root_mft;
         for p in ps {
-            if p.eq("") {
+            if p.is_empty() {
                 continue;
             }
             let subs = next.get_sub_files().unwrap();
@@ -238,6 +257,10 @@ impl Ntfs {
         self.bytes_per_sector as u64
     }
 
+    pub fn get_total_clusters(&self) -> u64 {
+        self.get_sector_num() * self.get_sector_bytes_num() / self.get_cluster_size()
+    }
+
     fn align(n: usize, alignment: usize) -> usize {
         (alignment - n % alignment) + n
     }
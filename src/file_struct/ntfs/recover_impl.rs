@@ -0,0 +1,188 @@
+use std::ops::Range;
+
+use crate::utils::MRError;
+
+use super::{
+    bitmap_impl::count_allocated,
+    mft_impl::{apply_fixup, FIXUP_SECTOR_SIZE},
+    DataDescriptor, MFTEntry, MFTValue, Ntfs, Recoverability, StreamRuns,
+};
+
+const RECOVER_CHUNK_SIZE: u64 = 0x100000;
+
+//Adds the attributes of the stream found in one entry, true if there was one
+fn add_stream_attrs(entry: &MFTEntry, name: &str, result: &mut StreamRuns) -> bool {
+    let attrs = match entry.map_attr_chains.get(&0x80) {
+        Some(s) => s,
+        None => return false,
+    };
+    let mut found = false;
+    for attr in attrs.iter().filter(|x| x.attr_name.eq(name)) {
+        found = true;
+        if let MFTValue::Data(d) = &attr.value {
+            result.runs.extend(d.datas.iter().cloned());
+        }
+        if attr.common.get_first_vcn() == 0 {
+            result.size = attr.common.get_data_size() as u64;
+            result.resident = attr.non_resident_flag == 0;
+            result.compressed = attr.common.is_compress();
+            result.encrypted = attr.is_encrypt();
+        }
+    }
+    found
+}
+
+impl MFTEntry {
+    //Runs of a named $DATA stream, "" is the file content
+    pub fn get_stream_runs(&self, name: &str) -> Result<StreamRuns, MRError> {
+        let mut result = StreamRuns {
+            runs: vec![],
+            size: 0,
+            resident: false,
+            compressed: false,
+            encrypted: false,
+        };
+        let mut found = false;
+        if let Some(MFTValue::AttrList(list)) = self.map_attr_chains.get(&0x20).and_then(|x| x.first()).map(|x| &x.value) {
+            let mut visited = vec![];
+            for l in list.list.iter().flatten() {
+                if l.attribute_type != 0x80 || !l.name.eq(name) || visited.contains(&l.file_reference.mft_index) {
+                    continue;
+                }
+                visited.push(l.file_reference.mft_index);
+                if l.file_reference.mft_index == self.index {
+                    found |= add_stream_attrs(self, name, &mut result);
+                    continue;
+                }
+                let entry = self
+                    .get_ntfs()
+                    .get_mft_entry_by_index(l.file_reference.mft_index)
+                    .ok_or(MRError::new("Not found mft"))?;
+                found |= add_stream_attrs(&entry, name, &mut result);
+            }
+        }
+        if !found && !add_stream_attrs(self, name, &mut result) {
+            return Err(MRError::new(&format!("Not found {} Stream, File", name)));
+        }
+        result.runs.sort_by_key(|x| x.vcn);
+        Ok(result)
+    }
+}
+
+impl StreamRuns {
+    pub fn get_runs(&self) -> &Vec<DataDescriptor> {
+        &self.runs
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_resident(&self) -> bool {
+        self.resident
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    //Clusters holding the first size bytes, the tail of the last cluster is slack
+    pub fn get_clusters(&self, cluster_size: u64) -> Vec<Range<u64>> {
+        if self.resident {
+            return vec![];
+        }
+        let mut result = vec![];
+        for run in &self.runs {
+            if run.vcn >= self.size {
+                continue;
+            }
+            let len = run.datasize.min(self.size - run.vcn);
+            let start = run.start_addr / cluster_size;
+            result.push(start..start + len.div_ceil(cluster_size));
+        }
+        result
+    }
+
+    //Clusters of the stream and how many of them are marked in use by $Bitmap
+    pub fn check_bitmap(&self, bits: &[u8], cluster_size: u64) -> (u64, u64) {
+        let clusters = self.get_clusters(cluster_size);
+        let total = clusters.iter().map(|x| x.end - x.start).sum();
+        let allocated = clusters.iter().map(|x| count_allocated(bits, x)).sum();
+        (allocated, total)
+    }
+
+    //Resident data is read from its record so the update sequence can be put back
+    fn read_resident(&self, ntfs: &Ntfs) -> Result<Vec<u8>, MRError> {
+        let run = match self.runs.first() {
+            Some(s) => s,
+            None => return Ok(vec![]),
+        };
+        let mft_size = ntfs.get_mft_size() as u64;
+        let mut start = run.start_addr / FIXUP_SECTOR_SIZE as u64 * FIXUP_SECTOR_SIZE as u64;
+        let lowest = start.saturating_sub(mft_size - FIXUP_SECTOR_SIZE as u64);
+        while start > lowest && ntfs.reader.read_n(start as usize, 4)? != b"FILE" {
+            start -= FIXUP_SECTOR_SIZE as u64;
+        }
+        let mut record = ntfs.reader.read_n(start as usize, mft_size as usize)?;
+        let offset = (run.start_addr - start) as usize;
+        if !record.starts_with(b"FILE") || offset + self.size as usize > record.len() {
+            return ntfs.reader.read_n(run.start_addr as usize, self.size as usize);
+        }
+        //A torn record keeps the sequence numbers, better than nothing
+        let _ = apply_fixup(&mut record);
+        Ok(record[offset..offset + self.size as usize].to_vec())
+    }
+
+    //Hands the content to f chunk by chunk, sparse parts are zero
+    pub fn read_chunks<F>(&self, ntfs: &Ntfs, mut f: F) -> Result<(), MRError>
+    where
+        F: FnMut(&[u8]) -> Result<(), MRError>,
+    {
+        if self.resident {
+            return f(&self.read_resident(ntfs)?);
+        }
+        let mut offset = 0;
+        while offset < self.size {
+            let n = (self.size - offset).min(RECOVER_CHUNK_SIZE);
+            let mut buf = vec![0; n as usize];
+            for run in &self.runs {
+                let start = offset.max(run.vcn);
+                let end = (offset + n).min(run.vcn + run.datasize);
+                if start >= end {
+                    continue;
+                }
+                let bs = ntfs
+                    .reader
+                    .read_n((run.start_addr + start - run.vcn) as usize, (end - start) as usize)?;
+                buf[(start - offset) as usize..(end - offset) as usize].copy_from_slice(&bs);
+            }
+            f(&buf)?;
+            offset += n;
+        }
+        Ok(())
+    }
+}
+
+impl Recoverability {
+    pub fn from_clusters(allocated: u64, total: u64) -> Self {
+        if allocated == 0 {
+            Self::Full
+        } else if allocated < total {
+            Self::Partial
+        } else {
+            Self::Overwritten
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Partial => "partial",
+            Self::Overwritten => "overwritten",
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+
+use super::{
+    mft_impl::FILE_NAME_DOS, MFTEntry, MFTTree, Ntfs, TreeLink, TreeNode,
+};
+
+pub const ROOT_INDEX: u64 = 5;
+
+//Real size of the unnamed $DATA, directories have none
+fn data_size(entry: &MFTEntry) -> u64 {
+    entry
+        .map_attr_chains
+        .get(&0x80)
+        .and_then(|x| {
+            x.iter()
+                .find(|a| a.attr_name.is_empty() && a.common.get_first_vcn() == 0)
+        })
+        .map(|a| a.common.get_data_size() as u64)
+        .unwrap_or(0)
+}
+
+impl TreeNode {
+    pub fn from_entry(entry: &MFTEntry) -> Option<Self> {
+        let names = entry.get_file_names();
+        let mut links = names
+            .iter()
+            .filter(|x| x.name_space != FILE_NAME_DOS)
+            .map(|x| TreeLink {
+                parent_index: x.parent_file_num,
+                parent_sequence: x.parent_seq_num,
+                name: x.name.to_string(),
+            })
+            .collect::<Vec<TreeLink>>();
+        //Only the DOS name is left
+        if links.is_empty() {
+            let x = names.first()?;
+            links.push(TreeLink {
+                parent_index: x.parent_file_num,
+                parent_sequence: x.parent_seq_num,
+                name: x.name.to_string(),
+            });
+        }
+        let times = match entry.get_standard_info() {
+            Some(si) => [
+                si.get_create_time().get_ticks(),
+                si.get_change_time().get_ticks(),
+                si.get_mft_change_time().get_ticks(),
+                si.get_access_time().get_ticks(),
+            ],
+            None => [0; 4],
+        };
+        Some(Self {
+            index: entry.get_index(),
+            sequence: entry.get_sequence(),
+            in_use: entry.is_in_use(),
+            is_dir: entry.get_flags() & 0x2 != 0,
+            size: data_size(entry),
+            times,
+            links,
+        })
+    }
+
+    pub fn get_index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn get_sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    pub fn is_in_use(&self) -> bool {
+        self.in_use
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn get_times(&self) -> &[u64; 4] {
+        &self.times
+    }
+
+    pub fn get_links(&self) -> &Vec<TreeLink> {
+        &self.links
+    }
+}
+
+impl TreeLink {
+    pub fn get_parent_index(&self) -> u64 {
+        self.parent_index
+    }
+
+    pub fn get_parent_sequence(&self) -> u16 {
+        self.parent_sequence
+    }
+
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+}
+
+impl MFTTree {
+    pub fn insert(&mut self, node: TreeNode) {
+        for link in &node.links {
+            //The root directory is its own parent
+            if link.parent_index == node.index {
+                continue;
+            }
+            let children = self.children.entry(link.parent_index).or_default();
+            if !children.contains(&node.index) {
+                children.push(node.index);
+            }
+        }
+        self.nodes.insert(node.index, node);
+    }
+
+    pub fn get_node(&self, index: u64) -> Option<&TreeNode> {
+        self.nodes.get(&index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    //The record the link points to is still the parent it was written for.
+    //Freeing a record increments its sequence, reusing it keeps that sequence
+    pub fn is_linked(&self, link: &TreeLink) -> bool {
+        match self.nodes.get(&link.parent_index) {
+            Some(p) => {
+                p.is_dir
+                    && (p.sequence == link.parent_sequence
+                        || (!p.in_use && p.sequence == link.parent_sequence.wrapping_add(1)))
+            }
+            None => false,
+        }
+    }
+
+    //Deleted entries none of whose parents can be trusted any more
+    pub fn is_orphan(&self, node: &TreeNode) -> bool {
+        node.index != ROOT_INDEX && node.links.iter().all(|x| !self.is_linked(x))
+    }
+
+    //Children of a directory, one item per link so hard links show in every parent
+    pub fn get_children(&self, index: u64) -> Vec<(&TreeNode, &TreeLink)> {
+        let mut result = vec![];
+        for child in self.children.get(&index).into_iter().flatten() {
+            let node = match self.nodes.get(child) {
+                Some(s) => s,
+                None => continue,
+            };
+            for link in node.links.iter().filter(|x| x.parent_index == index) {
+                if self.is_linked(link) {
+                    result.push((node, link));
+                }
+            }
+        }
+        result
+    }
+
+    //Path of the first link, "<index>:rest" when a parent can not be trusted like MFTEntry::fullpath
+    pub fn get_path(&self, index: u64) -> String {
+        let mut names = vec![];
+        let mut visited = HashSet::new();
+        let mut current = index;
+        while current != ROOT_INDEX && visited.insert(current) {
+            let link = match self.nodes.get(&current).and_then(|x| x.links.first()) {
+                Some(s) => s,
+                None => {
+                    names.reverse();
+                    return format!("<{}>:{}", current, names.join("\\"));
+                }
+            };
+            names.push(link.name.to_string());
+            if link.parent_index == ROOT_INDEX {
+                break;
+            }
+            if !self.is_linked(link) {
+                names.reverse();
+                return format!("<{}>:{}", link.parent_index, names.join("\\"));
+            }
+            current = link.parent_index;
+        }
+        names.reverse();
+        names.join("\\")
+    }
+
+    pub fn get_orphans(&self) -> Vec<&TreeNode> {
+        let mut result = self
+            .nodes
+            .values()
+            .filter(|x| !x.in_use && self.is_orphan(x))
+            .collect::<Vec<&TreeNode>>();
+        result.sort_by_key(|x| x.index);
+        result
+    }
+}
+
+impl Ntfs {
+    //One pass over the MFT
+    pub fn build_tree(&self) -> MFTTree {
+        let mut tree = MFTTree::default();
+        self.iter_mft(|_, entry, _, _| {
+            let entry = match entry {
+                Ok(o) => o,
+                Err(_) => return,
+            };
+            if let Some(node) = TreeNode::from_entry(&entry) {
+                tree.insert(node);
+            }
+        });
+        tree
+    }
+}
@@ -0,0 +1,439 @@
+use std::collections::{HashMap, HashSet};
+
+use bytes::{Buf, Bytes};
+
+use crate::utils::{
+    file::{BlockMap, BlockSource, MRFile},
+    funcs::sub_bytes,
+    MRError,
+};
+
+use super::{
+    mft_impl::vec_u8_to_utf16string, FileTime, Ntfs, VSSBlockDescriptor, VSSStore,
+    VSSVolumeHeader,
+};
+
+//{3808876b-c176-4e48-b7ae-04046e6cc752}
+const VSS_IDENTIFIER: [u8; 16] = [
+    0x6b, 0x87, 0x08, 0x38, 0x76, 0xc1, 0x48, 0x4e, 0xb7, 0xae, 0x04, 0x04, 0x6e, 0x6c, 0xc7, 0x52,
+];
+const VSS_VOLUME_HEADER_OFFSET: usize = 0x1e00;
+const VSS_BLOCK_SIZE: usize = 0x4000;
+const VSS_BLOCK_HEADER_SIZE: usize = 128;
+const VSS_CATALOG_ENTRY_SIZE: usize = 128;
+const VSS_BLOCK_DESCRIPTOR_SIZE: usize = 32;
+
+const VSS_RECORD_VOLUME_HEADER: u32 = 1;
+const VSS_RECORD_CATALOG: u32 = 2;
+const VSS_RECORD_BLOCK_LIST: u32 = 3;
+const VSS_RECORD_STORE_HEADER: u32 = 4;
+const VSS_RECORD_STORE_BITMAP: u32 = 6;
+
+const VSS_CATALOG_ENTRY_SNAPSHOT: u64 = 2;
+const VSS_CATALOG_ENTRY_STORE: u64 = 3;
+
+const VSS_BLOCK_FORWARDER: u32 = 0x1;
+const VSS_BLOCK_OVERLAY: u32 = 0x2;
+const VSS_BLOCK_NOT_USED: u32 = 0x4;
+
+//Catalog and block lists are chained, stop on a loop
+const VSS_MAX_CHAIN: usize = 0x100000;
+
+pub fn guid_to_string(bs: &[u8; 16]) -> String {
+    format!(
+        "{{{:08x}-{:04x}-{:04x}-{}-{}}}",
+        u32::from_le_bytes([bs[0], bs[1], bs[2], bs[3]]),
+        u16::from_le_bytes([bs[4], bs[5]]),
+        u16::from_le_bytes([bs[6], bs[7]]),
+        bs[8..10].iter().map(|x| format!("{:02x}", x)).collect::<String>(),
+        bs[10..16].iter().map(|x| format!("{:02x}", x)).collect::<String>()
+    )
+}
+
+fn read_guid(bs: &Bytes, offset: usize) -> Result<[u8; 16], MRError> {
+    let mut result = [0u8; 16];
+    result.copy_from_slice(sub_bytes(bs, offset..offset + 16)?);
+    Ok(result)
+}
+
+//Checks the common header of VSS blocks, returns the next block offset
+fn check_block(bs: &Bytes, record_type: u32) -> Result<u64, MRError> {
+    if sub_bytes(bs, 0..16)?[..] != VSS_IDENTIFIER
+        || (sub_bytes(bs, 20..24)?).get_u32_le() != record_type
+    {
+        return Err(MRError::new(&format!("Not a VSS block of type {}", record_type)));
+    }
+    Ok((sub_bytes(bs, 40..48)?).get_u64_le())
+}
+
+impl VSSVolumeHeader {
+    pub fn parse(bs: &Bytes) -> Result<Self, MRError> {
+        check_block(bs, VSS_RECORD_VOLUME_HEADER)
+            .map_err(|_| MRError::new("No volume shadow copy on the volume"))?;
+        Ok(Self {
+            version: (sub_bytes(bs, 16..20)?).get_u32_le(),
+            catalog_offset: (sub_bytes(bs, 48..56)?).get_u64_le(),
+            maximum_size: (sub_bytes(bs, 56..64)?).get_u64_le(),
+            volume_id: read_guid(bs, 64)?,
+            storage_volume_id: read_guid(bs, 80)?,
+        })
+    }
+
+    pub fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn get_catalog_offset(&self) -> u64 {
+        self.catalog_offset
+    }
+
+    pub fn get_maximum_size(&self) -> u64 {
+        self.maximum_size
+    }
+
+    pub fn get_volume_id(&self) -> String {
+        guid_to_string(&self.volume_id)
+    }
+
+    pub fn get_storage_volume_id(&self) -> String {
+        guid_to_string(&self.storage_volume_id)
+    }
+}
+
+impl VSSBlockDescriptor {
+    pub fn parse(bs: &Bytes) -> Result<Self, MRError> {
+        Ok(Self {
+            original_offset: (sub_bytes(bs, 0..8)?).get_u64_le(),
+            relative_offset: (sub_bytes(bs, 8..16)?).get_u64_le(),
+            store_offset: (sub_bytes(bs, 16..24)?).get_u64_le(),
+            flags: (sub_bytes(bs, 24..28)?).get_u32_le(),
+            bitmap: (sub_bytes(bs, 28..32)?).get_u32_le(),
+        })
+    }
+
+    pub fn is_forwarder(&self) -> bool {
+        self.flags & VSS_BLOCK_FORWARDER != 0
+    }
+
+    pub fn is_overlay(&self) -> bool {
+        self.flags & VSS_BLOCK_OVERLAY != 0
+    }
+}
+
+impl VSSStore {
+    pub fn get_store_id(&self) -> String {
+        guid_to_string(&self.store_id)
+    }
+
+    pub fn get_shadow_copy_id(&self) -> String {
+        guid_to_string(&self.shadow_copy_id)
+    }
+
+    pub fn get_shadow_copy_set_id(&self) -> String {
+        guid_to_string(&self.shadow_copy_set_id)
+    }
+
+    pub fn get_volume_size(&self) -> u64 {
+        self.volume_size
+    }
+
+    pub fn get_creation_time(&self) -> &FileTime {
+        &self.creation_time
+    }
+
+    pub fn get_attribute_flags(&self) -> u32 {
+        self.attribute_flags
+    }
+
+    pub fn get_operating_machine(&self) -> &String {
+        &self.operating_machine
+    }
+
+    pub fn get_service_machine(&self) -> &String {
+        &self.service_machine
+    }
+
+    pub fn get_block_count(&self) -> usize {
+        self.blocks.len() + self.overlays.values().map(|x| x.len()).sum::<usize>()
+    }
+
+    fn parse_header(&mut self, reader: &MRFile) -> Result<(), MRError> {
+        let bs = Bytes::from(reader.read_n(self.header_offset as usize, VSS_BLOCK_SIZE)?);
+        check_block(&bs, VSS_RECORD_STORE_HEADER)?;
+        let info = bs.slice(VSS_BLOCK_HEADER_SIZE..);
+        self.shadow_copy_id = read_guid(&info, 16)?;
+        self.shadow_copy_set_id = read_guid(&info, 32)?;
+        self.attribute_flags = (sub_bytes(&info, 56..60)?).get_u32_le();
+        //Both machine names are prefixed by their size in bytes
+        let mut offset = 64;
+        let mut names = vec![];
+        for _ in 0..2 {
+            let size = (sub_bytes(&info, offset..offset + 2)?).get_u16_le() as usize;
+            names.push(vec_u8_to_utf16string(sub_bytes(&info, offset + 2..offset + 2 + size)?));
+            offset += 2 + size;
+        }
+        self.service_machine = names.pop().unwrap_or_default();
+        self.operating_machine = names.pop().unwrap_or_default();
+        Ok(())
+    }
+
+    //The bitmap blocks are chained like the block list
+    fn parse_bitmap(&mut self, reader: &MRFile) -> Result<(), MRError> {
+        let mut offset = self.bitmap_offset;
+        let mut seen = HashSet::new();
+        while offset != 0 && seen.insert(offset) && seen.len() < VSS_MAX_CHAIN {
+            let bs = Bytes::from(reader.read_n(offset as usize, VSS_BLOCK_SIZE)?);
+            let next = check_block(&bs, VSS_RECORD_STORE_BITMAP)?;
+            self.bitmap.extend_from_slice(&bs[VSS_BLOCK_HEADER_SIZE..]);
+            offset = next;
+        }
+        Ok(())
+    }
+
+    fn parse_block_list(&mut self, reader: &MRFile) -> Result<(), MRError> {
+        let mut offset = self.block_list_offset;
+        let mut seen = HashSet::new();
+        while offset != 0 && seen.insert(offset) && seen.len() < VSS_MAX_CHAIN {
+            let bs = Bytes::from(reader.read_n(offset as usize, VSS_BLOCK_SIZE)?);
+            let next = check_block(&bs, VSS_RECORD_BLOCK_LIST)?;
+            let mut index = VSS_BLOCK_HEADER_SIZE;
+            while index + VSS_BLOCK_DESCRIPTOR_SIZE <= VSS_BLOCK_SIZE {
+                let descriptor =
+                    VSSBlockDescriptor::parse(&bs.slice(index..index + VSS_BLOCK_DESCRIPTOR_SIZE))?;
+                index += VSS_BLOCK_DESCRIPTOR_SIZE;
+                if descriptor.flags & VSS_BLOCK_NOT_USED != 0
+                    || (descriptor.original_offset == 0
+                        && descriptor.relative_offset == 0
+                        && descriptor.store_offset == 0)
+                {
+                    continue;
+                }
+                if descriptor.is_overlay() {
+                    self.overlays
+                        .entry(descriptor.original_offset)
+                        .or_default()
+                        .push(descriptor);
+                } else {
+                    self.blocks.insert(descriptor.original_offset, descriptor);
+                }
+            }
+            offset = next;
+        }
+        Ok(())
+    }
+}
+
+//Where a block of the snapshot at stores[0] is: the first store holding a copy made after the snapshot, else the volume
+fn resolve_block(stores: &[VSSStore], offset: u64) -> Option<Vec<BlockSource>> {
+    let mut current = offset;
+    let mut overlays = vec![];
+    let mut base = None;
+    for store in stores {
+        if let Some(s) = store.overlays.get(&current) {
+            overlays.push(s);
+        }
+        match store.blocks.get(&current) {
+            //Same data as another block at a later point in time
+            Some(s) if s.is_forwarder() => {
+                current = s.relative_offset;
+            }
+            Some(s) => {
+                base = Some(s.store_offset);
+                break;
+            }
+            None => {}
+        }
+    }
+    let base = base.unwrap_or(current);
+    if base == offset && overlays.is_empty() {
+        return None;
+    }
+    let mut result = vec![BlockSource::Base(base)];
+    //Overlays of the oldest store are the closest to the snapshot, they are applied last
+    for s in overlays.iter().rev() {
+        for descriptor in s.iter() {
+            result.push(BlockSource::Overlay {
+                offset: descriptor.store_offset,
+                sectors: descriptor.bitmap,
+            });
+        }
+    }
+    Some(result)
+}
+
+impl Ntfs {
+    pub fn get_vss_header(&self) -> Result<VSSVolumeHeader, MRError> {
+        let bs = self.reader.read_n(VSS_VOLUME_HEADER_OFFSET, 512)?;
+        VSSVolumeHeader::parse(&Bytes::from(bs))
+    }
+
+    //Shadow copies of the volume, oldest first
+    pub fn get_vss_stores(&self) -> Result<Vec<VSSStore>, MRError> {
+        let header = self.get_vss_header()?;
+        let mut snapshots: HashMap<[u8; 16], (u64, FileTime)> = HashMap::new();
+        let mut stores = vec![];
+        let mut offset = header.catalog_offset;
+        let mut seen = HashSet::new();
+        while offset != 0 && seen.insert(offset) && seen.len() < VSS_MAX_CHAIN {
+            let bs = Bytes::from(self.reader.read_n(offset as usize, VSS_BLOCK_SIZE)?);
+            let next = check_block(&bs, VSS_RECORD_CATALOG)?;
+            let mut index = VSS_BLOCK_HEADER_SIZE;
+            while index + VSS_CATALOG_ENTRY_SIZE <= VSS_BLOCK_SIZE {
+                let entry = bs.slice(index..index + VSS_CATALOG_ENTRY_SIZE);
+                index += VSS_CATALOG_ENTRY_SIZE;
+                match (sub_bytes(&entry, 0..8)?).get_u64_le() {
+                    VSS_CATALOG_ENTRY_SNAPSHOT => {
+                        let volume_size = (sub_bytes(&entry, 8..16)?).get_u64_le();
+                        let creation_time = FileTime::parse_from_u64((sub_bytes(&entry, 48..56)?).get_u64());
+                        snapshots.insert(read_guid(&entry, 16)?, (volume_size, creation_time));
+                    }
+                    VSS_CATALOG_ENTRY_STORE => {
+                        let store_id = read_guid(&entry, 16)?;
+                        let (volume_size, creation_time) = match snapshots.get(&store_id) {
+                            Some(s) => s.clone(),
+                            None => continue,
+                        };
+                        stores.push(VSSStore {
+                            store_id,
+                            volume_size,
+                            creation_time,
+                            block_list_offset: (sub_bytes(&entry, 8..16)?).get_u64_le(),
+                            header_offset: (sub_bytes(&entry, 32..40)?).get_u64_le(),
+                            bitmap_offset: (sub_bytes(&entry, 48..56)?).get_u64_le(),
+                            shadow_copy_id: [0; 16],
+                            shadow_copy_set_id: [0; 16],
+                            attribute_flags: 0,
+                            operating_machine: String::new(),
+                            service_machine: String::new(),
+                            blocks: HashMap::new(),
+                            overlays: HashMap::new(),
+                            bitmap: vec![],
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            offset = next;
+        }
+        for store in stores.iter_mut() {
+            store.parse_header(&self.reader)?;
+            store.parse_block_list(&self.reader)?;
+            store.parse_bitmap(&self.reader)?;
+        }
+        stores.sort_by_key(|x| x.creation_time.get_ticks());
+        Ok(stores)
+    }
+
+    //Blocks to read elsewhere to see the volume as it was when the n-th (from 1, oldest first) shadow copy was taken
+    pub fn get_vss_block_map(&self, n: usize) -> Result<BlockMap, MRError> {
+        let stores = self.get_vss_stores()?;
+        if n == 0 || n > stores.len() {
+            return Err(MRError::new(&format!(
+                "vss={} out of range, {} shadow copies on the volume",
+                n,
+                stores.len()
+            )));
+        }
+        let stores = &stores[n - 1..];
+        let mut map = BlockMap::new(VSS_BLOCK_SIZE as u64);
+        //Blocks free at the snapshot were not copied before being reused, they read as zeros
+        map.set_unused(stores[0].bitmap.clone());
+        let mut offsets = HashSet::new();
+        for store in stores {
+            offsets.extend(store.blocks.keys());
+            offsets.extend(store.overlays.keys());
+        }
+        for offset in offsets {
+            if let Some(sources) = resolve_block(stores, offset) {
+                map.insert(offset, sources);
+            }
+        }
+        Ok(map)
+    }
+
+    //The volume as seen by the n-th shadow copy, oldest first
+    pub fn into_vss(self, n: usize) -> Result<Ntfs, MRError> {
+        let map = self.get_vss_block_map(n)?;
+        let mut reader = self.reader.try_clone()?;
+        reader.set_block_map(map);
+        Ntfs::from_reader(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(blocks: Vec<VSSBlockDescriptor>) -> VSSStore {
+        let mut result = VSSStore {
+            store_id: [0; 16],
+            volume_size: 0,
+            creation_time: FileTime::parse_from_u64(0),
+            block_list_offset: 0,
+            header_offset: 0,
+            bitmap_offset: 0,
+            shadow_copy_id: [0; 16],
+            shadow_copy_set_id: [0; 16],
+            attribute_flags: 0,
+            operating_machine: String::new(),
+            service_machine: String::new(),
+            blocks: HashMap::new(),
+            overlays: HashMap::new(),
+            bitmap: vec![],
+        };
+        for descriptor in blocks {
+            if descriptor.is_overlay() {
+                result.overlays.entry(descriptor.original_offset).or_default().push(descriptor);
+            } else {
+                result.blocks.insert(descriptor.original_offset, descriptor);
+            }
+        }
+        result
+    }
+
+    fn descriptor(original_offset: u64, store_offset: u64, flags: u32, bitmap: u32) -> VSSBlockDescriptor {
+        VSSBlockDescriptor {
+            original_offset,
+            relative_offset: 0,
+            store_offset,
+            flags,
+            bitmap,
+        }
+    }
+
+    #[test]
+    fn full_overlay() {
+        let size = VSS_BLOCK_SIZE as u64;
+        //The newer store copied the block, the older one overwrote its first sector then all of it
+        let stores = vec![
+            store(vec![
+                descriptor(0, 2 * size, VSS_BLOCK_OVERLAY, 0x1),
+                descriptor(0, 3 * size, VSS_BLOCK_OVERLAY, u32::MAX),
+            ]),
+            store(vec![descriptor(0, size, 0, 0)]),
+        ];
+        let sources = resolve_block(&stores, 0).unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                BlockSource::Base(size),
+                BlockSource::Overlay { offset: 2 * size, sectors: 0x1 },
+                BlockSource::Overlay { offset: 3 * size, sectors: u32::MAX },
+            ]
+        );
+        let path = std::env::temp_dir().join(format!("meta_reader_vss_{}.bin", std::process::id()));
+        let bs: Vec<u8> = b"ABCDE".iter().flat_map(|x| vec![*x; VSS_BLOCK_SIZE]).collect();
+        std::fs::write(&path, bs).unwrap();
+        let mut reader = MRFile::new(path.to_string_lossy().to_string()).unwrap();
+        let mut map = BlockMap::new(size);
+        map.insert(0, sources);
+        //Block 4 was free at the snapshot
+        map.set_unused(vec![0x10]);
+        reader.set_block_map(map);
+        let data = reader.read_n(0, 5 * VSS_BLOCK_SIZE).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(data[..VSS_BLOCK_SIZE].iter().all(|x| *x == b'D'));
+        assert!(data[VSS_BLOCK_SIZE..2 * VSS_BLOCK_SIZE].iter().all(|x| *x == b'B'));
+        assert!(data[4 * VSS_BLOCK_SIZE..].iter().all(|x| *x == 0));
+    }
+}
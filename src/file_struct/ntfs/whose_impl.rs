@@ -0,0 +1,58 @@
+use crate::{file_struct::BlockOwner, utils::MRError};
+
+use super::{bitmap_impl::count_allocated, tree_impl::ROOT_INDEX, MFTIndex, Ntfs};
+
+const MFT_INDEX: u64 = 0;
+//Entries below are the file system metadata, $MFT to $Extend
+const FIRST_USER_INDEX: u64 = 16;
+
+impl Ntfs {
+    //Owner of a volume byte offset, bits is the $Bitmap content
+    pub fn whose(&self, index: &MFTIndex, bits: &[u8], offset: u64) -> Result<BlockOwner, MRError> {
+        let volume_size = self.get_sector_num() * self.get_sector_bytes_num();
+        if offset >= volume_size {
+            //The backup boot sector follows the last sector of the volume
+            if offset < volume_size + self.get_sector_bytes_num() {
+                return Ok(BlockOwner::Metadata {
+                    name: "$Boot (backup)".to_string(),
+                    record: None,
+                    offset: offset - volume_size,
+                });
+            }
+            return Err(MRError::new("Offset past the end of the volume"));
+        }
+
+        if let Some(extent) = index.find_extent(offset) {
+            let logical = extent.get_vcn() + offset - extent.get_start();
+            let path = index.get_full_path(extent.get_index());
+            let stream = extent.get_stream().to_string();
+            if extent.get_index() < FIRST_USER_INDEX && extent.get_index() != ROOT_INDEX {
+                //A $MFT byte also tells which record it belongs to
+                let record = (extent.get_index() == MFT_INDEX && stream.is_empty())
+                    .then(|| logical / self.get_mft_size() as u64);
+                let logical = match record {
+                    Some(_) => logical % self.get_mft_size() as u64,
+                    None => logical,
+                };
+                let name = match stream.is_empty() {
+                    true => path.trim_start_matches('\\').to_string(),
+                    false => format!("{}:{}", path.trim_start_matches('\\'), stream),
+                };
+                return Ok(BlockOwner::Metadata { name, record, offset: logical });
+            }
+            return Ok(BlockOwner::File {
+                id: extent.get_index(),
+                path,
+                stream,
+                offset: logical,
+                slack: logical >= extent.get_size(),
+            });
+        }
+
+        let cluster = offset / self.get_cluster_size();
+        match count_allocated(bits, &(cluster..cluster + 1)) {
+            0 => Ok(BlockOwner::Unallocated),
+            _ => Ok(BlockOwner::Unknown),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use memchr::memmem;
+
+use super::carver::Carving;
+
+pub const MAGIC: &[u8] = b"%PDF-";
+const EOF: &[u8] = b"%%EOF";
+const STARTXREF: &[u8] = b"startxref";
+const ENDOBJ: &[u8] = b"endobj";
+//No object ends for this long, the file got overwritten, a stream of a big picture is the longest part
+const MAX_OBJECT_GAP: usize = 0x1000000;
+
+//startxref before the %%EOF at pos must point at a xref table or a xref stream object
+fn check_startxref(bs: &[u8], pos: usize) -> bool {
+    let tail = &bs[pos.saturating_sub(64)..pos];
+    let start = match memmem::rfind(tail, STARTXREF) {
+        Some(s) => s + STARTXREF.len(),
+        None => return false,
+    };
+    let digits = String::from_utf8_lossy(&tail[start..]);
+    let xref = match digits.trim().parse::<usize>() {
+        Ok(o) => o,
+        Err(_) => return false,
+    };
+    match bs.get(xref..(xref + 32).min(pos)) {
+        Some(s) => is_xref(s),
+        None => false,
+    }
+}
+
+//A xref table or an object like "12 0 obj", xref streams are objects too
+fn is_xref(bs: &[u8]) -> bool {
+    if bs.starts_with(b"xref") {
+        return true;
+    }
+    let mut fields = bs.split(|x| x.is_ascii_whitespace()).filter(|x| !x.is_empty());
+    let mut number = || fields.next().is_some_and(|x| x.iter().all(|x| x.is_ascii_digit()));
+    number() && number() && fields.next().is_some_and(|x| x.starts_with(b"obj"))
+}
+
+//An incremental update follows the %%EOF ending at end, or the bytes given stop there
+fn continues(bs: &[u8], end: usize) -> bool {
+    let start = match bs[end..].iter().position(|x| !x.is_ascii_whitespace()) {
+        Some(s) => end + s,
+        None => return true,
+    };
+    let tail = &bs[start..(start + 32).min(bs.len())];
+    tail.len() < 32 || is_xref(tail)
+}
+
+//Bytes after the last object are too many to be part of the file
+fn gave_up(bs: &[u8]) -> bool {
+    let end = memmem::rfind(bs, ENDOBJ).map(|x| x + ENDOBJ.len()).unwrap_or(0);
+    bs.len() - end > MAX_OBJECT_GAP
+}
+
+//Ends at the last %%EOF whose startxref points inside the file, incremental updates add more of them
+pub fn carve(bs: &[u8], last: bool) -> Carving {
+    if bs.len() < 8 || !bs[5].is_ascii_digit() || bs[6] != b'.' || !bs[7].is_ascii_digit() {
+        return Carving::Invalid;
+    }
+    //The %%EOF of another file does not end this one
+    let next = memmem::find(&bs[1..], MAGIC).map(|x| x + 1).unwrap_or(bs.len());
+    let mut checked = None;
+    let mut unchecked = None;
+    for pos in memmem::find_iter(&bs[..next], EOF) {
+        let mut end = pos + EOF.len();
+        if bs.get(end) == Some(&b'\r') {
+            end += 1;
+        }
+        if bs.get(end) == Some(&b'\n') {
+            end += 1;
+        }
+        match check_startxref(bs, pos) {
+            true => checked = Some(end),
+            false => unchecked = unchecked.or(Some(end)),
+        }
+    }
+    match (checked, unchecked) {
+        (Some(end), _) if !last && continues(bs, end) => Carving::NeedMore,
+        (Some(end), _) => Carving::found(end, 100),
+        _ if next == bs.len() && !last && !gave_up(bs) => Carving::NeedMore,
+        (None, Some(end)) => Carving::found(end, 40),
+        (None, None) => Carving::Invalid,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body() -> Vec<u8> {
+        b"%PDF-1.4\n1 0 obj\n<< /Type /Catalog >>\nendobj\n".to_vec()
+    }
+
+    //One object, a xref table and the startxref pointing at it
+    fn sample() -> Vec<u8> {
+        let mut bs = body();
+        let xref = bs.len();
+        bs.extend(b"xref\n0 1\n0000000000 65535 f \ntrailer\n<< /Size 1 >>\n");
+        bs.extend(format!("startxref\n{}\n%%EOF\n", xref).as_bytes());
+        bs
+    }
+
+    #[test]
+    fn valid() {
+        let mut bs = sample();
+        let size = bs.len();
+        assert_eq!(carve(&bs, true), Carving::found(size, 100));
+        //Nothing after the %%EOF tells whether an update follows
+        assert_eq!(carve(&bs, false), Carving::NeedMore);
+        bs.extend([0; 64]);
+        assert_eq!(carve(&bs, false), Carving::found(size, 100));
+    }
+
+    #[test]
+    fn truncated() {
+        let bs = body();
+        assert_eq!(carve(&bs, false), Carving::NeedMore);
+        assert_eq!(carve(&bs, true), Carving::Invalid);
+        //startxref pointing nowhere
+        let mut bs = body();
+        bs.extend(b"startxref\n9999\n%%EOF\n");
+        assert_eq!(carve(&bs, true), Carving::found(bs.len(), 40));
+    }
+
+    #[test]
+    fn garbage() {
+        let mut bs = MAGIC.to_vec();
+        bs.extend(b"xyz\0\x01\x02");
+        assert_eq!(carve(&bs, false), Carving::Invalid);
+        //No object ends for too long, more bytes are not asked for
+        let mut bs = body();
+        bs.resize(bs.len() + MAX_OBJECT_GAP + 1, 0x41);
+        assert_eq!(carve(&bs, false), Carving::Invalid);
+    }
+}
@@ -0,0 +1,68 @@
+use super::carver::{le_u16, le_u32, Carving};
+
+pub const MAGIC: &[u8] = b"MZ";
+const SECTION_SIZE: usize = 40;
+const IMAGE_FILE_DLL: usize = 0x2000;
+
+//The file ends with the furthest section data or the certificate table, which is not mapped
+pub fn carve(bs: &[u8], last: bool) -> Carving {
+    let need = |n: usize| n > bs.len();
+    let more = || match last {
+        true => Carving::Invalid,
+        false => Carving::NeedMore,
+    };
+    if need(0x40) {
+        return more();
+    }
+    let pe = le_u32(bs, 0x3c);
+    if !(0x40..0x1000).contains(&pe) {
+        return Carving::Invalid;
+    }
+    if need(pe + 24) {
+        return more();
+    }
+    if &bs[pe..pe + 4] != b"PE\0\0" {
+        return Carving::Invalid;
+    }
+    let sections = le_u16(bs, pe + 6);
+    let optional_size = le_u16(bs, pe + 20);
+    let characteristics = le_u16(bs, pe + 22);
+    let optional = pe + 24;
+    let section_table = optional + optional_size;
+    if sections == 0 || sections > 96 || optional_size < 96 {
+        return Carving::Invalid;
+    }
+    if need(section_table + sections * SECTION_SIZE) {
+        return more();
+    }
+    let directories = match le_u16(bs, optional) {
+        0x10b => optional + 96,
+        0x20b => optional + 112,
+        _ => {
+            return Carving::Invalid;
+        }
+    };
+    if directories > section_table {
+        return Carving::Invalid;
+    }
+    let mut size = le_u32(bs, optional + 60);
+    //The security directory holds a file offset
+    if le_u32(bs, directories - 4) > 4 && directories + 40 <= section_table {
+        size = size.max(le_u32(bs, directories + 32) + le_u32(bs, directories + 36));
+    }
+    for i in 0..sections {
+        let section = section_table + i * SECTION_SIZE;
+        size = size.max(le_u32(bs, section + 20) + le_u32(bs, section + 16));
+    }
+    if size > bs.len() {
+        return match last {
+            true => Carving::found(bs.len(), 30),
+            false => Carving::NeedMore,
+        };
+    }
+    let ext = match characteristics & IMAGE_FILE_DLL {
+        0 => "exe",
+        _ => "dll",
+    };
+    Carving::Found { size, confidence: 90, ext: Some(ext) }
+}
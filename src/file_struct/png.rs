@@ -0,0 +1,133 @@
+use super::carver::{be_u32, Carving};
+
+pub const MAGIC: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+//crc32 of zlib, over the type and the data of a chunk
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in data {
+        crc = CRC_TABLE[((crc ^ *b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+//Follows the chunks up to IEND, every chunk must match its CRC
+pub fn carve(bs: &[u8], last: bool) -> Carving {
+    let mut offset = MAGIC.len();
+    let mut chunks = 0;
+    let mut data = false;
+    //Chunks before a broken one still make a picture once image data was read
+    let broken = |offset: usize, data: bool| match data {
+        true => Carving::found(offset, 30),
+        false => Carving::Invalid,
+    };
+    loop {
+        if offset + 12 > bs.len() {
+            return match last {
+                true => broken(offset, data),
+                false => Carving::NeedMore,
+            };
+        }
+        let length = be_u32(bs, offset);
+        let kind = &bs[offset + 4..offset + 8];
+        if length > 0x7fffffff || !kind.iter().all(|x| x.is_ascii_alphabetic()) {
+            return broken(offset, data);
+        }
+        if chunks == 0 && (kind != b"IHDR" || length != 13) {
+            return Carving::Invalid;
+        }
+        let end = offset + 12 + length;
+        if end > bs.len() {
+            return match last {
+                true => broken(offset, data),
+                false => Carving::NeedMore,
+            };
+        }
+        if crc32(&bs[offset + 4..end - 4]) != be_u32(bs, end - 4) as u32 {
+            return broken(offset, data);
+        }
+        chunks += 1;
+        offset = end;
+        match kind {
+            b"IDAT" => data = true,
+            b"IEND" => {
+                return match data {
+                    true => Carving::found(end, 100),
+                    false => Carving::Invalid,
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut bs = (data.len() as u32).to_be_bytes().to_vec();
+        bs.extend(kind);
+        bs.extend(data);
+        bs.extend(crc32(&bs[4..]).to_be_bytes());
+        bs
+    }
+
+    //Signature, IHDR and IDAT, IEND left out
+    fn head() -> Vec<u8> {
+        let mut bs = MAGIC.to_vec();
+        bs.extend(chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]));
+        bs.extend(chunk(b"IDAT", &[0x78, 0x9c, 0x63, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01]));
+        bs
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+    }
+
+    #[test]
+    fn valid() {
+        let mut bs = head();
+        bs.extend(chunk(b"IEND", &[]));
+        let size = bs.len();
+        bs.extend([0; 16]);
+        assert_eq!(carve(&bs, false), Carving::found(size, 100));
+    }
+
+    #[test]
+    fn truncated() {
+        let bs = head();
+        assert_eq!(carve(&bs, false), Carving::NeedMore);
+        assert_eq!(carve(&bs, true), Carving::found(bs.len(), 30));
+    }
+
+    #[test]
+    fn garbage() {
+        let mut bs = MAGIC.to_vec();
+        bs.extend([0x5a; 32]);
+        assert_eq!(carve(&bs, false), Carving::Invalid);
+        //A broken CRC in the header
+        let mut bs = head();
+        bs[MAGIC.len() + 20] ^= 0xff;
+        assert_eq!(carve(&bs, false), Carving::Invalid);
+    }
+}
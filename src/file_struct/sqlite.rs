@@ -0,0 +1,87 @@
+use super::carver::{be_u16, be_u32, Carving};
+
+pub const MAGIC: &[u8] = b"SQLite format 3\0";
+const HEADER_SIZE: usize = 100;
+
+//The header gives the page size and the page count, the count is only kept up to date when the
+//change counter matches version-valid-for
+pub fn carve(bs: &[u8], last: bool) -> Carving {
+    if bs.len() < HEADER_SIZE + 1 {
+        return match last {
+            true => Carving::Invalid,
+            false => Carving::NeedMore,
+        };
+    }
+    let page_size = match be_u16(bs, 16) {
+        1 => 65536,
+        n => n,
+    };
+    if !page_size.is_power_of_two() || page_size < 512 || bs[21..24] != [64, 32, 32] {
+        return Carving::Invalid;
+    }
+    if !(1..=2).contains(&bs[18]) || !(1..=2).contains(&bs[19]) {
+        return Carving::Invalid;
+    }
+    let pages = be_u32(bs, 28);
+    if pages == 0 || be_u32(bs, 24) != be_u32(bs, 92) {
+        return Carving::Invalid;
+    }
+    let size = page_size * pages;
+    if size > bs.len() {
+        return match last {
+            true => Carving::found(bs.len(), 30),
+            false => Carving::NeedMore,
+        };
+    }
+    //Page 1 is the b-tree of sqlite_master
+    let confidence = match bs[HEADER_SIZE] {
+        0x0d | 0x05 => 100,
+        _ => 70,
+    };
+    Carving::found(size, confidence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Two pages of 512 bytes, page 1 a leaf table b-tree
+    fn sample() -> Vec<u8> {
+        let mut bs = vec![0; 1024];
+        bs[..16].copy_from_slice(MAGIC);
+        bs[16..18].copy_from_slice(&512u16.to_be_bytes());
+        bs[18] = 1;
+        bs[19] = 1;
+        bs[21..24].copy_from_slice(&[64, 32, 32]);
+        bs[24..28].copy_from_slice(&3u32.to_be_bytes());
+        bs[28..32].copy_from_slice(&2u32.to_be_bytes());
+        bs[92..96].copy_from_slice(&3u32.to_be_bytes());
+        bs[HEADER_SIZE] = 0x0d;
+        bs
+    }
+
+    #[test]
+    fn valid() {
+        let mut bs = sample();
+        bs.extend([0xee; 64]);
+        assert_eq!(carve(&bs, false), Carving::found(1024, 100));
+    }
+
+    #[test]
+    fn truncated() {
+        let bs = &sample()[..600];
+        assert_eq!(carve(bs, false), Carving::NeedMore);
+        assert_eq!(carve(bs, true), Carving::found(600, 30));
+    }
+
+    #[test]
+    fn garbage() {
+        let mut bs = MAGIC.to_vec();
+        bs.extend([0x33; 200]);
+        assert_eq!(carve(&bs, false), Carving::Invalid);
+        //Page count not to be trusted
+        let mut bs = sample();
+        bs[92] = 9;
+        assert_eq!(carve(&bs, false), Carving::Invalid);
+    }
+}
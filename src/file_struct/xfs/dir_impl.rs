@@ -0,0 +1,219 @@
+use std::collections::BTreeSet;
+
+use crate::utils::MRError;
+
+use super::{
+    xfs_impl::{be_u16, be_u32, be_u64},
+    DirectoryEntry, ForkFormat, Inode, XFS, XFS_DIR2_DATA_FREE_TAG, XFS_DIR2_LEAF_OFFSET,
+};
+
+//Single block directories keep their hash index in the same block
+const DIR_BLOCK_MAGICS: &[&[u8]] = &[b"XDB3", b"XD2B"];
+const DIR_DATA_MAGICS: &[&[u8]] = &[b"XDD3", b"XD2D"];
+const DIR_FT_MAX: u8 = 8;
+
+impl DirectoryEntry {
+    pub fn get_inode(&self) -> u64 {
+        self.inode
+    }
+
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn get_file_type(&self) -> u8 {
+        self.file_type
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type == 2
+    }
+}
+
+impl XFS {
+    fn dir_entry_size(&self, name_len: usize) -> usize {
+        let ftype = if self.has_ftype() { 1 } else { 0 };
+        (8 + 1 + name_len + ftype + 2).next_multiple_of(8)
+    }
+
+    //Short form directories live in the inode: a count, the parent and packed entries
+    fn parse_short_form(&self, fork: &[u8]) -> Vec<DirectoryEntry> {
+        let count = fork.first().copied().unwrap_or(0) as usize;
+        let ino_size = match fork.get(1) {
+            Some(0) | None => 4,
+            _ => 8,
+        };
+        let ftype = if self.has_ftype() { 1 } else { 0 };
+        let mut result = vec![];
+        let mut offset = 2 + ino_size;
+        for _ in 0..count {
+            let name_len = match fork.get(offset) {
+                Some(s) => *s as usize,
+                None => break,
+            };
+            let name_start = offset + 3;
+            let ino_offset = name_start + name_len + ftype;
+            if ino_offset + ino_size > fork.len() {
+                break;
+            }
+            let inode = match ino_size {
+                4 => be_u32(fork, ino_offset) as u64,
+                _ => be_u64(fork, ino_offset),
+            };
+            result.push(DirectoryEntry {
+                inode,
+                name: String::from_utf8_lossy(&fork[name_start..name_start + name_len]).to_string(),
+                file_type: if ftype == 1 { fork[name_start + name_len] } else { 0 },
+                offset: be_u16(fork, offset + 1) as u64,
+            });
+            offset = ino_offset + ino_size;
+        }
+        result
+    }
+
+    //Data blocks of a block, leaf or node directory with their byte in the fork
+    fn get_dir_blocks(&self, inode: &Inode) -> Result<Vec<(u64, Vec<u8>)>, MRError> {
+        let extents = self.get_extents(inode)?;
+        let block_size = self.get_block_size();
+        let dir_block_size = self.get_dir_block_size();
+        let mut starts = BTreeSet::new();
+        for extent in &extents {
+            for block in extent.get_offset()..extent.get_offset() + extent.get_count() {
+                let offset = block * block_size;
+                if offset >= XFS_DIR2_LEAF_OFFSET {
+                    break;
+                }
+                starts.insert(offset - offset % dir_block_size);
+            }
+        }
+        let mut result = vec![];
+        for start in starts {
+            result.push((start, self.read_fork(&extents, start, dir_block_size)?));
+        }
+        Ok(result)
+    }
+
+    //Bytes holding entries and their header size, None for blocks of the hash index
+    fn dir_block_layout(&self, bs: &[u8]) -> Option<(usize, usize)> {
+        let magic = bs.get(0..4)?;
+        let header = if magic[3] == b'3' { 64 } else { 16 };
+        if DIR_DATA_MAGICS.contains(&magic) {
+            return Some((header, bs.len()));
+        }
+        if !DIR_BLOCK_MAGICS.contains(&magic) {
+            return None;
+        }
+        //The tail holds the count of the leaf entries right before it
+        let leaves = be_u32(bs, bs.len() - 8) as usize;
+        let end = bs.len().checked_sub(8 + leaves * 8)?;
+        Some((header, end.max(header)))
+    }
+
+    //Entries in use of a directory data block, or with deleted the ones left in its free space
+    pub fn parse_dir_block(&self, bs: &[u8], base: u64, deleted: bool) -> Vec<DirectoryEntry> {
+        let (mut pos, end) = match self.dir_block_layout(bs) {
+            Some(s) => s,
+            None => return vec![],
+        };
+        let mut result = vec![];
+        while pos + 8 <= end {
+            if be_u16(bs, pos) == XFS_DIR2_DATA_FREE_TAG {
+                let len = be_u16(bs, pos + 2) as usize;
+                if len == 0 || !len.is_multiple_of(8) || pos + len > end {
+                    break;
+                }
+                if deleted {
+                    result.extend(self.parse_free_space(bs, pos, pos + len, base));
+                }
+                pos += len;
+                continue;
+            }
+            let name_len = bs[pos + 8] as usize;
+            let size = self.dir_entry_size(name_len);
+            if name_len == 0 || pos + size > end {
+                break;
+            }
+            let name = &bs[pos + 9..pos + 9 + name_len];
+            if !deleted && name != b"." && name != b".." {
+                result.push(DirectoryEntry {
+                    inode: be_u64(bs, pos),
+                    name: String::from_utf8_lossy(name).to_string(),
+                    file_type: if self.has_ftype() { bs[pos + 9 + name_len] } else { 0 },
+                    offset: base + pos as u64,
+                });
+            }
+            pos += size;
+        }
+        result
+    }
+
+    //Freeing an entry writes the free tag and the length over the high half of its inode number,
+    //the low half, the name and the tag pointing back at the entry stay
+    fn parse_free_space(&self, bs: &[u8], start: usize, end: usize, base: u64) -> Vec<DirectoryEntry> {
+        let mut result = vec![];
+        let mut pos = start;
+        while pos + 16 <= end {
+            let name_len = bs[pos + 8] as usize;
+            let size = self.dir_entry_size(name_len);
+            let name = bs.get(pos + 9..pos + 9 + name_len).unwrap_or_default();
+            let file_type = match self.has_ftype() {
+                true => bs.get(pos + 9 + name_len).copied().unwrap_or(0),
+                false => 0,
+            };
+            let valid = name_len > 0
+                && pos + size <= end
+                && name.iter().all(|x| *x >= 0x20 && *x != b'/')
+                && name != b"."
+                && name != b".."
+                && (!self.has_ftype() || (1..=DIR_FT_MAX).contains(&file_type))
+                && be_u32(bs, pos + 4) != 0
+                && (be_u16(bs, pos + size - 2) as usize == pos || pos + size == end);
+            if !valid {
+                pos += 8;
+                continue;
+            }
+            result.push(DirectoryEntry {
+                inode: be_u32(bs, pos + 4) as u64,
+                name: String::from_utf8_lossy(name).to_string(),
+                file_type,
+                offset: base + pos as u64,
+            });
+            pos += size;
+        }
+        result
+    }
+
+    pub fn get_dir_entries(&self, inode: &Inode) -> Result<Vec<DirectoryEntry>, MRError> {
+        if !inode.is_dir() {
+            return Err(MRError::new("Not a dir"));
+        }
+        if inode.get_format() == ForkFormat::Local {
+            return Ok(self.parse_short_form(inode.get_data_fork()));
+        }
+        let mut result = vec![];
+        for (base, bs) in self.get_dir_blocks(inode)? {
+            result.extend(self.parse_dir_block(&bs, base, false));
+        }
+        Ok(result)
+    }
+
+    //Names left in the free space of the data blocks, only the low 32 bits of their inode number
+    //survive, short form directories move the entries after a removed one over it
+    pub fn get_deleted_dir_entries(&self, inode: &Inode) -> Result<Vec<DirectoryEntry>, MRError> {
+        if !inode.is_dir() {
+            return Err(MRError::new("Not a dir"));
+        }
+        if inode.get_format() == ForkFormat::Local {
+            return Ok(vec![]);
+        }
+        let mut result = vec![];
+        for (base, bs) in self.get_dir_blocks(inode)? {
+            result.extend(self.parse_dir_block(&bs, base, true));
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,338 @@
+use chrono::{DateTime, NaiveDateTime};
+
+use crate::utils::MRError;
+
+use super::{
+    xfs_impl::{be_u16, be_u64},
+    ForkFormat, Inode, XfsExtent, XFS, XFS_DIFLAG2_BIGTIME, XFS_DIFLAG2_NREXT64,
+};
+
+const INODE_MAGIC: u16 = 0x494e;
+const BMBT_V5_MAGIC: &[u8] = b"BMA3";
+const BMBT_MAGIC: &[u8] = b"BMAP";
+const EXTENT_SIZE: usize = 16;
+const MAX_BTREE_LEVEL: u16 = 16;
+//Big timestamps count nanoseconds from 1901-12-13, the lowest 32 bit time
+const BIGTIME_EPOCH_OFFSET: i64 = 1 << 31;
+
+//Fields of an inode core, the log keeps it in the byte order of the host that wrote it
+struct Core<'a> {
+    bs      : &'a [u8],
+    little  : bool
+}
+
+impl Core<'_> {
+    fn get<const N: usize>(&self, offset: usize) -> [u8; N] {
+        let mut b = [0; N];
+        if let Some(s) = self.bs.get(offset..offset + N) {
+            b.copy_from_slice(s);
+        }
+        if self.little {
+            b.reverse();
+        }
+        b
+    }
+
+    fn u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes(self.get(offset))
+    }
+
+    fn u32(&self, offset: usize) -> u32 {
+        u32::from_be_bytes(self.get(offset))
+    }
+
+    fn u64(&self, offset: usize) -> u64 {
+        u64::from_be_bytes(self.get(offset))
+    }
+
+    fn time(&self, offset: usize, bigtime: bool) -> NaiveDateTime {
+        let (secs, nsecs) = match bigtime {
+            true => {
+                let ns = self.u64(offset);
+                ((ns / 1_000_000_000) as i64 - BIGTIME_EPOCH_OFFSET, (ns % 1_000_000_000) as u32)
+            }
+            false => (self.u32(offset) as i32 as i64, self.u32(offset + 4)),
+        };
+        DateTime::from_timestamp(secs, nsecs).unwrap_or_default().naive_utc()
+    }
+}
+
+impl ForkFormat {
+    fn from(n: u8) -> Self {
+        match n {
+            0 => Self::Device,
+            1 => Self::Local,
+            2 => Self::Extents,
+            3 => Self::Btree,
+            n => Self::Unknown(n),
+        }
+    }
+}
+
+impl XfsExtent {
+    //Packed in 128 bits: unwritten flag, 54 bits of file offset, 52 of block and 21 of length
+    pub fn parse(bs: &[u8]) -> XfsExtent {
+        let hi = be_u64(bs, 0);
+        let lo = be_u64(bs, 8);
+        XfsExtent {
+            offset: (hi & !(1 << 63)) >> 9,
+            start_block: ((hi & 0x1ff) << 43) | (lo >> 21),
+            count: lo & ((1 << 21) - 1),
+            unwritten: hi >> 63 == 1,
+        }
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_start_block(&self) -> u64 {
+        self.start_block
+    }
+
+    pub fn get_count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_unwritten(&self) -> bool {
+        self.unwritten
+    }
+}
+
+impl Inode {
+    pub fn parse(bs: &[u8], id: u64) -> Result<Inode, MRError> {
+        Self::parse_core(Core { bs, little: false }, id)
+    }
+
+    //Inode core copied to the log by a little endian host, the forks are logged apart
+    pub fn parse_log(bs: &[u8], id: u64) -> Result<Inode, MRError> {
+        Self::parse_core(Core { bs, little: true }, id)
+    }
+
+    fn parse_core(core: Core, id: u64) -> Result<Inode, MRError> {
+        let bs = core.bs;
+        if bs.len() < 100 || core.u16(0) != INODE_MAGIC {
+            return Err(MRError::new(&format!("Inode {} has no magic", id)));
+        }
+        let version = bs[4];
+        let literal = match version {
+            3 => 176,
+            _ => 100,
+        };
+        let flags2 = if version == 3 { core.u64(120) } else { 0 };
+        let bigtime = flags2 & XFS_DIFLAG2_BIGTIME != 0;
+        let nextents = match flags2 & XFS_DIFLAG2_NREXT64 {
+            0 => core.u32(76) as u64,
+            _ => core.u64(24),
+        };
+        let fork_size = match bs[82] {
+            0 => bs.len().saturating_sub(literal),
+            n => n as usize * 8,
+        };
+        let data_fork = bs.get(literal..literal + fork_size).unwrap_or_default().to_vec();
+        Ok(Inode {
+            id,
+            mode: core.u16(2),
+            version,
+            format: ForkFormat::from(bs[5]),
+            uid: core.u32(8),
+            gid: core.u32(12),
+            nlink: if version == 1 { core.u16(6) as u32 } else { core.u32(16) },
+            atime: core.time(32, bigtime),
+            mtime: core.time(40, bigtime),
+            ctime: core.time(48, bigtime),
+            crtime: (version == 3 && bs.len() >= 152).then(|| core.time(144, bigtime)),
+            size: core.u64(56),
+            nblocks: core.u64(64),
+            nextents,
+            flags: core.u16(90),
+            flags2,
+            generation: core.u32(92),
+            data_fork,
+        })
+    }
+
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn get_mode(&self) -> u16 {
+        self.mode
+    }
+
+    pub fn get_version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn get_format(&self) -> ForkFormat {
+        self.format
+    }
+
+    pub fn get_uid(&self) -> u32 {
+        self.uid
+    }
+
+    pub fn get_gid(&self) -> u32 {
+        self.gid
+    }
+
+    pub fn get_nlink(&self) -> u32 {
+        self.nlink
+    }
+
+    pub fn get_atime(&self) -> NaiveDateTime {
+        self.atime
+    }
+
+    pub fn get_mtime(&self) -> NaiveDateTime {
+        self.mtime
+    }
+
+    pub fn get_ctime(&self) -> NaiveDateTime {
+        self.ctime
+    }
+
+    pub fn get_crtime(&self) -> Option<NaiveDateTime> {
+        self.crtime
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn get_nblocks(&self) -> u64 {
+        self.nblocks
+    }
+
+    pub fn get_nextents(&self) -> u64 {
+        self.nextents
+    }
+
+    pub fn get_flags(&self) -> u16 {
+        self.flags
+    }
+
+    pub fn get_flags2(&self) -> u64 {
+        self.flags2
+    }
+
+    pub fn get_generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn get_data_fork(&self) -> &Vec<u8> {
+        &self.data_fork
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & 0xf000 == 0x4000
+    }
+
+    pub fn is_regular(&self) -> bool {
+        self.mode & 0xf000 == 0x8000
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & 0xf000 == 0xa000
+    }
+
+    //Freed inodes get a zero mode and keep the rest of their core
+    pub fn is_free(&self) -> bool {
+        self.mode == 0
+    }
+}
+
+impl XFS {
+    fn bmbt_header_size(&self) -> usize {
+        match self.is_v5() {
+            true => 72,
+            false => 24,
+        }
+    }
+
+    //Data fork mapping of the inode, empty for local data
+    pub fn get_extents(&self, inode: &Inode) -> Result<Vec<XfsExtent>, MRError> {
+        let fork = inode.get_data_fork();
+        let mut result = vec![];
+        match inode.get_format() {
+            ForkFormat::Extents => {
+                let count = (inode.get_nextents() as usize).min(fork.len() / EXTENT_SIZE);
+                for i in 0..count {
+                    result.push(XfsExtent::parse(&fork[i * EXTENT_SIZE..]));
+                }
+            }
+            //The root sits in the inode with its keys and pointers sized to the fork
+            ForkFormat::Btree => {
+                let level = be_u16(fork, 0);
+                let recs = be_u16(fork, 2) as usize;
+                let max_recs = fork.len().saturating_sub(4) / 16;
+                if level == 0 || level > MAX_BTREE_LEVEL {
+                    return Err(MRError::new(&format!("Broken extent B+tree root of inode {}", inode.get_id())));
+                }
+                for i in 0..recs.min(max_recs) {
+                    let ptr = be_u64(fork, 4 + max_recs * 8 + i * 8);
+                    self.walk_bmbt(ptr, level - 1, &mut result)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(result)
+    }
+
+    fn walk_bmbt(&self, fsb: u64, level: u16, result: &mut Vec<XfsExtent>) -> Result<(), MRError> {
+        let bs = self.read_blocks(fsb, 1)?;
+        let magic = match self.is_v5() {
+            true => BMBT_V5_MAGIC,
+            false => BMBT_MAGIC,
+        };
+        if bs.get(0..4) != Some(magic) || be_u16(&bs, 4) != level {
+            return Err(MRError::new(&format!("Broken extent B+tree block {}", fsb)));
+        }
+        let recs = be_u16(&bs, 6) as usize;
+        let header = self.bmbt_header_size();
+        let max_recs = (bs.len() - header) / 16;
+        for i in 0..recs.min(max_recs) {
+            match level {
+                0 => result.push(XfsExtent::parse(&bs[header + i * EXTENT_SIZE..])),
+                _ => {
+                    let ptr = be_u64(&bs, header + max_recs * 8 + i * 8);
+                    self.walk_bmbt(ptr, level - 1, result)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //len bytes of the fork from offset, holes and unwritten extents read as zeros
+    pub fn read_fork(&self, extents: &[XfsExtent], offset: u64, len: u64) -> Result<Vec<u8>, MRError> {
+        let block_size = self.get_block_size();
+        let mut result = vec![0; len as usize];
+        let end = offset + len;
+        for extent in extents.iter().filter(|x| !x.unwritten) {
+            let start = (extent.offset * block_size).max(offset);
+            let stop = ((extent.offset + extent.count) * block_size).min(end);
+            if start >= stop {
+                continue;
+            }
+            let disk = self.fsb_to_offset(extent.start_block) + start - extent.offset * block_size;
+            let bs = self.get_reader().read_n(disk as usize, (stop - start) as usize)?;
+            result[(start - offset) as usize..(stop - offset) as usize].copy_from_slice(&bs);
+        }
+        Ok(result)
+    }
+
+    pub fn read_inode_data(&self, inode: &Inode) -> Result<Vec<u8>, MRError> {
+        let size = inode.get_size();
+        match inode.get_format() {
+            ForkFormat::Local => {
+                let fork = inode.get_data_fork();
+                Ok(fork[..(size as usize).min(fork.len())].to_vec())
+            }
+            ForkFormat::Extents | ForkFormat::Btree => {
+                let extents = self.get_extents(inode)?;
+                self.read_fork(&extents, 0, size)
+            }
+            format => Err(MRError::new(&format!("No data in a {:?} fork", format))),
+        }
+    }
+}
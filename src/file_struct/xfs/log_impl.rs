@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use crate::utils::MRError;
+
+use super::{
+    xfs_impl::{be_u32, be_u64},
+    Inode, LogInode, XFS,
+};
+
+const XLOG_HEADER_MAGIC: u32 = 0xfeedbabe;
+const BB_SIZE: usize = 512;
+//Bytes of record data whose first words one header keeps
+const XLOG_HEADER_CYCLE_SIZE: usize = 32 * 1024;
+const XLOG_CYCLE_DATA_OFFSET: usize = 44;
+const XLOG_OP_HEADER_SIZE: usize = 12;
+const XFS_LI_INODE: u16 = 0x123b;
+//xfs_inode_log_format and its packed 32 bit variant
+const INODE_LOG_FORMAT_SIZE: usize = 56;
+const INODE_LOG_FORMAT_32_SIZE: usize = 52;
+
+impl LogInode {
+    pub fn get_lsn(&self) -> u64 {
+        self.lsn
+    }
+
+    pub fn get_inode(&self) -> &Inode {
+        &self.inode
+    }
+}
+
+impl XFS {
+    //Inode cores in the records of the internal log, in the order the records are stored
+    pub fn get_log_inodes(&self) -> Result<Vec<LogInode>, MRError> {
+        let sb = self.get_super_block();
+        if sb.get_journal_block_number() == 0 {
+            return Err(MRError::new("The log is on an external device"));
+        }
+        let log = self.read_blocks(sb.get_journal_block_number(), sb.get_journal_size() as u64)?;
+        let mut result = vec![];
+        let mut bb = 0;
+        while (bb + 1) * BB_SIZE <= log.len() {
+            let header = &log[bb * BB_SIZE..(bb + 1) * BB_SIZE];
+            let version = be_u32(header, 8);
+            let len = be_u32(header, 12) as usize;
+            if be_u32(header, 0) != XLOG_HEADER_MAGIC || !(1..=2).contains(&version) || len == 0 {
+                bb += 1;
+                continue;
+            }
+            //Records bigger than 32k carry extended headers with the rest of the cycle data
+            let size = be_u32(header, 320) as usize;
+            let header_bbs = match version == 2 && size > XLOG_HEADER_CYCLE_SIZE {
+                true => size.div_ceil(XLOG_HEADER_CYCLE_SIZE),
+                false => 1,
+            };
+            let start = (bb + header_bbs) * BB_SIZE;
+            //Records wrapping around the end of the log are left out
+            if start + len > log.len() {
+                bb += 1;
+                continue;
+            }
+            //The first word of every basic block was replaced by the cycle number
+            let mut data = log[start..start + len].to_vec();
+            for i in 0..len.div_ceil(BB_SIZE) {
+                let (h, k) = (i / (BB_SIZE / 8), i % (BB_SIZE / 8));
+                let src = match h {
+                    0 => bb * BB_SIZE + XLOG_CYCLE_DATA_OFFSET + k * 4,
+                    _ => (bb + h) * BB_SIZE + 4 + k * 4,
+                };
+                let end = (i * BB_SIZE + 4).min(len);
+                data[i * BB_SIZE..end].copy_from_slice(&log[src..src + end - i * BB_SIZE]);
+            }
+            parse_log_ops(&data, be_u32(header, 40) as usize, be_u64(header, 16), &mut result);
+            bb = (start + len).div_ceil(BB_SIZE);
+        }
+        Ok(result)
+    }
+}
+
+//An inode item is its format header followed by the core, then the forks, with the same ticket
+fn parse_log_ops(data: &[u8], count: usize, lsn: u64, result: &mut Vec<LogInode>) {
+    let mut pending: HashMap<u32, u64> = HashMap::new();
+    let mut pos = 0;
+    for _ in 0..count {
+        if pos + XLOG_OP_HEADER_SIZE > data.len() {
+            break;
+        }
+        let tid = be_u32(data, pos);
+        let len = be_u32(data, pos + 4) as usize;
+        let op = match data.get(pos + XLOG_OP_HEADER_SIZE..pos + XLOG_OP_HEADER_SIZE + len) {
+            Some(s) => s,
+            None => break,
+        };
+        pos += XLOG_OP_HEADER_SIZE + len;
+        if op.len() >= 2 && u16::from_le_bytes([op[0], op[1]]) == XFS_LI_INODE {
+            let ino_offset = match op.len() {
+                INODE_LOG_FORMAT_SIZE => 16,
+                INODE_LOG_FORMAT_32_SIZE => 12,
+                _ => continue,
+            };
+            let mut ino = [0; 8];
+            ino.copy_from_slice(&op[ino_offset..ino_offset + 8]);
+            pending.insert(tid, u64::from_le_bytes(ino));
+            continue;
+        }
+        if let Some(ino) = pending.remove(&tid) {
+            if let Ok(inode) = Inode::parse_log(op, ino) {
+                result.push(LogInode { lsn, inode });
+            }
+        }
+    }
+}
@@ -1,35 +1,157 @@
+use chrono::NaiveDateTime;
+
+use crate::utils::file::MRFile;
+
+pub mod xfs_impl;
+pub mod inode_impl;
+pub mod dir_impl;
+pub mod log_impl;
+
+pub const XFS_SB_MAGIC              : &[u8] = b"XFSB";
+pub const XFS_AGF_MAGIC             : &[u8] = b"XAGF";
+pub const XFS_AGI_MAGIC             : &[u8] = b"XAGI";
+pub const XFS_SB_VERSION_QUOTABIT   : u16 = 0x40;
+pub const XFS_SB_VERSION_ALIGNBIT   : u16 = 0x80;
+pub const XFS_SB_VERSION_MOREBITSBIT    : u16 = 0x8000;
+pub const XFS_SB_VERSION2_FTYPE     : u32 = 0x200;
+pub const XFS_SB_FEAT_INCOMPAT_FTYPE    : u32 = 0x1;
+pub const XFS_SB_FEAT_INCOMPAT_SPINODES : u32 = 0x2;
+pub const XFS_SB_FEAT_INCOMPAT_BIGTIME  : u32 = 0x8;
+pub const XFS_SB_FEAT_INCOMPAT_NREXT64  : u32 = 0x20;
+pub const XFS_DIFLAG2_BIGTIME       : u64 = 0x8;
+pub const XFS_DIFLAG2_NREXT64       : u64 = 0x10;
+//Directory data blocks live below this byte of the data fork, the hash indexes above it
+pub const XFS_DIR2_LEAF_OFFSET      : u64 = 1 << 35;
+pub const XFS_DIR2_DATA_FREE_TAG    : u16 = 0xffff;
+
+#[derive(Debug, Default, Clone)]
 pub struct SuperBlock {
-    block_size          : u32,
-    block_number        : u64,
-    device_block_number : u64,
-    device_extent_number    : u64,
-    file_system_id          : [u8;16],
-    journal_block_number    : u64,
-    root_dir_inode          : u64,
-    rt_bitmap_extent_inode_number   : u64,
-    rt_bitmap_summary_inode_number  : u64,
-    rt_extent_size                  : u32,
-    alloc_group_size                : u32,
-    alloc_groups_number             : u32,
-    rt_bitmap_size                  : u32,
-    journal_size                    : u32,
-    feature_flags                   : u16,
-    sector_size                     : u16,
-    inode_size                      : u16,
-    inodes_per_block                : u16,
+    block_size          : u32,          //0x4
+    block_number        : u64,          //0x8
+    device_block_number : u64,          //0x10
+    device_extent_number    : u64,      //0x18
+    file_system_id          : [u8;16],  //0x20
+    journal_block_number    : u64,      //0x30
+    root_dir_inode          : u64,      //0x38
+    rt_bitmap_extent_inode_number   : u64,  //0x40
+    rt_bitmap_summary_inode_number  : u64,  //0x48
+    rt_extent_size                  : u32,  //0x50
+    alloc_group_size                : u32,  //0x54
+    alloc_groups_number             : u32,  //0x58
+    rt_bitmap_size                  : u32,  //0x5c
+    journal_size                    : u32,  //0x60
+    feature_flags                   : u16,  //0x64
+    sector_size                     : u16,  //0x66
+    inode_size                      : u16,  //0x68
+    inodes_per_block                : u16,  //0x6a
     //Only used in the first superblock
-    number_of_inodes                : Option<u64>,
-    number_of_free_inodes           : Option<u64>,
-    number_of_free_data_blocks      : Option<u64>,
-    number_of_rt_extents            : Option<u64>,
+    number_of_inodes                : Option<u64>,  //0x80
+    number_of_free_inodes           : Option<u64>,  //0x88
+    number_of_free_data_blocks      : Option<u64>,  //0x90
+    number_of_rt_extents            : Option<u64>,  //0x98
     //Only used if the XFS_SB_VERSION_QUOTABIT feature flag is set
-    user_quota_inode_number         : Option<u64>,
-    group_quota_inode_number        : Option<u64>,
-    quota_flags                     : Option<u16>,
+    user_quota_inode_number         : Option<u64>,  //0xa0
+    group_quota_inode_number        : Option<u64>,  //0xa8
+    quota_flags                     : Option<u16>,  //0xb0
     //Only used if the XFS_SB_VERSION_ALIGNBIT feature flag is set
-    inode_chunk_alignment_size      : Option<u32>,
+    inode_chunk_alignment_size      : Option<u32>,  //0xb4
+    volume_name                     : String,       //0x6c
+    block_log                       : u8,           //0x78
+    inodes_per_block_log            : u8,           //0x7b
+    alloc_group_block_log           : u8,           //0x7c
+    dir_block_log                   : u8,           //0xc0
+    feature_flags2                  : u32,          //0xc8
+    //Only used by version 5, the one with checksums
+    feature_incompat                : u32,          //0xd8
+}
+
+//AGF and AGI of an allocation group
+#[derive(Debug, Default, Clone)]
+pub struct AllocGroup {
+    number          : u32,
+    length          : u32,
+    free_blocks     : u32,
+    inode_count     : u32,
+    free_inodes     : u32,
+    //Root block and levels of the inode B+tree
+    inode_root      : u32,
+    inode_level     : u32
+}
+
+//A record of the inode B+tree, 64 inodes from start_ino
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InodeChunk {
+    ag              : u32,
+    start_ino       : u32,
+    //Each bit covers 4 inodes that were never allocated, sparse inodes only
+    hole_mask       : u16,
+    free_mask       : u64
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct XfsExtent {
+    //File block of the first block
+    offset          : u64,
+    start_block     : u64,
+    count           : u64,
+    //Allocated but not written yet, reads as zeros
+    unwritten       : bool
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ForkFormat {
+    Device,
+    Local,
+    Extents,
+    Btree,
+    Unknown(u8),
+}
+
+#[derive(Debug, Clone)]
+pub struct Inode {
+    id              : u64,
+    mode            : u16,
+    version         : u8,
+    format          : ForkFormat,
+    uid             : u32,
+    gid             : u32,
+    nlink           : u32,
+    atime           : NaiveDateTime,
+    mtime           : NaiveDateTime,
+    ctime           : NaiveDateTime,
+    //Version 3 only
+    crtime          : Option<NaiveDateTime>,
+    size            : u64,
+    nblocks         : u64,
+    nextents        : u64,
+    flags           : u16,
+    flags2          : u64,
+    generation      : u32,
+    //Bytes of the data fork in the inode
+    data_fork       : Vec<u8>
+}
+
+#[derive(Debug, Clone)]
+pub struct DirectoryEntry {
+    inode           : u64,
+    name            : String,
+    //0 when the file system keeps no type in the entries
+    file_type       : u8,
+    //Byte of the data fork the entry starts at, the block it sits in is offset / dir block size
+    offset          : u64
+}
+
+//Inode core logged by a transaction, the log keeps older copies of inodes freed since
+#[derive(Debug, Clone)]
+pub struct LogInode {
+    //Cycle in the high 32 bits and basic block of the record in the low ones
+    lsn             : u64,
+    inode           : Inode
 }
 
 pub struct XFS {
-    
-}
\ No newline at end of file
+    reader          : MRFile,
+    super_block     : SuperBlock,
+    //Entries carry a file type byte
+    has_ftype       : bool
+}
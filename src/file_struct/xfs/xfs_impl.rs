@@ -0,0 +1,434 @@
+use std::path::Path;
+
+use crate::utils::{file::MRFile, MRError};
+
+use super::{
+    AllocGroup, Inode, InodeChunk, SuperBlock, XFS, XFS_AGF_MAGIC, XFS_AGI_MAGIC, XFS_SB_FEAT_INCOMPAT_FTYPE,
+    XFS_SB_FEAT_INCOMPAT_SPINODES, XFS_SB_MAGIC, XFS_SB_VERSION2_FTYPE, XFS_SB_VERSION_ALIGNBIT,
+    XFS_SB_VERSION_MOREBITSBIT, XFS_SB_VERSION_QUOTABIT,
+};
+
+//Everything on disk is big endian, fields out of the bytes read as 0
+pub fn be_u16(bs: &[u8], offset: usize) -> u16 {
+    bs.get(offset..offset + 2).map(|x| u16::from_be_bytes([x[0], x[1]])).unwrap_or(0)
+}
+
+pub fn be_u32(bs: &[u8], offset: usize) -> u32 {
+    bs.get(offset..offset + 4).map(|x| u32::from_be_bytes([x[0], x[1], x[2], x[3]])).unwrap_or(0)
+}
+
+pub fn be_u64(bs: &[u8], offset: usize) -> u64 {
+    match bs.get(offset..offset + 8) {
+        Some(s) => u64::from_be_bytes([s[0], s[1], s[2], s[3], s[4], s[5], s[6], s[7]]),
+        None => 0,
+    }
+}
+
+const INOBT_V5_MAGIC: &[u8] = b"IAB3";
+const INOBT_MAGIC: &[u8] = b"IABT";
+const INOBT_RECORD_SIZE: usize = 16;
+//Deeper trees are a loop in a damaged volume
+const MAX_BTREE_LEVEL: u16 = 16;
+
+impl SuperBlock {
+    fn parse(bs: &[u8]) -> Result<SuperBlock, MRError> {
+        if bs.get(0..4) != Some(XFS_SB_MAGIC) {
+            return Err(MRError::new("Not a XFS super block"));
+        }
+        let feature_flags = be_u16(bs, 0x64);
+        let version = feature_flags & 0xf;
+        let mut file_system_id = [0; 16];
+        file_system_id.copy_from_slice(&bs[0x20..0x30]);
+        let quota = feature_flags & XFS_SB_VERSION_QUOTABIT != 0;
+        let align = feature_flags & XFS_SB_VERSION_ALIGNBIT != 0;
+        let sb = SuperBlock {
+            block_size: be_u32(bs, 0x4),
+            block_number: be_u64(bs, 0x8),
+            device_block_number: be_u64(bs, 0x10),
+            device_extent_number: be_u64(bs, 0x18),
+            file_system_id,
+            journal_block_number: be_u64(bs, 0x30),
+            root_dir_inode: be_u64(bs, 0x38),
+            rt_bitmap_extent_inode_number: be_u64(bs, 0x40),
+            rt_bitmap_summary_inode_number: be_u64(bs, 0x48),
+            rt_extent_size: be_u32(bs, 0x50),
+            alloc_group_size: be_u32(bs, 0x54),
+            alloc_groups_number: be_u32(bs, 0x58),
+            rt_bitmap_size: be_u32(bs, 0x5c),
+            journal_size: be_u32(bs, 0x60),
+            feature_flags,
+            sector_size: be_u16(bs, 0x66),
+            inode_size: be_u16(bs, 0x68),
+            inodes_per_block: be_u16(bs, 0x6a),
+            number_of_inodes: Some(be_u64(bs, 0x80)),
+            number_of_free_inodes: Some(be_u64(bs, 0x88)),
+            number_of_free_data_blocks: Some(be_u64(bs, 0x90)),
+            number_of_rt_extents: Some(be_u64(bs, 0x98)),
+            user_quota_inode_number: quota.then(|| be_u64(bs, 0xa0)),
+            group_quota_inode_number: quota.then(|| be_u64(bs, 0xa8)),
+            quota_flags: quota.then(|| be_u16(bs, 0xb0)),
+            inode_chunk_alignment_size: align.then(|| be_u32(bs, 0xb4)),
+            volume_name: String::from_utf8_lossy(&bs[0x6c..0x78]).trim_end_matches('\0').to_string(),
+            block_log: bs[0x78],
+            inodes_per_block_log: bs[0x7b],
+            alloc_group_block_log: bs[0x7c],
+            dir_block_log: bs[0xc0],
+            feature_flags2: match feature_flags & XFS_SB_VERSION_MOREBITSBIT {
+                0 => 0,
+                _ => be_u32(bs, 0xc8),
+            },
+            feature_incompat: match version {
+                5 => be_u32(bs, 0xd8),
+                _ => 0,
+            },
+        };
+        if !(4..=5).contains(&version) {
+            return Err(MRError::new(&format!("XFS version {} is not supported", version)));
+        }
+        if sb.block_size != 1 << sb.block_log || sb.inode_size == 0 || sb.alloc_group_size == 0 {
+            return Err(MRError::new("Broken XFS super block"));
+        }
+        Ok(sb)
+    }
+
+    pub fn get_block_size(&self) -> u32 {
+        self.block_size
+    }
+
+    pub fn get_block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    pub fn get_uuid(&self) -> &[u8; 16] {
+        &self.file_system_id
+    }
+
+    pub fn get_root_dir_inode(&self) -> u64 {
+        self.root_dir_inode
+    }
+
+    pub fn get_alloc_group_size(&self) -> u32 {
+        self.alloc_group_size
+    }
+
+    pub fn get_alloc_groups_number(&self) -> u32 {
+        self.alloc_groups_number
+    }
+
+    pub fn get_journal_block_number(&self) -> u64 {
+        self.journal_block_number
+    }
+
+    pub fn get_journal_size(&self) -> u32 {
+        self.journal_size
+    }
+
+    pub fn get_version(&self) -> u16 {
+        self.feature_flags & 0xf
+    }
+
+    pub fn get_sector_size(&self) -> u16 {
+        self.sector_size
+    }
+
+    pub fn get_inode_size(&self) -> u16 {
+        self.inode_size
+    }
+
+    pub fn get_number_of_inodes(&self) -> Option<u64> {
+        self.number_of_inodes
+    }
+
+    pub fn get_number_of_free_inodes(&self) -> Option<u64> {
+        self.number_of_free_inodes
+    }
+
+    pub fn get_number_of_free_data_blocks(&self) -> Option<u64> {
+        self.number_of_free_data_blocks
+    }
+
+    pub fn get_volume_name(&self) -> &String {
+        &self.volume_name
+    }
+
+    pub fn get_dir_block_log(&self) -> u8 {
+        self.dir_block_log
+    }
+
+    pub fn get_feature_incompat(&self) -> u32 {
+        self.feature_incompat
+    }
+}
+
+impl AllocGroup {
+    pub fn get_number(&self) -> u32 {
+        self.number
+    }
+
+    pub fn get_length(&self) -> u32 {
+        self.length
+    }
+
+    pub fn get_free_blocks(&self) -> u32 {
+        self.free_blocks
+    }
+
+    pub fn get_inode_count(&self) -> u32 {
+        self.inode_count
+    }
+
+    pub fn get_free_inodes(&self) -> u32 {
+        self.free_inodes
+    }
+}
+
+impl InodeChunk {
+    pub fn get_ag(&self) -> u32 {
+        self.ag
+    }
+
+    pub fn get_start_ino(&self) -> u32 {
+        self.start_ino
+    }
+
+    //Inode i of the chunk was never allocated
+    pub fn is_hole(&self, i: u32) -> bool {
+        self.hole_mask & (1 << (i / 4)) != 0
+    }
+
+    pub fn is_free(&self, i: u32) -> bool {
+        self.free_mask & (1 << i) != 0
+    }
+}
+
+impl XFS {
+    pub fn open<P>(path: P) -> Result<XFS, MRError>
+    where
+        P: AsRef<Path> + ToString,
+    {
+        let reader = MRFile::new(path)?;
+        let super_block = SuperBlock::parse(&reader.read_n(0, 512)?)?;
+        let has_ftype = match super_block.get_version() {
+            5 => super_block.feature_incompat & XFS_SB_FEAT_INCOMPAT_FTYPE != 0,
+            _ => super_block.feature_flags2 & XFS_SB_VERSION2_FTYPE != 0,
+        };
+        Ok(XFS {
+            reader,
+            super_block,
+            has_ftype,
+        })
+    }
+
+    pub fn get_reader(&self) -> &MRFile {
+        &self.reader
+    }
+
+    pub fn get_super_block(&self) -> &SuperBlock {
+        &self.super_block
+    }
+
+    pub fn has_ftype(&self) -> bool {
+        self.has_ftype
+    }
+
+    //Version 5 adds checksums, owners and uuids to every header
+    pub fn is_v5(&self) -> bool {
+        self.super_block.get_version() == 5
+    }
+
+    pub fn get_block_size(&self) -> u64 {
+        self.super_block.block_size as u64
+    }
+
+    pub fn get_dir_block_size(&self) -> u64 {
+        self.get_block_size() << self.super_block.dir_block_log
+    }
+
+    pub fn get_total_size(&self) -> u64 {
+        self.super_block.block_number * self.get_block_size()
+    }
+
+    //Block numbers of the forks hold the group in the high bits, groups are not a power of two long
+    pub fn fsb_to_offset(&self, fsb: u64) -> u64 {
+        let log = self.super_block.alloc_group_block_log;
+        let ag = fsb >> log;
+        let agbno = fsb & ((1 << log) - 1);
+        (ag * self.super_block.alloc_group_size as u64 + agbno) * self.get_block_size()
+    }
+
+    pub fn make_ino(&self, ag: u32, agino: u32) -> u64 {
+        let log = self.super_block.alloc_group_block_log + self.super_block.inodes_per_block_log;
+        ((ag as u64) << log) | agino as u64
+    }
+
+    //Group and inode number inside it
+    pub fn split_ino(&self, ino: u64) -> (u32, u32) {
+        let log = self.super_block.alloc_group_block_log + self.super_block.inodes_per_block_log;
+        ((ino >> log) as u32, (ino & ((1 << log) - 1)) as u32)
+    }
+
+    pub fn ino_to_offset(&self, ino: u64) -> u64 {
+        let (ag, agino) = self.split_ino(ino);
+        let log = self.super_block.inodes_per_block_log;
+        let agbno = (agino >> log) as u64;
+        let index = (agino & ((1 << log) - 1)) as u64;
+        (ag as u64 * self.super_block.alloc_group_size as u64 + agbno) * self.get_block_size()
+            + index * self.super_block.inode_size as u64
+    }
+
+    fn ag_offset(&self, ag: u32) -> u64 {
+        ag as u64 * self.super_block.alloc_group_size as u64 * self.get_block_size()
+    }
+
+    pub fn read_blocks(&self, fsb: u64, count: u64) -> Result<Vec<u8>, MRError> {
+        let offset = self.fsb_to_offset(fsb);
+        self.reader.read_n(offset as usize, (count * self.get_block_size()) as usize)
+    }
+
+    fn read_ag_block(&self, ag: u32, agbno: u32) -> Result<Vec<u8>, MRError> {
+        let offset = self.ag_offset(ag) + agbno as u64 * self.get_block_size();
+        self.reader.read_n(offset as usize, self.get_block_size() as usize)
+    }
+
+    //AGF is the second sector of the group and AGI the third
+    pub fn get_alloc_group(&self, ag: u32) -> Result<AllocGroup, MRError> {
+        if ag >= self.super_block.alloc_groups_number {
+            return Err(MRError::new("No such allocation group"));
+        }
+        let sector = self.super_block.sector_size as u64;
+        let bs = self.reader.read_n((self.ag_offset(ag) + sector) as usize, 2 * sector as usize)?;
+        let (agf, agi) = bs.split_at(sector as usize);
+        if agf.get(0..4) != Some(XFS_AGF_MAGIC) || agi.get(0..4) != Some(XFS_AGI_MAGIC) {
+            return Err(MRError::new(&format!("Broken headers of allocation group {}", ag)));
+        }
+        Ok(AllocGroup {
+            number: ag,
+            length: be_u32(agf, 12),
+            free_blocks: be_u32(agf, 52),
+            inode_count: be_u32(agi, 16),
+            free_inodes: be_u32(agi, 28),
+            inode_root: be_u32(agi, 20),
+            inode_level: be_u32(agi, 24),
+        })
+    }
+
+    fn inobt_header_size(&self) -> usize {
+        match self.is_v5() {
+            true => 56,
+            false => 16,
+        }
+    }
+
+    fn parse_inode_chunk(&self, ag: u32, bs: &[u8]) -> InodeChunk {
+        let sparse = self.super_block.feature_incompat & XFS_SB_FEAT_INCOMPAT_SPINODES != 0;
+        InodeChunk {
+            ag,
+            start_ino: be_u32(bs, 0),
+            hole_mask: if sparse { be_u16(bs, 4) } else { 0 },
+            free_mask: be_u64(bs, 8),
+        }
+    }
+
+    //Records of the inode B+tree of the group, in inode order
+    pub fn get_inode_chunks(&self, ag: u32) -> Result<Vec<InodeChunk>, MRError> {
+        let group = self.get_alloc_group(ag)?;
+        let mut result = vec![];
+        self.walk_inobt(ag, group.inode_root, MAX_BTREE_LEVEL, &mut |_, bs, recs| {
+            for i in 0..recs {
+                result.push(self.parse_inode_chunk(ag, &bs[i * INOBT_RECORD_SIZE..]));
+            }
+            true
+        })?;
+        Ok(result)
+    }
+
+    //f gets the leaves of the tree, with the bytes of the records and their count
+    fn walk_inobt<F>(&self, ag: u32, agbno: u32, depth: u16, f: &mut F) -> Result<bool, MRError>
+    where
+        F: FnMut(u32, &[u8], usize) -> bool,
+    {
+        let bs = self.read_ag_block(ag, agbno)?;
+        let magic = match self.is_v5() {
+            true => INOBT_V5_MAGIC,
+            false => INOBT_MAGIC,
+        };
+        if bs.get(0..4) != Some(magic) || depth == 0 {
+            return Err(MRError::new(&format!("Broken inode B+tree block {} of group {}", agbno, ag)));
+        }
+        let level = be_u16(&bs, 4);
+        let recs = be_u16(&bs, 6) as usize;
+        let header = self.inobt_header_size();
+        if level == 0 {
+            let recs = recs.min((bs.len() - header) / INOBT_RECORD_SIZE);
+            return Ok(f(agbno, &bs[header..], recs));
+        }
+        let max_recs = (bs.len() - header) / 8;
+        for i in 0..recs.min(max_recs) {
+            let child = be_u32(&bs, header + max_recs * 4 + i * 4);
+            if !self.walk_inobt(ag, child, depth - 1, f)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    //Record of the inode B+tree holding ino, None if the inode was never allocated
+    pub fn find_inode_chunk(&self, ino: u64) -> Result<Option<InodeChunk>, MRError> {
+        let (ag, agino) = self.split_ino(ino);
+        let group = self.get_alloc_group(ag)?;
+        let header = self.inobt_header_size();
+        let mut agbno = group.inode_root;
+        for _ in 0..MAX_BTREE_LEVEL {
+            let bs = self.read_ag_block(ag, agbno)?;
+            let level = be_u16(&bs, 4);
+            let recs = be_u16(&bs, 6) as usize;
+            if level == 0 {
+                let found = (0..recs.min((bs.len() - header) / INOBT_RECORD_SIZE))
+                    .map(|i| self.parse_inode_chunk(ag, &bs[header + i * INOBT_RECORD_SIZE..]))
+                    .find(|x| x.start_ino <= agino && agino < x.start_ino + 64);
+                return Ok(found);
+            }
+            //The last key not past agino leads to it
+            let max_recs = (bs.len() - header) / 8;
+            let recs = recs.min(max_recs);
+            let i = (0..recs).rev().find(|i| be_u32(&bs, header + i * 4) <= agino);
+            let i = match i {
+                Some(s) => s,
+                None => return Ok(None),
+            };
+            agbno = be_u32(&bs, header + max_recs * 4 + i * 4);
+        }
+        Err(MRError::new("Inode B+tree too deep"))
+    }
+
+    //In use per the inode B+tree, freed inodes keep most of their core
+    pub fn is_inode_allocated(&self, ino: u64) -> Result<bool, MRError> {
+        let (_, agino) = self.split_ino(ino);
+        Ok(match self.find_inode_chunk(ino)? {
+            Some(chunk) => {
+                let i = agino - chunk.start_ino;
+                !chunk.is_hole(i) && !chunk.is_free(i)
+            }
+            None => false,
+        })
+    }
+
+    pub fn get_inode_by_id(&self, ino: u64) -> Result<Inode, MRError> {
+        let size = self.super_block.inode_size as usize;
+        let bs = self.reader.read_n(self.ino_to_offset(ino) as usize, size)?;
+        Inode::parse(&bs, ino)
+    }
+
+    pub fn get_inode_by_path(&self, path: &str) -> Result<Inode, MRError> {
+        let mut inode = self.get_inode_by_id(self.super_block.root_dir_inode)?;
+        for name in path.split('/').filter(|x| !x.is_empty()) {
+            let entries = self.get_dir_entries(&inode)?;
+            let entry = entries
+                .iter()
+                .find(|x| x.get_name().eq(name))
+                .ok_or(MRError::new(&format!("{} not found", path)))?;
+            inode = self.get_inode_by_id(entry.get_inode())?;
+        }
+        Ok(inode)
+    }
+}
@@ -0,0 +1,292 @@
+use memchr::memmem;
+
+use super::carver::{le_u16, le_u32, Carving};
+
+pub const MAGIC: &[u8] = b"PK\x03\x04";
+const CENTRAL_MAGIC: &[u8] = b"PK\x01\x02";
+const END_MAGIC: &[u8] = b"PK\x05\x06";
+const DESCRIPTOR_MAGIC: &[u8] = b"PK\x07\x08";
+const ZIP64_END_MAGIC: &[u8] = b"PK\x06\x06";
+const ZIP64_LOCATOR_MAGIC: &[u8] = b"PK\x06\x07";
+const LOCAL_SIZE: usize = 30;
+const END_SIZE: usize = 22;
+const CENTRAL_SIZE: usize = 46;
+const DESCRIPTOR_SIZE: usize = 16;
+const ZIP64_LOCATOR_SIZE: usize = 20;
+
+//OOXML, jar and apk are zips told apart by the names they hold
+fn ext_of(names: &[&[u8]]) -> Option<&'static str> {
+    let has = |name: &[u8]| names.contains(&name);
+    let prefix = |prefix: &[u8]| names.iter().any(|x| x.starts_with(prefix));
+    if has(b"[Content_Types].xml") {
+        if prefix(b"word/") {
+            return Some("docx");
+        }
+        if prefix(b"xl/") {
+            return Some("xlsx");
+        }
+        if prefix(b"ppt/") {
+            return Some("pptx");
+        }
+    }
+    if has(b"AndroidManifest.xml") {
+        return Some("apk");
+    }
+    if has(b"META-INF/MANIFEST.MF") {
+        return Some("jar");
+    }
+    None
+}
+
+//Walks the central directory, every entry must point at a local header, None when one does not
+fn check_central(bs: &[u8], offset: usize, entries: usize, end: usize) -> Option<Vec<&[u8]>> {
+    let mut names = vec![];
+    let mut offset = offset;
+    for _ in 0..entries {
+        if offset + CENTRAL_SIZE > end || &bs[offset..offset + 4] != CENTRAL_MAGIC {
+            return None;
+        }
+        let name_len = le_u16(bs, offset + 28);
+        let extra_len = le_u16(bs, offset + 30);
+        let comment_len = le_u16(bs, offset + 32);
+        let local = le_u32(bs, offset + 42);
+        //Zip64 keeps the offset in the extra field
+        if local != 0xffffffff && bs.get(local..local + 4) != Some(MAGIC) {
+            return None;
+        }
+        let name_end = offset + CENTRAL_SIZE + name_len;
+        if name_end > end {
+            return None;
+        }
+        names.push(&bs[offset + CENTRAL_SIZE..name_end]);
+        offset = name_end + extra_len + comment_len;
+    }
+    match offset == end {
+        true => Some(names),
+        false => None,
+    }
+}
+
+//The zip64 extra field holds the sizes too large for the header, the uncompressed one first
+fn zip64_compressed(extra: &[u8]) -> Option<usize> {
+    let mut pos = 0;
+    while pos + 4 <= extra.len() {
+        let len = le_u16(extra, pos + 2);
+        if le_u16(extra, pos) == 1 && len >= 16 && pos + 20 <= extra.len() {
+            return usize::try_from(u64::from_le_bytes(extra[pos + 12..pos + 20].try_into().ok()?)).ok();
+        }
+        pos += 4 + len;
+    }
+    None
+}
+
+//Where the entry of the local header at offset ends, its data included
+enum Step {
+    Next(usize),
+    NeedMore,
+    Broken,
+}
+
+//Size of the data from the local header, or from the descriptor after the data when bit 3 of the
+//flags is set, the descriptor signature is optional
+fn local_entry_end(bs: &[u8], offset: usize) -> Step {
+    if offset + LOCAL_SIZE > bs.len() {
+        return Step::NeedMore;
+    }
+    let flags = le_u16(bs, offset + 6);
+    let compressed = le_u32(bs, offset + 18);
+    let name_len = le_u16(bs, offset + 26);
+    let extra_len = le_u16(bs, offset + 28);
+    if name_len == 0 {
+        return Step::Broken;
+    }
+    let data = offset + LOCAL_SIZE + name_len + extra_len;
+    let compressed = match compressed {
+        0xffffffff if data > bs.len() => return Step::NeedMore,
+        0xffffffff => match zip64_compressed(&bs[offset + LOCAL_SIZE + name_len..data]) {
+            Some(s) => s,
+            None => return Step::Broken,
+        },
+        n => n,
+    };
+    if flags & 0x8 == 0 || compressed != 0 {
+        let end = match data.checked_add(compressed) {
+            Some(s) => s,
+            None => return Step::Broken,
+        };
+        if end > bs.len() {
+            return Step::NeedMore;
+        }
+        if flags & 0x8 == 0 {
+            return Step::Next(end);
+        }
+        return match bs.get(end..end + 4) {
+            Some(DESCRIPTOR_MAGIC) => Step::Next(end + DESCRIPTOR_SIZE),
+            Some(_) => Step::Next(end + DESCRIPTOR_SIZE - 4),
+            None => Step::NeedMore,
+        };
+    }
+    //A descriptor whose size matches the bytes before it and followed by another header
+    for pos in memmem::find_iter(bs.get(data..).unwrap_or_default(), DESCRIPTOR_MAGIC).map(|x| data + x) {
+        if pos + DESCRIPTOR_SIZE + 4 > bs.len() {
+            return Step::NeedMore;
+        }
+        let next = &bs[pos + DESCRIPTOR_SIZE..pos + DESCRIPTOR_SIZE + 4];
+        if le_u32(bs, pos + 8) == pos - data && (next == MAGIC || next == CENTRAL_MAGIC) {
+            return Step::Next(pos + DESCRIPTOR_SIZE);
+        }
+    }
+    Step::NeedMore
+}
+
+//Walks the local entries, the central directory and the zip64 records up to the end of central
+//directory record, anything else in between is not a zip
+pub fn carve(bs: &[u8], last: bool) -> Carving {
+    let more = match last {
+        true => Carving::Invalid,
+        false => Carving::NeedMore,
+    };
+    if bs.len() < LOCAL_SIZE {
+        return more;
+    }
+    let mut offset = 0;
+    let mut central = None;
+    loop {
+        let magic = match bs.get(offset..offset + 4) {
+            Some(s) => s,
+            None => return more,
+        };
+        if magic == MAGIC && central.is_none() {
+            offset = match local_entry_end(bs, offset) {
+                Step::Next(s) => s,
+                Step::NeedMore => return more,
+                Step::Broken => return Carving::Invalid,
+            };
+        } else if magic == CENTRAL_MAGIC && offset > 0 {
+            if offset + CENTRAL_SIZE > bs.len() {
+                return more;
+            }
+            central = central.or(Some(offset));
+            offset += CENTRAL_SIZE + le_u16(bs, offset + 28) + le_u16(bs, offset + 30) + le_u16(bs, offset + 32);
+        } else if magic == ZIP64_END_MAGIC {
+            if offset + 12 > bs.len() {
+                return more;
+            }
+            offset += 12 + le_u32(bs, offset + 4);
+        } else if magic == ZIP64_LOCATOR_MAGIC {
+            offset += ZIP64_LOCATOR_SIZE;
+        } else if magic == END_MAGIC && offset > 0 {
+            break;
+        } else {
+            return Carving::Invalid;
+        }
+    }
+    if offset + END_SIZE > bs.len() {
+        return more;
+    }
+    let size = offset + END_SIZE + le_u16(bs, offset + 20);
+    if size > bs.len() {
+        return more;
+    }
+    let entries = le_u16(bs, offset + 10);
+    let central_offset = le_u32(bs, offset + 16);
+    //Zip64 keeps the real values in its own record
+    if central_offset == 0xffffffff {
+        return Carving::found(size, 80);
+    }
+    match central == Some(central_offset) {
+        true => match check_central(bs, central_offset, entries, offset) {
+            Some(names) => Carving::Found { size, confidence: 100, ext: ext_of(&names) },
+            None => Carving::found(size, 60),
+        },
+        false => Carving::found(size, 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(name: &[u8], data: &[u8], flags: u16) -> Vec<u8> {
+        let size = if flags & 0x8 != 0 { 0 } else { data.len() as u32 };
+        let mut bs = MAGIC.to_vec();
+        bs.extend(20u16.to_le_bytes());
+        bs.extend(flags.to_le_bytes());
+        bs.extend([0; 10]);
+        bs.extend(size.to_le_bytes());
+        bs.extend(size.to_le_bytes());
+        bs.extend((name.len() as u16).to_le_bytes());
+        bs.extend(0u16.to_le_bytes());
+        bs.extend(name);
+        bs.extend(data);
+        if flags & 0x8 != 0 {
+            bs.extend(DESCRIPTOR_MAGIC);
+            bs.extend([0; 4]);
+            bs.extend((data.len() as u32).to_le_bytes());
+            bs.extend((data.len() as u32).to_le_bytes());
+        }
+        bs
+    }
+
+    fn central(name: &[u8], size: u32, offset: u32) -> Vec<u8> {
+        let mut bs = CENTRAL_MAGIC.to_vec();
+        bs.extend([20, 0, 20, 0]);
+        bs.extend([0; 12]);
+        bs.extend(size.to_le_bytes());
+        bs.extend(size.to_le_bytes());
+        bs.extend((name.len() as u16).to_le_bytes());
+        bs.extend([0; 12]);
+        bs.extend(offset.to_le_bytes());
+        bs.extend(name);
+        bs
+    }
+
+    //Stored entries, the flags of each one are given with it
+    fn sample(entries: &[(&[u8], &[u8], u16)]) -> Vec<u8> {
+        let mut bs = vec![];
+        let mut dir = vec![];
+        for (name, data, flags) in entries {
+            dir.extend(central(name, data.len() as u32, bs.len() as u32));
+            bs.extend(local(name, data, *flags));
+        }
+        let offset = bs.len() as u32;
+        bs.extend(&dir);
+        bs.extend(END_MAGIC);
+        bs.extend([0; 4]);
+        bs.extend((entries.len() as u16).to_le_bytes());
+        bs.extend((entries.len() as u16).to_le_bytes());
+        bs.extend((dir.len() as u32).to_le_bytes());
+        bs.extend(offset.to_le_bytes());
+        bs.extend(0u16.to_le_bytes());
+        bs
+    }
+
+    #[test]
+    fn valid() {
+        let mut bs = sample(&[(b"a.txt", b"hello", 0), (b"b.txt", b"PK\x07\x08 inside", 0x8)]);
+        let size = bs.len();
+        bs.extend([0x77; 32]);
+        assert_eq!(carve(&bs, false), Carving::Found { size, confidence: 100, ext: None });
+        let bs = sample(&[(b"[Content_Types].xml", b"<x/>", 0), (b"word/document.xml", b"<w/>", 0)]);
+        assert_eq!(carve(&bs, true), Carving::Found { size: bs.len(), confidence: 100, ext: Some("docx") });
+    }
+
+    #[test]
+    fn truncated() {
+        let bs = sample(&[(b"a.txt", b"hello", 0)]);
+        let bs = &bs[..bs.len() - 10];
+        assert_eq!(carve(bs, false), Carving::NeedMore);
+        assert_eq!(carve(bs, true), Carving::Invalid);
+    }
+
+    #[test]
+    fn garbage() {
+        //No name
+        let bs = sample(&[(b"", b"hello", 0)]);
+        assert_eq!(carve(&bs, false), Carving::Invalid);
+        //Something else after the data of the first entry, no need to look for the end record
+        let mut bs = local(b"a.txt", b"hello", 0);
+        bs.extend([0x42; 64]);
+        assert_eq!(carve(&bs, false), Carving::Invalid);
+    }
+}
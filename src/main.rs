@@ -4,7 +4,7 @@ use std::{collections::HashMap, fs, path::PathBuf, ptr::addr_of};
 use clap::{Args, Parser, Subcommand};
 use colored::{Colorize, ColoredString};
 use meta_reader::{
-    modules::{ext4::Ext4Module, ntfs::NtfsModule}, utils::file::filesize_to_human_string
+    modules::{ext4::Ext4Module, fat::FatModule, ntfs::NtfsModule, raw::RawModule, xfs::XfsModule}, utils::file::filesize_to_human_string
 };
 
 #[derive(Subcommand, Debug)]
@@ -12,6 +12,11 @@ enum Commands {
     /// ntfs
     Ntfs(Ntfs),
     Ext4(Ext4),
+    /// images of any file system
+    Raw(Raw),
+    Xfs(Xfs),
+    /// FAT12, FAT16, FAT32 and exFAT
+    Fat(Fat),
 }
 
 #[derive(Parser, Debug)]
@@ -45,6 +50,42 @@ pub struct Ext4 {
     options: Option<String>
 }
 
+#[derive(Debug, Args)]
+pub struct Raw {
+    #[arg(short, long)]
+    function: String,
+
+    #[arg(short, long)]
+    device: String,
+
+    #[arg(short, long)]
+    options: Option<String>
+}
+
+#[derive(Debug, Args)]
+pub struct Xfs {
+    #[arg(short, long)]
+    function: String,
+
+    #[arg(short, long)]
+    device: String,
+
+    #[arg(short, long)]
+    options: Option<String>
+}
+
+#[derive(Debug, Args)]
+pub struct Fat {
+    #[arg(short, long)]
+    function: String,
+
+    #[arg(short, long)]
+    device: String,
+
+    #[arg(short, long)]
+    options: Option<String>
+}
+
 fn help(program_name: &ColoredString) {
     println!("{} ${{alias}} [${{option}}[${{option}}..]]", program_name);
     println!(
@@ -86,7 +127,14 @@ fn main() {
                 }
             });
             
-            let mut module = NtfsModule::new(img).unwrap();
+            let module = NtfsModule::with_options(img, &_f_args);
+            let mut module = match module {
+                Ok(o) => o,
+                Err(e) => {
+                    println!("[Error]:{}", e);
+                    return;
+                }
+            };
             let function = &ntfs.function;
 
             if function.eq("stat") {
@@ -99,6 +147,54 @@ fn main() {
                 }
             } else if function.eq("search_usn") {
                 module.search_usn(_f_args).unwrap();
+            } else if function.eq("dump_usn") {
+                if let Err(e) = module.dump_usn(_f_args) {
+                    println!("[Error]:{}", e);
+                }
+            } else if function.eq("carve") {
+                match module.carve(_f_args) {
+                    Ok(count) => println!("{} records carved", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("index_slack") {
+                match module.index_slack(_f_args) {
+                    Ok(count) => println!("{} entries recovered from index slack", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("search_deleted_files") {
+                match module.search_deleted_files(_f_args) {
+                    Ok(count) => println!("{} deleted entries found", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("recover") {
+                match module.recover(_f_args) {
+                    Ok(count) => println!("{} deleted files recovered", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("anomalies") {
+                match module.anomalies(_f_args) {
+                    Ok(count) => println!("{} timestamp anomalies", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("vss_list") {
+                match module.vss_list() {
+                    Ok(count) => println!("{} shadow copies", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("whose") {
+                if let Err(e) = module.whose(_f_args) {
+                    println!("[Error]:{}", e);
+                }
+            } else if function.eq("export_unallocated") {
+                match module.export_unallocated(_f_args) {
+                    Ok(size) => println!("{} bytes exported", size),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("carve_files") {
+                match module.carve_files(_f_args) {
+                    Ok(count) => println!("{} files carved", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
             }
         },
         Commands::Ext4(ext4) => {
@@ -112,7 +208,16 @@ fn main() {
                 }
             }
             
-            let mut module = Ext4Module::new(img).unwrap();
+            let mut module = match Ext4Module::new(img, _f_args.get("journal").map(|x| x.as_str())) {
+                Ok(o) => o,
+                Err(e) => {
+                    println!("[Error]:{}", e);
+                    return;
+                }
+            };
+            if _f_args.get("strict").is_some_and(|s| s.eq("true")) {
+                module.set_strict(true);
+            }
             let function = &ext4.function;
             if function.eq("list_deleted_files") {
                 let _dirs = module
@@ -241,7 +346,250 @@ fn main() {
                     .unwrap();
                 println!();
             } else if function.eq("search_disk") {
-                module.search_disk(_f_args);
+                match module.search_disk(_f_args) {
+                    Ok(count) => println!("{} matches", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("verify") {
+                match module.verify(_f_args, |mismatch| {
+                    println!("{}", mismatch);
+                }) {
+                    Ok(count) => println!("{} checksum mismatches", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("whose") {
+                if let Err(e) = module.whose(_f_args) {
+                    println!("[Error]:{}", e);
+                }
+            } else if function.eq("export_unallocated") {
+                match module.export_unallocated(_f_args) {
+                    Ok(size) => println!("{} bytes exported", size),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("carve_files") {
+                match module.carve_files(_f_args) {
+                    Ok(count) => println!("{} files carved", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("journal_timeline") {
+                if let Err(e) = module.journal_timeline(_f_args, |event| {
+                    println!("{}", event);
+                }) {
+                    println!("[Error]:{}", e);
+                }
+            }
+        },
+        Commands::Raw(raw) => {
+            let img = &raw.device;
+            if let Some(x) = raw.options.as_ref() {
+                let options = x.split(',');
+                for option in options {
+                    let kv = option.split('=');
+                    let kv = kv.collect::<Vec<&str>>();
+                    _f_args.insert(kv[0].trim().to_string(), kv[1].trim().to_string());
+                }
+            }
+
+            let module = match RawModule::new(img) {
+                Ok(o) => o,
+                Err(e) => {
+                    println!("[Error]:{}", e);
+                    return;
+                }
+            };
+            let function = &raw.function;
+            if function.eq("carve_files") {
+                match module.carve_files(_f_args) {
+                    Ok(count) => println!("{} files carved", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            }
+        },
+        Commands::Xfs(xfs) => {
+            let img = &xfs.device;
+            if let Some(x) = xfs.options.as_ref() {
+                let options = x.split(',');
+                for option in options {
+                    let kv = option.split('=');
+                    let kv = kv.collect::<Vec<&str>>();
+                    _f_args.insert(kv[0].trim().to_string(), kv[1].trim().to_string());
+                }
+            }
+
+            let module = match XfsModule::new(img) {
+                Ok(o) => o,
+                Err(e) => {
+                    println!("[Error]:{}", e);
+                    return;
+                }
+            };
+            let function = &xfs.function;
+            if function.eq("stat") {
+                if let Err(e) = module.stat(_f_args) {
+                    println!("[Error]:{}", e);
+                }
+            } else if function.eq("list_files") {
+                match module.list_files(_f_args) {
+                    Ok(dirs) => {
+                        for dir in dirs {
+                            println!("{}", dir.get_name());
+                        }
+                    }
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("read_file") {
+                if let Err(e) = module.read_file(_f_args) {
+                    println!("[Error]:{}", e);
+                }
+            } else if function.eq("list_deleted_files") {
+                let result = module.list_deleted_files(_f_args, |entry, inode, allocated| {
+                    println!("{}", entry.get_name());
+                    println!("\tinode id: {}", entry.get_inode());
+                    println!("\tentry offset: {}", entry.get_offset());
+                    let inode = match inode {
+                        Some(s) => s,
+                        None => {
+                            println!("\tinode unreadable");
+                            println!();
+                            return;
+                        }
+                    };
+                    if allocated {
+                        println!("\tinode reused, mode {:o}", inode.get_mode());
+                    } else {
+                        println!("\tatime: {}", inode.get_atime());
+                        println!("\tctime: {}", inode.get_ctime());
+                        println!("\tmtime: {}", inode.get_mtime());
+                        if let Some(crtime) = inode.get_crtime() {
+                            println!("\tbirth time: {}", crtime);
+                        }
+                        println!("\tuid: {}", inode.get_uid());
+                    }
+                    println!();
+                });
+                match result {
+                    Ok(entries) => println!("{} deleted entries", entries.len()),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("search_deleted_files") {
+                let result = module.search_deleted_files(_f_args, |name, entry, inode, allocated| {
+                    println!("{}", name);
+                    println!("\tinode id: {}", entry.get_inode());
+                    match inode {
+                        Some(s) if !allocated => {
+                            println!("\tatime: {}", s.get_atime());
+                            println!("\tctime: {}", s.get_ctime());
+                            println!("\tmtime: {}", s.get_mtime());
+                            if let Some(crtime) = s.get_crtime() {
+                                println!("\tbirth time: {}", crtime);
+                            }
+                        }
+                        Some(s) => println!("\tinode reused, mode {:o}", s.get_mode()),
+                        None => println!("\tinode unreadable"),
+                    }
+                    println!();
+                });
+                match result {
+                    Ok(count) => println!("{} deleted entries found", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("list_recoverable") {
+                let result = module.list_recoverable_inodes(_f_args, |inode, names, copies| {
+                    match names.is_empty() {
+                        true => println!("<unknown name>"),
+                        false => println!("{}", names.join(" | ")),
+                    }
+                    println!("\tinode id: {}", inode.get_id());
+                    println!("\tatime: {}", inode.get_atime());
+                    println!("\tctime: {}", inode.get_ctime());
+                    println!("\tmtime: {}", inode.get_mtime());
+                    if let Some(crtime) = inode.get_crtime() {
+                        println!("\tbirth time: {}", crtime);
+                    }
+                    for copy in copies {
+                        let logged = copy.get_inode();
+                        println!(
+                            "\tlog copy at {}:{}: mode {:o}, size {}, mtime {}",
+                            copy.get_lsn() >> 32,
+                            copy.get_lsn() & 0xffffffff,
+                            logged.get_mode(),
+                            filesize_to_human_string(logged.get_size() as usize),
+                            logged.get_mtime()
+                        );
+                    }
+                    println!();
+                });
+                match result {
+                    Ok(count) => println!("{} recoverable inodes", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            }
+        },
+        Commands::Fat(fat) => {
+            let img = &fat.device;
+            if let Some(x) = fat.options.as_ref() {
+                let options = x.split(',');
+                for option in options {
+                    let kv = option.split('=');
+                    let kv = kv.collect::<Vec<&str>>();
+                    _f_args.insert(kv[0].trim().to_string(), kv[1].trim().to_string());
+                }
+            }
+
+            let module = match FatModule::new(img) {
+                Ok(o) => o,
+                Err(e) => {
+                    println!("[Error]:{}", e);
+                    return;
+                }
+            };
+            let function = &fat.function;
+            if function.eq("stat") {
+                if let Err(e) = module.stat(_f_args) {
+                    println!("[Error]:{}", e);
+                }
+            } else if function.eq("list_files") {
+                match module.list_files(_f_args) {
+                    Ok(entries) => {
+                        for entry in entries {
+                            match entry.is_dir() {
+                                true => println!("{}/", entry.get_name()),
+                                false => println!("{}", entry.get_name()),
+                            }
+                        }
+                    }
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("read_file") {
+                if let Err(e) = module.read_file(_f_args) {
+                    println!("[Error]:{}", e);
+                }
+            } else if function.eq("list_deleted_files") {
+                let result = module.list_deleted_files(_f_args, |name, entry, chain| {
+                    println!("{}", name);
+                    println!("\tentry offset: {}", entry.get_offset());
+                    println!("\tfile size: {}", filesize_to_human_string(entry.get_size() as usize));
+                    println!("\tctime: {}", entry.get_ctime());
+                    println!("\tmtime: {}", entry.get_mtime());
+                    println!("\tatime: {}", entry.get_atime());
+                    println!("\tfirst cluster: {}", entry.get_first_cluster());
+                    println!(
+                        "\tclusters: {} by {:?}, {} reused",
+                        chain.get_clusters().len(),
+                        chain.get_guess(),
+                        chain.get_reused()
+                    );
+                    println!();
+                });
+                match result {
+                    Ok(count) => println!("{} deleted entries found", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
+            } else if function.eq("recover_files") {
+                match module.recover_files(_f_args) {
+                    Ok(count) => println!("{} deleted files recovered", count),
+                    Err(e) => println!("[Error]:{}", e),
+                }
             }
         },
     }
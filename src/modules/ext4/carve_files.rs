@@ -0,0 +1,28 @@
+use std::{collections::HashMap, ops::Range};
+
+use crate::{modules::raw::carve_files::carve_files, utils::MRError};
+
+use super::Ext4Module;
+
+impl Ext4Module {
+    //source=${default:unalloc|all}, other options as for raw carve_files
+    pub fn carve_files(&self, args: HashMap<String, String>) -> Result<usize, MRError> {
+        let block_size = self.ext4.get_block_size() as u64;
+        let ranges = match args.get("source").map(|x| x.as_str()).unwrap_or("unalloc") {
+            "unalloc" => self
+                .ext4
+                .get_unallocated_blocks()?
+                .into_iter()
+                .map(|x| x.start * block_size..x.end * block_size)
+                .collect(),
+            "all" => vec![Range {
+                start: 0,
+                end: self.ext4.get_total_size()?,
+            }],
+            _ => {
+                return Err(MRError::new("source=${unalloc|all}"));
+            }
+        };
+        carve_files(self.ext4.get_reader(), &ranges, &args)
+    }
+}
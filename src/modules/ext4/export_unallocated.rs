@@ -0,0 +1,43 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::utils::{
+    export::{export_ranges, ExportRange},
+    MRError,
+};
+
+use super::Ext4Module;
+
+impl Ext4Module {
+    //mode=${unallocated|slack},out=${default:./${mode}.bin},sparse=${true|false} keeps every byte at its volume offset
+    pub fn export_unallocated(&self, args: HashMap<String, String>) -> Result<u64, MRError> {
+        let mode = args.get("mode").map(|x| x.as_str()).unwrap_or("unallocated");
+        let block_size = self.ext4.get_block_size() as u64;
+        let ranges: Vec<ExportRange> = match mode {
+            "unallocated" => self
+                .ext4
+                .get_unallocated_blocks()?
+                .into_iter()
+                .map(|x| ExportRange {
+                    offset: x.start * block_size,
+                    length: (x.end - x.start) * block_size,
+                    owner: None,
+                })
+                .collect(),
+            "slack" => {
+                println!("Loading inodes....");
+                let map = self.ext4.build_block_map()?;
+                map.get_slacks(block_size).into_iter().map(ExportRange::from).collect()
+            }
+            _ => {
+                return Err(MRError::new("mode=${unallocated|slack}"));
+            }
+        };
+        let out = match args.get("out") {
+            Some(s) => s.to_string(),
+            None => format!("./{}.bin", mode),
+        };
+        let sparse = args.get("sparse").is_some_and(|x| x.eq("true"));
+        let volume_size = self.ext4.get_total_size()?;
+        export_ranges(self.ext4.get_reader(), &ranges, Path::new(&out), sparse, volume_size)
+    }
+}
@@ -15,7 +15,12 @@ impl Ext4Module {
             }
         };
 
-        let inode_id = inode_id.parse::<u32>().unwrap();
+        let inode_id = match inode_id.parse::<u32>() {
+            Ok(o) => o,
+            Err(_) => {
+                return Err(MRError::new("inode=${inode_id}"));
+            }
+        };
         let out_file = match args.get("out_file") {
             Some(o) => o,
             None => {
@@ -25,18 +30,29 @@ impl Ext4Module {
             }
         };
 
-        let jbd2 = self.ext4.get_jbd2().unwrap();
+        let jbd2 = self.ext4.get_jbd2()?;
         let inodes = jbd2.find_inodes(inode_id)?;
         let mut count = 0;
-        for i in inodes {
+        let mut write = |o: &[u8]| -> Result<(), MRError> {
+            let name = format!("{}.{}", out_file, count);
+            let mut f = fs::File::create(&name).map_err(|e| MRError::new(&e.to_string()))?;
+            f.write_all(o).map_err(|e| MRError::new(&e.to_string()))?;
+            count += 1;
+            Ok(())
+        };
+        for i in &inodes {
             if let Ok(o) = i.get_extents_value() {
                 if o.is_empty() {
                     continue;
                 }
-                let name = format!("{}.{}", out_file, count);
-                let mut f = fs::File::create(&name).unwrap();
-                f.write_all(&o).unwrap();
-                count += 1;
+                write(&o)?;
+            }
+        }
+
+        //Ranges logged by fast commits apply on top of the newest copy of the inode
+        if let Some(last) = inodes.last() {
+            if let Some(o) = jbd2.get_fast_commit_value(inode_id, last)? {
+                write(&o)?;
             }
         }
 
@@ -0,0 +1,260 @@
+use std::{collections::HashMap, fmt::Display};
+
+use bytes::{Buf, Bytes};
+use chrono::NaiveDateTime;
+
+use crate::{
+    file_struct::ext4::{DirectoryEntry, Inode},
+    utils::MRError,
+};
+
+use super::Ext4Module;
+
+#[derive(Debug, Clone)]
+pub enum TimelineChange {
+    Size(u64, u64),
+    Mtime(NaiveDateTime, NaiveDateTime),
+    LinkCount(u16, u16),
+    //Name and inode of the entry, the event is about the directory
+    EntryAdded(String, u32),
+    EntryRemoved(String, u32),
+    EntryRenamed(String, String, u32),
+}
+
+#[derive(Debug, Clone)]
+pub struct TimelineEvent {
+    sequence: u32,
+    commit_time: NaiveDateTime,
+    //Filesystem block whose journal copies were compared
+    block: u64,
+    //Directory of entry changes, 0 when the block owner is unknown
+    inode: u32,
+    change: TimelineChange,
+}
+
+impl TimelineChange {
+    //Inode of the directory entry the change is about
+    pub fn get_entry_inode(&self) -> Option<u32> {
+        match self {
+            Self::EntryAdded(_, id) | Self::EntryRemoved(_, id) | Self::EntryRenamed(_, _, id) => Some(*id),
+            _ => None,
+        }
+    }
+}
+
+impl TimelineEvent {
+    pub fn get_sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    pub fn get_commit_time(&self) -> NaiveDateTime {
+        self.commit_time
+    }
+
+    pub fn get_block(&self) -> u64 {
+        self.block
+    }
+
+    pub fn get_inode(&self) -> u32 {
+        self.inode
+    }
+
+    pub fn get_change(&self) -> &TimelineChange {
+        &self.change
+    }
+}
+
+impl Display for TimelineChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Size(old, new) => write!(f, "size {} -> {}", old, new),
+            Self::Mtime(old, new) => write!(f, "mtime {} -> {}", old, new),
+            Self::LinkCount(old, new) => write!(f, "link count {} -> {}", old, new),
+            Self::EntryAdded(name, id) => write!(f, "entry added {} (inode {})", name, id),
+            Self::EntryRemoved(name, id) => write!(f, "entry removed {} (inode {})", name, id),
+            Self::EntryRenamed(old, new, id) => write!(f, "entry renamed {} -> {} (inode {})", old, new, id),
+        }
+    }
+}
+
+impl Display for TimelineEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] {} inode {} (block {}): {}",
+            self.sequence, self.commit_time, self.inode, self.block, self.change
+        )
+    }
+}
+
+//Parses a block as a linear directory block, None if the rec_len chain does not cover it exactly
+fn parse_dir_block(bs: &Bytes) -> Option<Vec<DirectoryEntry>> {
+    let mut entries = vec![];
+    let mut offset = 0;
+    while offset < bs.len() {
+        if offset + 8 > bs.len() {
+            return None;
+        }
+        let inode = (&bs[offset..offset + 4]).get_u32_le();
+        let rec_len = (&bs[offset + 4..offset + 6]).get_u16_le() as usize;
+        let name_len = bs[offset + 6] as usize;
+        let file_type = bs[offset + 7];
+        if rec_len < 12 || !rec_len.is_multiple_of(4) || offset + rec_len > bs.len() {
+            return None;
+        }
+        //The checksum tail uses file type 0xDE
+        if file_type > 7 && !(inode == 0 && file_type == 0xDE) {
+            return None;
+        }
+        if inode != 0 {
+            if 8 + name_len > rec_len || name_len == 0 {
+                return None;
+            }
+            let (entry, _) = DirectoryEntry::parse_with_len_return(bs, offset);
+            entries.push(entry.ok()?);
+        }
+        offset += rec_len;
+    }
+    if entries.is_empty() {
+        return None;
+    }
+    Some(entries)
+}
+
+impl Ext4Module {
+    pub fn journal_timeline<F>(&self, args: HashMap<String, String>, mut f: F) -> Result<(), MRError>
+    where
+        F: FnMut(&TimelineEvent),
+    {
+        let ext4 = &self.ext4;
+        let jbd2 = ext4.get_jbd2()?;
+        let only_inode = match args.get("inode") {
+            Some(s) => Some(
+                s.parse::<u32>()
+                    .map_err(|_| MRError::new("inode=${inode_id}"))?,
+            ),
+            None => None,
+        };
+        let block_size = ext4.get_block_size();
+        let inode_size = ext4.get_s_inode_size() as usize;
+        let per_group = ext4.get_s_inodes_per_group()?;
+        let inodes_per_block = block_size / inode_size;
+        let table_blocks = (per_group as usize / inodes_per_block) as u64;
+        let tables = ext4
+            .get_descs()?
+            .iter()
+            .map(|x| (x.get_group(), x.get_inode_table()))
+            .collect::<Vec<(u32, u64)>>();
+
+        //Last seen version of every inode and directory block
+        let mut last_inodes: HashMap<u32, (u64, NaiveDateTime, u16)> = HashMap::new();
+        let mut last_dirs: HashMap<u64, Vec<DirectoryEntry>> = HashMap::new();
+        //Owners of the blocks of today, only built when a directory block has no "." entry
+        let mut block_map = None;
+
+        for transaction in jbd2.get_transactions()? {
+            let mut emit = |block: u64, inode: u32, change: TimelineChange| {
+                if only_inode.is_some_and(|x| x != inode && change.get_entry_inode() != Some(x)) {
+                    return;
+                }
+                f(&TimelineEvent {
+                    sequence: transaction.get_sequence(),
+                    commit_time: transaction.get_commit_time(),
+                    block,
+                    inode,
+                    change,
+                });
+            };
+
+            for data_block in transaction.get_data_blocks() {
+                //A revoke record of a later transaction cancelled this copy
+                if data_block.is_revoked() {
+                    continue;
+                }
+                let block_id = data_block.get_block_id();
+                let bs = Bytes::from(jbd2.read_data_block(data_block)?);
+                let table = tables
+                    .iter()
+                    .find(|(_, start)| block_id >= *start && block_id < start + table_blocks);
+
+                if let Some((group, start)) = table {
+                    for slot in 0..inodes_per_block {
+                        let id = group * per_group
+                            + ((block_id - start) as usize * inodes_per_block + slot) as u32
+                            + 1;
+                        let base = slot * inode_size;
+                        let inode = match Inode::parse(
+                            &bs.slice(base..base + inode_size),
+                            ext4,
+                            (data_block.get_range().start + base) as u64,
+                        ) {
+                            Ok(o) => o,
+                            Err(_) => continue,
+                        };
+                        let state = (inode.get_size(), inode.get_mtime(), inode.get_links_count());
+                        if let Some((size, mtime, links)) = last_inodes.insert(id, state) {
+                            if size != state.0 {
+                                emit(block_id, id, TimelineChange::Size(size, state.0));
+                            }
+                            if mtime != state.1 {
+                                emit(block_id, id, TimelineChange::Mtime(mtime, state.1));
+                            }
+                            if links != state.2 {
+                                emit(block_id, id, TimelineChange::LinkCount(links, state.2));
+                            }
+                        }
+                    }
+                    continue;
+                }
+
+                let entries = match parse_dir_block(&bs) {
+                    Some(o) => o,
+                    None => continue,
+                };
+                if let Some(old) = last_dirs.insert(block_id, entries.clone()) {
+                    //The first block of a directory starts with ".", the others are looked up
+                    let dir = match entries.iter().find(|x| x.get_name() == ".") {
+                        Some(s) => s.get_id(),
+                        None => block_map
+                            .get_or_insert_with(|| ext4.build_block_map().ok())
+                            .as_ref()
+                            .and_then(|x| x.find_extent(block_id))
+                            .map(|x| x.get_inode())
+                            .unwrap_or(0),
+                    };
+                    let same = |x: &DirectoryEntry, y: &DirectoryEntry| {
+                        x.get_id() == y.get_id() && x.get_name() == y.get_name()
+                    };
+                    for entry in &entries {
+                        if old.iter().any(|x| same(x, entry)) {
+                            continue;
+                        }
+                        //A rename keeps the inode but its old name is gone
+                        let renamed = old.iter().find(|x| {
+                            x.get_id() == entry.get_id() && !entries.iter().any(|y| same(x, y))
+                        });
+                        let change = match renamed {
+                            Some(o) => TimelineChange::EntryRenamed(
+                                o.get_name().clone(),
+                                entry.get_name().clone(),
+                                entry.get_id(),
+                            ),
+                            None => TimelineChange::EntryAdded(entry.get_name().clone(), entry.get_id()),
+                        };
+                        emit(block_id, dir, change);
+                    }
+                    for entry in &old {
+                        if entries.iter().all(|x| x.get_id() != entry.get_id()) {
+                            emit(
+                                block_id,
+                                dir,
+                                TimelineChange::EntryRemoved(entry.get_name().clone(), entry.get_id()),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
-use crate::{file_struct::ext4::DirectoryEntry, utils::MRError};
+use crate::{
+    file_struct::ext4::{DirectoryEntry, FastCommitTag, FileType},
+    utils::MRError,
+};
 
 use super::Ext4Module;
 
@@ -33,8 +36,8 @@ impl Ext4Module {
             vs.push(dir.clone())
         }
 
+        let jbd2 = self.ext4.get_jbd2()?;
         for sub_dir in vs {
-            let jbd2 = self.ext4.get_jbd2().unwrap();
             let inodes = jbd2.find_inodes(sub_dir.get_id()).unwrap();
             for i in inodes {
                 if !i.is_empty()? {
@@ -45,6 +48,27 @@ impl Ext4Module {
                 }
             }
         }
+
+        //Names unlinked by a fast commit are gone from the directory blocks altogether
+        for record in jbd2.get_fast_commits()? {
+            if let FastCommitTag::Unlink { parent, inode, name } = record.get_tag() {
+                if *parent != s.get_id()
+                    || dirs2.iter().any(|x| x.get_name() == name)
+                    || result.iter().any(|x| x.get_name() == name)
+                {
+                    continue;
+                }
+                let inodes = jbd2.find_inodes(*inode)?;
+                if let Some(copy) = inodes.iter().find(|x| !x.is_empty().unwrap_or(true)) {
+                    let file_type = if copy.is_dir() {
+                        FileType::Directory
+                    } else {
+                        FileType::RegularFile
+                    };
+                    result.push(DirectoryEntry::new(*inode, name, file_type));
+                }
+            }
+        }
         Ok(result)
     }
 }
@@ -23,6 +23,7 @@ impl Ext4Module {
         };
         let dirs = s.get_sub_dirs_raw().unwrap();
         let dirs2 = s.get_sub_dirs().unwrap();
+        let jbd2 = self.ext4.get_jbd2()?;
         for i in dirs {
             let inode = match self.ext4.get_inode_by_id(i.get_id()) {
                 Ok(o) => o,
@@ -39,7 +40,6 @@ impl Ext4Module {
 
                 
 
-                let jbd2 = self.ext4.get_jbd2().unwrap();
                 let jbd2_inodes = jbd2.find_inodes(i.get_id()).unwrap();
                 let ext4 = &self.ext4;
                 if !jbd2_inodes.is_empty() {
@@ -12,6 +12,11 @@ pub mod list_recoverable_inodes;
 pub mod search_deleted_files;
 pub mod search_recoverable_files;
 pub mod search_disk;
+pub mod verify;
+pub mod journal_timeline;
+pub mod whose;
+pub mod export_unallocated;
+pub mod carve_files;
 
 type Ext4Func = Box<dyn Fn(HashMap<String,String>)>;
 
@@ -22,13 +27,17 @@ pub struct Ext4Module {
 }
 
 impl Ext4Module {
-    pub fn new(file: &str) -> Result<Ext4Module, MRError> {
-        let ext4 = match Ext4::open(file) {
+    //`journal` is the image of an external journal device, the journal inode is used without it
+    pub fn new(file: &str, journal: Option<&str>) -> Result<Ext4Module, MRError> {
+        let mut ext4 = match Ext4::open(file) {
             Ok(o) => o,
             Err(e) => {
                 return Err(e);
             }
         };
+        if let Some(journal) = journal {
+            ext4.set_journal_device(journal)?;
+        }
 
         Ok(Self {
             ext4,
@@ -36,5 +45,9 @@ impl Ext4Module {
             func: Default::default(),
         })
     }
+
+    pub fn set_strict(&self, strict: bool) {
+        self.ext4.set_strict(strict);
+    }
 }
 
@@ -1,70 +1,135 @@
-use std::{collections::HashMap, fmt::Write, ops::Range};
+use std::{collections::HashMap, fs, ops::Range};
 
-use indicatif::{ProgressState, ProgressStyle};
+use colored::{ColoredString, Colorize};
+use memchr::memmem;
 
 use crate::{
-    file_struct::ext4::{Ext4, Inode},
-    modules::ntfs::MatchType,
-    utils::MRError,
+    file_struct::{
+        ext4::{BlockMap, Ext4},
+        BlockOwner,
+    },
+    utils::{funcs::hex_to_vec_u8, MRError},
 };
 
 use super::Ext4Module;
 
-fn cache_inodes<F>(ext4: &Ext4, mut filter: F) -> Vec<(Range<usize>, u32)>
-where
-    F: FnMut(u64, &Inode) -> bool,
-{
-    let mut result = Vec::new();
-    let mut cache = Vec::new();
-    ext4.iter_inodes(|id, entry, all| {
-        // unimplemented!();
-        // let data_value = entry.get_flat_extents();
-        // if let Ok(extents) = data_value {
-        //     for extent in extents {
-        //         let start = extent.get_start() * ext4.get_block_size();
-        //         let end = extent.get_start() * ext4.get_block_size() + extent.get_len() * ext4.get_block_size();
-        //         let v = (
-        //             Range {
-        //                 start: start,
-        //                 end: end
-        //             },
-        //             id
-        //         );
-        //         cache.push(v);
-        //     }
-        // }
-        if id % 888 == 0 {
-            println!("{}",id);
+//Bytes a regex match may run over the end of a chunk
+const REGEX_REDUNDANCY: usize = 0x1000;
+const CHUNK_BLOCKS: usize = 0x1000;
+
+enum SearchPattern {
+    Bytes(Vec<u8>),
+    Regex(regex::bytes::Regex),
+}
+
+impl SearchPattern {
+    fn from_args(args: &HashMap<String, String>) -> Result<Self, MRError> {
+        let to_search = match args.get("to_search") {
+            Some(s) => s,
+            None => {
+                return Err(MRError::new("search_disk encode=${default:string,hex,base64,file,u16string,regex},to_search=${value}"));
+            }
+        };
+        let encode = args.get("encode").map(|x| x.as_str()).unwrap_or("string");
+        let target = match encode {
+            "string" => to_search.as_bytes().to_vec(),
+            "hex" => hex_to_vec_u8(to_search)?,
+            "base64" => base64::decode(to_search).map_err(|_| MRError::new("Not a valid base64"))?,
+            "file" => fs::read(to_search).map_err(|e| MRError::new(&e.to_string()))?,
+            "u16string" => to_search.encode_utf16().flat_map(|x| x.to_le_bytes()).collect(),
+            "regex" => {
+                let regex = regex::bytes::Regex::new(to_search).map_err(|e| MRError::new(&e.to_string()))?;
+                return Ok(Self::Regex(regex));
+            }
+            _ => {
+                return Err(MRError::new("Not support type: string, hex, base64, file, u16string, regex"));
+            }
+        };
+        if target.is_empty() {
+            return Err(MRError::new("Nothing to search"));
+        }
+        Ok(Self::Bytes(target))
+    }
+
+    fn get_redundancy(&self) -> usize {
+        match self {
+            Self::Bytes(target) => target.len() - 1,
+            Self::Regex(_) => REGEX_REDUNDANCY,
         }
-    });
-    //cache.sort_by(|k, v| k.0.start.cmp(&v.0.start));
-    result.extend(cache);
-    result
+    }
+
+    fn find_iter(&self, bs: &[u8]) -> Vec<Range<usize>> {
+        match self {
+            Self::Bytes(target) => memmem::find_iter(bs, target).map(|x| x..x + target.len()).collect(),
+            Self::Regex(regex) => regex.find_iter(bs).map(|x| x.range()).collect(),
+        }
+    }
 }
 
-pub fn sec_to_s(secs: u64) -> String {
-    if secs >= 60 {
-        format!("{}m{}s", secs / 60, secs % 60)
-    } else {
-        format!("{}s", secs)
+//Owner of the offset, only the block number without the map
+fn ref_file(map: Option<&BlockMap>, ext4: &Ext4, offset: u64, drive: &str) -> ColoredString {
+    let block = offset / ext4.get_block_size() as u64;
+    let owner = match map.map(|x| ext4.whose(x, offset)) {
+        Some(Ok(o)) => o,
+        _ => {
+            return format!("block:{}", block).bright_red();
+        }
+    };
+    match owner {
+        BlockOwner::File { path, slack, .. } => {
+            let mut path = format!("{}:{}", drive, path);
+            if slack {
+                path.push_str(" (slack)");
+            }
+            path.bright_blue()
+        }
+        owner => format!("block:{} {}", block, owner).bright_red(),
     }
 }
 
 impl Ext4Module {
-    pub fn search_disk(&self, args: HashMap<String, String>) -> Result<(), MRError> {
-        let match_type: MatchType;
-        let default_to_file = "true".to_string();
-        let to_file = match args.get("ref_file") {
-            Some(s) => s,
-            None => &default_to_file,
+    //encode=${string|hex|base64|file|u16string|regex},to_search=${value},ref_file=${default:true},unallocated=${default:false} only scans the free blocks
+    pub fn search_disk(&self, args: HashMap<String, String>) -> Result<usize, MRError> {
+        let pattern = SearchPattern::from_args(&args)?;
+        let map = match args.get("ref_file").map(|x| x.as_str()) {
+            Some("false") => None,
+            _ => {
+                println!("Loading inodes....");
+                Some(self.ext4.build_block_map()?)
+            }
+        };
+        let block_size = self.ext4.get_block_size() as u64;
+        let ranges = match args.get("unallocated").map(|x| x.as_str()) {
+            Some("true") => self
+                .ext4
+                .get_unallocated_blocks()?
+                .into_iter()
+                .map(|x| x.start * block_size..x.end * block_size)
+                .collect(),
+            _ => vec![Range {
+                start: 0,
+                end: self.ext4.get_total_size()?,
+            }],
         };
-        let bool_to_file: bool = true;
-        let mut _inodes: Vec<(Range<usize>, u32)> = vec![];
-        println!("Loading inodes....");
-        _inodes = cache_inodes(&self.ext4, |offset, entry| {
-            true
-        });
 
-        Ok(())
+        let size = block_size as usize * CHUNK_BLOCKS;
+        let mut count = 0;
+        let ext4 = &self.ext4;
+        let drive = self.file.as_str();
+        self.ext4.iter_ranges(&ranges, size, pattern.get_redundancy(), |offset, bs| {
+            //Matches starting in the redundancy are found again by the next chunk
+            for range in pattern.find_iter(bs).into_iter().filter(|x| x.start < size) {
+                let offset = offset + range.start as u64;
+                println!(
+                    "{} {:?} -> ref_file: {}",
+                    offset,
+                    String::from_utf8_lossy(&bs[range]),
+                    ref_file(map.as_ref(), ext4, offset, drive)
+                );
+                count += 1;
+            }
+            false
+        })?;
+        Ok(count)
     }
 }
@@ -30,6 +30,7 @@ impl Ext4Module {
         let result = vec![];
         let mut stack = vec![];
         stack.push((path.to_string(),cur_inode));
+        let jbd2 = self.ext4.get_jbd2()?;
 
         while let Some(s) = stack.pop() {
             
@@ -64,7 +65,6 @@ impl Ext4Module {
                         name2.push('/');
                     }
                     name2.push_str(i.get_zero_end_name());
-                    let jbd2 = self.ext4.get_jbd2().unwrap();
                     let jbd2_inodes = jbd2.find_inodes(i.get_id()).unwrap();
                     let ext4 = &mut self.ext4;
                     if !jbd2_inodes.is_empty() {
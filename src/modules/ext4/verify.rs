@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use crate::{
+    file_struct::ext4::{ChecksumMismatch, MetadataStructure},
+    utils::MRError,
+};
+
+use super::Ext4Module;
+
+impl Ext4Module {
+    pub fn verify<F>(&self, args: HashMap<String, String>, mut f: F) -> Result<usize, MRError>
+    where
+        F: FnMut(&ChecksumMismatch),
+    {
+        let ext4 = &self.ext4;
+        let sb = ext4.get_super_block()?;
+        if !sb.has_metadata_csum() && !sb.has_gdt_csum() {
+            return Err(MRError::new("Filesystem has neither metadata_csum nor gdt_csum"));
+        }
+        let mut count = 0;
+        let mut report = |mismatch: &ChecksumMismatch| {
+            count += 1;
+            f(mismatch);
+        };
+
+        if let Some(mismatch) = ext4.verify_super_block()? {
+            report(&mismatch);
+        }
+
+        let block_size = ext4.get_block_size();
+        let inode_size = ext4.get_s_inode_size() as usize;
+        let per_group = ext4.get_s_inodes_per_group()?;
+        let desc_size = ext4.get_desc_size()? as usize;
+        let gdt_offset = (ext4.get_first_data_block()? as usize + 1) * block_size;
+        let reader = ext4.get_reader();
+        let only_inode = match args.get("inode") {
+            Some(s) => Some(s.parse::<u32>().map_err(|_| MRError::new("inode=${inode id}"))?),
+            None => None,
+        };
+
+        for desc in ext4.get_descs()? {
+            let group = desc.get_group();
+            let raw = reader.read_n(gdt_offset + group as usize * desc_size, desc_size)?;
+            if let Some(mismatch) = desc.verify_checksum(&raw)? {
+                report(&mismatch);
+            }
+            if !sb.has_metadata_csum() {
+                continue;
+            }
+
+            let block_bitmap = reader.read_range(desc.get_block_bitmap())?;
+            if let Some(mismatch) = desc.verify_block_bitmap(&block_bitmap)? {
+                report(&mismatch);
+            }
+            let inode_bitmap = reader.read_range(desc.get_inode_bitmap())?;
+            if let Some(mismatch) = desc.verify_inode_bitmap(&inode_bitmap)? {
+                report(&mismatch);
+            }
+            if desc.is_inode_uninit() {
+                continue;
+            }
+
+            let table = reader.read_n(
+                desc.get_inode_table() as usize * block_size,
+                per_group as usize * inode_size,
+            )?;
+            for index in 0..per_group {
+                if (inode_bitmap[(index / 8) as usize] >> (index % 8)) & 1 == 0 {
+                    continue;
+                }
+                let id = group * per_group + index + 1;
+                if let Some(only) = &only_inode {
+                    if *only != id {
+                        continue;
+                    }
+                }
+                let raw = &table[index as usize * inode_size..(index as usize + 1) * inode_size];
+                let (stored, computed) = ext4.calc_inode_checksum(id, raw)?;
+                if stored != computed {
+                    let block = desc.get_inode_table()
+                        + (index as u64 * inode_size as u64) / block_size as u64;
+                    report(
+                        &ChecksumMismatch::new(
+                            MetadataStructure::Inode,
+                            block,
+                            stored,
+                            computed,
+                        )
+                        .with_group(group)
+                        .with_inode(id),
+                    );
+                    //The rest of the inode can not be trusted
+                    continue;
+                }
+
+                let inode = match ext4.get_inode_by_id(id) {
+                    Ok(o) => o,
+                    Err(_) => continue,
+                };
+                if !inode.is_extents() {
+                    continue;
+                }
+                let node_blocks = match inode.get_extent_node_blocks() {
+                    Ok(o) => o,
+                    Err(_) => continue,
+                };
+                for block in node_blocks {
+                    let bs = reader.read_n(block as usize * block_size, block_size)?;
+                    if let Some(mismatch) = ext4.verify_extent_block(&inode, &bs, block)? {
+                        report(&mismatch);
+                    }
+                }
+
+                if !inode.is_dir() {
+                    continue;
+                }
+                let extents = match inode.get_flat_extents() {
+                    Ok(o) => o,
+                    Err(_) => continue,
+                };
+                for extent in extents {
+                    for i in 0..extent.get_len() {
+                        let block = (extent.get_start() + i) as u64;
+                        let bs = reader.read_n(block as usize * block_size, block_size)?;
+                        if let Some(mismatch) = ext4.verify_dir_block(&inode, &bs, block)? {
+                            report(&mismatch);
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Ok(jbd2) = ext4.get_jbd2() {
+            jbd2.verify(&mut report)?;
+        }
+        Ok(count)
+    }
+}
@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+
+use crate::utils::{funcs::parse_number, MRError};
+
+use super::Ext4Module;
+
+impl Ext4Module {
+    //offset=${bytes} or block=${block}, several joined by |, hex with 0x
+    pub fn whose(&self, args: HashMap<String, String>) -> Result<(), MRError> {
+        let block_size = self.ext4.get_block_size() as u64;
+        let offsets = match (args.get("offset"), args.get("block")) {
+            (Some(s), _) => s.split('|').map(parse_number).collect::<Result<Vec<u64>, MRError>>()?,
+            (None, Some(s)) => s
+                .split('|')
+                .map(|x| parse_number(x).map(|x| x * block_size))
+                .collect::<Result<Vec<u64>, MRError>>()?,
+            (None, None) => {
+                return Err(MRError::new("whose offset=${bytes} or block=${block}"));
+            }
+        };
+
+        println!("Loading inodes....");
+        let map = self.ext4.build_block_map()?;
+        for offset in offsets {
+            let owner = self.ext4.whose(&map, offset)?;
+            println!("{} (block {}): {}", offset, offset / block_size, owner);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    file_struct::fat::{DirectoryEntry, RecoveredChain},
+    utils::MRError,
+};
+
+use super::FatModule;
+
+impl FatModule {
+    //Walks the directories under path, deleted ones too while their first cluster is free,
+    //f gets the full path of every deleted entry and the clusters guessed for its data
+    pub fn list_deleted_files<F>(&self, args: HashMap<String, String>, mut f: F) -> Result<usize, MRError>
+    where
+        F: FnMut(&String, &DirectoryEntry, &RecoveredChain),
+    {
+        let base = self.get_entry(&args)?;
+        let path = args.get("path").map(|x| x.trim_end_matches('/')).unwrap_or_default();
+        let mut visited = HashSet::new();
+        let mut stack = vec![(path.to_string(), base, false)];
+        let mut count = 0;
+        while let Some((dir_path, dir, deleted_dir)) = stack.pop() {
+            if !visited.insert((dir.get_first_cluster(), dir.get_offset())) {
+                continue;
+            }
+            //Entries of a deleted directory are all deleted, marked or not, broken directories are left out
+            let (live, deleted) = match deleted_dir {
+                true => {
+                    let chain = self.fat.recover_chain(&dir);
+                    let runs = self.fat.clusters_to_runs(chain.get_clusters(), None);
+                    let marked = self.fat.parse_dir_runs(&runs, true).unwrap_or_default();
+                    (vec![], [marked, self.fat.parse_dir_runs(&runs, false).unwrap_or_default()].concat())
+                }
+                false => (
+                    self.fat.get_dir_entries(&dir).unwrap_or_default(),
+                    self.fat.get_deleted_dir_entries(&dir).unwrap_or_default(),
+                ),
+            };
+            for entry in deleted.iter().filter(|x| !x.is_dot()) {
+                let name = format!("{}/{}", dir_path, entry.get_name());
+                let chain = self.fat.recover_chain(entry);
+                f(&name, entry, &chain);
+                count += 1;
+                let first = entry.get_first_cluster();
+                if entry.is_dir() && self.fat.is_valid_cluster(first) && !self.fat.is_cluster_allocated(first) {
+                    stack.push((name, entry.clone(), true));
+                }
+            }
+            for entry in live.into_iter().filter(|x| x.is_dir()) {
+                stack.push((format!("{}/{}", dir_path, entry.get_name()), entry, false));
+            }
+        }
+        Ok(count)
+    }
+}
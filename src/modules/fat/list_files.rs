@@ -0,0 +1,12 @@
+use std::collections::HashMap;
+
+use crate::{file_struct::fat::DirectoryEntry, utils::MRError};
+
+use super::FatModule;
+
+impl FatModule {
+    pub fn list_files(&self, args: HashMap<String, String>) -> Result<Vec<DirectoryEntry>, MRError> {
+        let dir = self.get_entry(&args)?;
+        self.fat.get_dir_entries(&dir)
+    }
+}
@@ -0,0 +1,31 @@
+use std::collections::HashMap;
+
+use crate::{file_struct::fat::{DirectoryEntry, Fat}, utils::MRError};
+
+pub mod stat;
+pub mod list_files;
+pub mod read_file;
+pub mod list_deleted_files;
+pub mod recover_files;
+
+pub struct FatModule {
+    fat     : Fat,
+    file    : String
+}
+
+impl FatModule {
+    pub fn new(file: &str) -> Result<FatModule, MRError> {
+        Ok(Self {
+            fat: Fat::open(file)?,
+            file: file.to_string(),
+        })
+    }
+
+    //path=${path}
+    fn get_entry(&self, args: &HashMap<String, String>) -> Result<DirectoryEntry, MRError> {
+        match args.get("path") {
+            Some(path) => self.fat.get_entry_by_path(path),
+            None => Err(MRError::new("path=${target_path}")),
+        }
+    }
+}
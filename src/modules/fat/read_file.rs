@@ -0,0 +1,21 @@
+use std::{collections::HashMap, io::Write};
+
+use crate::{file_struct::FileSystem, utils::MRError};
+
+use super::FatModule;
+
+impl FatModule {
+    pub fn read_file(&self, args: HashMap<String, String>) -> Result<(), MRError> {
+        let path = match args.get("path") {
+            Some(s) => s,
+            None => return Err(MRError::new("path=${target_path}")),
+        };
+        let file = self.fat.open_file(path)?;
+        let value = file.read(0, file.get_size()?)?;
+        let mut out = std::io::stdout();
+        if let Err(e) = out.write_all(&value) {
+            return Err(MRError::from(Box::new(e)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use std::{collections::HashMap, fs, io::Write, path::Path};
+
+use crate::{
+    file_struct::{fat::ChainGuess, File},
+    utils::{export::csv_field, MRError},
+};
+
+use super::FatModule;
+
+impl FatModule {
+    //Writes the deleted files under path=${dir} to out=${dir}, listed in recovered_files.csv with
+    //the way their clusters were guessed and how many of them other files use now
+    pub fn recover_files(&self, args: HashMap<String, String>) -> Result<usize, MRError> {
+        let out = Path::new(args.get("out").map(|x| x.as_str()).unwrap_or("./recovered_files"));
+        fs::create_dir_all(out).map_err(|e| MRError::new(&e.to_string()))?;
+        let mut index_file = fs::File::create(out.join("recovered_files.csv"))
+            .map_err(|e| MRError::new(&e.to_string()))?;
+        index_file
+            .write_all(b"path,entry_offset,size,guess,reused_clusters,file\n")
+            .map_err(|e| MRError::new(&e.to_string()))?;
+
+        let mut files = vec![];
+        self.list_deleted_files(args.clone(), |path, entry, chain| {
+            if !entry.is_dir() && !chain.get_clusters().is_empty() {
+                files.push((path.clone(), entry.clone(), chain.clone()));
+            }
+        })?;
+        let mut count = 0;
+        for (path, entry, chain) in files {
+            let name = format!("{:x}_{}", entry.get_offset(), entry.get_name().replace(['/', '\\', ':'], "_"));
+            let file = self.fat.open_chain(&entry, &chain)?;
+            let data = file.read(0, file.get_size()?)?;
+            let guess = match chain.get_guess() {
+                ChainGuess::FatChain => "fat_chain",
+                ChainGuess::Contiguous => "contiguous",
+                ChainGuess::SkipAllocated => "skip_allocated",
+            };
+            let line = format!(
+                "{},{},{},{},{},{}\n",
+                csv_field(&path),
+                entry.get_offset(),
+                entry.get_size(),
+                guess,
+                chain.get_reused(),
+                csv_field(&name)
+            );
+            fs::write(out.join(&name), &data)
+                .and_then(|_| index_file.write_all(line.as_bytes()))
+                .map_err(|e| MRError::new(&e.to_string()))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+use crate::utils::MRError;
+
+use super::FatModule;
+
+impl FatModule {
+    //The volume without path
+    pub fn stat(&self, args: HashMap<String, String>) -> Result<(), MRError> {
+        if !args.contains_key("path") {
+            return self.stat_volume();
+        }
+        let entry = self.get_entry(&args)?;
+        println!("name: {}", entry.get_name());
+        if !entry.get_short_name().is_empty() {
+            println!("\tshort name: {}", entry.get_short_name());
+        }
+        println!("\tattributes: {:#x}", entry.get_attributes());
+        println!("\tsize: {}", entry.get_size());
+        println!("\tfirst cluster: {}", entry.get_first_cluster());
+        println!("\tcontiguous: {}", entry.is_contiguous());
+        println!("\tentry offset: {}", entry.get_offset());
+        println!("\tctime: {}", entry.get_ctime());
+        println!("\tmtime: {}", entry.get_mtime());
+        println!("\tatime: {}", entry.get_atime());
+        for run in self.fat.get_runs(&entry)? {
+            println!("\trun: offset {}, {} bytes", run.start, run.end - run.start);
+        }
+        Ok(())
+    }
+
+    fn stat_volume(&self) -> Result<(), MRError> {
+        let bs = self.fat.get_boot_sector();
+        println!("volume: {}", self.file);
+        println!("\ttype: {:?}", self.fat.get_fat_type());
+        println!("\toem name: {}", bs.get_oem_name());
+        println!("\tlabel: {}", self.fat.get_volume_label());
+        println!("\tserial: {:04X}-{:04X}", bs.get_volume_serial() >> 16, bs.get_volume_serial() & 0xffff);
+        println!("\tsector size: {}", bs.get_bytes_per_sector());
+        println!("\tcluster size: {}", self.fat.get_cluster_size());
+        println!("\tsectors: {}", bs.get_total_sectors());
+        println!("\tfats: {}, {} sectors each from sector {}", bs.get_fat_count(), bs.get_sectors_per_fat(), bs.get_fat_offset());
+        println!("\tclusters: {} from sector {}", bs.get_cluster_count(), bs.get_cluster_heap_offset());
+        println!("\tfree clusters: {}", self.fat.get_free_clusters());
+        match bs.get_root_cluster() {
+            0 => println!("\troot: {} entries", bs.get_root_entries()),
+            n => println!("\troot cluster: {}", n),
+        }
+        Ok(())
+    }
+}
@@ -4,6 +4,9 @@ use crate::utils::MRError;
 
 pub mod ext4;
 pub mod ntfs;
+pub mod raw;
+pub mod xfs;
+pub mod fat;
 pub trait Hanlder {
     fn run(&self, args: HashMap<String, String>) -> Result<(), MRError>;
 
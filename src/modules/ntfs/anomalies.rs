@@ -0,0 +1,244 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    file_struct::ntfs::{
+        journal_impl::{
+            USN_REASON_DATA_EXTEND, USN_REASON_DATA_OVERWRITE, USN_REASON_DATA_TRUNCATION,
+            USN_REASON_FILE_CREATE,
+        },
+        FileTime, MFTEntry,
+    },
+    utils::MRError,
+};
+
+use super::{parse_time, NtfsModule};
+
+const TICKS_PER_SECOND: u64 = 10_000_000;
+const UNIX_EPOCH_TICKS: u64 = 116_444_736_000_000_000;
+//USN records are written a moment after the change they log
+const USN_TOLERANCE: u64 = 2 * TICKS_PER_SECOND;
+const USN_DATA_CHANGE: u32 = USN_REASON_DATA_OVERWRITE | USN_REASON_DATA_EXTEND | USN_REASON_DATA_TRUNCATION;
+
+pub const SI_BEFORE_FN: &str = "SI_BEFORE_FN";
+pub const SI_ZERO_FRACTION: &str = "SI_ZERO_FRACTION";
+pub const CREATED_AFTER_MODIFIED: &str = "CREATED_AFTER_MODIFIED";
+pub const FUTURE_TIME: &str = "FUTURE_TIME";
+pub const BEFORE_VOLUME: &str = "BEFORE_VOLUME";
+pub const USN_CREATE_MISMATCH: &str = "USN_CREATE_MISMATCH";
+pub const USN_WRITE_AFTER_MODIFIED: &str = "USN_WRITE_AFTER_MODIFIED";
+
+//Times the journal saw for one (index, sequence)
+#[derive(Default)]
+struct UsnTimes {
+    created: Option<u64>,
+    last_write: Option<u64>,
+}
+
+struct Bounds {
+    volume_created: u64,
+    now: u64,
+}
+
+fn time_string(ticks: u64) -> String {
+    FileTime::from_ticks(ticks)
+        .to_native_date()
+        .map(|s| s.to_string())
+        .unwrap_or(format!("{:#x}", ticks))
+}
+
+fn unix_to_ticks(s: u64) -> u64 {
+    s * TICKS_PER_SECOND + UNIX_EPOCH_TICKS
+}
+
+fn check_entry(
+    entry: &MFTEntry,
+    bounds: &Bounds,
+    usn: Option<&UsnTimes>,
+) -> Vec<(&'static str, String)> {
+    let mut result = vec![];
+    let si = match entry.get_standard_info() {
+        Some(s) => s,
+        None => return result,
+    };
+    let si_times = [
+        ("creation", si.get_create_time().get_ticks()),
+        ("modification", si.get_change_time().get_ticks()),
+        ("mft change", si.get_mft_change_time().get_ticks()),
+        ("access", si.get_access_time().get_ticks()),
+    ];
+    let si_created = si_times[0].1;
+    let si_modified = si_times[1].1;
+    let file_names = entry.get_file_names();
+
+    //$FN times are only set by the kernel, tools like SetFileTime rewrite $SI
+    if let Some(fn_created) = file_names.iter().map(|x| x.get_create_time().get_ticks()).min() {
+        if si_created != 0 && si_created < fn_created {
+            result.push((
+                SI_BEFORE_FN,
+                format!("$SI creation {} < $FN creation {}", time_string(si_created), time_string(fn_created)),
+            ));
+        }
+    }
+
+    //Extracted and installed files keep the whole or 2 second modification times of their archive
+    let zeroed = [si_times[0], si_times[2]]
+        .iter()
+        .filter(|(_, t)| *t != 0 && t.is_multiple_of(TICKS_PER_SECOND))
+        .map(|(name, _)| *name)
+        .collect::<Vec<&str>>();
+    if !zeroed.is_empty() {
+        result.push((SI_ZERO_FRACTION, format!("$SI {} without sub-second part", zeroed.join(","))));
+    }
+
+    if si_modified != 0 && si_created > si_modified {
+        result.push((
+            CREATED_AFTER_MODIFIED,
+            format!("$SI creation {} > modification {}", time_string(si_created), time_string(si_modified)),
+        ));
+    }
+
+    let mut all_times = si_times
+        .iter()
+        .map(|(name, t)| (format!("$SI {}", name), *t))
+        .collect::<Vec<(String, u64)>>();
+    for name in &file_names {
+        all_times.push(("$FN creation".to_string(), name.get_create_time().get_ticks()));
+        all_times.push(("$FN modification".to_string(), name.get_change_time().get_ticks()));
+        all_times.push(("$FN mft change".to_string(), name.get_mft_change_time().get_ticks()));
+        all_times.push(("$FN access".to_string(), name.get_access_time().get_ticks()));
+    }
+    //Copies keep the $SI modification and access times of their source, only the times set when
+    //the record was made on this volume can not be older than it
+    let volume_times = all_times
+        .iter()
+        .filter(|(name, _)| name.starts_with("$FN") || name == "$SI creation")
+        .collect::<Vec<&(String, u64)>>();
+    let future = all_times
+        .iter()
+        .filter(|(_, t)| *t > bounds.now)
+        .map(|(name, t)| format!("{} {}", name, time_string(*t)))
+        .collect::<Vec<String>>();
+    if !future.is_empty() {
+        result.push((FUTURE_TIME, future.join(", ")));
+    }
+    let before = volume_times
+        .iter()
+        .filter(|(_, t)| *t != 0 && *t < bounds.volume_created)
+        .map(|(name, t)| format!("{} {}", name, time_string(*t)))
+        .collect::<Vec<String>>();
+    if !before.is_empty() {
+        result.push((
+            BEFORE_VOLUME,
+            format!("{} before volume creation {}", before.join(", "), time_string(bounds.volume_created)),
+        ));
+    }
+
+    if let Some(usn) = usn {
+        if let Some(created) = usn.created {
+            if si_created.abs_diff(created) > USN_TOLERANCE {
+                result.push((
+                    USN_CREATE_MISMATCH,
+                    format!("$SI creation {} but FILE_CREATE logged at {}", time_string(si_created), time_string(created)),
+                ));
+            }
+        }
+        //A copy keeps the modification time of its source, it is created after it
+        if let Some(last_write) = usn.last_write {
+            if si_created <= si_modified && last_write > si_modified + USN_TOLERANCE {
+                result.push((
+                    USN_WRITE_AFTER_MODIFIED,
+                    format!("$SI modification {} but data changed at {}", time_string(si_modified), time_string(last_write)),
+                ));
+            }
+        }
+    }
+    result
+}
+
+impl NtfsModule {
+    //Creation and last data change of every file the journal knows about
+    fn usn_times(&mut self) -> Result<HashMap<(u64, u16), UsnTimes>, MRError> {
+        let mut result: HashMap<(u64, u16), UsnTimes> = HashMap::new();
+        let journal = self.ntfs.get_usn_journal()?;
+        journal.process_entry(|entry| -> bool {
+            let t = match entry.filetime() {
+                Some(s) => s.get_ticks(),
+                None => return true,
+            };
+            let key = (entry.get_index(), entry.get_reference().get_seq_number() as u16);
+            let reason = entry.get_reason_flags();
+            if reason & USN_REASON_FILE_CREATE != 0 {
+                let times = result.entry(key).or_default();
+                times.created = Some(times.created.map_or(t, |x| x.min(t)));
+            }
+            if reason & USN_DATA_CHANGE != 0 {
+                let times = result.entry(key).or_default();
+                times.last_write = Some(times.last_write.map_or(t, |x| x.max(t)));
+            }
+            true
+        })?;
+        Ok(result)
+    }
+
+    //now=${time} replaces the current time for old images, usn=false skips the journal checks
+    pub fn anomalies(&mut self, args: HashMap<String, String>) -> Result<usize, MRError> {
+        let now = match args.get("now") {
+            Some(s) => unix_to_ticks(parse_time(s)?),
+            None => unix_to_ticks(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|e| MRError::new(&e.to_string()))?
+                    .as_secs(),
+            ),
+        };
+        //$MFT is the first file written by format
+        let mft = self
+            .ntfs
+            .get_mft_entry_by_index(0)
+            .ok_or(MRError::new("Can not read $MFT entry"))?;
+        let volume_created = mft
+            .get_file_names()
+            .iter()
+            .map(|x| x.get_create_time().get_ticks())
+            .min()
+            .or(mft.get_standard_info().map(|x| x.get_create_time().get_ticks()))
+            .ok_or(MRError::new("$MFT has no timestamp"))?;
+        let bounds = Bounds { volume_created, now };
+
+        let usn = if args.get("usn").is_some_and(|x| x.eq("false")) {
+            HashMap::new()
+        } else {
+            match self.usn_times() {
+                Ok(o) => o,
+                Err(e) => {
+                    println!("[Warning]: USN checks skipped, {}", e);
+                    HashMap::new()
+                }
+            }
+        };
+
+        let mut count = 0;
+        self.ntfs.iter_mft(|index, entry, is_deleted, _| {
+            let entry = match entry {
+                Ok(o) => o,
+                Err(_) => return,
+            };
+            if is_deleted {
+                return;
+            }
+            let hits = check_entry(&entry, &bounds, usn.get(&(index, entry.get_sequence())));
+            if hits.is_empty() {
+                return;
+            }
+            let path = entry.fullpath().unwrap_or_else(|| format!("<{}>", index));
+            for (code, detail) in hits {
+                println!("{}\t{}\t{}\t{}", code, index, path, detail);
+                count += 1;
+            }
+        });
+        Ok(count)
+    }
+}
@@ -0,0 +1,198 @@
+use std::{collections::HashMap, fs, io::Write, ops::Range, path::Path};
+
+use crate::{
+    file_struct::ntfs::{CarvedKind, CarvedRecord, Ntfs},
+    utils::{export::csv_field, funcs::bytes_progress_bar, MRError},
+};
+
+use super::NtfsModule;
+
+//Space between the end of every non resident file and the end of its clusters
+fn slack_ranges(ntfs: &Ntfs) -> Vec<Range<u64>> {
+    let mut result = vec![];
+    ntfs.iter_mft(|_, entry, _, _| {
+        let entry = match entry {
+            Ok(o) => o,
+            Err(_) => return,
+        };
+        if entry.get_resident_data_range().is_some() {
+            return;
+        }
+        let datas = match entry.get_data_value() {
+            Some(s) => s.get_datas(),
+            None => return,
+        };
+        let size = match entry.get_filesize() {
+            Some(s) => s as u64,
+            None => return,
+        };
+        for data in datas {
+            let end = data.get_start_addr() + data.get_datasize();
+            let start = if data.get_vcn() >= size {
+                data.get_start_addr()
+            } else if data.get_vcn() + data.get_datasize() > size {
+                //USN records are 8 bytes aligned
+                (data.get_start_addr() + size - data.get_vcn()).next_multiple_of(8)
+            } else {
+                continue;
+            };
+            if start < end {
+                result.push(start..end);
+            }
+        }
+    });
+    result
+}
+
+impl NtfsModule {
+    fn carve_ranges(&mut self, args: &HashMap<String, String>) -> Result<Vec<Range<u64>>, MRError> {
+        let source = args.get("source").map(|x| x.as_str()).unwrap_or("unalloc");
+        match source {
+            "unalloc" => {
+                let cluster_size = self.ntfs.get_cluster_size();
+                let bitmap = self.ntfs.get_bitmap()?;
+                let (ranges, _) = bitmap.generate_unalloc()?;
+                Ok(ranges
+                    .iter()
+                    .map(|x| x.start as u64 * cluster_size..x.end as u64 * cluster_size)
+                    .collect())
+            }
+            "file" => {
+                let path = args
+                    .get("path")
+                    .ok_or(MRError::new("source=file needs path=${file_path}, e.g. \\pagefile.sys"))?;
+                let mft = self.ntfs.get_mft_by_path(path)?;
+                let datas = mft
+                    .get_data_value()
+                    .ok_or(MRError::new("File has no data"))?;
+                if mft.get_resident_data_range().is_some() {
+                    return Err(MRError::new("File data is resident, nothing to carve"));
+                }
+                Ok(datas
+                    .get_datas()
+                    .iter()
+                    .map(|x| x.get_start_addr()..x.get_start_addr() + x.get_datasize())
+                    .collect())
+            }
+            "slack" => Ok(slack_ranges(&self.ntfs)),
+            _ => Err(MRError::new("source=${unalloc|file|slack}")),
+        }
+    }
+
+    //Writes carved FILE, INDX and USN records under out=${dir}, listed in carved.csv with their offsets
+    pub fn carve(&mut self, args: HashMap<String, String>) -> Result<usize, MRError> {
+        let out = args.get("out").map(|x| x.as_str()).unwrap_or("./carved");
+        let out = Path::new(out);
+        fs::create_dir_all(out).map_err(|e| MRError::new(&e.to_string()))?;
+        let mut index_file = fs::File::create(out.join("carved.csv"))
+            .map_err(|e| MRError::new(&e.to_string()))?;
+        index_file
+            .write_all(b"kind,offset,size,index,sequence,in_use,name,detail\n")
+            .map_err(|e| MRError::new(&e.to_string()))?;
+
+        let ranges = self.carve_ranges(&args)?;
+        let total = ranges.iter().map(|x| x.end - x.start).sum();
+        let pb = bytes_progress_bar(total);
+
+        let ntfs = &self.ntfs;
+        let mut count = 0;
+        let mut error = None;
+        ntfs.carve(&ranges, |record: &CarvedRecord, scanned| {
+            pb.set_position(scanned);
+            let line = match record_line(ntfs, record, out) {
+                Ok(o) => o,
+                Err(e) => {
+                    error = Some(e);
+                    return false;
+                }
+            };
+            if let Some(line) = line {
+                if let Err(e) = index_file.write_all(line.as_bytes()) {
+                    error = Some(MRError::new(&e.to_string()));
+                    return false;
+                }
+                count += 1;
+            }
+            true
+        })?;
+        pb.finish();
+        match error {
+            Some(e) => Err(e),
+            None => Ok(count),
+        }
+    }
+}
+
+//A csv line for the record, raw FILE and INDX records and resident data are written next to it
+fn record_line(ntfs: &Ntfs, record: &CarvedRecord, out: &Path) -> Result<Option<String>, MRError> {
+    let offset = record.get_offset();
+    let size = record.get_data().len();
+    let write = |name: String, bs: &[u8]| -> Result<(), MRError> {
+        fs::write(out.join(name), bs).map_err(|e| MRError::new(&e.to_string()))
+    };
+    let line = match record.get_kind() {
+        CarvedKind::FileRecord => {
+            let entry = match record.parse_mft(ntfs) {
+                Ok(o) => o,
+                Err(_) => return Ok(None),
+            };
+            write(format!("file_{:x}.bin", offset), record.get_data())?;
+            let name = entry.filename().unwrap_or_default();
+            let mut detail = String::new();
+            //Small files live inside the entry and can be recovered as a whole
+            if let Some(range) = entry.get_resident_data_range() {
+                let start = (range.start - offset) as usize;
+                let end = (range.end - offset) as usize;
+                if let Some(bs) = record.get_data().get(start..end) {
+                    let data_name = format!("data_{:x}", offset);
+                    write(data_name.clone(), bs)?;
+                    detail = format!("resident data {} bytes in {}", bs.len(), data_name);
+                }
+            }
+            format!(
+                "FILE,{},{},{},{},{},{},{}\n",
+                offset,
+                size,
+                entry.get_index(),
+                entry.get_sequence(),
+                entry.is_in_use(),
+                csv_field(&name),
+                csv_field(&detail)
+            )
+        }
+        CarvedKind::IndexRecord => {
+            let values = record.parse_index_values().unwrap_or_default();
+            write(format!("indx_{:x}.bin", offset), record.get_data())?;
+            let names = values
+                .iter()
+                .filter_map(|(_, x)| x.get_name().cloned())
+                .collect::<Vec<String>>();
+            format!(
+                "INDX,{},{},,,,,{}\n",
+                offset,
+                size,
+                csv_field(&names.join("|"))
+            )
+        }
+        CarvedKind::UsnRecord => {
+            let entry = match record.parse_usn() {
+                Ok(o) => o,
+                Err(_) => return Ok(None),
+            };
+            format!(
+                "USN,{},{},{},{},,{},{}\n",
+                offset,
+                size,
+                entry.get_index(),
+                entry.get_reference().get_seq_number(),
+                csv_field(entry.filename()),
+                csv_field(&format!(
+                    "{} {}",
+                    entry.get_time_string().unwrap_or_default(),
+                    entry.get_update_reason()
+                ))
+            )
+        }
+    };
+    Ok(Some(line))
+}
@@ -0,0 +1,57 @@
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    file_struct::carver::{Carver, CARVE_CHUNK_SIZE, MAGIC_OVERLAP},
+    modules::raw::carve_files::CarveJob,
+    utils::MRError,
+};
+
+use super::NtfsModule;
+
+impl NtfsModule {
+    //source=${default:unalloc|all}, other options as for raw carve_files, the blocks come from
+    //iter_sp_block over the free clusters or from iter_diy_block over the whole volume
+    pub fn carve_files(&mut self, args: HashMap<String, String>) -> Result<usize, MRError> {
+        let cluster_size = self.ntfs.get_cluster_size();
+        let source = args.get("source").map(|x| x.as_str()).unwrap_or("unalloc");
+        let clusters = match source {
+            "unalloc" => self.ntfs.get_unallocated_clusters()?,
+            "all" => vec![Range {
+                start: 0,
+                end: self.ntfs.get_total_clusters(),
+            }],
+            _ => {
+                return Err(MRError::new("source=${unalloc|all}"));
+            }
+        };
+        let ranges: Vec<Range<u64>> = clusters.iter().map(|x| x.start * cluster_size..x.end * cluster_size).collect();
+        let mut job = CarveJob::new(&args, ranges.iter().map(|x| x.end - x.start).sum())?;
+        let signatures = job.get_signatures().to_vec();
+        let mut carver = Carver::new(self.ntfs.get_reader(), &ranges, &signatures, job.get_align());
+        //The iterators stop when true is returned
+        let mut carve_block = |base: u64, bs: &[u8], len: usize, scanned: u64| {
+            match carver.carve_block(base, bs, len, scanned, &mut |file, data, scanned| job.write(file, data, scanned)) {
+                Ok(go_on) => !go_on,
+                Err(e) => {
+                    job.fail(e);
+                    true
+                }
+            }
+        };
+        match source {
+            "unalloc" => {
+                let clusters = clusters.iter().map(|x| x.start as usize..x.end as usize).collect();
+                //Blocks follow each other without overlap
+                self.ntfs.iter_sp_block(&clusters, CARVE_CHUNK_SIZE, MAGIC_OVERLAP, 1, |_, offset, bs, read_size| {
+                    carve_block(offset, &bs, bs.len(), read_size - bs.len() as u64)
+                });
+            }
+            _ => {
+                self.ntfs.iter_diy_block(CARVE_CHUNK_SIZE, MAGIC_OVERLAP, 1, |_, offset, bs| {
+                    carve_block(offset, &bs, CARVE_CHUNK_SIZE, offset)
+                });
+            }
+        }
+        job.finish()
+    }
+}
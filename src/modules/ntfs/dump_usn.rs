@@ -1,8 +1,8 @@
 use std::{collections::HashMap, fs, io::Write};
 
-use crate::utils::MRError;
+use crate::{file_struct::ntfs::{USNChangeJournalEntry, USNPathResolver}, utils::MRError};
 
-use super::NtfsModule;
+use super::{usn_filter_from_args, NtfsModule};
 
 impl NtfsModule {
     pub fn dump_usn(&mut self, args: HashMap<String,String>) -> Result<(),MRError> {
@@ -14,13 +14,66 @@ impl NtfsModule {
                 &_s
             }
         };
-        let mut journal = self.ntfs.get_usn_journal().unwrap();
-        let mut out_file = fs::File::create(out).unwrap();
+        let filter = usn_filter_from_args(&args)?;
+        //last=true dumps the matching records newest first, last=${n} only the n newest
+        let last = match args.get("last").map(|x| x.as_str()) {
+            None | Some("false") => None,
+            Some("true") => Some(usize::MAX),
+            Some(s) => Some(s.parse::<usize>().map_err(|_| MRError::new("last=true or last=${count}"))?),
+        };
+        let journal = self.ntfs.get_usn_journal()?;
+        let mut out_file = fs::File::create(out).map_err(|e| MRError::new(&e.to_string()))?;
+        //A new journal id means the journal was deleted and recreated
+        if let Ok(meta) = journal.get_metadata() {
+            let line = format!(
+                "journal id: {:#x} created: {:?} maximum size: {} allocation delta: {} lowest valid usn: {:?}\n",
+                meta.get_journal_id(),
+                meta.get_journal_create_time(),
+                meta.get_maximum_size(),
+                meta.get_allocation_delta(),
+                meta.get_lowest_valid_usn()
+            );
+            out_file.write_all(line.as_bytes()).map_err(|e| MRError::new(&e.to_string()))?;
+        }
+        if last == Some(0) {
+            return Ok(());
+        }
+        //First pass collects the directory names seen in the journal, so renamed and deleted parents resolve
+        let mut resolver = USNPathResolver::new(&self.ntfs);
         journal.process_entry(|entry| -> bool {
-            let line = format!("{}:{} {:?} {}\n", entry.get_index(), entry.filename(), entry.get_time_string(), entry.get_update_reason());
-            out_file.write_all(line.as_bytes());
+            resolver.add_entry(entry);
             true
-        });
-        Ok(())
+        })?;
+        let mut count = 0;
+        let mut error = None;
+        let handle = |entry: &USNChangeJournalEntry| -> bool {
+            let (path, resolved) = resolver.resolve(entry);
+            let path = if resolved { path } else { format!("{} [unresolved]", path) };
+            let line = if entry.get_version().0 == 4 {
+                let extents = entry
+                    .get_extents()
+                    .iter()
+                    .map(|x| format!("{}+{}", x.get_offset(), x.get_length()))
+                    .collect::<Vec<String>>();
+                format!("{} usn:{} extents:[{}] {}\n", entry.get_index(), entry.get_usn(), extents.join(","), entry.get_update_reason())
+            } else {
+                format!("{}:{} {:?} {}\n", entry.get_index(), path, entry.get_time_string(), entry.get_update_reason())
+            };
+            if let Err(e) = out_file.write_all(line.as_bytes()) {
+                error = Some(MRError::new(&e.to_string()));
+                return false;
+            }
+            count += 1;
+            last.is_none_or(|x| count < x)
+        };
+        if last.is_some() {
+            journal.query_last(&filter, handle)?;
+        } else {
+            journal.query(&filter, handle)?;
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
     }
-}
\ No newline at end of file
+}
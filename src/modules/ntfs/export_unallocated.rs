@@ -0,0 +1,39 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::utils::{
+    export::{export_ranges, ExportRange},
+    MRError,
+};
+
+use super::NtfsModule;
+
+impl NtfsModule {
+    //mode=${unallocated|slack},out=${default:./${mode}.bin},sparse=${true|false} keeps every byte at its volume offset
+    pub fn export_unallocated(&mut self, args: HashMap<String, String>) -> Result<u64, MRError> {
+        let mode = args.get("mode").map(|x| x.as_str()).unwrap_or("unallocated");
+        let cluster_size = self.ntfs.get_cluster_size();
+        let ranges: Vec<ExportRange> = match mode {
+            "unallocated" => self
+                .ntfs
+                .get_unallocated_clusters()?
+                .into_iter()
+                .map(|x| ExportRange {
+                    offset: x.start * cluster_size,
+                    length: (x.end - x.start) * cluster_size,
+                    owner: None,
+                })
+                .collect(),
+            "slack" => self.get_index().get_slacks().into_iter().map(ExportRange::from).collect(),
+            _ => {
+                return Err(MRError::new("mode=${unallocated|slack}"));
+            }
+        };
+        let out = match args.get("out") {
+            Some(s) => s.to_string(),
+            None => format!("./{}.bin", mode),
+        };
+        let sparse = args.get("sparse").is_some_and(|x| x.eq("true"));
+        let volume_size = self.ntfs.get_total_clusters() * cluster_size;
+        export_ranges(self.ntfs.get_reader(), &ranges, Path::new(&out), sparse, volume_size)
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use crate::{
+    file_struct::ntfs::{FileTime, IndexSlackEntry, MFTEntry},
+    utils::MRError,
+};
+
+use super::NtfsModule;
+
+fn time_string(t: &FileTime) -> String {
+    t.to_native_date().map(|s| s.to_string()).unwrap_or("-".to_string())
+}
+
+fn print_entry(dir: &str, entry: &IndexSlackEntry) {
+    let name = entry.get_file_name();
+    let reference = match entry.get_file_reference() {
+        Some(s) => format!("{}-{}", s.get_mft_index(), s.get_sequence()),
+        None => "?".to_string(),
+    };
+    println!("{}\\{}", dir, name.get_name());
+    println!("\tfile reference: {}", reference);
+    println!("\tparent reference: {}-{}", name.get_parent_index(), name.get_parent_sequence());
+    println!("\tcreation: {}", time_string(name.get_create_time()));
+    println!("\tmodify: {}", time_string(name.get_change_time()));
+    println!("\tmft change: {}", time_string(name.get_mft_change_time()));
+    println!("\taccess: {}", time_string(name.get_access_time()));
+    println!("\tsize: {}", name.get_real_size());
+    println!("\toffset: {:#x}", entry.get_offset());
+}
+
+fn print_dir(entry: &MFTEntry) -> Result<usize, MRError> {
+    let entries = entry.get_index_slack()?;
+    if entries.is_empty() {
+        return Ok(0);
+    }
+    let dir = entry
+        .fullpath()
+        .unwrap_or_else(|| format!("<{}>", entry.get_index()));
+    for e in &entries {
+        print_entry(&dir, e);
+    }
+    Ok(entries.len())
+}
+
+impl NtfsModule {
+    //path=${dir} scans the $I30 slack of one directory, every directory in use otherwise
+    pub fn index_slack(&mut self, args: HashMap<String, String>) -> Result<usize, MRError> {
+        if let Some(path) = args.get("path") {
+            let mft = self.ntfs.get_mft_by_path(path)?;
+            if !mft.is_dir() {
+                return Err(MRError::new("path=${dir} is not a directory"));
+            }
+            return print_dir(&mft);
+        }
+        let mut count = 0;
+        self.ntfs.iter_mft(|_, entry, is_deleted, _| {
+            let entry = match entry {
+                Ok(o) => o,
+                Err(_) => return,
+            };
+            //Clusters of a deleted directory may belong to another file by now
+            if is_deleted || !entry.is_dir() {
+                return;
+            }
+            if let Ok(o) = print_dir(&entry) {
+                count += o;
+            }
+        });
+        Ok(count)
+    }
+}
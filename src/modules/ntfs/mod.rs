@@ -1,20 +1,33 @@
 #![allow(unused)]
-use std::{collections::HashMap, path::{Path, self}};
+use std::{collections::HashMap, fs, path::{Path, PathBuf, self}, rc::Rc};
 
-use crate::{file_struct::ntfs::Ntfs, utils::MRError};
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::{file_struct::{bitlocker::BDEUnlockKey, ntfs::{MFTIndex, Ntfs, USNFilter}}, utils::MRError};
 pub mod stat;
 pub mod deleted_files;
 pub mod search_disk;
 pub mod search_files_content;
 pub mod dump_usn;
 pub mod search_usn;
+pub mod carve;
+pub mod index_slack;
+pub mod anomalies;
+pub mod vss_list;
+pub mod recover;
+pub mod search_deleted_files;
+pub mod whose;
+pub mod export_unallocated;
+pub mod carve_files;
 
 type NtfsFunc = Box<dyn Fn(HashMap<String,String>)>;
 
 pub struct NtfsModule {
     ntfs    : Ntfs,
     file    : String,
-    func    : HashMap<String,NtfsFunc>
+    func    : HashMap<String,NtfsFunc>,
+    //Where the MFT index is saved, only with cache=${file}
+    cache   : Option<PathBuf>
 }
 
 impl NtfsModule {
@@ -27,12 +40,48 @@ impl NtfsModule {
                 return Err(e);
             }
         };
+        let cache = None;
+        Ok(Self {
+            ntfs,
+            file: s,
+            func: Default::default(),
+            cache,
+        })
+    }
+
+    //recovery=${password} or bek=${file} unlock a BitLocker volume, vss=${n} opens the n-th shadow copy, every function then works on it
+    pub fn with_options<P>(file: P, args: &HashMap<String,String>) -> Result<NtfsModule, MRError>
+    where P: AsRef<path::Path> + ToString {
+        let s = file.to_string();
+        let mut ntfs = if let Some(password) = args.get("recovery") {
+            Ntfs::open_bitlocker(&s, &BDEUnlockKey::RecoveryPassword(password.to_string()))?
+        } else if let Some(bek) = args.get("bek") {
+            let bek = fs::read(bek).map_err(|e| MRError::new(&e.to_string()))?;
+            Ntfs::open_bitlocker(&s, &BDEUnlockKey::StartupKey(bek))?
+        } else {
+            Ntfs::open(&s)?
+        };
+        if let Some(n) = args.get("vss") {
+            let n = n.parse::<usize>().map_err(|_| MRError::new("vss=${n}, see vss_list"))?;
+            ntfs = ntfs.into_vss(n)?;
+        }
+        //cache=${file} keeps the MFT index in a file, a saved index still current speeds up path lookups
+        let cache = args.get("cache").filter(|x| *x != "false").map(PathBuf::from);
+        if let Some(path) = &cache {
+            ntfs.load_index(path);
+        }
         Ok(Self {
             ntfs,
             file: s,
             func: Default::default(),
+            cache,
         })
     }
+
+    //Tree and cluster map of the MFT, built on the first call and saved for the next runs with cache=${file}
+    pub fn get_index(&mut self) -> Rc<MFTIndex> {
+        self.ntfs.get_index(self.cache.as_deref())
+    }
 }
 
 #[derive(PartialEq)]
@@ -40,4 +89,51 @@ pub enum MatchType {
     Equal,
     Regex,
     RegexUtf16,
-}
\ No newline at end of file
+}
+
+//Unix timestamp, or a UTC date as "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S" or "%Y-%m-%d"
+fn parse_time(s: &str) -> Result<u64, MRError> {
+    if let Ok(o) = s.parse::<u64>() {
+        return Ok(o);
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"] {
+        if let Ok(o) = NaiveDateTime::parse_from_str(s, format) {
+            return Ok(o.and_utc().timestamp() as u64);
+        }
+    }
+    match NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        Ok(o) => Ok(o.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp() as u64),
+        Err(_) => Err(MRError::new(&format!("Can not parse time {}", s))),
+    }
+}
+
+//after=${time},before=${time},reason=${FILE_DELETE|RENAME_NEW_NAME},name=${regex},index=${mft_index},parent=${mft_index},ext=${exe|dll}
+pub fn usn_filter_from_args(args: &HashMap<String, String>) -> Result<USNFilter, MRError> {
+    let mut filter = USNFilter::default();
+    if let Some(s) = args.get("after") {
+        filter.after = Some(parse_time(s)?);
+    }
+    if let Some(s) = args.get("before") {
+        filter.before = Some(parse_time(s)?);
+    }
+    if let Some(s) = args.get("reason") {
+        filter.reason_mask = Some(USNFilter::parse_reason_mask(s)?);
+    }
+    if let Some(s) = args.get("name") {
+        filter.name = Some(regex::Regex::new(s).map_err(|e| MRError::new(&e.to_string()))?);
+    }
+    if let Some(s) = args.get("index") {
+        filter.index = Some(s.parse::<u64>().map_err(|_| MRError::new("index=${mft_index}"))?);
+    }
+    if let Some(s) = args.get("parent") {
+        filter.parent_index = Some(s.parse::<u64>().map_err(|_| MRError::new("parent=${mft_index}"))?);
+    }
+    if let Some(s) = args.get("ext") {
+        filter.extensions = s
+            .split('|')
+            .map(|x| x.trim().trim_start_matches('.').to_lowercase())
+            .filter(|x| !x.is_empty())
+            .collect();
+    }
+    Ok(filter)
+}
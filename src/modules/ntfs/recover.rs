@@ -0,0 +1,192 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    file_struct::ntfs::{MFTEntry, Ntfs, Recoverability},
+    utils::{export::csv_field, MRError},
+};
+
+use super::NtfsModule;
+
+//Windows names may hold characters the output file system refuses
+fn clean_component(s: &str) -> String {
+    let s = s
+        .chars()
+        .map(|x| match x {
+            '<' | '>' | ':' | '"' | '/' | '|' | '?' | '*' => '_',
+            x if x.is_control() => '_',
+            x => x,
+        })
+        .collect::<String>();
+    match s.as_str() {
+        "" | "." | ".." => "_".to_string(),
+        _ => s,
+    }
+}
+
+//Relative output path, an unresolved parent "<index>:rest" goes under orphan_${index}
+fn relative_path(path: &str) -> PathBuf {
+    let mut result = PathBuf::new();
+    let mut path = path;
+    if let Some(rest) = path.strip_prefix('<') {
+        if let Some((index, rest)) = rest.split_once(">:") {
+            result.push(format!("orphan_{}", clean_component(index)));
+            path = rest;
+        }
+    }
+    for name in path.split('\\').filter(|x| !x.is_empty()) {
+        result.push(clean_component(name));
+    }
+    result
+}
+
+//Two deleted files may have had the same path, the later one gets its index appended
+fn unique_path(path: PathBuf, index: u64) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", index));
+    path.with_file_name(name)
+}
+
+fn recover_entry(ntfs: &Ntfs, entry: &MFTEntry, bits: &[u8], out: &Path) -> Result<String, MRError> {
+    let index = entry.get_index();
+    let path = entry
+        .get_paths()
+        .first()
+        .map(|x| x.get_path().to_string())
+        .unwrap_or(format!("<{}>:", index));
+    let stream = entry.get_stream_runs("")?;
+    let (allocated, total) = stream.check_bitmap(bits, ntfs.get_cluster_size());
+    let status = Recoverability::from_clusters(allocated, total);
+
+    let mut detail = vec![];
+    if stream.is_resident() {
+        detail.push("resident");
+    }
+    if stream.is_encrypted() {
+        detail.push("EFS encrypted, raw data written");
+    }
+    let mut output = String::new();
+    let mut sha256 = String::new();
+    //LZNT1 is not decoded, raw compression units would look like a corrupted file
+    if stream.is_compressed() {
+        detail.push("compressed, not written");
+    } else {
+        let mut name = relative_path(&path);
+        if name.file_name().is_none() {
+            name.push(format!("entry_{}", index));
+        }
+        let target = unique_path(out.join(&name), index);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| MRError::new(&e.to_string()))?;
+        }
+        let mut file = fs::File::create(&target).map_err(|e| MRError::new(&e.to_string()))?;
+        let mut hasher = Sha256::new();
+        stream.read_chunks(ntfs, |bs| {
+            hasher.update(bs);
+            file.write_all(bs).map_err(|e| MRError::new(&e.to_string()))
+        })?;
+        sha256 = hasher.finalize().iter().map(|x| format!("{:02x}", x)).collect();
+        output = target
+            .strip_prefix(out)
+            .unwrap_or(&target)
+            .to_string_lossy()
+            .to_string();
+    }
+    println!("{}\t{}\t{}\t{}/{} clusters reused", status.as_str(), index, path, allocated, total);
+    Ok(format!(
+        "{},{},{},{},{},{},{},{},{},{}\n",
+        index,
+        entry.get_sequence(),
+        status.as_str(),
+        stream.get_size(),
+        total,
+        allocated,
+        sha256,
+        csv_field(&path),
+        csv_field(&output),
+        csv_field(&detail.join("; "))
+    ))
+}
+
+impl NtfsModule {
+    //Writes the $DATA of deleted files under out=${dir} with their original paths, listed in manifest.csv
+    //path=${dir} keeps the files deleted from that directory, index=${index} recovers one entry
+    pub fn recover(&mut self, args: HashMap<String, String>) -> Result<usize, MRError> {
+        let out = args.get("out").map(|x| x.as_str()).unwrap_or("./recovered");
+        let out = Path::new(out);
+        fs::create_dir_all(out).map_err(|e| MRError::new(&e.to_string()))?;
+        let mut manifest = fs::File::create(out.join("manifest.csv"))
+            .map_err(|e| MRError::new(&e.to_string()))?;
+        manifest
+            .write_all(b"index,sequence,status,size,clusters,reused_clusters,sha256,path,output,detail\n")
+            .map_err(|e| MRError::new(&e.to_string()))?;
+
+        let parent_index = match args.get("path") {
+            Some(s) => Some(self.ntfs.get_mft_by_path(s)?.get_index()),
+            None => None,
+        };
+        let only = match args.get("index") {
+            Some(s) => Some(s.parse::<u64>().map_err(|_| MRError::new("index=${index}"))?),
+            None => None,
+        };
+        let bits = self.ntfs.get_bitmap()?.read_all()?;
+
+        let ntfs = &self.ntfs;
+        let mut count = 0;
+        let mut error = None;
+        let mut handle = |entry: &MFTEntry| {
+            if error.is_some() {
+                return;
+            }
+            let line = match recover_entry(ntfs, entry, &bits, out) {
+                Ok(o) => o,
+                Err(e) => {
+                    println!("[Warning]: entry {} not recovered, {}", entry.get_index(), e);
+                    return;
+                }
+            };
+            match manifest.write_all(line.as_bytes()) {
+                Ok(_) => count += 1,
+                Err(e) => error = Some(MRError::new(&e.to_string())),
+            }
+        };
+
+        if let Some(index) = only {
+            let entry = ntfs
+                .get_mft_entry_by_index(index)
+                .ok_or(MRError::new("not found index"))?;
+            if entry.is_in_use() {
+                return Err(MRError::new("Entry is in use, not a deleted file"));
+            }
+            handle(&entry);
+        } else {
+            ntfs.iter_mft(|_, entry, is_deleted, _| {
+                let entry = match entry {
+                    Ok(o) => o,
+                    Err(_) => return,
+                };
+                //Deleted directories have no $DATA, extension entries no $FILE_NAME
+                if !is_deleted || entry.get_flags() & 0x2 != 0 || !entry.contains_attr(0x30) {
+                    return;
+                }
+                if parent_index.is_some_and(|x| entry.get_parent_index() != x as i64) {
+                    return;
+                }
+                handle(&entry);
+            });
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(count),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    file_struct::ntfs::{MFTTree, TreeNode},
+    utils::MRError,
+};
+
+use super::NtfsModule;
+
+fn print_node(path: &str, node: &TreeNode, tree: &MFTTree) {
+    println!("{}", path);
+    println!("\tindex: {}-{}", node.get_index(), node.get_sequence());
+    println!("\ttype: {}", if node.is_dir() { "directory" } else { "file" });
+    if tree.is_orphan(node) {
+        let parents = node
+            .get_links()
+            .iter()
+            .map(|x| format!("{}-{}", x.get_parent_index(), x.get_parent_sequence()))
+            .collect::<Vec<String>>();
+        println!("\torphan: parent {} reused or lost", parents.join(", "));
+    }
+    if !node.is_dir() {
+        println!("\tsize: {}", node.get_size());
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        return name.to_string();
+    }
+    format!("{}\\{}", dir, name)
+}
+
+//Deleted children at any depth, deleted directories are walked as well
+fn walk(tree: &MFTTree, index: u64, path: String, visited: &mut HashSet<u64>) -> usize {
+    let mut count = 0;
+    let mut stack = vec![(index, path)];
+    while let Some((index, path)) = stack.pop() {
+        if !visited.insert(index) {
+            continue;
+        }
+        for (node, link) in tree.get_children(index) {
+            let child_path = join(&path, link.get_name());
+            if !node.is_in_use() {
+                print_node(&child_path, node, tree);
+                count += 1;
+            }
+            if node.is_dir() {
+                stack.push((node.get_index(), child_path));
+            }
+        }
+    }
+    count
+}
+
+impl NtfsModule {
+    //path=${dir} defaults to the root, orphans=${true|false} lists the deleted entries whose parent was reused, on by default from the root
+    pub fn search_deleted_files(&mut self, args: HashMap<String, String>) -> Result<usize, MRError> {
+        let path = args.get("path").map(|x| x.as_str()).unwrap_or("\\");
+        let root = self.ntfs.get_mft_by_path(path)?;
+        if !root.is_dir() {
+            return Err(MRError::new("path=${dir} is not a directory"));
+        }
+        let orphans = match args.get("orphans") {
+            Some(s) => s.eq("true"),
+            None => root.get_index() == 5,
+        };
+
+        let index = self.get_index();
+        let tree = index.get_tree();
+        let mut visited = HashSet::new();
+        let mut count = walk(tree, root.get_index(), tree.get_path(root.get_index()), &mut visited);
+        if orphans {
+            for node in tree.get_orphans() {
+                let path = tree.get_path(node.get_index());
+                print_node(&path, node, tree);
+                count += 1;
+                if node.is_dir() {
+                    count += walk(tree, node.get_index(), path, &mut visited);
+                }
+            }
+        }
+        Ok(count)
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap, fmt::Write, fs, num::ParseIntError, ops::Range, rc::Rc, str::FromStr,
+    collections::HashMap, fmt::Write, fs, rc::Rc, str::FromStr,
 };
 
 use bytes::Bytes;
@@ -7,51 +7,46 @@ use colored::{ColoredString, Colorize};
 use indicatif::{ProgressBar, ProgressState, ProgressStyle};
 
 use crate::{
-    file_struct::ntfs::{MFTEntry, Ntfs},
-    utils::MRError,
+    file_struct::{
+        ntfs::{MFTIndex, Ntfs},
+        BlockOwner,
+    },
+    utils::{funcs::hex_to_vec_u8, MRError},
 };
 
 use super::{MatchType, NtfsModule};
 use memchr::memmem;
-pub fn hex_to_vec_u8(s: &str) -> Result<Vec<u8>, ParseIntError> {
-    (0..s.len())
-        .step_by(2)
-        .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
-        .collect()
-}
 
 pub fn vs_contains_sub(haystack: &[u8], needle: &[u8]) -> Option<usize> {
     memmem::find(haystack, needle)
 }
 
-fn ref_file(
-    mfts: &[(Range<usize>, u64)],
-    ntfs: &Ntfs,
-    offset: u64,
-    drive: &str,
-    is_ref_file: bool,
-) -> ColoredString {
-    if !is_ref_file {
-        return format!("lcn:{}", offset / ntfs.get_cluster_size()).bright_red();
-    }
-    let mft = search_addr_belong(mfts, offset);
-
-    if let Some(mft) = mft {
-        let mft = match ntfs.get_mft_entry_by_index(mft) {
-            Some(s) => s,
-            None => {
-                return format!("lcn:{}", offset / ntfs.get_cluster_size()).bright_red();
+//Owner of the offset, only the cluster number without the index
+fn ref_file(index: Option<(&MFTIndex, &[u8])>, ntfs: &Ntfs, offset: u64, drive: &str) -> ColoredString {
+    let lcn = offset / ntfs.get_cluster_size();
+    let owner = match index.map(|(index, bits)| ntfs.whose(index, bits, offset)) {
+        Some(Ok(o)) => o,
+        _ => {
+            return format!("lcn:{}", lcn).bright_red();
+        }
+    };
+    match owner {
+        BlockOwner::File { path, stream, slack, .. } => {
+            let mut path = match path.starts_with('\\') {
+                true => format!("{}{}", drive, path),
+                false => format!("{}\\{}", drive, path),
+            };
+            if !stream.is_empty() {
+                path.push(':');
+                path.push_str(&stream);
             }
-        };
-        let filename = match mft.fullpath() {
-            Some(s) => s,
-            None => {
-                return format!("lcn:{}", offset / ntfs.get_cluster_size()).bright_red();
+            if slack {
+                path.push_str(" (slack)");
             }
-        };
-        return format!("{}\\{}", drive, filename).bright_blue();
+            path.bright_blue()
+        }
+        owner => format!("lcn:{} {}", lcn, owner).bright_red(),
     }
-    format!("lcn:{}", offset / ntfs.get_cluster_size()).bright_red()
 }
 
 fn vec_u8_to_utf16string(bytes: &[u8]) -> String {
@@ -72,69 +67,6 @@ pub fn sec_to_s(secs: u64) -> String {
     }
 }
 
-fn cache_mfts<F>(ntfs: &mut Ntfs, mut filter: F) -> Vec<(Range<usize>, u64)>
-where
-    F: FnMut(u64, &MFTEntry) -> bool,
-{
-    let mut result = Vec::new();
-    let mut cache = Vec::new();
-    ntfs.iter_mft(|index, entry, is_deleted, ntfs| {
-        let entry = match entry {
-            Ok(o) => o,
-            Err(_) => {
-                return;
-            }
-        };
-        let flag = filter(entry.get_index() * ntfs.get_mft_size() as u64, &entry);
-        if flag {
-            let entry = Rc::new(entry);
-            let data_value = entry.get_data_value();
-            if let Some(value) = data_value {
-                let datas = value.get_datas();
-                for data in datas {
-                    let v = (
-                        Range {
-                            start: data.get_start_addr() as usize,
-                            end: data.get_start_addr() as usize + data.get_datasize() as usize,
-                        },
-                        entry.get_index(),
-                    );
-                    cache.push(v);
-                }
-            }
-        }
-    });
-    cache.sort_by(|k, v| k.0.start.cmp(&v.0.start));
-    result.extend(cache);
-    result
-}
-
-pub fn search_addr_belong(mfts: &[(Range<usize>, u64)], addr: u64) -> Option<u64> {
-    let addr = addr as usize;
-
-    let mut start = 0;
-    let mut end = mfts.len();
-    let mut middle = (start + end) / 2;
-    let mut mft = &mfts[middle];
-    while start <= end {
-        if addr > mft.0.end {
-            start = middle + 1;
-            middle = (start + end) / 2;
-            mft = &mfts[middle];
-        } else if addr < mft.0.start {
-            end = middle - 1;
-            middle = (start + end) / 2;
-            mft = &mfts[middle];
-        } else if (start == end && (addr > mft.0.start && addr < mft.0.end))
-            || (addr >= mft.0.start && addr <= mft.0.end)
-        {
-            return Some(mft.1);
-        } else {
-            return None;
-        }
-    }
-    None
-}
 impl NtfsModule {
     pub fn search_disk(&mut self, args: HashMap<String, String>) -> Result<(), MRError> {
         let match_type: MatchType;
@@ -155,46 +87,14 @@ impl NtfsModule {
             Some(s) => s,
             None => &default_to_file,
         };
-        let bool_to_file;
-        let mut _mfts = vec![];
-        if to_file.eq("true") {
-            bool_to_file = true;
-            let mft_mft = self.ntfs.get_datas_of_mft();
-            let mut total = 0;
-            for mft in &*mft_mft.borrow() {
-                total += mft.get_datasize();
-            }
-
-            let pb = ProgressBar::new(total);
-            let mut save_offset = 0;
-            println!("Loading mft....");
-            pb.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}), {eta}")
-                .unwrap()
-                .with_key("eta", |state: &ProgressState, w: &mut dyn Write| write!(w, "{}s", sec_to_s(state.eta().as_secs())).unwrap())
-                .progress_chars("#>-"));
-            _mfts = cache_mfts(&mut self.ntfs, |offset, entry| {
-                // pb.set_position(offset);
-                let value = entry.get_data_value();
-                if value.is_none() {
-                    return false;
-                }
-
-                if let Some(datas) = value {
-                    if datas.get_datas().is_empty() {
-                        return false;
-                    }
-                }
-                save_offset = offset;
-                true
-            });
-            pb.finish();
-            println!(
-                "Loaded {} Master Entries",
-                save_offset / self.ntfs.get_mft_size() as u64
-            );
+        let mft_index = if to_file.eq("true") {
+            let index = self.get_index();
+            println!("Loaded {} Master Entries", index.get_tree().len());
+            let bits = self.ntfs.get_bitmap().and_then(|x| x.read_all()).unwrap_or_default();
+            Some((index, bits))
         } else {
-            bool_to_file = false;
-        }
+            None
+        };
 
         let target: Vec<u8>;
         let mut regex_bytes_pattern = None::<regex::bytes::Regex>;
@@ -254,21 +154,20 @@ impl NtfsModule {
                 if match_type.eq(&MatchType::Equal) {
                     // pb2.set_position(progress);
                     let size = vs_contains_sub(&bs, &target);
-                    if size.is_some() {
+                    if let Some(size) = size {
                         let sub = String::from_utf8_lossy(
-                            &bs[size.unwrap()..size.unwrap() + target.len()],
+                            &bs[size..size + target.len()],
                         )
                         .to_string();
                         let s = format!(
                             "{} {:?} -> ref_file: {}",
-                            progress + size.unwrap() as u64,
+                            progress + size as u64,
                             sub,
                             ref_file(
-                                &_mfts,
+                                mft_index.as_ref().map(|(x, bits)| (x.as_ref(), bits.as_slice())),
                                 ntfs,
-                                progress + size.unwrap() as u64,
-                                drive,
-                                bool_to_file
+                                progress + size as u64,
+                                drive
                             )
                         );
                         println!("{}" ,s);
@@ -285,11 +184,10 @@ impl NtfsModule {
                                 progress + mt.start() as u64,
                                 rs,
                                 ref_file(
-                                    &_mfts,
+                                    mft_index.as_ref().map(|(x, bits)| (x.as_ref(), bits.as_slice())),
                                     ntfs,
                                     progress + mt.start() as u64,
-                                    drive,
-                                    bool_to_file
+                                    drive
                                 )
                             );
                             println!("{}" ,s);
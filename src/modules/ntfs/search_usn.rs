@@ -1,24 +1,20 @@
 use std::{
-    collections::HashMap, fmt::Write, fs, num::ParseIntError, ops::Range, rc::Rc, str::FromStr,
+    collections::HashMap, fs, ops::Range, rc::Rc, str::FromStr,
 };
 
 use bytes::{Buf, Bytes};
 use colored::{ColoredString, Colorize};
-use indicatif::{ProgressBar, ProgressState, ProgressStyle};
 
 use crate::{
-    file_struct::ntfs::{FileTime, MFTEntry, Ntfs, USNChangeJournalEntry},
-    utils::MRError,
+    file_struct::ntfs::{
+        journal_impl::{match_usn_struct, USN_RECORD_V4_SIZE},
+        MFTEntry, Ntfs, USNChangeJournalEntry,
+    },
+    utils::{funcs::bytes_progress_bar, MRError},
 };
 
-use super::{MatchType, NtfsModule};
+use super::{usn_filter_from_args, MatchType, NtfsModule};
 use memchr::memmem;
-pub fn hex_to_vec_u8(s: &str) -> Result<Vec<u8>, ParseIntError> {
-    (0..s.len())
-        .step_by(2)
-        .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
-        .collect()
-}
 
 pub fn vs_contains_sub(haystack: &[u8], needle: &[u8]) -> Option<usize> {
     memmem::find(haystack, needle)
@@ -104,7 +100,7 @@ where
             }
         }
     });
-    cache.sort_by(|k, v| k.0.start.cmp(&v.0.start));
+    cache.sort_by_key(|k| k.0.start);
     result.extend(cache);
     result
 }
@@ -157,130 +153,10 @@ fn check(mfts: &[(Range<usize>, u64)]) -> Vec<Range<usize>> {
 
 
 
-fn match_filetime(filetime: Bytes) -> bool {
-    let update_time = FileTime::parse_from_u64((&filetime[0..8]).get_u64());
-    let scond = update_time.to_native_date();
-    if scond.is_none() {
-        return false;
-    }
-    true
-}
-
-fn match_usn_struct(bs: &Bytes) -> Option<usize> {
-    let entry_size = (&bs[0..4]).get_u32_le();
-    if entry_size > 78 + 255 || entry_size % 4 != 0 {
-        return None
-    }
-    let majar_ver = (&bs[4..6]).get_u16_le();
-    let minjar_ver = (&bs[6..8]).get_u16_le();
-
-    match majar_ver {
-        3 => {
-            if entry_size <= 60 {
-                return None;
-            }
-        },
-        2 => {
-            if entry_size <= 78 {
-                return None
-            }
-        },
-        _ => {
-            return None
-        }
-    };
-    
-
-    if majar_ver != 3 && majar_ver != 2 {
-        return None
-    }
-
-    if minjar_ver != 0 {
-        return None
-    }
-
-    let filetime = bs.slice(32..40);
-    if !match_filetime(filetime) {
-        return None
-    }
-
-    let name_offset = match majar_ver {
-        3 => {
-            let v = (&bs[74..76]).get_u16_le();
-            if v != 76 {
-                return None;
-            }
-
-            v
-        },
-        2 => {
-            let v = (&bs[58..60]).get_u16_le();
-            if v != 60 {
-                return None;
-            }
-
-            v
-        },
-        _ => {
-            return None
-        }
-    };
-
-    let name_size = match majar_ver {
-        3 => {
-            (&bs[72..74]).get_u16_le()
-        },
-        2 => {
-            (&bs[56..58]).get_u16_le()
-            
-        },
-        _ => {
-            return None
-        }
-    };
-
-    let v = match majar_ver {
-        3 => {
-            let v = 78 + name_size as usize;
-            if v > bs.len() {
-                return None
-            }
-            v
-        },
-        2 => {
-            let v = 60 + name_size as usize;
-            if v > bs.len() {
-                return None
-            }
-            v
-        },
-        _ => {
-            return None;
-        }
-    };
-
-
-
-    let name = match majar_ver {
-        3 => {
-            vec_u8_to_utf16string(bs.get(78..78+name_size as usize).unwrap())
-        },
-        2 => {
-            vec_u8_to_utf16string(bs.get(60..60+name_size as usize).unwrap())
-        },
-        _ => {
-            return None;
-        }
-    };
-
-    Some(entry_size as usize)
-}
-
-
-
 impl NtfsModule {
     pub fn search_usn(&mut self, args: HashMap<String, String>) -> Result<(), MRError> {
         let match_type: MatchType;
+        let filter = usn_filter_from_args(&args)?;
 
         // let mut _mfts = vec![];
 
@@ -337,11 +213,7 @@ impl NtfsModule {
         let read_size = self.ntfs.get_cluster_size() * 0x100;
         //let target = Bytes::from(target);
         
-        let pb = ProgressBar::new(ranges.1 as u64 * self.ntfs.get_cluster_size());
-        pb.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}), {eta}")
-                .unwrap()
-                .with_key("eta", |state: &ProgressState, w: &mut dyn Write| write!(w, "{}s", sec_to_s(state.eta().as_secs())).unwrap())
-                .progress_chars("#>-"));
+        let pb = bytes_progress_bar(ranges.1 as u64 * self.ntfs.get_cluster_size());
         let pb2 = &pb;
         let mut count = 0;
         let ntfs = &self.ntfs;
@@ -357,7 +229,7 @@ impl NtfsModule {
                 let mut offset = 0;
                 while offset < bs.len() {
                     let t = bs.slice(offset..bs.len());
-                    if t.len() < 58 {
+                    if t.len() < USN_RECORD_V4_SIZE {
                         return false
                     }
                     match match_usn_struct(&t) {
@@ -378,6 +250,9 @@ impl NtfsModule {
                             continue;
                         }
                     };
+                    if !filter.matches(&entry) {
+                        continue;
+                    }
                     println!("{:?}  {:?}", entry.get_time_string(), entry);
                 
 
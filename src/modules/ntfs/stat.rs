@@ -13,8 +13,8 @@ impl NtfsModule {
             return Err(MRError::new("must set path=${path} or index=${index}"));
         }
 
-        let mft = if path.is_some() {
-            match self.ntfs.get_mft_by_path(path.unwrap()) {
+        let mft = if let Some(path) = path {
+            match self.ntfs.get_mft_by_path(path) {
                 Ok(o) => o,
                 Err(e) => {
                     return Err(e);
@@ -34,6 +34,16 @@ impl NtfsModule {
         println!("filename: {:?}",mft.filename());
         println!("\tindex: {:?}", mft.get_index());
         println!("\tfullpath: {:?}", mft.fullpath());
+        println!("\tshort name: {:?}", mft.get_short_name());
+        for path in mft.get_paths() {
+            println!(
+                "\tlink: {} (namespace {}, parent {}, short name {:?})",
+                path.get_path(),
+                path.get_name_space(),
+                path.get_parent_index(),
+                path.get_short_name()
+            );
+        }
         println!("\tcreation: {:?}", mft.get_creation_time());
         println!("\taccess: {:?}", mft.get_access_time());
         println!("\tmodify: {:?}", mft.get_change_time());
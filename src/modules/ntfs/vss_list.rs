@@ -0,0 +1,26 @@
+use crate::utils::{file::filesize_to_human_string, MRError};
+
+use super::NtfsModule;
+
+impl NtfsModule {
+    pub fn vss_list(&mut self) -> Result<usize,MRError> {
+        let header = self.ntfs.get_vss_header()?;
+        println!("vss version: {}", header.get_version());
+        println!("\tvolume id: {}", header.get_volume_id());
+        println!("\tstorage volume id: {}", header.get_storage_volume_id());
+        println!("\tmaximum size: {}", filesize_to_human_string(header.get_maximum_size() as usize));
+        let stores = self.ntfs.get_vss_stores()?;
+        for (i, store) in stores.iter().enumerate() {
+            println!("vss={}", i + 1);
+            println!("\tcreation: {:?}", store.get_creation_time().to_native_date());
+            println!("\tshadow copy id: {}", store.get_shadow_copy_id());
+            println!("\tshadow copy set id: {}", store.get_shadow_copy_set_id());
+            println!("\tstore id: {}", store.get_store_id());
+            println!("\tvolume size: {}", filesize_to_human_string(store.get_volume_size() as usize));
+            println!("\tattribute flags: {:#x}", store.get_attribute_flags());
+            println!("\tmachine: {} {}", store.get_operating_machine(), store.get_service_machine());
+            println!("\tstored blocks: {}", store.get_block_count());
+        }
+        Ok(stores.len())
+    }
+}
@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+
+use crate::{
+    file_struct::BlockOwner,
+    utils::{funcs::parse_number, MRError},
+};
+
+use super::NtfsModule;
+
+impl NtfsModule {
+    //offset=${bytes} or cluster=${lcn}, several joined by |, hex with 0x
+    pub fn whose(&mut self, args: HashMap<String, String>) -> Result<(), MRError> {
+        let cluster_size = self.ntfs.get_cluster_size();
+        let offsets = match (args.get("offset"), args.get("cluster")) {
+            (Some(s), _) => s.split('|').map(parse_number).collect::<Result<Vec<u64>, MRError>>()?,
+            (None, Some(s)) => s
+                .split('|')
+                .map(|x| parse_number(x).map(|x| x * cluster_size))
+                .collect::<Result<Vec<u64>, MRError>>()?,
+            (None, None) => {
+                return Err(MRError::new("whose offset=${bytes} or cluster=${lcn}"));
+            }
+        };
+
+        let index = self.get_index();
+        let bits = self.ntfs.get_bitmap()?.read_all()?;
+        for offset in offsets {
+            let owner = self.ntfs.whose(&index, &bits, offset)?;
+            println!("{} (lcn {}): {}", offset, offset / cluster_size, owner);
+            if let BlockOwner::Metadata { record: Some(record), .. } = owner {
+                if index.get_tree().get_node(record).is_some() {
+                    println!("\trecord path: {}", index.get_full_path(record));
+                }
+            }
+        }
+        Ok(())
+    }
+}
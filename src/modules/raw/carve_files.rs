@@ -0,0 +1,119 @@
+use std::{collections::HashMap, fs, io::Write, ops::Range, path::PathBuf};
+
+use indicatif::ProgressBar;
+
+use crate::{
+    file_struct::carver::{self, CarvedFile, Signature},
+    utils::{file::MRFile, funcs::bytes_progress_bar, MRError},
+};
+
+use super::RawModule;
+
+//Options and output of a carving, shared by the carve_files of every module,
+//types=${jpg|png|pdf|zip|sqlite|elf|pe|eml},align=${default:512},min_confidence=${default:0},
+//out=${default:./carved_files} holding the files and carved_files.csv
+pub struct CarveJob {
+    signatures      : Vec<&'static Signature>,
+    align           : u64,
+    min_confidence  : u8,
+    out             : PathBuf,
+    index_file      : fs::File,
+    pb              : ProgressBar,
+    count           : usize,
+    error           : Option<MRError>
+}
+
+impl CarveJob {
+    //total is the count of bytes to scan, for the progress bar
+    pub fn new(args: &HashMap<String, String>, total: u64) -> Result<Self, MRError> {
+        let kinds = args.get("types").map(|x| x.split('|').collect::<Vec<&str>>());
+        let signatures = carver::get_signatures(kinds.as_deref())?;
+        let align = match args.get("align") {
+            Some(s) => s.parse::<u64>().map_err(|_| MRError::new("align=${bytes}"))?,
+            None => 512,
+        };
+        let min_confidence = match args.get("min_confidence") {
+            Some(s) => s.parse::<u8>().map_err(|_| MRError::new("min_confidence=${0..100}"))?,
+            None => 0,
+        };
+        let out = PathBuf::from(args.get("out").map(|x| x.as_str()).unwrap_or("./carved_files"));
+        fs::create_dir_all(&out).map_err(|e| MRError::new(&e.to_string()))?;
+        let mut index_file = fs::File::create(out.join("carved_files.csv"))
+            .map_err(|e| MRError::new(&e.to_string()))?;
+        index_file
+            .write_all(b"kind,offset,size,confidence,file\n")
+            .map_err(|e| MRError::new(&e.to_string()))?;
+        Ok(Self {
+            signatures,
+            align,
+            min_confidence,
+            out,
+            index_file,
+            pb: bytes_progress_bar(total),
+            count: 0,
+            error: None,
+        })
+    }
+
+    pub fn get_signatures(&self) -> &[&'static Signature] {
+        &self.signatures
+    }
+
+    pub fn get_align(&self) -> u64 {
+        self.align
+    }
+
+    //Writes a carved file and its line of the csv, false on an error to stop the carving
+    pub fn write(&mut self, file: &CarvedFile, data: &[u8], scanned: u64) -> bool {
+        self.pb.set_position(scanned);
+        if file.get_confidence() < self.min_confidence {
+            return true;
+        }
+        let name = format!("{}_{:x}.{}", file.get_kind(), file.get_offset(), file.get_ext());
+        let line = format!(
+            "{},{},{},{},{}\n",
+            file.get_kind(),
+            file.get_offset(),
+            file.get_size(),
+            file.get_confidence(),
+            name
+        );
+        let written = fs::write(self.out.join(&name), data).and_then(|_| self.index_file.write_all(line.as_bytes()));
+        if let Err(e) = written {
+            self.error = Some(MRError::new(&e.to_string()));
+            return false;
+        }
+        self.count += 1;
+        true
+    }
+
+    //Stops the carving on an error of the reader
+    pub fn fail(&mut self, e: MRError) {
+        self.error = Some(e);
+    }
+
+    //Count of files written
+    pub fn finish(self) -> Result<usize, MRError> {
+        self.pb.finish();
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.count),
+        }
+    }
+}
+
+//Writes the files carved from the sorted byte ranges of reader, see CarveJob for the options
+pub fn carve_files(reader: &MRFile, ranges: &[Range<u64>], args: &HashMap<String, String>) -> Result<usize, MRError> {
+    let mut job = CarveJob::new(args, ranges.iter().map(|x| x.end - x.start).sum())?;
+    let signatures = job.get_signatures().to_vec();
+    carver::carve(reader, ranges, &signatures, job.get_align(), |file, data, scanned| job.write(file, data, scanned))?;
+    job.finish()
+}
+
+impl RawModule {
+    //Carves the whole image, see carve_files for the options
+    pub fn carve_files(&self, args: HashMap<String, String>) -> Result<usize, MRError> {
+        let size = self.reader.get_size()?;
+        carve_files(&self.reader, &[Range { start: 0, end: size }], &args)
+    }
+}
@@ -0,0 +1,16 @@
+use crate::utils::{file::MRFile, MRError};
+
+pub mod carve_files;
+
+//Images without a known file system, read byte by byte
+pub struct RawModule {
+    reader  : MRFile
+}
+
+impl RawModule {
+    pub fn new(file: &str) -> Result<RawModule, MRError> {
+        Ok(Self {
+            reader: MRFile::new(file)?,
+        })
+    }
+}
@@ -0,0 +1,25 @@
+use std::collections::HashMap;
+
+use crate::{
+    file_struct::xfs::{DirectoryEntry, Inode},
+    utils::MRError,
+};
+
+use super::XfsModule;
+
+impl XfsModule {
+    //f gets the entry, its inode when it can be read and whether the inode is allocated again
+    pub fn list_deleted_files<F>(&self, args: HashMap<String, String>, mut f: F) -> Result<Vec<DirectoryEntry>, MRError>
+    where
+        F: FnMut(&DirectoryEntry, Option<&Inode>, bool),
+    {
+        let dir = self.get_inode(&args)?;
+        let entries = self.xfs.get_deleted_dir_entries(&dir)?;
+        for entry in &entries {
+            let inode = self.xfs.get_inode_by_id(entry.get_inode()).ok();
+            let allocated = self.xfs.is_inode_allocated(entry.get_inode()).unwrap_or(false);
+            f(entry, inode.as_ref(), allocated);
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,12 @@
+use std::collections::HashMap;
+
+use crate::{file_struct::xfs::DirectoryEntry, utils::MRError};
+
+use super::XfsModule;
+
+impl XfsModule {
+    pub fn list_files(&self, args: HashMap<String, String>) -> Result<Vec<DirectoryEntry>, MRError> {
+        let inode = self.get_inode(&args)?;
+        self.xfs.get_dir_entries(&inode)
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use crate::{
+    file_struct::xfs::{Inode, LogInode},
+    utils::MRError,
+};
+
+use super::XfsModule;
+
+impl XfsModule {
+    //Freed inodes of the inode chunks that were used once, f gets the inode, the paths of the
+    //freed entries naming it and its older copies in the log, log=false leaves the log out
+    pub fn list_recoverable_inodes<F>(&self, args: HashMap<String, String>, mut f: F) -> Result<usize, MRError>
+    where
+        F: FnMut(&Inode, &[String], &[LogInode]),
+    {
+        let use_log = args.get("log").map(|x| x != "false").unwrap_or(true);
+        //Freed entries only keep the low half of the inode number
+        let mut names: HashMap<u64, Vec<String>> = HashMap::new();
+        let root = HashMap::from([("path".to_string(), "/".to_string())]);
+        self.search_deleted_files(root, |path, entry, _, _| {
+            names.entry(entry.get_inode() & 0xffffffff).or_default().push(path.clone());
+        })?;
+        let mut copies: HashMap<u64, Vec<LogInode>> = HashMap::new();
+        if use_log {
+            for copy in self.xfs.get_log_inodes()? {
+                copies.entry(copy.get_inode().get_id()).or_default().push(copy);
+            }
+        }
+
+        let mut count = 0;
+        for ag in 0..self.xfs.get_super_block().get_alloc_groups_number() {
+            for chunk in self.xfs.get_inode_chunks(ag)? {
+                for i in 0..64 {
+                    if chunk.is_hole(i) || !chunk.is_free(i) {
+                        continue;
+                    }
+                    let ino = self.xfs.make_ino(ag, chunk.get_start_ino() + i);
+                    let inode = match self.xfs.get_inode_by_id(ino) {
+                        Ok(o) => o,
+                        Err(_) => continue,
+                    };
+                    let names = names.get(&(ino & 0xffffffff)).map(|x| x.as_slice()).unwrap_or_default();
+                    let copies = copies.get(&ino).map(|x| x.as_slice()).unwrap_or_default();
+                    //Inodes never used since the chunk was made have zero times, 1901 with big timestamps
+                    if inode.get_ctime().and_utc().timestamp() <= 0 && names.is_empty() && copies.is_empty() {
+                        continue;
+                    }
+                    f(&inode, names, copies);
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+}
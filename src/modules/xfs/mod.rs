@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+
+use crate::{file_struct::xfs::{Inode, XFS}, utils::MRError};
+
+pub mod stat;
+pub mod list_files;
+pub mod read_file;
+pub mod list_deleted_files;
+pub mod search_deleted_files;
+pub mod list_recoverable_inodes;
+
+pub struct XfsModule {
+    xfs     : XFS,
+    file    : String
+}
+
+impl XfsModule {
+    pub fn new(file: &str) -> Result<XfsModule, MRError> {
+        Ok(Self {
+            xfs: XFS::open(file)?,
+            file: file.to_string(),
+        })
+    }
+
+    //path=${path} or inode=${number}
+    fn get_inode(&self, args: &HashMap<String, String>) -> Result<Inode, MRError> {
+        match (args.get("path"), args.get("inode")) {
+            (Some(path), _) => self.xfs.get_inode_by_path(path),
+            (None, Some(inode)) => {
+                let inode = inode.parse::<u64>().map_err(|_| MRError::new("inode=${number}"))?;
+                self.xfs.get_inode_by_id(inode)
+            }
+            (None, None) => Err(MRError::new("path=${target_path} or inode=${number}")),
+        }
+    }
+}
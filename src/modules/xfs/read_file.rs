@@ -0,0 +1,17 @@
+use std::{collections::HashMap, io::Write};
+
+use crate::utils::MRError;
+
+use super::XfsModule;
+
+impl XfsModule {
+    pub fn read_file(&self, args: HashMap<String, String>) -> Result<(), MRError> {
+        let inode = self.get_inode(&args)?;
+        let value = self.xfs.read_inode_data(&inode)?;
+        let mut out = std::io::stdout();
+        if let Err(e) = out.write_all(&value) {
+            return Err(MRError::from(Box::new(e)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    file_struct::xfs::{DirectoryEntry, Inode},
+    utils::MRError,
+};
+
+use super::XfsModule;
+
+impl XfsModule {
+    //Walks the directories under path, f gets the full path of every freed entry, its inode when
+    //it can be read and whether the inode is allocated again
+    pub fn search_deleted_files<F>(&self, args: HashMap<String, String>, mut f: F) -> Result<usize, MRError>
+    where
+        F: FnMut(&String, &DirectoryEntry, Option<&Inode>, bool),
+    {
+        let path = match args.get("path") {
+            Some(s) => s,
+            None => return Err(MRError::new("path=${target_dir}")),
+        };
+        let base = self.xfs.get_inode_by_path(path)?;
+        let mut visited = HashSet::new();
+        let mut stack = vec![(path.trim_end_matches('/').to_string(), base)];
+        let mut count = 0;
+        while let Some((dir_path, dir)) = stack.pop() {
+            if !visited.insert(dir.get_id()) {
+                continue;
+            }
+            //Broken directories are left out, the rest of the tree is still worth a look
+            let deleted = self.xfs.get_deleted_dir_entries(&dir).unwrap_or_default();
+            for entry in &deleted {
+                let name = format!("{}/{}", dir_path, entry.get_name());
+                let inode = self.xfs.get_inode_by_id(entry.get_inode()).ok();
+                let allocated = self.xfs.is_inode_allocated(entry.get_inode()).unwrap_or(false);
+                f(&name, entry, inode.as_ref(), allocated);
+                count += 1;
+            }
+            for entry in self.xfs.get_dir_entries(&dir).unwrap_or_default() {
+                if entry.get_name() == "." || entry.get_name() == ".." {
+                    continue;
+                }
+                if self.xfs.has_ftype() && !entry.is_dir() {
+                    continue;
+                }
+                if let Ok(inode) = self.xfs.get_inode_by_id(entry.get_inode()) {
+                    if inode.is_dir() {
+                        stack.push((format!("{}/{}", dir_path, entry.get_name()), inode));
+                    }
+                }
+            }
+        }
+        Ok(count)
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use crate::utils::MRError;
+
+use super::XfsModule;
+
+impl XfsModule {
+    //The volume without path or inode
+    pub fn stat(&self, args: HashMap<String, String>) -> Result<(), MRError> {
+        if !args.contains_key("path") && !args.contains_key("inode") {
+            return self.stat_volume();
+        }
+        let inode = self.get_inode(&args)?;
+        println!("inode: {}", inode.get_id());
+        println!("\tversion: {}", inode.get_version());
+        println!("\tmode: {:o}", inode.get_mode());
+        println!("\tformat: {:?}", inode.get_format());
+        println!("\tuid: {}", inode.get_uid());
+        println!("\tgid: {}", inode.get_gid());
+        println!("\tlinks: {}", inode.get_nlink());
+        println!("\tsize: {}", inode.get_size());
+        println!("\tblocks: {}", inode.get_nblocks());
+        println!("\textents: {}", inode.get_nextents());
+        println!("\tgeneration: {}", inode.get_generation());
+        println!("\tatime: {}", inode.get_atime());
+        println!("\tmtime: {}", inode.get_mtime());
+        println!("\tctime: {}", inode.get_ctime());
+        if let Some(crtime) = inode.get_crtime() {
+            println!("\tbirth time: {}", crtime);
+        }
+        println!("\tallocated: {}", self.xfs.is_inode_allocated(inode.get_id())?);
+        for extent in self.xfs.get_extents(&inode)? {
+            println!(
+                "\textent: file block {}, disk block {}, {} blocks{}",
+                extent.get_offset(),
+                extent.get_start_block(),
+                extent.get_count(),
+                if extent.is_unwritten() { ", unwritten" } else { "" }
+            );
+        }
+        Ok(())
+    }
+
+    fn stat_volume(&self) -> Result<(), MRError> {
+        let sb = self.xfs.get_super_block();
+        println!("volume: {}", self.file);
+        println!("\tname: {}", sb.get_volume_name());
+        println!("\tuuid: {}", sb.get_uuid().iter().map(|x| format!("{:02x}", x)).collect::<String>());
+        println!("\tversion: {}", sb.get_version());
+        println!("\tblock size: {}", sb.get_block_size());
+        println!("\tblocks: {}", sb.get_block_number());
+        println!("\tsector size: {}", sb.get_sector_size());
+        println!("\tinode size: {}", sb.get_inode_size());
+        println!("\tdir block size: {}", self.xfs.get_dir_block_size());
+        println!("\troot inode: {}", sb.get_root_dir_inode());
+        println!("\tinodes: {:?}, free {:?}", sb.get_number_of_inodes(), sb.get_number_of_free_inodes());
+        println!("\tfree blocks: {:?}", sb.get_number_of_free_data_blocks());
+        println!("\tlog: block {}, {} blocks", sb.get_journal_block_number(), sb.get_journal_size());
+        for ag in 0..sb.get_alloc_groups_number() {
+            let group = self.xfs.get_alloc_group(ag)?;
+            println!(
+                "\tgroup {}: {} blocks, {} free, {} inodes, {} free",
+                group.get_number(),
+                group.get_length(),
+                group.get_free_blocks(),
+                group.get_inode_count(),
+                group.get_free_inodes()
+            );
+        }
+        Ok(())
+    }
+}
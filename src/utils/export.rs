@@ -0,0 +1,84 @@
+use std::{
+    fs,
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::file_struct::FileSlack;
+
+use super::{file::MRFile, MRError};
+
+const READ_SIZE: u64 = 1024 * 1024;
+
+//Volume bytes to export, owner is the id and path of the file a slack belongs to
+pub struct ExportRange {
+    pub offset  : u64,
+    pub length  : u64,
+    pub owner   : Option<(u64, String)>
+}
+
+impl From<FileSlack> for ExportRange {
+    fn from(slack: FileSlack) -> Self {
+        Self {
+            offset: slack.get_offset(),
+            length: slack.get_length(),
+            owner: Some((slack.get_id(), slack.get_path().to_string())),
+        }
+    }
+}
+
+fn io_error(e: std::io::Error) -> MRError {
+    MRError::new(&e.to_string())
+}
+
+//Copies the ranges to out one after the other, or at their own offset in an image of
+//volume_size bytes with sparse, ${out}.map.csv maps every piece back to the volume
+pub fn export_ranges(
+    reader: &MRFile,
+    ranges: &[ExportRange],
+    out: &Path,
+    sparse: bool,
+    volume_size: u64,
+) -> Result<u64, MRError> {
+    let mut file = fs::File::create(out).map_err(io_error)?;
+    let mut map_name = out.file_name().unwrap_or_default().to_os_string();
+    map_name.push(".map.csv");
+    let mut map = fs::File::create(out.with_file_name(map_name)).map_err(io_error)?;
+    map.write_all(b"output_offset,volume_offset,length,id,path\n").map_err(io_error)?;
+
+    let mut total = 0;
+    for range in ranges {
+        let output_offset = match sparse {
+            true => range.offset,
+            false => total,
+        };
+        let (id, path) = match &range.owner {
+            Some((id, path)) => (id.to_string(), csv_field(path)),
+            None => (String::new(), String::new()),
+        };
+        writeln!(map, "{},{},{},{},{}", output_offset, range.offset, range.length, id, path).map_err(io_error)?;
+
+        file.seek(SeekFrom::Start(output_offset)).map_err(io_error)?;
+        let mut offset = range.offset;
+        let end = range.offset + range.length;
+        while offset < end {
+            let n = (end - offset).min(READ_SIZE);
+            let bs = reader.read_n(offset as usize, n as usize)?;
+            file.write_all(&bs).map_err(io_error)?;
+            offset += n;
+        }
+        total += range.length;
+    }
+    if sparse {
+        file.set_len(volume_size).map_err(io_error)?;
+    }
+    Ok(total)
+}
+
+//Quotes a field holding a comma, a quote or a new line
+pub fn csv_field(s: &str) -> String {
+    if s.contains(',') || s.contains('"') || s.contains('\n') {
+        return format!("\"{}\"", s.replace('"', "\"\""));
+    }
+    s.to_string()
+}
@@ -1,12 +1,79 @@
 #![allow(unused)]
-use std::{cell::RefCell, fs::File, io::{BufReader, Read, Seek, SeekFrom}, ops::Range, path::Path};
+use std::{cell::RefCell, collections::HashMap, fmt::Debug, fs::File, io::{BufReader, Read, Seek, SeekFrom}, ops::Range, path::Path, rc::Rc};
 
 use super::{MRError};
 
 #[derive(Debug)]
 pub struct MRFile {
     path    : String,
-    reader  : RefCell<BufReader<File>>
+    reader  : RefCell<BufReader<File>>,
+    //Set when the file is read as a virtual volume, e.g. a shadow copy
+    block_map   : Option<BlockMap>,
+    //Set when the sectors of the file are encrypted, e.g. a BitLocker volume
+    decryptor   : Option<Rc<dyn VolumeDecryptor>>
+}
+
+//Turns the sectors stored in a file into the sectors of the volume
+pub trait VolumeDecryptor: Debug {
+    fn get_sector_size(&self) -> usize;
+
+    //Where the sector at `offset` of the volume is stored, None if it reads as zeros
+    fn map_sector(&self, offset: u64) -> Option<u64>;
+
+    //`offset` is where the sector is stored in the file
+    fn decrypt_sector(&self, offset: u64, data: &mut [u8]);
+}
+
+//Where the data of one block of a virtual volume is, the overlays are applied in order
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockSource {
+    //The whole block, the same offset of the file when a block has none
+    Base(u64),
+    //One bit per 1/32 of the block, the sectors set are read at offset
+    Overlay { offset: u64, sectors: u32 }
+}
+
+//Blocks missing from the map are read at the same offset of the file, or as zeros when they are unused
+#[derive(Debug, Default)]
+pub struct BlockMap {
+    block_size      : u64,
+    blocks          : HashMap<u64, Vec<BlockSource>>,
+    //One bit per block, set for the blocks not in use
+    unused          : Vec<u8>
+}
+
+impl BlockMap {
+    pub fn new(block_size: u64) -> Self {
+        Self {
+            block_size,
+            blocks: HashMap::new(),
+            unused: vec![],
+        }
+    }
+
+    pub fn insert(&mut self, block_offset: u64, sources: Vec<BlockSource>) {
+        self.blocks.insert(block_offset, sources);
+    }
+
+    pub fn set_unused(&mut self, bitmap: Vec<u8>) {
+        self.unused = bitmap;
+    }
+
+    pub fn is_unused(&self, block_offset: u64) -> bool {
+        let block = block_offset / self.block_size;
+        match self.unused.get((block / 8) as usize) {
+            Some(s) => s & (1 << (block % 8)) != 0,
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
 }
 
 impl MRFile {
@@ -24,10 +91,129 @@ impl MRFile {
         Ok(MRFile {
             path: s,
             reader: f,
+            block_map: None,
+            decryptor: None,
         })
     }
 
+    pub fn with_decryptor<P>(p: P, decryptor: Rc<dyn VolumeDecryptor>) -> Result<MRFile,MRError>
+    where P: AsRef<Path> + ToString {
+        let mut f = Self::new(p)?;
+        f.decryptor = Some(decryptor);
+        Ok(f)
+    }
+
+    //Same file and decryption, without the block map
+    pub fn try_clone(&self) -> Result<MRFile,MRError> {
+        let mut f = Self::new(&self.path)?;
+        f.decryptor = self.decryptor.clone();
+        Ok(f)
+    }
+
+    pub fn set_block_map(&mut self, block_map: BlockMap) {
+        self.block_map = Some(block_map);
+    }
+
+    pub fn get_path(&self) -> &String {
+        &self.path
+    }
+
     pub fn read_n(&self,addr: usize,n: usize) -> Result<Vec<u8>,MRError> {
+        let map = match &self.block_map {
+            Some(s) => s,
+            None => {
+                return self.read_plain(addr, n);
+            }
+        };
+        let block_size = map.block_size as usize;
+        let end = addr + n;
+        let mut result = Vec::with_capacity(n);
+        let mut pos = addr;
+        while pos < end {
+            let block = pos - pos % block_size;
+            match map.blocks.get(&(block as u64)) {
+                Some(sources) => {
+                    let data = self.read_block(block, block_size, sources)?;
+                    let stop = (block + block_size).min(end);
+                    result.extend_from_slice(&data[pos - block..stop - block]);
+                    pos = stop;
+                }
+                None if map.is_unused(block as u64) => {
+                    let stop = (block + block_size).min(end);
+                    result.resize(result.len() + stop - pos, 0);
+                    pos = stop;
+                }
+                None => {
+                    //Unmapped blocks in a row are read at once
+                    let mut next = block + block_size;
+                    while next < end
+                        && !map.blocks.contains_key(&(next as u64))
+                        && !map.is_unused(next as u64)
+                    {
+                        next += block_size;
+                    }
+                    let stop = next.min(end);
+                    result.extend(self.read_plain(pos, stop - pos)?);
+                    pos = stop;
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn read_block(&self, block: usize, block_size: usize, sources: &[BlockSource]) -> Result<Vec<u8>,MRError> {
+        let sector_size = block_size / 32;
+        let base = sources.iter().find_map(|x| match x {
+            BlockSource::Base(s) => Some(*s as usize),
+            _ => None,
+        });
+        let mut result = self.read_plain(base.unwrap_or(block), block_size)?;
+        for source in sources {
+            let (offset, sectors) = match source {
+                BlockSource::Overlay { offset, sectors } => (*offset, *sectors),
+                BlockSource::Base(_) => continue,
+            };
+            let data = self.read_plain(offset as usize, block_size)?;
+            for i in 0..32 {
+                if sectors & (1 << i) != 0 {
+                    let range = i * sector_size..(i + 1) * sector_size;
+                    result[range.clone()].copy_from_slice(&data[range]);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn read_plain(&self,addr: usize,n: usize) -> Result<Vec<u8>,MRError> {
+        let decryptor = match &self.decryptor {
+            Some(s) => s,
+            None => {
+                return self.read_raw(addr, n);
+            }
+        };
+        let sector_size = decryptor.get_sector_size();
+        let start = addr - addr % sector_size;
+        let end = (addr + n).next_multiple_of(sector_size);
+        let mut data = self.read_raw(start, end - start)?;
+        for (i, sector) in data.chunks_mut(sector_size).enumerate() {
+            let offset = (start + i * sector_size) as u64;
+            match decryptor.map_sector(offset) {
+                Some(s) if s == offset => {
+                    decryptor.decrypt_sector(s, sector);
+                }
+                Some(s) => {
+                    sector.copy_from_slice(&self.read_raw(s as usize, sector_size)?);
+                    decryptor.decrypt_sector(s, sector);
+                }
+                None => {
+                    sector.fill(0);
+                }
+            }
+        }
+        Ok(data[addr - start..addr - start + n].to_vec())
+    }
+
+    fn read_raw(&self,addr: usize,n: usize) -> Result<Vec<u8>,MRError> {
         let mut reader = self.reader.borrow_mut();
         if let Err(e) = reader.seek(SeekFrom::Start(addr as u64)) {
             return Err(MRError::from(Box::from(e)));
@@ -45,6 +231,12 @@ impl MRFile {
         Ok(result)
     }
 
+    //Bytes of the image or device, block devices report no length in their metadata
+    pub fn get_size(&self) -> Result<u64,MRError> {
+        let mut reader = self.reader.borrow_mut();
+        reader.seek(SeekFrom::End(0)).map_err(|e| MRError::from(Box::new(e)))
+    }
+
     pub fn read_range(&self, range: Range<usize>) -> Result<Vec<u8>,MRError> {
         self.read_n(range.start, range.end-range.start)
     }   
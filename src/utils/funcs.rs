@@ -2,6 +2,7 @@ use std::ops::Range;
 
 use bytes::Bytes;
 use colored::Colorize;
+use indicatif::{ProgressBar, ProgressState, ProgressStyle};
 use log::{Level, Log};
 
 pub struct Logger;
@@ -51,7 +52,7 @@ impl Log for Logger {
                     None => "".to_string(),
                 };
                 if record.target().starts_with("burp_rs") {
-                    LOGS.push(log);
+                    (*std::ptr::addr_of_mut!(LOGS)).push(log);
                 }
             }
         }
@@ -113,3 +114,39 @@ pub fn can_debug() -> bool {
 pub fn sub_bytes(bs: &Bytes, range: Range<usize>) -> Result<&[u8], MRError> {
     bs.get(range).ok_or(MRError::new_with_kind("Out of range", MRErrKind::OutOfByteRange))
 }
+
+//Decimal, or hex with a 0x prefix
+pub fn parse_number(s: &str) -> Result<u64, MRError> {
+    let result = match s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse::<u64>(),
+    };
+    result.map_err(|_| MRError::new(&format!("{} is not a number", s)))
+}
+
+//Bytes of a hex string such as "4d5a90", two digits per byte
+pub fn hex_to_vec_u8(s: &str) -> Result<Vec<u8>, MRError> {
+    if !s.is_ascii() || !s.len().is_multiple_of(2) {
+        return Err(MRError::new("Not a valid hex"));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| MRError::new("Not a valid hex")))
+        .collect()
+}
+
+//Bar of a scan over total bytes, the eta in minutes and seconds
+pub fn bytes_progress_bar(total: u64) -> ProgressBar {
+    let pb = ProgressBar::new(total);
+    pb.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}), {eta}")
+            .unwrap()
+            .with_key("eta", |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+                let secs = state.eta().as_secs();
+                match secs >= 60 {
+                    true => write!(w, "{}m{}s", secs / 60, secs % 60).unwrap(),
+                    false => write!(w, "{}s", secs).unwrap(),
+                }
+            })
+            .progress_chars("#>-"));
+    pb
+}
@@ -1,6 +1,7 @@
 use std::{error::Error, fmt::Display};
 
 pub mod error;
+pub mod export;
 pub mod funcs;
 pub mod file;
 pub mod log;